@@ -0,0 +1,172 @@
+//! Structured postal addresses with per-country validation
+//!
+//! Replaces free-text address strings: the parts are stored separately (as a BSON subdocument
+//! through serde) and `validate` checks the fields each country requires and its postcode format:
+//!
+//! ```json
+//! { "line1": "Rua Augusta 100", "city": "Lisboa", "postalCode": "1100-053", "countryCode": "PT" }
+//! ```
+
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
+use serde::{ Deserialize, Serialize };
+
+use crate::common_lib::country_utils::CountryService;
+use crate::common_lib::error::ApiError;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Address {
+    pub line1: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line2: Option<String>,
+    pub city: String,
+    /// State, province or emirate; required in some countries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postal_code: Option<String>,
+    /// ISO 3166-1 alpha-2
+    pub country_code: String,
+}
+
+/// Postcodes a country uses
+enum Postcodes {
+    /// The country has no postcodes
+    None,
+    /// Country without known rules: optional and unchecked
+    Any,
+    /// Required, in one of these formats: `9` is a digit, `A` a letter, `X` either, anything else literal
+    Formats(&'static [&'static str]),
+}
+
+/// Whether the region is required, and the postcode rules
+fn rules_for(country_code: &str) -> (bool, Postcodes) {
+    match country_code {
+        "US" => (true, Postcodes::Formats(&["99999", "99999-9999"])),
+        "CA" => (true, Postcodes::Formats(&["A9A 9A9"])),
+        "BR" => (true, Postcodes::Formats(&["99999-999"])),
+        "AU" => (true, Postcodes::Formats(&["9999"])),
+        "MX" => (true, Postcodes::Formats(&["99999"])),
+        "AE" => (true, Postcodes::None),
+        "GB" => (false, Postcodes::Formats(&["A9 9AA", "A99 9AA", "AA9 9AA", "AA99 9AA", "A9A 9AA", "AA9A 9AA"])),
+        "IE" => (false, Postcodes::Formats(&["A99 XXXX"])),
+        "PT" => (false, Postcodes::Formats(&["9999-999"])),
+        "NL" => (false, Postcodes::Formats(&["9999 AA"])),
+        "SE" => (false, Postcodes::Formats(&["999 99"])),
+        "PL" => (false, Postcodes::Formats(&["99-999"])),
+        "JP" => (false, Postcodes::Formats(&["999-9999"])),
+        "IN" => (false, Postcodes::Formats(&["999999"])),
+        "DE" | "FR" | "ES" | "IT" | "SA" | "TR" => (false, Postcodes::Formats(&["99999"])),
+        "AT" | "BE" | "CH" | "DK" | "NO" | "ZA" => (false, Postcodes::Formats(&["9999"])),
+        _ => (false, Postcodes::Any),
+    }
+}
+
+/// Whether a code matches a format mask: `9` is a digit, `A` a letter, `X` either, anything else literal
+pub(crate) fn matches_format(postal_code: &str, format: &str) -> bool {
+    postal_code.chars().count() == format.chars().count() &&
+        postal_code.chars().zip(format.chars()).all(|(c, f)| {
+            match f {
+                '9' => c.is_ascii_digit(),
+                'A' => c.is_ascii_uppercase(),
+                'X' => c.is_ascii_digit() || c.is_ascii_uppercase(),
+                _ => c == f,
+            }
+        })
+}
+
+impl Address {
+    /// Copy with trimmed fields, empty optionals as `None`, and uppercase country and postcode
+    pub fn normalized(&self) -> Self {
+        let optional = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|value| value.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|value| !value.is_empty())
+        };
+
+        Self {
+            line1: self.line1.trim().to_string(),
+            line2: optional(&self.line2),
+            city: self.city.trim().to_string(),
+            region: optional(&self.region),
+            postal_code: optional(&self.postal_code).map(|code| code.to_uppercase()),
+            country_code: self.country_code.trim().to_uppercase(),
+        }
+    }
+
+    /// Check required fields and the postcode format; call on the `normalized` address
+    pub fn validate(&self) -> Result<(), ApiError> {
+        let invalid = |message: String| Err(ApiError::BadRequest { message });
+
+        if !CountryService::is_valid_country_code(&self.country_code) {
+            return invalid(format!("Invalid country code format: '{}'", self.country_code));
+        }
+        if self.line1.trim().is_empty() {
+            return invalid("Address line 1 is required".to_string());
+        }
+        if self.city.trim().is_empty() {
+            return invalid("City is required".to_string());
+        }
+
+        let (region_required, postcodes) = rules_for(&self.country_code);
+        if region_required && self.region.as_deref().is_none_or(|region| region.trim().is_empty()) {
+            return invalid(format!("Region is required for addresses in {}", self.country_code));
+        }
+
+        match (postcodes, self.postal_code.as_deref()) {
+            (Postcodes::None, Some(postal_code)) => {
+                invalid(format!("Addresses in {} have no postal code, got '{}'", self.country_code, postal_code))
+            }
+            (Postcodes::Formats(_), None) => {
+                invalid(format!("Postal code is required for addresses in {}", self.country_code))
+            }
+            (Postcodes::Formats(formats), Some(postal_code)) if
+                !formats.iter().any(|format| matches_format(postal_code, format))
+            => {
+                invalid(format!("Postal code '{}' is not valid for {}", postal_code, self.country_code))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(country_code: &str, region: Option<&str>, postal_code: Option<&str>) -> Address {
+        Address {
+            line1: " Rua Augusta 100 ".to_string(),
+            line2: Some("  ".to_string()),
+            city: "Lisboa".to_string(),
+            region: region.map(str::to_string),
+            postal_code: postal_code.map(str::to_string),
+            country_code: country_code.to_string(),
+        }.normalized()
+    }
+
+    #[test]
+    fn test_country_rules() {
+        let lisbon = address("pt", None, Some("1100-053"));
+        assert_eq!(lisbon.line1, "Rua Augusta 100");
+        assert_eq!(lisbon.line2, None);
+        assert!(lisbon.validate().is_ok());
+
+        assert!(address("PT", None, Some("1100053")).validate().is_err());
+        assert!(address("PT", None, None).validate().is_err(), "postcode required");
+        assert!(address("GB", None, Some("sw1a 1aa")).validate().is_ok());
+        assert!(address("US", None, Some("94043")).validate().is_err(), "state required");
+        assert!(address("US", Some("CA"), Some("94043-1351")).validate().is_ok());
+        assert!(address("AE", Some("Dubai"), Some("00000")).validate().is_err(), "no postcodes");
+        assert!(address("KE", None, None).validate().is_ok(), "unknown country");
+
+        let json = serde_json::to_value(&lisbon).unwrap();
+        assert_eq!(json["postalCode"], "1100-053");
+        assert!(json.get("line2").is_none());
+    }
+}
@@ -0,0 +1,450 @@
+//! Feature usage analytics: validated, sampled and batched events
+//!
+//! Services register an `EventSchema` for each event they emit and call `track`. Events are checked
+//! against their schema, sampled, and buffered; the task started by `spawn` hands them to an
+//! `AnalyticsSink` every `flush_interval_seconds`, or as soon as `batch_size` are buffered, and
+//! backs off while the sink is down, so tracking never publishes in the request path. Routes can
+//! take the `Tracker` guard (compiled out by the `no_web` and `no_geo` features) so events carry the
+//! caller's country, locale and timezone from `RequestContext`.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex };
+use std::time::Duration;
+use chrono::{ DateTime, Utc };
+use rand::Rng;
+#[cfg(not(any(feature = "no_web", feature = "no_geo")))]
+use rocket::request::{ FromRequest, Outcome, Request };
+use serde::{ Deserialize, Serialize };
+use serde_json::{ Map, Value };
+use tokio::sync::Notify;
+use tracing::{ debug, warn };
+
+use crate::common_lib::error::ApiError;
+#[cfg(not(any(feature = "no_web", feature = "no_geo")))]
+use crate::common_lib::request_context::RequestContext;
+
+pub type PublishFuture<'a> = Pin<Box<dyn Future<Output = Result<(), ApiError>> + Send + 'a>>;
+
+/// Type of an event property
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyType {
+    String,
+    Number,
+    Bool,
+}
+
+impl PropertyType {
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            PropertyType::String => value.is_string(),
+            PropertyType::Number => value.is_number(),
+            PropertyType::Bool => value.is_boolean(),
+        }
+    }
+}
+
+/// Allowed properties of an event; properties not listed are rejected so no stray PII is shipped
+#[derive(Debug, Clone)]
+pub struct EventSchema {
+    pub name: String,
+    pub required: Vec<(String, PropertyType)>,
+    pub optional: Vec<(String, PropertyType)>,
+    /// Fraction of events kept, between 0.0 and 1.0
+    pub sample_rate: f64,
+}
+
+impl EventSchema {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            required: Vec::new(),
+            optional: Vec::new(),
+            sample_rate: 1.0,
+        }
+    }
+
+    pub fn required(mut self, property: &str, property_type: PropertyType) -> Self {
+        self.required.push((property.to_string(), property_type));
+        self
+    }
+
+    pub fn optional(mut self, property: &str, property_type: PropertyType) -> Self {
+        self.optional.push((property.to_string(), property_type));
+        self
+    }
+
+    pub fn sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = sample_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Check properties against the schema
+    pub fn validate(&self, properties: &Map<String, Value>) -> Result<(), ApiError> {
+        let invalid = |message: String| Err(ApiError::BadRequest { message });
+
+        for (property, property_type) in &self.required {
+            match properties.get(property) {
+                None => {
+                    return invalid(format!("Event '{}' is missing property '{}'", self.name, property));
+                }
+                Some(value) if !property_type.matches(value) => {
+                    return invalid(
+                        format!("Event '{}' property '{}' must be {:?}", self.name, property, property_type)
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+
+        for (property, value) in properties {
+            if self.required.iter().any(|(name, _)| name == property) {
+                continue;
+            }
+            match self.optional.iter().find(|(name, _)| name == property) {
+                Some((_, property_type)) if property_type.matches(value) || value.is_null() => {}
+                Some((_, property_type)) => {
+                    return invalid(
+                        format!("Event '{}' property '{}' must be {:?}", self.name, property, property_type)
+                    );
+                }
+                None => {
+                    return invalid(format!("Event '{}' does not allow property '{}'", self.name, property));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A tracked event as shipped to the sink
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyticsEvent {
+    pub name: String,
+    pub properties: Map<String, Value>,
+    pub timestamp: DateTime<Utc>,
+    pub country_code: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+}
+
+/// Destination for event batches (Kinesis, SQS, a warehouse collector, ...)
+pub trait AnalyticsSink: Send + Sync {
+    fn publish<'a>(&'a self, events: &'a [AnalyticsEvent]) -> PublishFuture<'a>;
+}
+
+#[derive(Debug, Clone)]
+pub struct AnalyticsConfig {
+    /// Buffered events that wake the flush task early
+    pub batch_size: usize,
+    pub flush_interval_seconds: u64,
+    /// Longest wait between retries while the sink is down; waits double from the flush interval
+    pub max_backoff_seconds: u64,
+    /// Events dropped when the sink is down and the buffer is full
+    pub max_buffered_events: usize,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            flush_interval_seconds: 10,
+            max_backoff_seconds: 300,
+            max_buffered_events: 10_000,
+        }
+    }
+}
+
+/// Validates, samples and batches events for an `AnalyticsSink`
+pub struct AnalyticsEmitter {
+    config: AnalyticsConfig,
+    sink: Arc<dyn AnalyticsSink>,
+    schemas: HashMap<String, EventSchema>,
+    buffer: Mutex<Vec<AnalyticsEvent>>,
+    /// Woken when a full batch is buffered
+    batch_ready: Notify,
+}
+
+impl AnalyticsEmitter {
+    pub fn new(config: AnalyticsConfig, sink: Arc<dyn AnalyticsSink>) -> Self {
+        Self {
+            config,
+            sink,
+            schemas: HashMap::new(),
+            buffer: Mutex::new(Vec::new()),
+            batch_ready: Notify::new(),
+        }
+    }
+
+    pub fn with_schema(mut self, schema: EventSchema) -> Self {
+        self.schemas.insert(schema.name.clone(), schema);
+        self
+    }
+
+    /// Track an event without request context; `Ok` once it is buffered or sampled out
+    pub async fn track(&self, event_name: &str, properties: Value) -> Result<(), ApiError> {
+        self.track_event(event_name, properties, None, None, None).await
+    }
+
+    /// Track an event with the caller's localization
+    #[cfg(not(any(feature = "no_web", feature = "no_geo")))]
+    pub async fn track_with_context(
+        &self,
+        context: &RequestContext,
+        event_name: &str,
+        properties: Value
+    ) -> Result<(), ApiError> {
+        self.track_event(
+            event_name,
+            properties,
+            context.country_code.clone(),
+            Some(context.locale.clone()),
+            Some(context.timezone.clone())
+        ).await
+    }
+
+    async fn track_event(
+        &self,
+        event_name: &str,
+        properties: Value,
+        country_code: Option<String>,
+        locale: Option<String>,
+        timezone: Option<String>
+    ) -> Result<(), ApiError> {
+        let schema = self.schemas.get(event_name).ok_or_else(|| ApiError::BadRequest {
+            message: format!("Unknown analytics event '{}'", event_name),
+        })?;
+        let properties = match properties {
+            Value::Object(properties) => properties,
+            Value::Null => Map::new(),
+            _ => {
+                return Err(ApiError::BadRequest {
+                    message: format!("Event '{}' properties must be a JSON object", event_name),
+                });
+            }
+        };
+        schema.validate(&properties)?;
+
+        if schema.sample_rate < 1.0 && rand::rng().random::<f64>() >= schema.sample_rate {
+            return Ok(());
+        }
+
+        let batch_ready = {
+            let mut buffer = self.buffer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if buffer.len() >= self.config.max_buffered_events {
+                warn!("ANALYTICS:track [BUFFER_FULL] Dropping event - event: {}", event_name);
+                return Ok(());
+            }
+            buffer.push(AnalyticsEvent {
+                name: event_name.to_string(),
+                properties,
+                timestamp: Utc::now(),
+                country_code,
+                locale,
+                timezone,
+            });
+            buffer.len() >= self.config.batch_size
+        };
+
+        if batch_ready {
+            self.batch_ready.notify_one();
+        }
+        Ok(())
+    }
+
+    /// Ship buffered events; on failure they are kept for the next flush
+    pub async fn flush(&self) -> Result<(), ApiError> {
+        let batch = std::mem::take(&mut *self.buffer.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        if let Err(e) = self.sink.publish(&batch).await {
+            let mut buffer = self.buffer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let room = self.config.max_buffered_events.saturating_sub(buffer.len());
+            let kept: Vec<AnalyticsEvent> = batch.into_iter().take(room).collect();
+            buffer.splice(0..0, kept);
+            return Err(e);
+        }
+
+        debug!("ANALYTICS:flush [SUCCESS] Events published - count: {}", batch.len());
+        Ok(())
+    }
+
+    /// Spawn the flush task, woken by the interval or a full batch
+    /// Failed flushes are retried after a doubling backoff, during which full batches just buffer.
+    pub fn spawn(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let emitter = Arc::clone(self);
+        let interval = Duration::from_secs(emitter.config.flush_interval_seconds.max(1));
+        let max_backoff = Duration::from_secs(emitter.config.max_backoff_seconds).max(interval);
+
+        tokio::spawn(async move {
+            let mut failures: u32 = 0;
+
+            loop {
+                if failures == 0 {
+                    let _ = tokio::time::timeout(interval, emitter.batch_ready.notified()).await;
+                } else {
+                    let backoff = interval.saturating_mul(2u32.saturating_pow(failures)).min(max_backoff);
+                    tokio::time::sleep(backoff).await;
+                }
+
+                match emitter.flush().await {
+                    Ok(()) => {
+                        failures = 0;
+                    }
+                    Err(e) => {
+                        failures = failures.saturating_add(1);
+                        warn!(
+                            "ANALYTICS:flush [PUBLISH_ERROR] Failed to publish events, backing off - failures: {}, error: {}",
+                            failures,
+                            e
+                        );
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Request guard that tracks events enriched with the caller's `RequestContext`
+/// Requires a managed `Arc<AnalyticsEmitter>`.
+#[cfg(not(any(feature = "no_web", feature = "no_geo")))]
+pub struct Tracker {
+    emitter: Arc<AnalyticsEmitter>,
+    context: RequestContext,
+}
+
+#[cfg(not(any(feature = "no_web", feature = "no_geo")))]
+impl Tracker {
+    /// Track an event; analytics failures are logged rather than failing the request
+    pub async fn track(&self, event_name: &str, properties: Value) {
+        if let Err(e) = self.emitter.track_with_context(&self.context, event_name, properties).await {
+            warn!("ANALYTICS:track [TRACK_ERROR] Event not tracked - event: {}, error: {}", event_name, e);
+        }
+    }
+}
+
+#[cfg(not(any(feature = "no_web", feature = "no_geo")))]
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Tracker {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(emitter) = request.rocket().state::<Arc<AnalyticsEmitter>>() else {
+            return Outcome::Error((
+                rocket::http::Status::InternalServerError,
+                ApiError::InternalServerError {
+                    message: "Analytics emitter is not configured".to_string(),
+                },
+            ));
+        };
+        let context = request.guard::<RequestContext>().await.succeeded();
+
+        match context {
+            Some(context) => Outcome::Success(Tracker { emitter: Arc::clone(emitter), context }),
+            None =>
+                Outcome::Error((
+                    rocket::http::Status::InternalServerError,
+                    ApiError::InternalServerError {
+                        message: "Request context unavailable".to_string(),
+                    },
+                )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        batches: Mutex<Vec<Vec<AnalyticsEvent>>>,
+        failing: Mutex<bool>,
+    }
+
+    impl AnalyticsSink for RecordingSink {
+        fn publish<'a>(&'a self, events: &'a [AnalyticsEvent]) -> PublishFuture<'a> {
+            let failing = *self.failing.lock().unwrap();
+            if !failing {
+                self.batches.lock().unwrap().push(events.to_vec());
+            }
+            Box::pin(async move {
+                if failing {
+                    Err(ApiError::InternalServerError { message: "sink down".to_string() })
+                } else {
+                    Ok(())
+                }
+            })
+        }
+    }
+
+    async fn published(sink: &RecordingSink, batches: usize) {
+        for _ in 0..100 {
+            if sink.batches.lock().unwrap().len() >= batches {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("expected {} published batches", batches);
+    }
+
+    fn emitter(sink: Arc<RecordingSink>) -> AnalyticsEmitter {
+        let config = AnalyticsConfig { batch_size: 2, ..AnalyticsConfig::default() };
+
+        AnalyticsEmitter::new(config, sink)
+            .with_schema(
+                EventSchema::new("message_sent")
+                    .required("channel", PropertyType::String)
+                    .optional("attachments", PropertyType::Number)
+            )
+            .with_schema(EventSchema::new("app_opened").sample_rate(0.0))
+    }
+
+    #[tokio::test]
+    async fn test_events_are_validated_and_batched() {
+        let sink = Arc::new(RecordingSink::default());
+        let analytics = Arc::new(emitter(sink.clone()));
+        let task = analytics.spawn();
+
+        assert!(analytics.track("unknown", json!({})).await.is_err());
+        assert!(analytics.track("message_sent", json!({})).await.is_err());
+        assert!(analytics.track("message_sent", json!({ "channel": 1 })).await.is_err());
+        assert!(analytics.track("message_sent", json!({ "channel": "chat", "email": "a@b.c" })).await.is_err());
+
+        analytics.track("app_opened", json!(null)).await.unwrap();
+        analytics.track("message_sent", json!({ "channel": "chat" })).await.unwrap();
+        assert!(sink.batches.lock().unwrap().is_empty());
+
+        analytics.track("message_sent", json!({ "channel": "group", "attachments": 2 })).await.unwrap();
+        // The full batch wakes the flush task instead of publishing in `track`
+        published(&sink, 1).await;
+        let batches = sink.batches.lock().unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].len(), 2);
+        assert_eq!(batches[0][1].properties["attachments"], json!(2));
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_keeps_events() {
+        let sink = Arc::new(RecordingSink::default());
+        let analytics = emitter(sink.clone());
+        *sink.failing.lock().unwrap() = true;
+
+        analytics.track("message_sent", json!({ "channel": "chat" })).await.unwrap();
+        analytics.track("message_sent", json!({ "channel": "chat" })).await.unwrap();
+        // A full batch is buffered and left to the flush task, even with the sink down
+        assert!(analytics.track("message_sent", json!({ "channel": "chat" })).await.is_ok());
+        assert!(analytics.flush().await.is_err());
+
+        *sink.failing.lock().unwrap() = false;
+        analytics.flush().await.unwrap();
+        assert_eq!(sink.batches.lock().unwrap()[0].len(), 3);
+    }
+}
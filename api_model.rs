@@ -0,0 +1,114 @@
+//! `api_model!` declares a DTO with the standard attribute stack and a builder
+//!
+//! common-lib is compiled as a module inside each service rather than as its own crate, so it cannot
+//! host a proc-macro; this declarative macro covers the same boilerplate:
+//!
+//! ```ignore
+//! api_model! {
+//!     pub struct VenueSummary {
+//!         pub id: MyObjectId,
+//!         pub display_name: String,
+//!         pub created_at: MyDateTime,
+//!     }
+//! }
+//!
+//! let venue = VenueSummary::builder().id(id).display_name("Cafe").created_at(now).build()?;
+//! ```
+//!
+//! Use `MyObjectId` / `MyDateTime` for ids and timestamps so they convert to BSON natively; field
+//! attributes pass through, e.g. `#[serde(serialize_with = "serialize_object_id")]` for hex ids.
+
+/// Declare a camelCase Serialize/Deserialize/JsonSchema DTO with a `builder()`
+/// `build()` returns `ApiError::BadRequest` naming the first field that was not set.
+#[macro_export]
+macro_rules! api_model {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$field_attr:meta])*
+                $field_vis:vis $field:ident : $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, rocket_okapi::okapi::schemars::JsonSchema)]
+        #[serde(rename_all = "camelCase")]
+        #[schemars(crate = "rocket_okapi::okapi::schemars")]
+        $vis struct $name {
+            $(
+                $(#[$field_attr])*
+                $field_vis $field: $ty,
+            )*
+        }
+
+        paste::paste! {
+            #[doc = "Builder for [`" $name "`]"]
+            #[derive(Default)]
+            $vis struct [<$name Builder>] {
+                $( $field: Option<$ty>, )*
+            }
+
+            impl [<$name Builder>] {
+                $(
+                    pub fn $field(mut self, value: impl Into<$ty>) -> Self {
+                        self.$field = Some(value.into());
+                        self
+                    }
+                )*
+
+                pub fn build(self) -> Result<$name, $crate::common_lib::error::ApiError> {
+                    Ok($name {
+                        $(
+                            $field: self.$field.ok_or_else(|| $crate::common_lib::error::ApiError::BadRequest {
+                                message: format!(
+                                    "Missing field '{}' for {}",
+                                    stringify!($field),
+                                    stringify!($name)
+                                ),
+                            })?,
+                        )*
+                    })
+                }
+            }
+
+            impl $name {
+                pub fn builder() -> [<$name Builder>] {
+                    [<$name Builder>]::default()
+                }
+            }
+        }
+    };
+}
+
+#[cfg(all(test, not(feature = "no_mongo")))]
+mod tests {
+    use crate::common_lib::error::ApiError;
+    use crate::common_lib::shared_models::MyObjectId;
+
+    crate::api_model! {
+        /// Test model
+        pub struct VenueSummary {
+            pub id: MyObjectId,
+            pub display_name: String,
+            #[serde(default)]
+            pub tags: Vec<String>,
+        }
+    }
+
+    #[test]
+    fn test_api_model_serde_and_builder() {
+        let venue = VenueSummary::builder()
+            .id(MyObjectId::new())
+            .display_name("Cafe Lisboa")
+            .tags(vec!["coffee".to_string()])
+            .build()
+            .unwrap();
+
+        let json = serde_json::to_value(&venue).unwrap();
+        assert_eq!(json["displayName"], "Cafe Lisboa");
+
+        let missing = VenueSummary::builder().display_name("Cafe").build();
+        assert!(matches!(missing, Err(ApiError::BadRequest { message }) if message.contains("'id'")));
+    }
+}
@@ -0,0 +1,317 @@
+//! Minimum app version enforcement for mobile clients, compiled out by the `no_web` feature
+//!
+//! Clients send `X-App-Version` (semver, e.g. "2.4.1" or "2.5.0-beta.2") and `X-App-Platform`
+//! ("ios", "android", ...). Manage an `AppVersionPolicy`, add `ClientAppVersion` to the gated
+//! handlers and register `catchers()` so outdated clients get a 426 with the upgrade details:
+//!
+//! ```ignore
+//! rocket::build()
+//!     .manage(AppVersionPolicy::new().with_minimum("ios", "2.4.0")?.with_minimum("android", "2.3.0")?)
+//!     .register("/", app_version::catchers())
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use rocket::http::Status;
+use rocket::request::{ FromRequest, Outcome, Request };
+use rocket::{ catch, catchers, Catcher };
+use tracing::{ error, warn };
+
+use crate::common_lib::constants::{ X_APP_PLATFORM, X_APP_VERSION };
+use crate::common_lib::error::ApiError;
+
+/// Semantic version of a client build; build metadata ("+45") is ignored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// Pre-release identifiers, e.g. ["beta", "2"]
+    pub pre_release: Vec<String>,
+}
+
+impl AppVersion {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self { major, minor, patch, pre_release: Vec::new() }
+    }
+}
+
+impl FromStr for AppVersion {
+    type Err = ApiError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || ApiError::BadRequest { message: format!("Invalid app version: {}", value) };
+
+        let version = value.trim().trim_start_matches('v');
+        let version = version.split('+').next().unwrap_or_default();
+        let (core, pre_release) = match version.split_once('-') {
+            Some((core, pre_release)) => (core, Some(pre_release)),
+            None => (version, None),
+        };
+
+        let numbers = core
+            .split('.')
+            .map(|part| part.parse::<u64>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let [major, minor, patch] = numbers[..] else {
+            return Err(invalid());
+        };
+
+        let pre_release = match pre_release {
+            Some(pre_release) => {
+                let identifiers: Vec<String> = pre_release.split('.').map(str::to_string).collect();
+                if identifiers.iter().any(String::is_empty) {
+                    return Err(invalid());
+                }
+                identifiers
+            }
+            None => Vec::new(),
+        };
+
+        Ok(Self { major, minor, patch, pre_release })
+    }
+}
+
+impl fmt::Display for AppVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre_release.is_empty() {
+            write!(f, "-{}", self.pre_release.join("."))?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for AppVersion {
+    /// Semver precedence: a pre-release sorts before its release, numeric identifiers compare numerically
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| {
+                match (self.pre_release.is_empty(), other.pre_release.is_empty()) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => Ordering::Greater,
+                    (false, true) => Ordering::Less,
+                    (false, false) => {
+                        for (left, right) in self.pre_release.iter().zip(&other.pre_release) {
+                            let ordering = match (left.parse::<u64>(), right.parse::<u64>()) {
+                                (Ok(left), Ok(right)) => left.cmp(&right),
+                                (Ok(_), Err(_)) => Ordering::Less,
+                                (Err(_), Ok(_)) => Ordering::Greater,
+                                (Err(_), Err(_)) => left.cmp(right),
+                            };
+                            if ordering != Ordering::Equal {
+                                return ordering;
+                            }
+                        }
+                        self.pre_release.len().cmp(&other.pre_release.len())
+                    }
+                }
+            })
+    }
+}
+
+impl PartialOrd for AppVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Minimum supported app version per platform
+#[derive(Debug, Clone, Default)]
+pub struct AppVersionPolicy {
+    /// Keyed by lowercase platform name
+    pub minimums: HashMap<String, AppVersion>,
+    /// Store links shown to blocked clients, keyed by lowercase platform name
+    pub update_urls: HashMap<String, String>,
+    /// Whether requests without `X-App-Version` are rejected; web and server clients usually don't send it
+    pub require_version: bool,
+}
+
+impl AppVersionPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_minimum(mut self, platform: &str, minimum_version: &str) -> Result<Self, ApiError> {
+        self.minimums.insert(platform.to_lowercase(), minimum_version.parse()?);
+        Ok(self)
+    }
+
+    pub fn with_update_url(mut self, platform: &str, update_url: &str) -> Self {
+        self.update_urls.insert(platform.to_lowercase(), update_url.to_string());
+        self
+    }
+
+    pub fn with_require_version(mut self, require_version: bool) -> Self {
+        self.require_version = require_version;
+        self
+    }
+
+    /// Checks a client against the minimum of its platform; unknown platforms are let through
+    pub fn check(&self, platform: Option<&str>, version: Option<&str>) -> Result<Option<AppVersion>, ApiError> {
+        let Some(version) = version else {
+            if self.require_version {
+                return Err(ApiError::BadRequest { message: format!("Missing {} header", X_APP_VERSION) });
+            }
+            return Ok(None);
+        };
+        let version: AppVersion = version.parse()?;
+
+        let platform = platform.unwrap_or_default().to_lowercase();
+        match self.minimums.get(&platform) {
+            Some(minimum) if &version < minimum =>
+                Err(ApiError::UpgradeRequired {
+                    message: format!(
+                        "App version {} is no longer supported, please update to {} or later",
+                        version,
+                        minimum
+                    ),
+                    platform: platform.clone(),
+                    minimum_version: minimum.to_string(),
+                    update_url: self.update_urls.get(&platform).cloned(),
+                }),
+            _ => Ok(Some(version)),
+        }
+    }
+}
+
+/// Request guard enforcing the managed `AppVersionPolicy`
+#[derive(Debug, Clone)]
+pub struct ClientAppVersion {
+    pub platform: Option<String>,
+    /// `None` when the client sent no version and the policy doesn't require one
+    pub version: Option<AppVersion>,
+}
+
+fn check_request(request: &Request<'_>, policy: &AppVersionPolicy) -> Result<ClientAppVersion, ApiError> {
+    let platform = request.headers().get_one(X_APP_PLATFORM);
+    let version = policy.check(platform, request.headers().get_one(X_APP_VERSION))?;
+
+    Ok(ClientAppVersion { platform: platform.map(str::to_lowercase), version })
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientAppVersion {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(policy) = request.rocket().state::<AppVersionPolicy>() else {
+            error!("APP_VERSION:check [CONFIG] AppVersionPolicy is not managed by Rocket");
+            return Outcome::Error((
+                Status::InternalServerError,
+                ApiError::InternalServerError {
+                    message: "App version policy is not configured".to_string(),
+                },
+            ));
+        };
+
+        match check_request(request, policy) {
+            Ok(client_app_version) => Outcome::Success(client_app_version),
+            Err(error) => {
+                warn!(
+                    "APP_VERSION:check [REJECTED] Client app version rejected - platform: {:?}, version: {:?}, error: {}",
+                    request.headers().get_one(X_APP_PLATFORM),
+                    request.headers().get_one(X_APP_VERSION),
+                    error
+                );
+                Outcome::Error((error.http_status(), error))
+            }
+        }
+    }
+}
+
+#[catch(426)]
+fn upgrade_required(request: &Request<'_>) -> ApiError {
+    // Guard errors don't reach catchers, so the rejection is recomputed from the same headers
+    request
+        .rocket()
+        .state::<AppVersionPolicy>()
+        .and_then(|policy| check_request(request, policy).err())
+        .unwrap_or_else(|| ApiError::UpgradeRequired {
+            message: "This app version is no longer supported, please update".to_string(),
+            platform: String::new(),
+            minimum_version: String::new(),
+            update_url: None,
+        })
+}
+
+/// Catchers rendering rejected clients as JSON errors, e.g. `rocket.register("/", catchers())`
+pub fn catchers() -> Vec<Catcher> {
+    catchers![upgrade_required]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::{ get, routes };
+    use rocket::http::Header;
+    use crate::common_lib::test_client::{ test_client, test_rocket };
+
+    fn version(value: &str) -> AppVersion {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_ordering() {
+        assert_eq!(version("v2.4.1+45"), AppVersion::new(2, 4, 1));
+        assert_eq!(version("2.5.0-beta.2").to_string(), "2.5.0-beta.2");
+        assert!("2.4".parse::<AppVersion>().is_err());
+        assert!("2.4.x".parse::<AppVersion>().is_err());
+
+        assert!(version("2.10.0") > version("2.9.9"));
+        assert!(version("2.5.0-beta.2") < version("2.5.0"));
+        assert!(version("2.5.0-beta.2") < version("2.5.0-beta.10"));
+        assert!(version("2.5.0-alpha") < version("2.5.0-alpha.1"));
+    }
+
+    #[test]
+    fn test_policy_check() {
+        let policy = AppVersionPolicy::new().with_minimum("iOS", "2.4.0").unwrap();
+
+        assert!(policy.check(Some("ios"), Some("2.4.0")).is_ok());
+        assert!(policy.check(Some("android"), Some("1.0.0")).is_ok());
+        assert_eq!(policy.check(Some("ios"), None).unwrap(), None);
+        assert!(matches!(policy.check(Some("ios"), Some("2.3.9")), Err(ApiError::UpgradeRequired { .. })));
+        assert!(matches!(policy.check(Some("ios"), Some("latest")), Err(ApiError::BadRequest { .. })));
+        assert!(policy.with_require_version(true).check(Some("ios"), None).is_err());
+    }
+
+    #[get("/gated")]
+    fn gated(client: ClientAppVersion) -> String {
+        client.version.map(|version| version.to_string()).unwrap_or_default()
+    }
+
+    #[rocket::async_test]
+    async fn test_outdated_client_gets_426() {
+        let policy = AppVersionPolicy::new()
+            .with_minimum("android", "3.0.0")
+            .unwrap()
+            .with_update_url("android", "https://play.google.com/store/apps/details?id=app");
+        let client = test_client(test_rocket(routes![gated]).manage(policy)).await;
+
+        let current = client
+            .get("/gated")
+            .header(Header::new(X_APP_PLATFORM, "android"))
+            .header(Header::new(X_APP_VERSION, "3.1.0"))
+            .dispatch().await;
+        assert_eq!(current.status(), Status::Ok);
+        assert_eq!(current.into_string().await.unwrap(), "3.1.0");
+
+        let outdated = client
+            .get("/gated")
+            .header(Header::new(X_APP_PLATFORM, "android"))
+            .header(Header::new(X_APP_VERSION, "2.9.0"))
+            .dispatch().await;
+        assert_eq!(outdated.status(), Status::UpgradeRequired);
+        let body: serde_json::Value = outdated.into_json().await.unwrap();
+        assert_eq!(
+            body["error"],
+            "Upgrade Required: App version 2.9.0 is no longer supported, please update to 3.0.0 or later"
+        );
+        assert_eq!(body["minimumVersion"], "3.0.0");
+        assert_eq!(body["updateUrl"], "https://play.google.com/store/apps/details?id=app");
+    }
+}
@@ -0,0 +1,523 @@
+//! Brute-force protection for login, OTP and PIN flows
+//!
+//! Failed attempts are counted per identifier (phone number, user ID, ...) and per client IP.
+//! After `free_failures` failures each further one blocks the key for a doubling delay, and
+//! reaching `max_failures` blocks it for `lockout_duration`. Blocked keys get
+//! `ApiError::TooManyRequests` with the remaining time. Every flow shares the same policy; the
+//! flow name only namespaces the counters, so OTP failures don't lock the PIN flow.
+//!
+//! Flows call `record_failure_and_check` before verifying: it counts the attempt as a failure up
+//! front, so concurrent guesses each consume one and no more than `max_failures` of them are
+//! ever verified. `record_success` clears the identifier once the attempt turns out to be right
+//! and takes the attempt back from the IP's count, so busy shared addresses (NAT, CGNAT) aren't
+//! locked out by successful logins:
+//!
+//! ```ignore
+//! let attempts = AttemptLimiter::new(AttemptPolicy::default(), Arc::new(RedisAttemptStore::connect(url).await?));
+//! attempts.record_failure_and_check("otp", &phone_number, ip, &req_id).await?;
+//! if otp_matches {
+//!     attempts.record_success("otp", &phone_number, ip).await?;
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+#[cfg(feature = "redis")]
+use redis::AsyncCommands;
+use tokio::sync::Mutex;
+
+use crate::common_lib::clock::{ system_clock, Clock };
+use crate::common_lib::error::ApiError;
+use crate::common_lib::identity_document::mask_document_number;
+use crate::log_security;
+
+pub type AttemptFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, ApiError>> + Send + 'a>>;
+
+/// Lockout policy shared by every authentication flow
+#[derive(Debug, Clone)]
+pub struct AttemptPolicy {
+    /// Failures allowed before delays start
+    pub free_failures: u32,
+    /// Failures after which the identifier is locked out
+    pub max_failures: u32,
+    /// Failures from one IP, across identifiers, after which the IP is locked out
+    pub max_ip_failures: u32,
+    /// Delay after the first failure past `free_failures`, doubled for each further one
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub lockout_duration: Duration,
+    /// Failures older than this are forgotten
+    pub window: Duration,
+}
+
+impl Default for AttemptPolicy {
+    fn default() -> Self {
+        Self {
+            free_failures: 3,
+            max_failures: 10,
+            max_ip_failures: 50,
+            base_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(60),
+            lockout_duration: Duration::from_secs(15 * 60),
+            window: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl AttemptPolicy {
+    /// How long a key is blocked after its `failures`-th failure
+    pub fn block_for(&self, failures: u32) -> Option<Duration> {
+        if failures >= self.max_failures {
+            return Some(self.lockout_duration);
+        }
+        let delayed = failures.checked_sub(self.free_failures).filter(|delayed| *delayed > 0)?;
+        let delay = self.base_delay.saturating_mul(2u32.saturating_pow(delayed - 1));
+        Some(delay.min(self.max_delay))
+    }
+}
+
+/// Failure counters and blocks; implementations must be shared by every instance of a service
+pub trait AttemptStore: Send + Sync {
+    /// Count a failure, returning the failures within `window` including this one
+    fn increment_failures<'a>(&'a self, key: &'a str, window: Duration) -> AttemptFuture<'a, u32>;
+    /// Take back one failure counted within the current window; no-op once it has expired
+    fn forget_failure<'a>(&'a self, key: &'a str) -> AttemptFuture<'a, ()>;
+    fn block<'a>(&'a self, key: &'a str, duration: Duration) -> AttemptFuture<'a, ()>;
+    /// Time left on the key's block, if blocked
+    fn blocked_for<'a>(&'a self, key: &'a str) -> AttemptFuture<'a, Option<Duration>>;
+    /// Forget the key's failures and block
+    fn clear<'a>(&'a self, key: &'a str) -> AttemptFuture<'a, ()>;
+}
+
+#[derive(Debug, Default)]
+struct InMemoryAttempts {
+    /// Failure count and when the window ends
+    failures: HashMap<String, (u32, Instant)>,
+    blocked_until: HashMap<String, Instant>,
+}
+
+/// In-process attempt store for tests and single-instance tools
+pub struct InMemoryAttemptStore {
+    attempts: Mutex<InMemoryAttempts>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for InMemoryAttemptStore {
+    fn default() -> Self {
+        Self::with_clock(system_clock())
+    }
+}
+
+impl InMemoryAttemptStore {
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self { attempts: Mutex::new(InMemoryAttempts::default()), clock }
+    }
+}
+
+impl AttemptStore for InMemoryAttemptStore {
+    fn increment_failures<'a>(&'a self, key: &'a str, window: Duration) -> AttemptFuture<'a, u32> {
+        Box::pin(async move {
+            let now = self.clock.now();
+            let mut attempts = self.attempts.lock().await;
+            let entry = attempts.failures.entry(key.to_string()).or_insert((0, now + window));
+            if entry.1 <= now {
+                *entry = (0, now + window);
+            }
+            entry.0 += 1;
+            Ok(entry.0)
+        })
+    }
+
+    fn forget_failure<'a>(&'a self, key: &'a str) -> AttemptFuture<'a, ()> {
+        Box::pin(async move {
+            let now = self.clock.now();
+            let mut attempts = self.attempts.lock().await;
+            if let Some(entry) = attempts.failures.get_mut(key).filter(|entry| entry.1 > now) {
+                entry.0 = entry.0.saturating_sub(1);
+            }
+            Ok(())
+        })
+    }
+
+    fn block<'a>(&'a self, key: &'a str, duration: Duration) -> AttemptFuture<'a, ()> {
+        Box::pin(async move {
+            let until = self.clock.now() + duration;
+            self.attempts.lock().await.blocked_until.insert(key.to_string(), until);
+            Ok(())
+        })
+    }
+
+    fn blocked_for<'a>(&'a self, key: &'a str) -> AttemptFuture<'a, Option<Duration>> {
+        Box::pin(async move {
+            let now = self.clock.now();
+            let attempts = self.attempts.lock().await;
+            Ok(
+                attempts.blocked_until
+                    .get(key)
+                    .map(|until| until.saturating_duration_since(now))
+                    .filter(|remaining| !remaining.is_zero())
+            )
+        })
+    }
+
+    fn clear<'a>(&'a self, key: &'a str) -> AttemptFuture<'a, ()> {
+        Box::pin(async move {
+            let mut attempts = self.attempts.lock().await;
+            attempts.failures.remove(key);
+            attempts.blocked_until.remove(key);
+            Ok(())
+        })
+    }
+}
+
+/// Attempt store shared across instances through Redis, enabled with the `redis` feature
+#[cfg(feature = "redis")]
+pub struct RedisAttemptStore {
+    connection: redis::aio::MultiplexedConnection,
+}
+
+#[cfg(feature = "redis")]
+impl RedisAttemptStore {
+    const FAILURES_PREFIX: &'static str = "attempts:failures:";
+    const BLOCK_PREFIX: &'static str = "attempts:block:";
+    /// Expiry is only set by the first failure, keeping the window anchored there; a script
+    /// rather than `EXPIRE ... NX`, which needs Redis 7
+    const INCREMENT_SCRIPT: &'static str = r"
+        local failures = redis.call('INCR', KEYS[1])
+        if failures == 1 then
+            redis.call('PEXPIRE', KEYS[1], ARGV[1])
+        end
+        return failures
+    ";
+    /// DECR keeps the key's expiry; a missing key stays missing rather than going negative
+    const FORGET_SCRIPT: &'static str = r"
+        if tonumber(redis.call('GET', KEYS[1]) or '0') > 0 then
+            redis.call('DECR', KEYS[1])
+        end
+        return 0
+    ";
+
+    pub async fn connect(redis_url: &str) -> Result<Self, ApiError> {
+        let client = redis::Client::open(redis_url).map_err(Self::redis_error)?;
+        let connection = client.get_multiplexed_async_connection().await.map_err(Self::redis_error)?;
+
+        Ok(Self { connection })
+    }
+
+    fn redis_error(e: redis::RedisError) -> ApiError {
+        ApiError::InternalServerError {
+            message: format!("Attempt store error: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+impl AttemptStore for RedisAttemptStore {
+    fn increment_failures<'a>(&'a self, key: &'a str, window: Duration) -> AttemptFuture<'a, u32> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let key = format!("{}{}", Self::FAILURES_PREFIX, key);
+            let failures: u32 = redis
+                ::cmd("EVAL")
+                .arg(Self::INCREMENT_SCRIPT)
+                .arg(1)
+                .arg(&key)
+                .arg(window.as_millis().max(1) as u64)
+                .query_async(&mut connection).await
+                .map_err(Self::redis_error)?;
+
+            Ok(failures)
+        })
+    }
+
+    fn forget_failure<'a>(&'a self, key: &'a str) -> AttemptFuture<'a, ()> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            redis
+                ::cmd("EVAL")
+                .arg(Self::FORGET_SCRIPT)
+                .arg(1)
+                .arg(format!("{}{}", Self::FAILURES_PREFIX, key))
+                .query_async::<()>(&mut connection).await
+                .map_err(Self::redis_error)
+        })
+    }
+
+    fn block<'a>(&'a self, key: &'a str, duration: Duration) -> AttemptFuture<'a, ()> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            connection
+                .pset_ex::<_, _, ()>(format!("{}{}", Self::BLOCK_PREFIX, key), "1", duration.as_millis().max(1) as u64).await
+                .map_err(Self::redis_error)
+        })
+    }
+
+    fn blocked_for<'a>(&'a self, key: &'a str) -> AttemptFuture<'a, Option<Duration>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            // -2 when the key doesn't exist
+            let remaining_ms: i64 = connection
+                .pttl(format!("{}{}", Self::BLOCK_PREFIX, key)).await
+                .map_err(Self::redis_error)?;
+
+            Ok((remaining_ms > 0).then(|| Duration::from_millis(remaining_ms as u64)))
+        })
+    }
+
+    fn clear<'a>(&'a self, key: &'a str) -> AttemptFuture<'a, ()> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            connection
+                .del::<_, ()>(&[format!("{}{}", Self::FAILURES_PREFIX, key), format!("{}{}", Self::BLOCK_PREFIX, key)]).await
+                .map_err(Self::redis_error)
+        })
+    }
+}
+
+/// Result of recording a failure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttemptOutcome {
+    /// Failures of the identifier within the window
+    pub failures: u32,
+    /// How long the identifier must wait before the next attempt, if at all
+    pub retry_after: Option<Duration>,
+    pub locked_out: bool,
+}
+
+/// Applies the `AttemptPolicy` to a flow's identifiers and client IPs
+pub struct AttemptLimiter {
+    policy: AttemptPolicy,
+    store: Arc<dyn AttemptStore>,
+}
+
+impl AttemptLimiter {
+    pub fn new(policy: AttemptPolicy, store: Arc<dyn AttemptStore>) -> Self {
+        Self { policy, store }
+    }
+
+    fn identifier_key(flow: &str, identifier: &str) -> String {
+        format!("{}:id:{}", flow, identifier)
+    }
+
+    fn ip_key(flow: &str, ip_address: &str) -> String {
+        format!("{}:ip:{}", flow, ip_address)
+    }
+
+    fn too_many_requests(retry_after: Duration) -> ApiError {
+        let retry_after_seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        ApiError::TooManyRequests {
+            message: format!("Too many failed attempts, try again in {} seconds", retry_after_seconds),
+            retry_after_seconds,
+        }
+    }
+
+    /// Reject the attempt if the identifier or the IP is currently blocked
+    pub async fn check(
+        &self,
+        flow: &str,
+        identifier: &str,
+        ip_address: Option<&str>,
+        req_id: &str
+    ) -> Result<(), ApiError> {
+        let mut keys = vec![("identifier", Self::identifier_key(flow, identifier))];
+        keys.extend(ip_address.map(|ip_address| ("ip", Self::ip_key(flow, ip_address))));
+
+        for (blocked, key) in keys {
+            if let Some(remaining) = self.store.blocked_for(&key).await? {
+                log_security!(
+                    info,
+                    "attempts",
+                    "BLOCKED_ATTEMPT",
+                    req_id,
+                    "flow: {}, identifier: {}, ip: {:?}, blocked: {}, remaining_seconds: {}",
+                    flow,
+                    mask_document_number(identifier),
+                    ip_address,
+                    blocked,
+                    remaining.as_secs()
+                );
+                return Err(Self::too_many_requests(remaining));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Count a failed attempt against the identifier and the IP, blocking them per the policy
+    async fn record_failure(
+        &self,
+        flow: &str,
+        identifier: &str,
+        ip_address: Option<&str>,
+        req_id: &str
+    ) -> Result<AttemptOutcome, ApiError> {
+        let key = Self::identifier_key(flow, identifier);
+        let failures = self.store.increment_failures(&key, self.policy.window).await?;
+        let retry_after = self.policy.block_for(failures);
+        let locked_out = failures >= self.policy.max_failures;
+
+        if let Some(duration) = retry_after {
+            self.store.block(&key, duration).await?;
+        }
+        if locked_out {
+            log_security!(
+                warn,
+                "attempts",
+                "LOCKOUT",
+                req_id,
+                "flow: {}, identifier: {}, ip: {:?}, failures: {}, lockout_seconds: {}",
+                flow,
+                mask_document_number(identifier),
+                ip_address,
+                failures,
+                self.policy.lockout_duration.as_secs()
+            );
+        }
+
+        if let Some(ip_address) = ip_address {
+            let ip_key = Self::ip_key(flow, ip_address);
+            let ip_failures = self.store.increment_failures(&ip_key, self.policy.window).await?;
+            if ip_failures >= self.policy.max_ip_failures {
+                self.store.block(&ip_key, self.policy.lockout_duration).await?;
+                log_security!(
+                    warn,
+                    "attempts",
+                    "IP_LOCKOUT",
+                    req_id,
+                    "flow: {}, ip: {}, failures: {}, lockout_seconds: {}",
+                    flow,
+                    ip_address,
+                    ip_failures,
+                    self.policy.lockout_duration.as_secs()
+                );
+            }
+        }
+
+        Ok(AttemptOutcome { failures, retry_after, locked_out })
+    }
+
+    /// Count an attempt before verifying it, rejecting it if the identifier or the IP is blocked
+    ///
+    /// The entry point for flows: the failure is counted atomically before the check, so
+    /// concurrent attempts can't all pass a `check` made before any of them failed. Call
+    /// `record_success` once the attempt is verified to clear the count.
+    pub async fn record_failure_and_check(
+        &self,
+        flow: &str,
+        identifier: &str,
+        ip_address: Option<&str>,
+        req_id: &str
+    ) -> Result<AttemptOutcome, ApiError> {
+        self.check(flow, identifier, ip_address, req_id).await?;
+        let outcome = self.record_failure(flow, identifier, ip_address, req_id).await?;
+
+        // Attempts racing past `check` are still bounded by the count each of them got
+        if outcome.failures > self.policy.max_failures {
+            return Err(Self::too_many_requests(self.policy.lockout_duration));
+        }
+        Ok(outcome)
+    }
+
+    /// Reset the identifier after a successful attempt and take the attempt back from the IP's
+    /// count; the IP's earlier failures are kept
+    pub async fn record_success(
+        &self,
+        flow: &str,
+        identifier: &str,
+        ip_address: Option<&str>
+    ) -> Result<(), ApiError> {
+        self.store.clear(&Self::identifier_key(flow, identifier)).await?;
+        if let Some(ip_address) = ip_address {
+            self.store.forget_failure(&Self::ip_key(flow, ip_address)).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_lib::clock::MockClock;
+
+    #[test]
+    fn test_progressive_delays() {
+        let policy = AttemptPolicy::default();
+        let delays: Vec<Option<u64>> = (1..=10)
+            .map(|failures| policy.block_for(failures).map(|delay| delay.as_secs()))
+            .collect();
+
+        assert_eq!(delays, vec![None, None, None, Some(2), Some(4), Some(8), Some(16), Some(32), Some(60), Some(900)]);
+    }
+
+    #[tokio::test]
+    async fn test_lockout_and_reset() {
+        let clock = Arc::new(MockClock::default());
+        let policy = AttemptPolicy { free_failures: 1, max_failures: 3, max_ip_failures: 4, ..AttemptPolicy::default() };
+        let limiter = AttemptLimiter::new(policy, Arc::new(InMemoryAttemptStore::with_clock(clock.clone())));
+        let ip = Some("203.0.113.9");
+
+        assert!(limiter.record_failure("otp", "+351910000000", ip, "req").await.unwrap().retry_after.is_none());
+        let outcome = limiter.record_failure("otp", "+351910000000", ip, "req").await.unwrap();
+        assert_eq!(outcome.retry_after, Some(Duration::from_secs(2)));
+        assert!(matches!(
+            limiter.check("otp", "+351910000000", ip, "req").await,
+            Err(ApiError::TooManyRequests { retry_after_seconds: 2, .. })
+        ));
+        assert!(limiter.check("pin", "+351910000000", None, "req").await.is_ok());
+
+        clock.advance(Duration::from_secs(2));
+        assert!(limiter.check("otp", "+351910000000", ip, "req").await.is_ok());
+        assert!(limiter.record_failure("otp", "+351910000000", ip, "req").await.unwrap().locked_out);
+        clock.advance(Duration::from_secs(60));
+        assert!(limiter.check("otp", "+351910000000", None, "req").await.is_err());
+
+        limiter.record_success("otp", "+351910000000", None).await.unwrap();
+        assert!(limiter.check("otp", "+351910000000", None, "req").await.is_ok());
+
+        // The fourth failure from the IP locks it for every identifier
+        limiter.record_failure("otp", "+351920000000", ip, "req").await.unwrap();
+        assert!(limiter.check("otp", "+351930000000", ip, "req").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_attempts_are_bounded() {
+        let policy = AttemptPolicy { free_failures: 10, max_failures: 3, ..AttemptPolicy::default() };
+        let limiter = Arc::new(AttemptLimiter::new(policy, Arc::new(InMemoryAttemptStore::default())));
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..10 {
+            let limiter = limiter.clone();
+            tasks.spawn(async move { limiter.record_failure_and_check("otp", "+351910000000", None, "req").await });
+        }
+        let attempts = tasks.join_all().await;
+        assert_eq!(attempts.iter().filter(|attempt| attempt.is_ok()).count(), 3);
+        assert!(attempts.iter().any(|attempt| matches!(attempt, Err(ApiError::TooManyRequests { .. }))));
+
+        limiter.record_success("otp", "+351910000000", None).await.unwrap();
+        let outcome = limiter.record_failure_and_check("otp", "+351910000000", None, "req").await.unwrap();
+        assert_eq!(outcome.failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_successes_from_a_shared_ip_dont_lock_it() {
+        let policy = AttemptPolicy { max_ip_failures: 4, ..AttemptPolicy::default() };
+        let limiter = AttemptLimiter::new(policy, Arc::new(InMemoryAttemptStore::default()));
+        let ip = Some("100.64.0.1");
+
+        for user in 0..10 {
+            let phone = format!("+35191000000{}", user);
+            limiter.record_failure_and_check("otp", &phone, ip, "req").await.unwrap();
+            limiter.record_success("otp", &phone, ip).await.unwrap();
+        }
+        assert!(limiter.check("otp", "+351920000000", ip, "req").await.is_ok());
+
+        // Failed attempts still add up
+        for user in 0..4 {
+            limiter.record_failure_and_check("otp", &format!("+35193000000{}", user), ip, "req").await.unwrap();
+        }
+        assert!(limiter.check("otp", "+351920000000", ip, "req").await.is_err());
+    }
+}
@@ -0,0 +1,286 @@
+//! Weekly availability schedules for booking, e.g. a venue's opening hours
+//!
+//! Hours are local to the schedule's timezone, so they follow DST. Dated exceptions replace
+//! the weekly hours for that day (an empty list closes it), and holidays close the day unless an
+//! exception says otherwise:
+//!
+//! ```json
+//! {
+//!   "timezone": "Europe/Lisbon",
+//!   "weekly": [{ "weekday": "Mon", "start": "09:00", "end": "13:00" }, { "weekday": "Fri", "start": "20:00", "end": "02:00" }],
+//!   "exceptions": [{ "date": "2025-12-24", "slots": [{ "start": "09:00", "end": "12:00" }] }]
+//! }
+//! ```
+
+use std::collections::HashSet;
+use chrono::{ DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday };
+use chrono_tz::Tz;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
+use serde::{ Deserialize, Serialize };
+
+use crate::common_lib::error::ApiError;
+
+/// Local hours within a day; an `end` at or before `start` runs past midnight (22:00 -> 02:00)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct TimeRange {
+    /// "HH:MM"
+    pub start: String,
+    /// "HH:MM"
+    pub end: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct WeeklySlot {
+    /// "Mon" to "Sun"
+    #[cfg_attr(not(feature = "no_web"), schemars(with = "String"))]
+    pub weekday: Weekday,
+    pub start: String,
+    pub end: String,
+}
+
+/// Hours for one date replacing the weekly ones; no slots means closed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleException {
+    #[cfg_attr(not(feature = "no_web"), schemars(with = "String"))]
+    pub date: NaiveDate,
+    #[serde(default)]
+    pub slots: Vec<TimeRange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// A period of availability, `end` exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Interval {
+    #[cfg_attr(not(feature = "no_web"), schemars(with = "String"))]
+    pub start: DateTime<Utc>,
+    #[cfg_attr(not(feature = "no_web"), schemars(with = "String"))]
+    pub end: DateTime<Utc>,
+}
+
+/// Public holidays of the schedule's country, supplied by the caller
+pub trait HolidayCalendar {
+    fn is_holiday(&self, date: NaiveDate) -> bool;
+}
+
+/// Calendar without holidays
+pub struct NoHolidays;
+
+impl HolidayCalendar for NoHolidays {
+    fn is_holiday(&self, _date: NaiveDate) -> bool {
+        false
+    }
+}
+
+impl HolidayCalendar for HashSet<NaiveDate> {
+    fn is_holiday(&self, date: NaiveDate) -> bool {
+        self.contains(&date)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct AvailabilitySchedule {
+    /// IANA timezone name, e.g. "Europe/Lisbon"
+    pub timezone: String,
+    pub weekly: Vec<WeeklySlot>,
+    #[serde(default)]
+    pub exceptions: Vec<ScheduleException>,
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, ApiError> {
+    NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| ApiError::BadRequest {
+        message: format!("Invalid schedule time: '{}'", value),
+    })
+}
+
+/// Local time to UTC; times skipped by a DST change move forward by an hour
+fn local_to_utc(tz: &Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(local + Duration::hours(1))).earliest())
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+}
+
+/// Sort and merge overlapping or touching intervals
+fn merge(mut intervals: Vec<Interval>) -> Vec<Interval> {
+    intervals.sort();
+    let mut merged: Vec<Interval> = Vec::with_capacity(intervals.len());
+    for interval in intervals {
+        match merged.last_mut() {
+            Some(last) if interval.start <= last.end => {
+                last.end = last.end.max(interval.end);
+            }
+            _ => merged.push(interval),
+        }
+    }
+    merged
+}
+
+/// Periods present in both lists, e.g. when two schedules are both available
+pub fn intersect(a: &[Interval], b: &[Interval]) -> Vec<Interval> {
+    let (a, b) = (merge(a.to_vec()), merge(b.to_vec()));
+    let (mut i, mut j) = (0, 0);
+    let mut common = Vec::new();
+
+    while i < a.len() && j < b.len() {
+        let start = a[i].start.max(b[j].start);
+        let end = a[i].end.min(b[j].end);
+        if start < end {
+            common.push(Interval { start, end });
+        }
+        if a[i].end < b[j].end {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    common
+}
+
+impl AvailabilitySchedule {
+    fn tz(&self) -> Result<Tz, ApiError> {
+        self.timezone.parse().map_err(|_| ApiError::BadRequest {
+            message: format!("Invalid schedule timezone: '{}'", self.timezone),
+        })
+    }
+
+    /// Check the timezone and every time in the schedule
+    pub fn validate(&self) -> Result<(), ApiError> {
+        self.tz()?;
+        let weekly = self.weekly.iter().map(|slot| (slot.start.as_str(), slot.end.as_str()));
+        let exceptions = self.exceptions
+            .iter()
+            .flat_map(|exception| &exception.slots)
+            .map(|slot| (slot.start.as_str(), slot.end.as_str()));
+        for (start, end) in weekly.chain(exceptions) {
+            parse_time(start)?;
+            parse_time(end)?;
+        }
+        Ok(())
+    }
+
+    /// Local hours on a date, before applying the time window
+    fn hours_on(&self, date: NaiveDate, holidays: &dyn HolidayCalendar) -> Vec<(&str, &str)> {
+        if let Some(exception) = self.exceptions.iter().find(|exception| exception.date == date) {
+            return exception.slots
+                .iter()
+                .map(|slot| (slot.start.as_str(), slot.end.as_str()))
+                .collect();
+        }
+        if holidays.is_holiday(date) {
+            return Vec::new();
+        }
+
+        self.weekly
+            .iter()
+            .filter(|slot| slot.weekday == date.weekday())
+            .map(|slot| (slot.start.as_str(), slot.end.as_str()))
+            .collect()
+    }
+
+    /// Available periods within `[from, to)`, merged and in order
+    pub fn available_intervals(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        holidays: &dyn HolidayCalendar
+    ) -> Result<Vec<Interval>, ApiError> {
+        let tz = self.tz()?;
+        if from >= to {
+            return Ok(Vec::new());
+        }
+
+        let mut intervals = Vec::new();
+        // Start a day early for slots running past midnight into the window
+        let mut date = from.with_timezone(&tz).date_naive() - Duration::days(1);
+        let last = to.with_timezone(&tz).date_naive();
+
+        while date <= last {
+            for (start, end) in self.hours_on(date, holidays) {
+                let (start, end) = (parse_time(start)?, parse_time(end)?);
+                let end_date = if end <= start { date + Duration::days(1) } else { date };
+                let interval = Interval {
+                    start: local_to_utc(&tz, date.and_time(start)).max(from),
+                    end: local_to_utc(&tz, end_date.and_time(end)).min(to),
+                };
+                if interval.start < interval.end {
+                    intervals.push(interval);
+                }
+            }
+            date += Duration::days(1);
+        }
+
+        Ok(merge(intervals))
+    }
+
+    pub fn is_available_at(&self, at: DateTime<Utc>, holidays: &dyn HolidayCalendar) -> Result<bool, ApiError> {
+        Ok(!self.available_intervals(at, at + Duration::seconds(1), holidays)?.is_empty())
+    }
+
+    /// Whether `[start, end)` lies entirely within available time, e.g. for a booking
+    pub fn covers(&self, start: DateTime<Utc>, end: DateTime<Utc>, holidays: &dyn HolidayCalendar) -> Result<bool, ApiError> {
+        let intervals = self.available_intervals(start, end, holidays)?;
+        Ok(intervals == [Interval { start, end }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 7, day, hour, minute, 0).unwrap()
+    }
+
+    fn schedule() -> AvailabilitySchedule {
+        serde_json::from_value(serde_json::json!({
+            "timezone": "Europe/Lisbon",
+            "weekly": [
+                { "weekday": "Mon", "start": "09:00", "end": "13:00" },
+                { "weekday": "Mon", "start": "12:00", "end": "18:00" },
+                { "weekday": "Fri", "start": "22:00", "end": "02:00" }
+            ],
+            "exceptions": [{ "date": "2025-07-14", "slots": [] }]
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_availability_in_local_time() {
+        let schedule = schedule();
+        assert!(schedule.validate().is_ok());
+
+        // Monday 7 July, Lisbon is UTC+1: overlapping slots merge into 08:00-17:00 UTC
+        let monday = schedule.available_intervals(utc(7, 0, 0), utc(8, 0, 0), &NoHolidays).unwrap();
+        assert_eq!(monday, vec![Interval { start: utc(7, 8, 0), end: utc(7, 17, 0) }]);
+        assert!(schedule.is_available_at(utc(7, 12, 30), &NoHolidays).unwrap());
+        assert!(!schedule.is_available_at(utc(7, 17, 0), &NoHolidays).unwrap());
+        assert!(schedule.covers(utc(7, 9, 0), utc(7, 16, 0), &NoHolidays).unwrap());
+        assert!(!schedule.covers(utc(7, 16, 0), utc(7, 18, 0), &NoHolidays).unwrap());
+
+        // Friday night slot runs into Saturday
+        assert!(schedule.is_available_at(utc(12, 0, 30), &NoHolidays).unwrap());
+
+        // Exception closes Monday 14 July; a holiday closes Monday 21 July
+        assert!(!schedule.is_available_at(utc(14, 10, 0), &NoHolidays).unwrap());
+        let holidays = HashSet::from([NaiveDate::from_ymd_opt(2025, 7, 21).unwrap()]);
+        assert!(!schedule.is_available_at(utc(21, 10, 0), &holidays).unwrap());
+
+        let other = vec![Interval { start: utc(7, 16, 0), end: utc(7, 20, 0) }];
+        assert_eq!(intersect(&monday, &other), vec![Interval { start: utc(7, 16, 0), end: utc(7, 17, 0) }]);
+    }
+}
@@ -0,0 +1,259 @@
+use tracing::debug;
+use crate::common_lib::country_utils::CountryService;
+use crate::common_lib::error::ApiError;
+
+/// Bank detail utilities for payout IBAN/SWIFT validation and log-safe masking
+pub struct BankDetailsService;
+
+impl BankDetailsService {
+    /// Validate an IBAN and return its normalized electronic form (uppercase, no spaces)
+    /// Checks the country-specific length and the ISO 7064 mod-97 checksum
+    pub fn validate_iban(iban: &str) -> Result<String, ApiError> {
+        let normalized = Self::normalize(iban);
+
+        debug!(
+            "BANK:validate_iban [VALIDATION] Validating IBAN: '{}'",
+            Self::mask_iban(&normalized)
+        );
+
+        if normalized.len() < 5 || !normalized.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(ApiError::BadRequest {
+                message: "Invalid IBAN format".to_string(),
+            });
+        }
+
+        let country_code = &normalized[0..2];
+        let check_digits = &normalized[2..4];
+
+        if !CountryService::is_valid_country_code(country_code) {
+            return Err(ApiError::BadRequest {
+                message: "Invalid IBAN country code".to_string(),
+            });
+        }
+
+        if !check_digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(ApiError::BadRequest {
+                message: "Invalid IBAN check digits".to_string(),
+            });
+        }
+
+        let expected_length = Self::iban_length_for_country(country_code).ok_or_else(|| {
+            ApiError::BadRequest {
+                message: format!("IBAN is not supported for country '{}'", country_code),
+            }
+        })?;
+
+        if normalized.len() != expected_length {
+            return Err(ApiError::BadRequest {
+                message: format!(
+                    "Invalid IBAN length for {}: expected {} characters, got {}",
+                    country_code,
+                    expected_length,
+                    normalized.len()
+                ),
+            });
+        }
+
+        if Self::iban_mod97(&normalized) != 1 {
+            return Err(ApiError::BadRequest {
+                message: "Invalid IBAN checksum".to_string(),
+            });
+        }
+
+        Ok(normalized)
+    }
+
+    /// Validate a SWIFT/BIC code and return its normalized uppercase form
+    /// Format: 4-letter bank code, 2-letter country code, 2-char location, optional 3-char branch
+    pub fn validate_swift(swift: &str) -> Result<String, ApiError> {
+        let normalized = Self::normalize(swift);
+
+        debug!("BANK:validate_swift [VALIDATION] Validating SWIFT/BIC: '{}'", normalized);
+
+        if !normalized.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(ApiError::BadRequest {
+                message: "Invalid SWIFT/BIC format".to_string(),
+            });
+        }
+
+        if normalized.len() != 8 && normalized.len() != 11 {
+            return Err(ApiError::BadRequest {
+                message: "SWIFT/BIC must be 8 or 11 characters".to_string(),
+            });
+        }
+
+        let bank_code = &normalized[0..4];
+        let country_code = &normalized[4..6];
+        let location_code = &normalized[6..8];
+        let branch_code = &normalized[8..];
+
+        if !bank_code.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(ApiError::BadRequest {
+                message: "Invalid SWIFT/BIC bank code".to_string(),
+            });
+        }
+
+        if !CountryService::is_valid_country_code(country_code) {
+            return Err(ApiError::BadRequest {
+                message: "Invalid SWIFT/BIC country code".to_string(),
+            });
+        }
+
+        if
+            !location_code.chars().all(|c| c.is_ascii_alphanumeric()) ||
+            !branch_code.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err(ApiError::BadRequest {
+                message: "Invalid SWIFT/BIC location or branch code".to_string(),
+            });
+        }
+
+        Ok(normalized)
+    }
+
+    /// Validate an IBAN and SWIFT/BIC pair for a payout destination
+    /// Both codes must be valid and refer to the same country
+    pub fn validate_bank_details(iban: &str, swift: &str) -> Result<(String, String), ApiError> {
+        let iban = Self::validate_iban(iban)?;
+        let swift = Self::validate_swift(swift)?;
+
+        if iban[0..2] != swift[4..6] {
+            return Err(ApiError::BadRequest {
+                message: format!(
+                    "IBAN country '{}' does not match SWIFT/BIC country '{}'",
+                    &iban[0..2],
+                    &swift[4..6]
+                ),
+            });
+        }
+
+        Ok((iban, swift))
+    }
+
+    /// Mask an IBAN for logging, keeping the country code, check digits and last 4 characters
+    /// e.g. "DE89370400440532013000" -> "DE89**************3000"
+    pub fn mask_iban(iban: &str) -> String {
+        let normalized = Self::normalize(iban);
+        let len = normalized.chars().count();
+
+        if len <= 8 {
+            return "*".repeat(len);
+        }
+
+        normalized
+            .chars()
+            .enumerate()
+            .map(|(i, c)| if i < 4 || i >= len - 4 { c } else { '*' })
+            .collect()
+    }
+
+    /// Remove whitespace and uppercase the input
+    fn normalize(value: &str) -> String {
+        value
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_uppercase()
+    }
+
+    /// Compute the ISO 7064 mod-97 remainder of an IBAN (1 means valid)
+    fn iban_mod97(iban: &str) -> u32 {
+        let rearranged = iban[4..].chars().chain(iban[0..4].chars());
+
+        rearranged.fold(0u32, |remainder, c| {
+            let value = c.to_digit(36).unwrap_or(0);
+            if value >= 10 {
+                (remainder * 100 + value) % 97
+            } else {
+                (remainder * 10 + value) % 97
+            }
+        })
+    }
+
+    /// IBAN length per country as published in the SWIFT IBAN registry
+    fn iban_length_for_country(country_code: &str) -> Option<usize> {
+        let length = match country_code {
+            "NO" => 15,
+            "BE" => 16,
+            "DK" | "FI" | "FO" | "GL" | "NL" => 18,
+            "MK" | "SI" => 19,
+            "AT" | "BA" | "EE" | "KZ" | "LT" | "LU" | "XK" => 20,
+            "CH" | "HR" | "LI" | "LV" => 21,
+            "BG" | "BH" | "CR" | "DE" | "GB" | "GE" | "IE" | "ME" | "RS" | "VA" => 22,
+            "AE" | "GI" | "IL" | "IQ" | "TL" => 23,
+            "AD" | "CZ" | "ES" | "MD" | "PK" | "RO" | "SA" | "SE" | "SK" | "TN" | "VG" => 24,
+            "PT" | "ST" => 25,
+            "IS" | "TR" => 26,
+            "FR" | "GR" | "IT" | "MC" | "MR" | "SM" => 27,
+            "AL" | "AZ" | "BY" | "CY" | "DO" | "GT" | "HU" | "LB" | "PL" | "SV" => 28,
+            "BR" | "EG" | "PS" | "QA" | "UA" => 29,
+            "JO" | "KW" | "MU" => 30,
+            "MT" | "SC" => 31,
+            "LC" => 32,
+            _ => {
+                return None;
+            }
+        };
+
+        Some(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_iban() {
+        // Valid IBANs, with and without spacing
+        assert_eq!(
+            BankDetailsService::validate_iban("DE89 3704 0044 0532 0130 00").unwrap(),
+            "DE89370400440532013000"
+        );
+        assert!(BankDetailsService::validate_iban("GB82WEST12345698765432").is_ok());
+        assert!(BankDetailsService::validate_iban("sa0380000000608010167519").is_ok());
+
+        // Bad checksum
+        assert!(BankDetailsService::validate_iban("DE89370400440532013001").is_err());
+
+        // Wrong length for country
+        assert!(BankDetailsService::validate_iban("DE8937040044053201300").is_err());
+
+        // Unsupported country and garbage input
+        assert!(BankDetailsService::validate_iban("US12345678901234567890").is_err());
+        assert!(BankDetailsService::validate_iban("DE89-3704").is_err());
+        assert!(BankDetailsService::validate_iban("").is_err());
+    }
+
+    #[test]
+    fn test_validate_swift() {
+        assert_eq!(BankDetailsService::validate_swift("deutdeff").unwrap(), "DEUTDEFF");
+        assert!(BankDetailsService::validate_swift("DEUTDEFF500").is_ok());
+
+        assert!(BankDetailsService::validate_swift("DEUTDEF").is_err());
+        assert!(BankDetailsService::validate_swift("DEU1DEFF").is_err());
+        assert!(BankDetailsService::validate_swift("DEUT1EFF").is_err());
+        // Multibyte characters must not land on a slice boundary
+        assert!(BankDetailsService::validate_swift("DEUTDEFÉ").is_err());
+        assert!(BankDetailsService::validate_swift("DEUÉEFF").is_err());
+    }
+
+    #[test]
+    fn test_validate_bank_details_country_mismatch() {
+        assert!(
+            BankDetailsService::validate_bank_details("DE89370400440532013000", "DEUTDEFF").is_ok()
+        );
+        assert!(
+            BankDetailsService::validate_bank_details("GB82WEST12345698765432", "DEUTDEFF").is_err()
+        );
+    }
+
+    #[test]
+    fn test_mask_iban() {
+        assert_eq!(
+            BankDetailsService::mask_iban("DE89 3704 0044 0532 0130 00"),
+            "DE89**************3000"
+        );
+        assert_eq!(BankDetailsService::mask_iban("DE89"), "****");
+    }
+}
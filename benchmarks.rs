@@ -0,0 +1,179 @@
+//! Criterion benchmarks for common-lib hot paths, enabled with the `benchmarks` feature
+//!
+//! common-lib is compiled inside each service crate, so the bench target lives in the host:
+//!
+//! ```ignore
+//! // benches/common_lib.rs (with `harness = false` in Cargo.toml)
+//! use criterion::{ criterion_group, criterion_main };
+//! criterion_group!(benches, my_service::common_lib::benchmarks::hot_paths);
+//! criterion_main!(benches);
+//! ```
+//!
+//! Compare against the main branch in CI with
+//! `cargo bench --features benchmarks -- --save-baseline main` on main and
+//! `cargo bench --features benchmarks -- --baseline main` on the PR branch.
+
+use std::hint::black_box;
+use std::sync::Arc;
+use criterion::Criterion;
+#[cfg(feature = "binary_formats")]
+use criterion::Throughput;
+use reqwest::Client;
+
+use crate::common_lib::bank_utils::BankDetailsService;
+#[cfg(feature = "binary_formats")]
+use crate::common_lib::content_negotiation::WireFormat;
+use crate::common_lib::country_utils::CountryService;
+use crate::common_lib::error::ApiError;
+use crate::common_lib::geolocation::{ GeolocationConfig, GeolocationService, LocationInfo };
+use crate::common_lib::logging::{ generate_correlation_id, OperationTimer, RequestId };
+use crate::common_lib::shared_models::MyObjectId;
+use crate::common_lib::utils::{
+    generate_random_alphanumeric_string,
+    generate_random_token,
+    parse_required_object_id,
+};
+
+/// Register all common-lib hot path benchmarks
+pub fn hot_paths(c: &mut Criterion) {
+    object_id_parsing(c);
+    token_generation(c);
+    error_serialization(c);
+    validation(c);
+    geolocation_cache(c);
+    #[cfg(feature = "binary_formats")]
+    wire_formats(c);
+}
+
+pub fn object_id_parsing(c: &mut Criterion) {
+    let id = MyObjectId::new().to_string();
+
+    c.bench_function("object_id/parse_string", |b| {
+        b.iter(|| MyObjectId::parse_string(black_box(&id)))
+    });
+    c.bench_function("object_id/parse_required", |b| {
+        b.iter(|| parse_required_object_id(black_box(Some(id.as_str())), "id"))
+    });
+    c.bench_function("object_id/to_string", |b| {
+        let parsed = MyObjectId::parse_string(&id).unwrap();
+        b.iter(|| black_box(parsed).to_string())
+    });
+}
+
+pub fn token_generation(c: &mut Criterion) {
+    c.bench_function("token/correlation_id", |b| b.iter(generate_correlation_id));
+    c.bench_function("token/request_id_new", |b| b.iter(RequestId::new));
+    c.bench_function("token/request_id_format", |b| {
+        let req_id = RequestId::new();
+        b.iter(|| black_box(req_id).as_str().len())
+    });
+    c.bench_function("timer/new_with_string_id", |b| {
+        let req_id = generate_correlation_id();
+        b.iter(|| OperationTimer::new(black_box("SERVICE:bench"), &req_id))
+    });
+    c.bench_function("timer/for_request", |b| {
+        let req_id = RequestId::new();
+        b.iter(|| OperationTimer::for_request(black_box("SERVICE:bench"), req_id))
+    });
+    c.bench_function("token/random_token", |b| b.iter(generate_random_token));
+    c.bench_function("token/random_alphanumeric", |b| {
+        b.iter(generate_random_alphanumeric_string)
+    });
+}
+
+pub fn error_serialization(c: &mut Criterion) {
+    let error = ApiError::QuotaExceeded {
+        resource: "sparks".to_string(),
+        monthly_count: 10,
+        lifetime_count: 120,
+        monthly_limit: 10,
+        lifetime_limit: 500,
+    };
+
+    c.bench_function("error/display", |b| b.iter(|| black_box(&error).to_string()));
+    c.bench_function("error/serde_json", |b| {
+        b.iter(|| serde_json::to_string(black_box(&error)))
+    });
+}
+
+pub fn validation(c: &mut Criterion) {
+    c.bench_function("validation/country_code", |b| {
+        b.iter(|| CountryService::validate_and_normalize_country_code(black_box("gb")))
+    });
+    c.bench_function("validation/iban", |b| {
+        b.iter(|| BankDetailsService::validate_iban(black_box("GB82 WEST 1234 5698 7654 32")))
+    });
+}
+
+pub fn geolocation_cache(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let service = GeolocationService::new(Arc::new(Client::new()), GeolocationConfig::default()).unwrap();
+    let location = LocationInfo {
+        country_code: "GB".to_string(),
+        country_name: "United Kingdom".to_string(),
+        continent_code: Some("EU".to_string()),
+        city: Some("London".to_string()),
+        region: Some("England".to_string()),
+        latitude: Some(51.5074),
+        longitude: Some(-0.1278),
+        timezone: Some("Europe/London".to_string()),
+        asn: None,
+        isp: None,
+        organization: None,
+    };
+
+    runtime.block_on(async {
+        for i in 0..1000 {
+            service.cache_location(&format!("10.0.{}.{}", i / 256, i % 256), &location).await;
+        }
+    });
+
+    c.bench_function("geo_cache/get_hit", |b| {
+        b.iter(|| runtime.block_on(service.get_from_cache(black_box("10.0.1.1"))))
+    });
+    c.bench_function("geo_cache/get_miss", |b| {
+        b.iter(|| runtime.block_on(service.get_from_cache(black_box("192.0.2.1"))))
+    });
+    c.bench_function("geo_cache/set", |b| {
+        b.iter(|| runtime.block_on(service.cache_location(black_box("10.0.1.1"), &location)))
+    });
+}
+
+/// Encode/decode cost per wire format for a page of locations; payload sizes are printed once
+#[cfg(feature = "binary_formats")]
+pub fn wire_formats(c: &mut Criterion) {
+    let page: Vec<LocationInfo> = (0..100)
+        .map(|i| LocationInfo {
+            country_code: "GB".to_string(),
+            country_name: "United Kingdom".to_string(),
+            continent_code: Some("EU".to_string()),
+            city: Some(format!("City {}", i)),
+            region: Some("England".to_string()),
+            latitude: Some(51.5074 + (i as f64) / 100.0),
+            longitude: Some(-0.1278),
+            timezone: Some("Europe/London".to_string()),
+            asn: None,
+            isp: None,
+            organization: None,
+        })
+        .collect();
+
+    // Throughput is the encoded size, so criterion's bytes/s figures also compare payload sizes
+    let mut group = c.benchmark_group("wire");
+    for (name, format) in [
+        ("json", WireFormat::Json),
+        ("msgpack", WireFormat::MessagePack),
+        ("cbor", WireFormat::Cbor),
+    ] {
+        let encoded = format.encode(&page).unwrap();
+        group.throughput(Throughput::Bytes(encoded.len() as u64));
+
+        group.bench_function(format!("{}_encode", name), |b| {
+            b.iter(|| format.encode(black_box(&page)))
+        });
+        group.bench_function(format!("{}_decode", name), |b| {
+            b.iter(|| format.decode::<Vec<LocationInfo>>(black_box(&encoded)))
+        });
+    }
+    group.finish();
+}
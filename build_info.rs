@@ -0,0 +1,96 @@
+//! Build and deployment information for the `/version` route
+//!
+//! Values are captured when the host service is compiled. Set them from the service's `build.rs`:
+//!
+//! ```ignore
+//! fn main() {
+//!     let sha = std::process::Command::new("git").args(["rev-parse", "HEAD"]).output().unwrap();
+//!     println!("cargo:rustc-env=BUILD_GIT_SHA={}", String::from_utf8_lossy(&sha.stdout).trim());
+//!     println!("cargo:rustc-env=BUILD_TIMESTAMP={}", chrono::Utc::now().to_rfc3339());
+//!     let rustc = std::process::Command::new("rustc").arg("--version").output().unwrap();
+//!     println!("cargo:rustc-env=BUILD_RUSTC_VERSION={}", String::from_utf8_lossy(&rustc.stdout).trim());
+//! }
+//! ```
+//!
+//! CI builds can export the same variables instead; missing values are reported as "unknown".
+
+#[cfg(not(feature = "no_web"))]
+use rocket::{ get, routes, serde::json::Json, Route };
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
+use serde::{ Deserialize, Serialize };
+
+const UNKNOWN: &str = "unknown";
+
+/// What is deployed: service version, source revision, toolchain and region
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+    pub service: String,
+    pub version: String,
+    pub git_sha: String,
+    pub build_timestamp: String,
+    pub rustc_version: String,
+    pub region: Option<String>,
+}
+
+impl BuildInfo {
+    /// Build information for the running service
+    pub fn current() -> Self {
+        Self {
+            service: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_sha: option_env!("BUILD_GIT_SHA").unwrap_or(UNKNOWN).to_string(),
+            build_timestamp: option_env!("BUILD_TIMESTAMP").unwrap_or(UNKNOWN).to_string(),
+            rustc_version: option_env!("BUILD_RUSTC_VERSION").unwrap_or(UNKNOWN).to_string(),
+            region: std::env::var("AWS_REGION").ok(),
+        }
+    }
+
+    /// Abbreviated git SHA for logs and dashboards
+    pub fn short_sha(&self) -> &str {
+        self.git_sha.get(..7).unwrap_or(&self.git_sha)
+    }
+}
+
+#[cfg(not(feature = "no_web"))]
+#[get("/version")]
+pub fn version() -> Json<BuildInfo> {
+    Json(BuildInfo::current())
+}
+
+/// Routes to mount, e.g. `rocket.mount("/", build_info::routes())`
+#[cfg(not(feature = "no_web"))]
+pub fn routes() -> Vec<Route> {
+    routes![version]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_info_current() {
+        let info = BuildInfo::current();
+
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.git_sha.is_empty());
+        assert!(info.short_sha().len() <= 7);
+    }
+
+    #[cfg(not(feature = "no_web"))]
+    #[rocket::async_test]
+    async fn test_version_route() {
+        use crate::common_lib::test_client::{ test_client, test_rocket };
+
+        let client = test_client(test_rocket(routes())).await;
+        let response = client.get("/version").dispatch().await;
+
+        assert_eq!(response.status(), rocket::http::Status::Ok);
+        let body: serde_json::Value = response.into_json().await.unwrap();
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    }
+}
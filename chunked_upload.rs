@@ -0,0 +1,986 @@
+//! Resumable chunked uploads of large attachments to S3, compiled out by the `no_aws` feature
+//!
+//! Clients on flaky mobile networks upload videos in fixed-size parts through three calls:
+//!
+//! 1. `init` declares the file size and its composite SHA-256 (see `composite_checksum`) and
+//!    returns a session with the part size and part count to use.
+//! 2. `upload_part` sends each part, in any order and as often as needed. Re-sending a part that
+//!    was already stored with the same checksum is a no-op, so retries after a lost response are cheap.
+//!    After a reconnect, `status` lists the parts still missing.
+//! 3. `complete` checks that every part is present and the composite checksum matches before the
+//!    S3 multipart upload is assembled.
+//!
+//! With a scanner configured, `complete` also runs a malware scan (see `upload_scan`). Only
+//! sessions where `is_servable` is true, i.e. scanned clean, should be handed to consumers.
+//!
+//! Session metadata lives in an `UploadSessionStore` so any instance can continue an upload. Buckets
+//! should still have an `AbortIncompleteMultipartUpload` lifecycle rule for sessions that are never
+//! completed.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{ DateTime, Utc };
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
+use rusoto_core::Region;
+use rusoto_s3::{
+    AbortMultipartUploadRequest,
+    CompleteMultipartUploadRequest,
+    CompletedMultipartUpload,
+    CompletedPart,
+    CreateMultipartUploadRequest,
+    S3Client,
+    UploadPartRequest,
+    S3,
+};
+use serde::{ Deserialize, Serialize };
+use sha2::{ Digest, Sha256 };
+use tokio::sync::RwLock;
+use tracing::{ info, warn };
+use uuid::Uuid;
+
+use crate::common_lib::clock::{ system_clock, Clock };
+use crate::common_lib::error::ApiError;
+use crate::common_lib::logging::RequestId;
+use crate::common_lib::secret::redact_uris;
+use crate::common_lib::upload_scan::{ ScanProvider, ScanStatus, ScanVerdict };
+use crate::log_security;
+
+pub type UploadFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, ApiError>> + Send + 'a>>;
+
+/// S3 rejects multipart uploads with more parts than this
+pub const MAX_PARTS: u64 = 10_000;
+/// S3 minimum size of every part except the last
+pub const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum UploadStatus {
+    InProgress,
+    Completed,
+    Aborted,
+}
+
+/// A part stored in S3
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct UploadedPart {
+    pub part_number: u32,
+    pub e_tag: String,
+    pub size: u64,
+    /// Hex SHA-256 of the part body
+    pub sha256: String,
+}
+
+/// Resumability metadata for one upload
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct UploadSession {
+    pub id: String,
+    pub owner_id: String,
+    pub bucket: String,
+    pub key: String,
+    /// Upload ID assigned by S3
+    pub s3_upload_id: String,
+    pub content_type: String,
+    pub total_size: u64,
+    pub part_size: u64,
+    /// Composite checksum declared by the client at init
+    pub checksum_sha256: String,
+    /// Stored parts ordered by part number
+    pub parts: Vec<UploadedPart>,
+    pub status: UploadStatus,
+    #[serde(default)]
+    pub scan_status: ScanStatus,
+    /// Signature that caused the quarantine
+    #[serde(default)]
+    pub quarantine_reason: Option<String>,
+    #[cfg_attr(not(feature = "no_web"), schemars(with = "String"))]
+    pub created_at: DateTime<Utc>,
+    #[cfg_attr(not(feature = "no_web"), schemars(with = "String"))]
+    pub expires_at: DateTime<Utc>,
+}
+
+impl UploadSession {
+    pub fn part_count(&self) -> u32 {
+        self.total_size.div_ceil(self.part_size) as u32
+    }
+
+    /// Size a part must have; every part is `part_size` except the last
+    pub fn expected_part_size(&self, part_number: u32) -> u64 {
+        if part_number == self.part_count() {
+            self.total_size - self.part_size * ((part_number as u64) - 1)
+        } else {
+            self.part_size
+        }
+    }
+
+    /// Part numbers the client still has to send
+    pub fn missing_parts(&self) -> Vec<u32> {
+        (1..=self.part_count())
+            .filter(|part_number| !self.parts.iter().any(|part| part.part_number == *part_number))
+            .collect()
+    }
+
+    pub fn uploaded_bytes(&self) -> u64 {
+        self.parts
+            .iter()
+            .map(|part| part.size)
+            .sum()
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now >= self.expires_at
+    }
+
+    /// Whether the object may be served or processed further: assembled and scanned clean
+    /// Uploads that were never scanned are not servable, so a missing scanner fails closed.
+    pub fn is_servable(&self) -> bool {
+        self.status == UploadStatus::Completed && self.scan_status == ScanStatus::Clean
+    }
+}
+
+/// Client request to start an upload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct InitUploadRequest {
+    pub file_name: String,
+    pub content_type: String,
+    pub total_size: u64,
+    /// Composite SHA-256 of the file, as computed by `composite_checksum`
+    pub checksum_sha256: String,
+}
+
+/// Persistence for upload sessions
+///
+/// `add_part` must replace an existing part with the same number atomically (e.g. a MongoDB
+/// `$pull` + `$push` in one update), since parts of the same session are uploaded concurrently.
+pub trait UploadSessionStore: Send + Sync {
+    fn insert<'a>(&'a self, session: &'a UploadSession) -> UploadFuture<'a, ()>;
+    fn get<'a>(&'a self, session_id: &'a str) -> UploadFuture<'a, Option<UploadSession>>;
+    fn add_part<'a>(&'a self, session_id: &'a str, part: UploadedPart) -> UploadFuture<'a, UploadSession>;
+    fn set_status<'a>(&'a self, session_id: &'a str, status: UploadStatus) -> UploadFuture<'a, ()>;
+    fn set_scan_status<'a>(
+        &'a self,
+        session_id: &'a str,
+        scan_status: ScanStatus,
+        quarantine_reason: Option<String>
+    ) -> UploadFuture<'a, ()>;
+}
+
+/// In-process session store for tests and single-instance tools
+#[derive(Default)]
+pub struct InMemoryUploadSessionStore {
+    sessions: RwLock<HashMap<String, UploadSession>>,
+}
+
+impl InMemoryUploadSessionStore {
+    fn not_found(session_id: &str) -> ApiError {
+        ApiError::NotFound {
+            message: format!("Upload session {} not found", session_id),
+        }
+    }
+}
+
+impl UploadSessionStore for InMemoryUploadSessionStore {
+    fn insert<'a>(&'a self, session: &'a UploadSession) -> UploadFuture<'a, ()> {
+        Box::pin(async move {
+            self.sessions.write().await.insert(session.id.clone(), session.clone());
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, session_id: &'a str) -> UploadFuture<'a, Option<UploadSession>> {
+        Box::pin(async move { Ok(self.sessions.read().await.get(session_id).cloned()) })
+    }
+
+    fn add_part<'a>(&'a self, session_id: &'a str, part: UploadedPart) -> UploadFuture<'a, UploadSession> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions.get_mut(session_id).ok_or_else(|| Self::not_found(session_id))?;
+
+            session.parts.retain(|existing| existing.part_number != part.part_number);
+            session.parts.push(part);
+            session.parts.sort_by_key(|part| part.part_number);
+
+            Ok(session.clone())
+        })
+    }
+
+    fn set_status<'a>(&'a self, session_id: &'a str, status: UploadStatus) -> UploadFuture<'a, ()> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions.get_mut(session_id).ok_or_else(|| Self::not_found(session_id))?;
+            session.status = status;
+            Ok(())
+        })
+    }
+
+    fn set_scan_status<'a>(
+        &'a self,
+        session_id: &'a str,
+        scan_status: ScanStatus,
+        quarantine_reason: Option<String>
+    ) -> UploadFuture<'a, ()> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.write().await;
+            let session = sessions.get_mut(session_id).ok_or_else(|| Self::not_found(session_id))?;
+            session.scan_status = scan_status;
+            session.quarantine_reason = quarantine_reason;
+            Ok(())
+        })
+    }
+}
+
+/// The multipart operations of an object store
+pub trait MultipartStorage: Send + Sync {
+    /// Start a multipart upload and return its upload ID
+    fn create<'a>(&'a self, bucket: &'a str, key: &'a str, content_type: &'a str) -> UploadFuture<'a, String>;
+    /// Store one part and return its ETag
+    fn upload_part<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+        part_number: u32,
+        body: Vec<u8>
+    ) -> UploadFuture<'a, String>;
+    /// Assemble the object from `(part_number, e_tag)` pairs in ascending order
+    fn complete<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+        parts: Vec<(u32, String)>
+    ) -> UploadFuture<'a, ()>;
+    fn abort<'a>(&'a self, bucket: &'a str, key: &'a str, upload_id: &'a str) -> UploadFuture<'a, ()>;
+}
+
+/// `MultipartStorage` backed by S3
+pub struct S3MultipartStorage {
+    client: S3Client,
+}
+
+impl S3MultipartStorage {
+    pub fn new(region: Region) -> Self {
+        Self { client: S3Client::new(region) }
+    }
+
+    fn s3_error(operation: &str, e: impl std::fmt::Display) -> ApiError {
+        ApiError::InternalServerError {
+            message: format!("S3 {} failed: {}", operation, redact_uris(&e.to_string())),
+        }
+    }
+}
+
+impl MultipartStorage for S3MultipartStorage {
+    fn create<'a>(&'a self, bucket: &'a str, key: &'a str, content_type: &'a str) -> UploadFuture<'a, String> {
+        Box::pin(async move {
+            let request = CreateMultipartUploadRequest {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+                content_type: Some(content_type.to_string()),
+                ..Default::default()
+            };
+
+            self.client
+                .create_multipart_upload(request).await
+                .map_err(|e| Self::s3_error("CreateMultipartUpload", e))?
+                .upload_id.ok_or_else(|| Self::s3_error("CreateMultipartUpload", "no upload id returned"))
+        })
+    }
+
+    fn upload_part<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+        part_number: u32,
+        body: Vec<u8>
+    ) -> UploadFuture<'a, String> {
+        Box::pin(async move {
+            let request = UploadPartRequest {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+                upload_id: upload_id.to_string(),
+                part_number: part_number as i64,
+                body: Some(body.into()),
+                ..Default::default()
+            };
+
+            self.client
+                .upload_part(request).await
+                .map_err(|e| Self::s3_error("UploadPart", e))?
+                .e_tag.ok_or_else(|| Self::s3_error("UploadPart", "no ETag returned"))
+        })
+    }
+
+    fn complete<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+        parts: Vec<(u32, String)>
+    ) -> UploadFuture<'a, ()> {
+        Box::pin(async move {
+            let parts = parts
+                .into_iter()
+                .map(|(part_number, e_tag)| CompletedPart {
+                    e_tag: Some(e_tag),
+                    part_number: Some(part_number as i64),
+                })
+                .collect();
+            let request = CompleteMultipartUploadRequest {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+                upload_id: upload_id.to_string(),
+                multipart_upload: Some(CompletedMultipartUpload { parts: Some(parts) }),
+                ..Default::default()
+            };
+
+            self.client
+                .complete_multipart_upload(request).await
+                .map(|_| ())
+                .map_err(|e| Self::s3_error("CompleteMultipartUpload", e))
+        })
+    }
+
+    fn abort<'a>(&'a self, bucket: &'a str, key: &'a str, upload_id: &'a str) -> UploadFuture<'a, ()> {
+        Box::pin(async move {
+            let request = AbortMultipartUploadRequest {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+                upload_id: upload_id.to_string(),
+                ..Default::default()
+            };
+
+            self.client
+                .abort_multipart_upload(request).await
+                .map(|_| ())
+                .map_err(|e| Self::s3_error("AbortMultipartUpload", e))
+        })
+    }
+}
+
+/// Upload limits and placement
+#[derive(Debug, Clone)]
+pub struct ChunkedUploadConfig {
+    pub bucket: String,
+    /// Objects are stored under `{key_prefix}/{owner_id}/{session_id}/{file_name}`
+    pub key_prefix: String,
+    pub part_size: u64,
+    pub max_size: u64,
+    /// How long a client may take to finish an upload
+    pub session_ttl: Duration,
+}
+
+impl ChunkedUploadConfig {
+    pub fn new(bucket: &str) -> Self {
+        Self {
+            bucket: bucket.to_string(),
+            key_prefix: "uploads".to_string(),
+            part_size: 8 * 1024 * 1024,
+            max_size: 5 * 1024 * 1024 * 1024,
+            session_ttl: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+
+    /// Check configuration invariants
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.part_size < MIN_PART_SIZE {
+            return Err(ApiError::BadRequest {
+                message: format!("Upload part size must be at least {} bytes, got {}", MIN_PART_SIZE, self.part_size),
+            });
+        }
+        if self.max_size.div_ceil(self.part_size) > MAX_PARTS {
+            return Err(ApiError::BadRequest {
+                message: format!(
+                    "Upload max size {} needs more than {} parts of {} bytes",
+                    self.max_size,
+                    MAX_PARTS,
+                    self.part_size
+                ),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// SHA-256 over the concatenated SHA-256 digests of the parts, suffixed with the part count
+/// (e.g. "9f86...0f00-3"), the same scheme S3 uses for composite checksums. Clients compute it
+/// while splitting the file, so the server can verify the whole file without reading it back.
+pub fn composite_checksum<'a>(part_digests: impl IntoIterator<Item = &'a str>) -> Result<String, ApiError> {
+    let mut hasher = Sha256::new();
+    let mut count = 0;
+
+    for digest in part_digests {
+        let bytes = hex::decode(digest).map_err(|_| ApiError::BadRequest {
+            message: format!("Invalid SHA-256 digest '{}'", digest),
+        })?;
+        hasher.update(bytes);
+        count += 1;
+    }
+
+    Ok(format!("{}-{}", hex::encode(hasher.finalize()), count))
+}
+
+/// Hex SHA-256 of a part body
+pub fn part_checksum(body: &[u8]) -> String {
+    hex::encode(Sha256::digest(body))
+}
+
+/// Coordinates upload sessions between the session store and the object store
+pub struct ChunkedUploadService {
+    storage: Arc<dyn MultipartStorage>,
+    store: Arc<dyn UploadSessionStore>,
+    config: ChunkedUploadConfig,
+    clock: Arc<dyn Clock>,
+    scanner: Option<Arc<dyn ScanProvider>>,
+}
+
+impl ChunkedUploadService {
+    /// Fails with `BadRequest` when the configuration breaks an invariant, see `ChunkedUploadConfig::validate`
+    pub fn new(
+        storage: Arc<dyn MultipartStorage>,
+        store: Arc<dyn UploadSessionStore>,
+        config: ChunkedUploadConfig
+    ) -> Result<Self, ApiError> {
+        Self::with_clock(storage, store, config, system_clock())
+    }
+
+    pub fn with_clock(
+        storage: Arc<dyn MultipartStorage>,
+        store: Arc<dyn UploadSessionStore>,
+        config: ChunkedUploadConfig,
+        clock: Arc<dyn Clock>
+    ) -> Result<Self, ApiError> {
+        config.validate()?;
+
+        Ok(Self { storage, store, config, clock, scanner: None })
+    }
+
+    /// Scan every completed upload before it becomes servable
+    pub fn with_scanner(mut self, scanner: Arc<dyn ScanProvider>) -> Self {
+        self.scanner = Some(scanner);
+        self
+    }
+
+    /// Start an upload session and the underlying S3 multipart upload
+    pub async fn init(&self, owner_id: &str, request: InitUploadRequest) -> Result<UploadSession, ApiError> {
+        if request.total_size == 0 || request.total_size > self.config.max_size {
+            return Err(ApiError::BadRequest {
+                message: format!(
+                    "Upload size must be between 1 and {} bytes, got {}",
+                    self.config.max_size,
+                    request.total_size
+                ),
+            });
+        }
+
+        let id = Uuid::new_v4().to_string();
+        let key = format!(
+            "{}/{}/{}/{}",
+            self.config.key_prefix,
+            owner_id,
+            id,
+            sanitize_file_name(&request.file_name)
+        );
+        let s3_upload_id = self.storage.create(&self.config.bucket, &key, &request.content_type).await?;
+        let now = self.clock.now_utc();
+        let ttl = chrono::Duration::from_std(self.config.session_ttl).unwrap_or(chrono::Duration::days(1));
+
+        let session = UploadSession {
+            id,
+            owner_id: owner_id.to_string(),
+            bucket: self.config.bucket.clone(),
+            key,
+            s3_upload_id,
+            content_type: request.content_type,
+            total_size: request.total_size,
+            part_size: self.config.part_size,
+            checksum_sha256: request.checksum_sha256.to_lowercase(),
+            parts: Vec::new(),
+            status: UploadStatus::InProgress,
+            scan_status: ScanStatus::NotScanned,
+            quarantine_reason: None,
+            created_at: now,
+            expires_at: now + ttl,
+        };
+        self.store.insert(&session).await?;
+
+        info!(
+            "UPLOAD:init [STARTED] Upload session created - session_id: {}, owner_id: {}, size: {}, parts: {}",
+            session.id,
+            owner_id,
+            session.total_size,
+            session.part_count()
+        );
+        Ok(session)
+    }
+
+    /// Store one part; `sha256` is the client's digest of the part and is verified when present
+    pub async fn upload_part(
+        &self,
+        session_id: &str,
+        owner_id: &str,
+        part_number: u32,
+        body: Vec<u8>,
+        sha256: Option<&str>
+    ) -> Result<UploadSession, ApiError> {
+        let session = self.owned_session(session_id, owner_id).await?;
+        self.ensure_active(&session)?;
+
+        if part_number == 0 || part_number > session.part_count() {
+            return Err(ApiError::BadRequest {
+                message: format!("Part number must be between 1 and {}, got {}", session.part_count(), part_number),
+            });
+        }
+        let expected_size = session.expected_part_size(part_number);
+        if (body.len() as u64) != expected_size {
+            return Err(ApiError::BadRequest {
+                message: format!("Part {} must be {} bytes, got {}", part_number, expected_size, body.len()),
+            });
+        }
+
+        let checksum = part_checksum(&body);
+        if let Some(expected) = sha256 {
+            if !expected.eq_ignore_ascii_case(&checksum) {
+                warn!(
+                    "UPLOAD:part [CHECKSUM_MISMATCH] Part corrupted in transit - session_id: {}, part: {}",
+                    session_id,
+                    part_number
+                );
+                return Err(ApiError::BadRequest {
+                    message: format!("Checksum mismatch for part {}", part_number),
+                });
+            }
+        }
+
+        // A retry after a lost response re-sends a part S3 already has
+        if session.parts.iter().any(|part| part.part_number == part_number && part.sha256 == checksum) {
+            return Ok(session);
+        }
+
+        let size = body.len() as u64;
+        let e_tag = self.storage.upload_part(
+            &session.bucket,
+            &session.key,
+            &session.s3_upload_id,
+            part_number,
+            body
+        ).await?;
+
+        self.store.add_part(session_id, UploadedPart { part_number, e_tag, size, sha256: checksum }).await
+    }
+
+    /// Session with its stored parts, for clients resuming after a reconnect
+    pub async fn status(&self, session_id: &str, owner_id: &str) -> Result<UploadSession, ApiError> {
+        self.owned_session(session_id, owner_id).await
+    }
+
+    /// Verify the parts against the declared checksum, assemble the object and scan it
+    /// On a checksum mismatch the session stays open so the client can compare part digests and
+    /// re-send the corrupted parts. Calling it again after a failed scan retries the scan.
+    pub async fn complete(&self, session_id: &str, owner_id: &str) -> Result<UploadSession, ApiError> {
+        let mut session = self.owned_session(session_id, owner_id).await?;
+        if session.status == UploadStatus::Completed {
+            if matches!(session.scan_status, ScanStatus::Pending | ScanStatus::ScanFailed) {
+                self.scan(&mut session).await?;
+            }
+            return Ok(session);
+        }
+        self.ensure_active(&session)?;
+
+        let missing = session.missing_parts();
+        if !missing.is_empty() {
+            return Err(ApiError::BadRequest {
+                message: format!("Upload is missing {} parts, first missing part: {}", missing.len(), missing[0]),
+            });
+        }
+
+        let checksum = composite_checksum(session.parts.iter().map(|part| part.sha256.as_str()))?;
+        if checksum != session.checksum_sha256 {
+            warn!(
+                "UPLOAD:complete [CHECKSUM_MISMATCH] Assembled file does not match declared checksum - session_id: {}",
+                session_id
+            );
+            return Err(ApiError::BadRequest {
+                message: "Upload checksum does not match the declared checksum".to_string(),
+            });
+        }
+
+        let parts = session.parts
+            .iter()
+            .map(|part| (part.part_number, part.e_tag.clone()))
+            .collect();
+        self.storage.complete(&session.bucket, &session.key, &session.s3_upload_id, parts).await?;
+        if self.scanner.is_some() {
+            // Recorded before the session completes, so a crash mid-scan leaves it retryable
+            self.store.set_scan_status(session_id, ScanStatus::Pending, None).await?;
+            session.scan_status = ScanStatus::Pending;
+        }
+        self.store.set_status(session_id, UploadStatus::Completed).await?;
+        session.status = UploadStatus::Completed;
+
+        info!(
+            "UPLOAD:complete [COMPLETED] Upload assembled - session_id: {}, key: {}, size: {}",
+            session_id,
+            session.key,
+            session.total_size
+        );
+
+        self.scan(&mut session).await?;
+        Ok(session)
+    }
+
+    /// Run the configured scanner and record the outcome on the session
+    async fn scan(&self, session: &mut UploadSession) -> Result<(), ApiError> {
+        let Some(scanner) = &self.scanner else {
+            return Ok(());
+        };
+
+        let outcome = scanner.scan(&session.bucket, &session.key, session.total_size).await;
+        let (scan_status, quarantine_reason) = match &outcome {
+            Ok(ScanVerdict::Clean) => (ScanStatus::Clean, None),
+            Ok(ScanVerdict::Infected { signature }) => (ScanStatus::Quarantined, Some(signature.clone())),
+            Err(_) => (ScanStatus::ScanFailed, None),
+        };
+        self.store.set_scan_status(&session.id, scan_status, quarantine_reason.clone()).await?;
+        session.scan_status = scan_status;
+        session.quarantine_reason = quarantine_reason;
+
+        match outcome {
+            Ok(ScanVerdict::Clean) => Ok(()),
+            Ok(ScanVerdict::Infected { signature }) => {
+                log_security!(
+                    warn,
+                    "upload_scan",
+                    "MALWARE_QUARANTINED",
+                    RequestId::new(),
+                    "session_id: {}, owner_id: {}, key: {}, scanner: {}, signature: {}",
+                    session.id,
+                    session.owner_id,
+                    session.key,
+                    scanner.name(),
+                    signature
+                );
+                Err(ApiError::BadRequest {
+                    message: "Upload was rejected by the malware scan".to_string(),
+                })
+            }
+            Err(e) => {
+                warn!(
+                    "UPLOAD:scan [SCAN_ERROR] Malware scan failed - session_id: {}, scanner: {}, error: {}",
+                    session.id,
+                    scanner.name(),
+                    e
+                );
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Cancel an upload and release the parts stored in S3
+    pub async fn abort(&self, session_id: &str, owner_id: &str) -> Result<(), ApiError> {
+        let session = self.owned_session(session_id, owner_id).await?;
+        if session.status != UploadStatus::InProgress {
+            return Ok(());
+        }
+
+        self.storage.abort(&session.bucket, &session.key, &session.s3_upload_id).await?;
+        self.store.set_status(session_id, UploadStatus::Aborted).await
+    }
+
+    /// Sessions of other users are reported as missing so IDs cannot be probed
+    async fn owned_session(&self, session_id: &str, owner_id: &str) -> Result<UploadSession, ApiError> {
+        match self.store.get(session_id).await? {
+            Some(session) if session.owner_id == owner_id => Ok(session),
+            _ =>
+                Err(ApiError::NotFound {
+                    message: format!("Upload session {} not found", session_id),
+                }),
+        }
+    }
+
+    fn ensure_active(&self, session: &UploadSession) -> Result<(), ApiError> {
+        if session.status != UploadStatus::InProgress {
+            return Err(ApiError::BadRequest {
+                message: format!("Upload session {} is no longer in progress", session.id),
+            });
+        }
+        if session.is_expired(self.clock.now_utc()) {
+            return Err(ApiError::BadRequest {
+                message: format!("Upload session {} has expired", session.id),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Keep object keys predictable whatever the client sends as a file name
+fn sanitize_file_name(file_name: &str) -> String {
+    let sanitized: String = file_name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .collect();
+
+    if sanitized.trim_matches('.').is_empty() { "upload".to_string() } else { sanitized }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::collections::VecDeque;
+    use crate::common_lib::clock::MockClock;
+    use crate::common_lib::upload_scan::{ ScanError, ScanFuture };
+
+    const PART_SIZE: u64 = MIN_PART_SIZE;
+
+    #[derive(Default)]
+    struct FakeStorage {
+        uploads: Mutex<Vec<u32>>,
+        completed: Mutex<Option<Vec<(u32, String)>>>,
+    }
+
+    impl MultipartStorage for FakeStorage {
+        fn create<'a>(&'a self, _: &'a str, _: &'a str, _: &'a str) -> UploadFuture<'a, String> {
+            Box::pin(async { Ok("s3-upload-1".to_string()) })
+        }
+
+        fn upload_part<'a>(
+            &'a self,
+            _: &'a str,
+            _: &'a str,
+            _: &'a str,
+            part_number: u32,
+            _: Vec<u8>
+        ) -> UploadFuture<'a, String> {
+            self.uploads.lock().unwrap().push(part_number);
+            Box::pin(async move { Ok(format!("etag-{}", part_number)) })
+        }
+
+        fn complete<'a>(&'a self, _: &'a str, _: &'a str, _: &'a str, parts: Vec<(u32, String)>) -> UploadFuture<'a, ()> {
+            *self.completed.lock().unwrap() = Some(parts);
+            Box::pin(async { Ok(()) })
+        }
+
+        fn abort<'a>(&'a self, _: &'a str, _: &'a str, _: &'a str) -> UploadFuture<'a, ()> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    /// Returns queued outcomes in order
+    struct ScriptedScanner {
+        outcomes: Mutex<VecDeque<Result<ScanVerdict, ScanError>>>,
+    }
+
+    impl ScanProvider for ScriptedScanner {
+        fn name(&self) -> &str {
+            "scripted"
+        }
+
+        fn scan<'a>(&'a self, _: &'a str, _: &'a str, _: u64) -> ScanFuture<'a> {
+            let outcome = self.outcomes.lock().unwrap().pop_front().unwrap_or(Ok(ScanVerdict::Clean));
+            Box::pin(async move { outcome })
+        }
+    }
+
+    fn service(storage: Arc<FakeStorage>, clock: Arc<MockClock>) -> ChunkedUploadService {
+        let mut config = ChunkedUploadConfig::new("attachments");
+        config.part_size = PART_SIZE;
+        let store = Arc::new(InMemoryUploadSessionStore::default());
+        ChunkedUploadService::with_clock(storage, store, config, clock).unwrap()
+    }
+
+    fn parts() -> Vec<Vec<u8>> {
+        vec![vec![1; PART_SIZE as usize], vec![2; PART_SIZE as usize], vec![3; 1024]]
+    }
+
+    fn init_request(parts: &[Vec<u8>]) -> InitUploadRequest {
+        let digests: Vec<String> = parts
+            .iter()
+            .map(|part| part_checksum(part))
+            .collect();
+
+        InitUploadRequest {
+            file_name: "../holiday video.mp4".to_string(),
+            content_type: "video/mp4".to_string(),
+            total_size: parts
+                .iter()
+                .map(|part| part.len() as u64)
+                .sum(),
+            checksum_sha256: composite_checksum(digests.iter().map(String::as_str)).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_config_and_file_names() {
+        assert!(ChunkedUploadConfig::new("attachments").validate().is_ok());
+
+        let mut config = ChunkedUploadConfig::new("attachments");
+        config.part_size = 1024;
+        assert!(config.validate().is_err());
+
+        // A zero part size would divide by zero in `part_count`, so the service refuses it
+        config.part_size = 0;
+        let store = Arc::new(InMemoryUploadSessionStore::default());
+        assert!(ChunkedUploadService::new(Arc::new(FakeStorage::default()), store, config).is_err());
+
+        assert_eq!(sanitize_file_name("../holiday video.mp4"), "holiday_video.mp4");
+        assert_eq!(sanitize_file_name(".."), "upload");
+    }
+
+    #[tokio::test]
+    async fn test_resumed_upload_completes() {
+        let storage = Arc::new(FakeStorage::default());
+        let uploads = service(storage.clone(), Arc::new(MockClock::default()));
+        let parts = parts();
+
+        let session = uploads.init("user-1", init_request(&parts)).await.unwrap();
+        assert_eq!(session.part_count(), 3);
+        assert!(session.key.ends_with("/holiday_video.mp4"));
+
+        // Parts arrive out of order and part 3 is retried after a lost response
+        uploads.upload_part(&session.id, "user-1", 3, parts[2].clone(), None).await.unwrap();
+        uploads.upload_part(&session.id, "user-1", 3, parts[2].clone(), None).await.unwrap();
+        uploads.upload_part(&session.id, "user-1", 1, parts[0].clone(), None).await.unwrap();
+
+        let resumed = uploads.status(&session.id, "user-1").await.unwrap();
+        assert_eq!(resumed.missing_parts(), vec![2]);
+        assert!(uploads.complete(&session.id, "user-1").await.is_err());
+
+        let wrong_digest = part_checksum(b"something else");
+        let corrupted = uploads.upload_part(&session.id, "user-1", 2, parts[1].clone(), Some(&wrong_digest)).await;
+        assert!(matches!(corrupted, Err(ApiError::BadRequest { .. })));
+        uploads
+            .upload_part(&session.id, "user-1", 2, parts[1].clone(), Some(&part_checksum(&parts[1]))).await
+            .unwrap();
+
+        let completed = uploads.complete(&session.id, "user-1").await.unwrap();
+        assert_eq!(completed.status, UploadStatus::Completed);
+        assert!(!completed.is_servable(), "never scanned");
+        assert_eq!(completed.uploaded_bytes(), completed.total_size);
+        assert_eq!(*storage.uploads.lock().unwrap(), vec![3, 1, 2]);
+        assert_eq!(
+            storage.completed.lock().unwrap().as_ref().unwrap()[2],
+            (3, "etag-3".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_parts_and_sessions() {
+        let clock = Arc::new(MockClock::default());
+        let uploads = service(Arc::new(FakeStorage::default()), clock.clone());
+        let parts = parts();
+        let mut request = init_request(&parts);
+        request.checksum_sha256 = composite_checksum([part_checksum(b"other").as_str()]).unwrap();
+        let session = uploads.init("user-1", request).await.unwrap();
+
+        let short_part = uploads.upload_part(&session.id, "user-1", 1, vec![0; 10], None).await;
+        assert!(matches!(short_part, Err(ApiError::BadRequest { .. })));
+        let other_user = uploads.upload_part(&session.id, "user-2", 1, parts[0].clone(), None).await;
+        assert!(matches!(other_user, Err(ApiError::NotFound { .. })));
+
+        for (index, part) in parts.iter().enumerate() {
+            uploads.upload_part(&session.id, "user-1", (index as u32) + 1, part.clone(), None).await.unwrap();
+        }
+        let mismatch = uploads.complete(&session.id, "user-1").await;
+        assert!(matches!(mismatch, Err(ApiError::BadRequest { .. })));
+        assert_eq!(uploads.status(&session.id, "user-1").await.unwrap().status, UploadStatus::InProgress);
+
+        clock.advance(Duration::from_secs(25 * 60 * 60));
+        let expired = uploads.upload_part(&session.id, "user-1", 1, parts[0].clone(), None).await;
+        assert!(matches!(expired, Err(ApiError::BadRequest { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_scan_quarantines_and_retries() {
+        let parts = parts();
+        let scanner = Arc::new(ScriptedScanner {
+            outcomes: Mutex::new(
+                VecDeque::from([
+                    Err(ScanError::Timeout),
+                    Ok(ScanVerdict::Infected { signature: "Eicar-Test-Signature".to_string() }),
+                ])
+            ),
+        });
+        let uploads = service(Arc::new(FakeStorage::default()), Arc::new(MockClock::default()))
+            .with_scanner(scanner);
+
+        let session = uploads.init("user-1", init_request(&parts)).await.unwrap();
+        for (index, part) in parts.iter().enumerate() {
+            uploads.upload_part(&session.id, "user-1", (index as u32) + 1, part.clone(), None).await.unwrap();
+        }
+
+        let timed_out = uploads.complete(&session.id, "user-1").await;
+        assert!(matches!(timed_out, Err(ApiError::InternalServerError { .. })));
+        let stored = uploads.status(&session.id, "user-1").await.unwrap();
+        assert_eq!((stored.status, stored.scan_status), (UploadStatus::Completed, ScanStatus::ScanFailed));
+        assert!(!stored.is_servable());
+
+        let infected = uploads.complete(&session.id, "user-1").await;
+        assert!(matches!(infected, Err(ApiError::BadRequest { .. })));
+        let stored = uploads.status(&session.id, "user-1").await.unwrap();
+        assert_eq!(stored.scan_status, ScanStatus::Quarantined);
+        assert_eq!(stored.quarantine_reason.as_deref(), Some("Eicar-Test-Signature"));
+        assert!(!stored.is_servable());
+    }
+
+    /// Never returns, like an instance that dies mid-scan
+    struct HangingScanner;
+
+    impl ScanProvider for HangingScanner {
+        fn name(&self) -> &str {
+            "hanging"
+        }
+
+        fn scan<'a>(&'a self, _: &'a str, _: &'a str, _: u64) -> ScanFuture<'a> {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_scan_is_pending_until_rerun() {
+        let parts = parts();
+        let store = Arc::new(InMemoryUploadSessionStore::default());
+        let instance = |scanner: Arc<dyn ScanProvider>| {
+            let mut config = ChunkedUploadConfig::new("attachments");
+            config.part_size = PART_SIZE;
+            ChunkedUploadService::new(Arc::new(FakeStorage::default()), store.clone(), config)
+                .unwrap()
+                .with_scanner(scanner)
+        };
+        let crashing = instance(Arc::new(HangingScanner));
+
+        let session = crashing.init("user-1", init_request(&parts)).await.unwrap();
+        for (index, part) in parts.iter().enumerate() {
+            crashing.upload_part(&session.id, "user-1", (index as u32) + 1, part.clone(), None).await.unwrap();
+        }
+        let completing = crashing.complete(&session.id, "user-1");
+        assert!(tokio::time::timeout(Duration::from_millis(50), completing).await.is_err());
+
+        let stored = crashing.status(&session.id, "user-1").await.unwrap();
+        assert_eq!((stored.status, stored.scan_status), (UploadStatus::Completed, ScanStatus::Pending));
+        assert!(!stored.is_servable());
+
+        let restarted = instance(Arc::new(ScriptedScanner { outcomes: Mutex::new(VecDeque::new()) }));
+        let rescanned = restarted.complete(&session.id, "user-1").await.unwrap();
+        assert_eq!(rescanned.scan_status, ScanStatus::Clean);
+        assert!(rescanned.is_servable());
+    }
+}
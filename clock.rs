@@ -0,0 +1,133 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex, OnceLock };
+use std::time::{ Duration, Instant };
+use chrono::{ DateTime, Utc };
+
+/// Boxed future returned by `Clock::sleep`
+pub type SleepFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// Source of time for time-dependent logic (timers, cache TTLs, expiry checks)
+/// Use `SystemClock` in production and `MockClock` in tests to avoid real sleeps
+pub trait Clock: Send + Sync + Debug {
+    /// Monotonic instant, used for measuring durations and TTLs
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time, used for timestamps and expiry dates
+    fn now_utc(&self) -> DateTime<Utc>;
+
+    /// Wait for the given duration
+    fn sleep(&self, duration: Duration) -> SleepFuture<'_>;
+}
+
+/// Clock backed by the operating system and tokio timers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn now_utc(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> SleepFuture<'_> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+static SYSTEM_CLOCK: OnceLock<Arc<dyn Clock>> = OnceLock::new();
+
+/// Shared system clock, the default for every service in this crate
+pub fn system_clock() -> Arc<dyn Clock> {
+    Arc::clone(SYSTEM_CLOCK.get_or_init(|| Arc::new(SystemClock)))
+}
+
+/// Manually driven clock for deterministic tests
+/// Time only moves through `advance`, and `sleep` advances time instead of waiting
+#[derive(Debug)]
+pub struct MockClock {
+    base_instant: Instant,
+    base_utc: DateTime<Utc>,
+    offset: Mutex<Duration>,
+}
+
+impl MockClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            base_instant: Instant::now(),
+            base_utc: start,
+            offset: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, duration: Duration) {
+        let mut offset = self.offset.lock().unwrap_or_else(|e| e.into_inner());
+        *offset += duration;
+    }
+
+    /// Time elapsed since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        *self.offset.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.base_instant + self.elapsed()
+    }
+
+    fn now_utc(&self) -> DateTime<Utc> {
+        // Saturates so a test advancing far past the representable range can't panic
+        chrono::Duration::from_std(self.elapsed())
+            .ok()
+            .and_then(|elapsed| self.base_utc.checked_add_signed(elapsed))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+
+    fn sleep(&self, duration: Duration) -> SleepFuture<'_> {
+        self.advance(duration);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn test_mock_clock_advances_without_waiting() {
+        let start = Utc.with_ymd_and_hms(2025, 1, 10, 12, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let instant_before = clock.now();
+
+        clock.sleep(Duration::from_secs(3600)).await;
+        clock.advance(Duration::from_secs(60));
+
+        assert_eq!(clock.now().duration_since(instant_before), Duration::from_secs(3660));
+        assert_eq!(clock.now_utc(), Utc.with_ymd_and_hms(2025, 1, 10, 13, 1, 0).unwrap());
+    }
+
+    #[test]
+    fn test_mock_clock_wall_time_saturates() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2025, 1, 10, 12, 0, 0).unwrap());
+
+        // Representable as a chrono duration, but past the last representable date
+        clock.advance(Duration::from_secs(10_000_000_000_000));
+        assert_eq!(clock.now_utc(), DateTime::<Utc>::MAX_UTC);
+
+        clock.advance(Duration::from_secs(u64::MAX / 2));
+        assert_eq!(clock.now_utc(), DateTime::<Utc>::MAX_UTC);
+    }
+}
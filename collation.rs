@@ -0,0 +1,96 @@
+//! Locale-aware sorting through MongoDB collations, compiled out by the `no_mongo` feature
+//!
+//! Without a collation MongoDB sorts strings by byte order, which puts "Ýmir" after "Zeynep", sorts
+//! Turkish "ı"/"İ" wrongly and ignores Arabic alphabetical order. MongoDB collations are backed
+//! by ICU, so list queries get correct ordering by passing the caller's locale:
+//!
+//! ```ignore
+//! let users = collection
+//!     .find(filter, sorted_find_options(&request_locale, doc! { "display_name": 1 }, limit))
+//!     .await?;
+//! ```
+//!
+//! A query only uses an index when the index has the same collation, so create sort indexes
+//! with `collated_index_options` for the locales served.
+
+use mongodb::bson::Document;
+use mongodb::options::{ Collation, CollationStrength, FindOptions, IndexOptions };
+
+/// Languages with tailored ICU collation rules in MongoDB
+const SUPPORTED_LANGUAGES: &[&str] = &[
+    "af", "ar", "az", "be", "bg", "bn", "bs", "ca", "cs", "cy", "da", "de", "el", "en", "eo", "es", "et", "fa", "fi",
+    "fil", "fo", "fr", "ga", "gu", "ha", "haw", "he", "hi", "hr", "hu", "hy", "id", "ig", "is", "it", "ja", "kk",
+    "kl", "km", "kn", "ko", "kok", "ky", "lb", "lkt", "ln", "lo", "lt", "lv", "mk", "ml", "mr", "mt", "my", "nb",
+    "ne", "nl", "nn", "om", "or", "pa", "pl", "ps", "pt", "ro", "ru", "se", "si", "sk", "sl", "smn", "sq", "sr",
+    "sv", "sw", "ta", "te", "th", "to", "tr", "uk", "ur", "vi", "wae", "yi", "yo", "zh", "zu",
+];
+
+/// Locale used when the requested one has no tailored rules; its order is the ICU root order
+const DEFAULT_COLLATION_LOCALE: &str = "en";
+
+/// MongoDB collation locale for a BCP 47 tag, e.g. "tr-TR" -> "tr", "zh-TW" -> "zh_Hant"
+/// Unknown or malformed tags fall back to "en", whose rules are the ICU root order.
+pub fn collation_locale(locale: &str) -> String {
+    let mut subtags = locale.split(['-', '_']).map(str::to_ascii_lowercase);
+    let language = subtags.next().unwrap_or_default();
+    if !SUPPORTED_LANGUAGES.contains(&language.as_str()) {
+        return DEFAULT_COLLATION_LOCALE.to_string();
+    }
+
+    // Traditional Chinese has its own rules; simplified is the "zh" default
+    if language == "zh" && subtags.any(|subtag| matches!(subtag.as_str(), "hant" | "tw" | "hk" | "mo")) {
+        return "zh_Hant".to_string();
+    }
+
+    language
+}
+
+/// Collation for user-facing lists: case-insensitive, accent-sensitive, and "item 10" after "item 9"
+pub fn collation_for_locale(locale: &str) -> Collation {
+    Collation::builder()
+        .locale(collation_locale(locale))
+        .strength(CollationStrength::Secondary)
+        .numeric_ordering(true)
+        .build()
+}
+
+/// Find options sorting with the locale's collation
+pub fn sorted_find_options(locale: &str, sort: Document, limit: usize) -> FindOptions {
+    FindOptions::builder()
+        .sort(sort)
+        .collation(collation_for_locale(locale))
+        .limit(limit as i64)
+        .build()
+}
+
+/// Index options matching `collation_for_locale`, so collated sorts can use the index
+pub fn collated_index_options(locale: &str, name: &str) -> IndexOptions {
+    IndexOptions::builder()
+        .name(name.to_string())
+        .collation(collation_for_locale(locale))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::doc;
+
+    #[test]
+    fn test_collation_locale_mapping() {
+        assert_eq!(collation_locale("tr-TR"), "tr");
+        assert_eq!(collation_locale("ar_SA"), "ar");
+        assert_eq!(collation_locale("PT-br"), "pt");
+        assert_eq!(collation_locale("zh-Hant-TW"), "zh_Hant");
+        assert_eq!(collation_locale("zh-CN"), "zh");
+        assert_eq!(collation_locale("tlh"), "en");
+        assert_eq!(collation_locale(""), "en");
+
+        let options = sorted_find_options("tr-TR", doc! { "display_name": 1 }, 20);
+        let collation = options.collation.unwrap();
+        assert_eq!(collation.locale, "tr");
+        assert!(matches!(collation.strength, Some(CollationStrength::Secondary)));
+        assert_eq!(collation.numeric_ordering, Some(true));
+        assert_eq!(options.limit, Some(20));
+    }
+}
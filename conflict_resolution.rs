@@ -0,0 +1,378 @@
+//! Conflict resolution for documents edited offline on several devices, compiled out by the `no_mongo` feature
+//!
+//! Each device increments its own counter in the document's `VersionVector` when it edits. When an
+//! upload is compared with the stored document the vectors tell whether one edit already saw the
+//! other (no conflict) or both were made concurrently, in which case the configured strategy decides.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
+use serde::de::DeserializeOwned;
+use serde::{ Deserialize, Serialize };
+use serde_json::{ Map, Value };
+
+use crate::common_lib::delta_sync::Versioned;
+use crate::common_lib::error::ApiError;
+
+/// Per-device edit counters
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+pub struct VersionVector(pub BTreeMap<String, u64>);
+
+/// How two versions relate causally
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Causality {
+    Equal,
+    /// The local version already includes the remote edits
+    LocalNewer,
+    /// The remote version already includes the local edits
+    RemoteNewer,
+    /// Both sides have edits the other has not seen
+    Concurrent,
+}
+
+impl VersionVector {
+    /// Record an edit made on a device
+    pub fn increment(&mut self, device_id: &str) {
+        *self.0.entry(device_id.to_string()).or_insert(0) += 1;
+    }
+
+    /// Element-wise maximum, the version after merging both sides
+    pub fn merge(&self, other: &VersionVector) -> VersionVector {
+        let mut merged = self.0.clone();
+        for (device_id, counter) in &other.0 {
+            let entry = merged.entry(device_id.clone()).or_insert(0);
+            *entry = (*entry).max(*counter);
+        }
+
+        VersionVector(merged)
+    }
+
+    /// Compare this (local) version with a remote one
+    pub fn compare(&self, remote: &VersionVector) -> Causality {
+        let mut ordering = Ordering::Equal;
+
+        for device_id in self.0.keys().chain(remote.0.keys()) {
+            let local = self.0.get(device_id).copied().unwrap_or(0);
+            let remote = remote.0.get(device_id).copied().unwrap_or(0);
+
+            ordering = match (ordering, local.cmp(&remote)) {
+                (current, Ordering::Equal) => current,
+                (Ordering::Equal, next) => next,
+                (current, next) if current == next => current,
+                _ => {
+                    return Causality::Concurrent;
+                }
+            };
+        }
+
+        match ordering {
+            Ordering::Equal => Causality::Equal,
+            Ordering::Greater => Causality::LocalNewer,
+            Ordering::Less => Causality::RemoteNewer,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ConflictStrategy {
+    /// Keep whichever whole document was written last
+    LastWriterWins,
+    /// Merge field by field against the common ancestor; fields edited on both sides fall back to last writer wins
+    FieldMerge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Side {
+    Local,
+    Remote,
+}
+
+/// A field both devices changed to different values
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct FieldConflict {
+    pub field: String,
+    pub local: Value,
+    pub remote: Value,
+    pub winner: Side,
+}
+
+/// What happened when two versions of a document were reconciled, for logs and client UIs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ConflictReport {
+    pub document_id: String,
+    pub strategy: ConflictStrategy,
+    pub causality: Causality,
+    /// Side whose version (or conflicting fields) won
+    pub winner: Side,
+    pub field_conflicts: Vec<FieldConflict>,
+}
+
+impl ConflictReport {
+    /// Whether any edit was discarded
+    pub fn has_conflicts(&self) -> bool {
+        self.causality == Causality::Concurrent &&
+            (self.strategy == ConflictStrategy::LastWriterWins || !self.field_conflicts.is_empty())
+    }
+}
+
+/// Merged document (already carrying the merged version vector), that vector and the report
+#[derive(Debug, Clone)]
+pub struct Resolution<T> {
+    pub merged: T,
+    pub version_vector: VersionVector,
+    pub report: ConflictReport,
+}
+
+/// Reconciles a local (stored) and remote (uploaded) version of the same document
+#[derive(Debug, Clone)]
+pub struct ConflictResolver {
+    pub strategy: ConflictStrategy,
+    /// Bookkeeping fields taken from the winning side instead of being merged
+    pub metadata_fields: Vec<String>,
+}
+
+impl ConflictResolver {
+    pub fn new(strategy: ConflictStrategy) -> Self {
+        Self {
+            strategy,
+            metadata_fields: ["_id", "id", "updated_at", "updatedAt", "version_vector", "versionVector"]
+                .iter()
+                .map(|field| field.to_string())
+                .collect(),
+        }
+    }
+
+    /// Reconcile two versions; `base` is the last version both devices agreed on, if known
+    pub fn resolve<T>(&self, base: Option<&T>, local: T, remote: T) -> Result<Resolution<T>, ApiError>
+        where T: Versioned + Serialize + DeserializeOwned
+    {
+        let local_vector = local.version_vector();
+        let remote_vector = remote.version_vector();
+        let causality = local_vector.compare(&remote_vector);
+        let version_vector = local_vector.merge(&remote_vector);
+
+        // Ties in time go to the stored version so repeated uploads are idempotent
+        let last_writer = if remote.updated_at() > local.updated_at() { Side::Remote } else { Side::Local };
+
+        let mut report = ConflictReport {
+            document_id: local.sync_id(),
+            strategy: self.strategy,
+            causality,
+            winner: last_writer,
+            field_conflicts: Vec::new(),
+        };
+
+        let mut merged = match (causality, self.strategy) {
+            (Causality::Equal | Causality::LocalNewer, _) => {
+                report.winner = Side::Local;
+                local
+            }
+            (Causality::RemoteNewer, _) => {
+                report.winner = Side::Remote;
+                remote
+            }
+            (Causality::Concurrent, ConflictStrategy::LastWriterWins) =>
+                match last_writer {
+                    Side::Local => local,
+                    Side::Remote => remote,
+                }
+            (Causality::Concurrent, ConflictStrategy::FieldMerge) => {
+                let (merged, field_conflicts) = self.merge_fields(base, &local, &remote, last_writer)?;
+                report.field_conflicts = field_conflicts;
+                merged
+            }
+        };
+
+        merged.set_version_vector(version_vector.clone());
+
+        Ok(Resolution { merged, version_vector, report })
+    }
+
+    fn merge_fields<T>(
+        &self,
+        base: Option<&T>,
+        local: &T,
+        remote: &T,
+        last_writer: Side
+    ) -> Result<(T, Vec<FieldConflict>), ApiError>
+        where T: Serialize + DeserializeOwned
+    {
+        let base = base.map(to_object).transpose()?.unwrap_or_default();
+        let local = to_object(local)?;
+        let remote = to_object(remote)?;
+
+        let mut merged = Map::new();
+        let mut conflicts = Vec::new();
+        let mut fields: Vec<&String> = local.keys().chain(remote.keys()).collect();
+        fields.sort();
+        fields.dedup();
+
+        for field in fields {
+            let local_value = local.get(field).cloned().unwrap_or(Value::Null);
+            let remote_value = remote.get(field).cloned().unwrap_or(Value::Null);
+            let base_value = base.get(field);
+
+            let value = if self.metadata_fields.contains(field) {
+                match last_writer {
+                    Side::Local => local_value,
+                    Side::Remote => remote_value,
+                }
+            } else if local_value == remote_value || base_value == Some(&remote_value) {
+                local_value
+            } else if base_value == Some(&local_value) {
+                remote_value
+            } else {
+                let winner_value = match last_writer {
+                    Side::Local => local_value.clone(),
+                    Side::Remote => remote_value.clone(),
+                };
+                conflicts.push(FieldConflict {
+                    field: field.clone(),
+                    local: local_value,
+                    remote: remote_value,
+                    winner: last_writer,
+                });
+                winner_value
+            };
+
+            merged.insert(field.clone(), value);
+        }
+
+        let merged = serde_json::from_value(Value::Object(merged)).map_err(|e| ApiError::InternalServerError {
+            message: format!("Failed to rebuild merged document: {}", e),
+        })?;
+
+        Ok((merged, conflicts))
+    }
+}
+
+fn to_object<T: Serialize>(value: &T) -> Result<Map<String, Value>, ApiError> {
+    match serde_json::to_value(value) {
+        Ok(Value::Object(object)) => Ok(object),
+        Ok(_) =>
+            Err(ApiError::BadRequest {
+                message: "Field merge requires documents that serialize to JSON objects".to_string(),
+            }),
+        Err(e) => Err(ApiError::InternalServerError { message: e.to_string() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{ DateTime, Duration, Utc };
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Contact {
+        id: String,
+        name: String,
+        phone: String,
+        updated_at: DateTime<Utc>,
+        version_vector: VersionVector,
+    }
+
+    impl Versioned for Contact {
+        fn sync_id(&self) -> String {
+            self.id.clone()
+        }
+
+        fn updated_at(&self) -> DateTime<Utc> {
+            self.updated_at
+        }
+
+        fn version_vector(&self) -> VersionVector {
+            self.version_vector.clone()
+        }
+
+        fn set_version_vector(&mut self, version_vector: VersionVector) {
+            self.version_vector = version_vector;
+        }
+    }
+
+    fn edit(base: &Contact, device_id: &str, minutes: i64, change: impl FnOnce(&mut Contact)) -> Contact {
+        let mut edited = base.clone();
+        change(&mut edited);
+        edited.version_vector.increment(device_id);
+        edited.updated_at = base.updated_at + Duration::minutes(minutes);
+        edited
+    }
+
+    fn base() -> Contact {
+        Contact {
+            id: "c1".to_string(),
+            name: "Ana".to_string(),
+            phone: "+351900000000".to_string(),
+            updated_at: Utc::now(),
+            version_vector: VersionVector::default(),
+        }
+    }
+
+    #[test]
+    fn test_version_vector_compare() {
+        let mut a = VersionVector::default();
+        a.increment("phone");
+        let mut b = a.clone();
+        b.increment("tablet");
+
+        assert_eq!(a.compare(&a), Causality::Equal);
+        assert_eq!(a.compare(&b), Causality::RemoteNewer);
+        assert_eq!(b.compare(&a), Causality::LocalNewer);
+
+        a.increment("phone");
+        assert_eq!(a.compare(&b), Causality::Concurrent);
+        assert_eq!(a.merge(&b).0, BTreeMap::from([("phone".to_string(), 2), ("tablet".to_string(), 1)]));
+    }
+
+    #[test]
+    fn test_field_merge_keeps_both_edits() {
+        let base = base();
+        let local = edit(&base, "phone", 1, |contact| contact.name = "Ana Maria".to_string());
+        let remote = edit(&base, "tablet", 2, |contact| contact.phone = "+351911111111".to_string());
+
+        let resolution = ConflictResolver::new(ConflictStrategy::FieldMerge)
+            .resolve(Some(&base), local, remote)
+            .unwrap();
+
+        assert_eq!(resolution.merged.name, "Ana Maria");
+        assert_eq!(resolution.merged.phone, "+351911111111");
+        assert!(!resolution.report.has_conflicts());
+        assert_eq!(resolution.version_vector.0.len(), 2);
+        assert_eq!(resolution.merged.version_vector, resolution.version_vector);
+    }
+
+    #[test]
+    fn test_concurrent_edits_of_same_field() {
+        let base = base();
+        let local = edit(&base, "phone", 1, |contact| contact.name = "Ana M.".to_string());
+        let remote = edit(&base, "tablet", 2, |contact| contact.name = "Ana Maria".to_string());
+
+        let merged = ConflictResolver::new(ConflictStrategy::FieldMerge)
+            .resolve(Some(&base), local.clone(), remote.clone())
+            .unwrap();
+        assert_eq!(merged.merged.name, "Ana Maria");
+        assert_eq!(merged.report.field_conflicts.len(), 1);
+        assert_eq!(merged.report.field_conflicts[0].winner, Side::Remote);
+        assert!(merged.report.has_conflicts());
+
+        let (local_vector, remote_vector) = (local.version_vector.clone(), remote.version_vector.clone());
+        let lww = ConflictResolver::new(ConflictStrategy::LastWriterWins).resolve(None, local, remote).unwrap();
+        assert_eq!(lww.report.winner, Side::Remote);
+        assert_eq!(lww.report.causality, Causality::Concurrent);
+        // The winner's own vector would make the next upload from the losing device look concurrent again
+        assert_eq!(lww.merged.version_vector, local_vector.merge(&remote_vector));
+    }
+}
@@ -20,6 +20,8 @@ pub const CHECKINS_SERVICE_BASE_URL: &str = "CHECKINS_SERVICE_BASE_URL";
 pub const AI_SERVICE_BASE_URL: &str = "AI_SERVICE_BASE_URL";
 pub const GRAPHQL_GATEWAY_SERVICE_BASE_URL: &str = "GRAPHQL_GATEWAY_SERVICE_BASE_URL";
 pub const REDIS_URL: &str = "REDIS_URL";
+pub const AWS_REGION: &str = "AWS_REGION";
+pub const AWS_ENDPOINT_URL: &str = "AWS_ENDPOINT_URL";
 pub const MAX_CONCURRENT_AI_CALLS: &str = "MAX_CONCURRENT_AI_CALLS";
 pub const MAX_CONCURRENT_VENUES: &str = "MAX_CONCURRENT_VENUES";
 pub const SYSTEM_USER_COUNTRY_CODE: &str = "SYSTEM_USER_COUNTRY_CODE";
@@ -36,6 +38,15 @@ pub const X_PHONE_NUMBER: &str = "X-Phone-Number";
 pub const X_FIREBASE_UID: &str = "X-Firebase-UID";
 pub const X_COUNTRY_CODE: &str = "X-Country-Code";
 pub const X_CITY: &str = "X-City";
+pub const X_CORRELATION_ID: &str = "X-Correlation-ID";
+pub const X_SHADOW_REQUEST: &str = "X-Shadow-Request";
+pub const X_CURRENCY: &str = "X-Currency";
+pub const X_TIMEZONE: &str = "X-Timezone";
+pub const X_APP_VERSION: &str = "X-App-Version";
+pub const X_APP_PLATFORM: &str = "X-App-Platform";
+pub const X_REQUEST_TIMESTAMP: &str = "X-Request-Timestamp";
+pub const X_REQUEST_NONCE: &str = "X-Request-Nonce";
+pub const X_REQUEST_SIGNATURE: &str = "X-Request-Signature";
 pub const MAXMIND_API_KEY: &str = "MAXMIND_API_KEY";
 pub const MAXMIND_API_URL: &str = "MAXMIND_API_URL";
 pub const GEOLOCATION_CACHE_TTL_SECONDS: &str = "GEOLOCATION_CACHE_TTL_SECONDS";
@@ -0,0 +1,204 @@
+//! Privacy-preserving contact matching ("find friends"), enabled with the `contact_matching` feature
+//!
+//! Phone numbers (E.164) and emails are normalized and hashed with a service-wide salt, so the
+//! same contact yields the same hash in every service. Users' own identifiers are stored hashed.
+//! Uploaded address books arrive as raw numbers and emails; they are hashed on receipt and only
+//! the hashes are queried and kept, so the raw entries must not be logged or persisted. Phone
+//! numbers have few enough possible values that anyone holding the salt can reverse a hash, so the
+//! hashes are pseudonymous, not anonymous, and the salt must stay secret:
+//!
+//! ```ignore
+//! let matcher = ContactMatcher::new(contact_salt);
+//! let hashes = matcher.hash_contacts(&request.contacts, Some(&user_country))?;
+//! let stored = users.find(stored_hash_filter("contact_hashes", &hashes)?, None).await?;
+//! let matched = matcher.intersect(&hashes, stored_hashes)?;
+//! ```
+
+use std::collections::HashSet;
+#[cfg(not(feature = "no_mongo"))]
+use mongodb::bson::{ doc, Document };
+use serde::{ Deserialize, Serialize };
+use sha2::{ Digest, Sha256 };
+
+use crate::common_lib::country_utils::CountryService;
+use crate::common_lib::error::ApiError;
+use crate::common_lib::secret::SecretString;
+
+/// Most hashes accepted in one matching request, to bound work per address book upload
+pub const MAX_MATCH_BATCH: usize = 5_000;
+
+fn check_batch_size(len: usize) -> Result<(), ApiError> {
+    if len > MAX_MATCH_BATCH {
+        return Err(ApiError::BadRequest {
+            message: format!("At most {} contacts can be matched at once", MAX_MATCH_BATCH),
+        });
+    }
+    Ok(())
+}
+
+/// Domains where dots and "+tags" in the local part don't change the mailbox
+const DOT_INSENSITIVE_DOMAINS: &[&str] = &["gmail.com", "googlemail.com"];
+
+/// Identifier kind, part of the hash so a phone and an email never collide
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContactKind {
+    Phone,
+    Email,
+}
+
+impl ContactKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ContactKind::Phone => "phone",
+            ContactKind::Email => "email",
+        }
+    }
+}
+
+/// An address book entry as uploaded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawContact {
+    pub kind: ContactKind,
+    pub value: String,
+}
+
+/// Lowercase, trim, and fold Gmail aliases ("J.Doe+news@GoogleMail.com" -> "jdoe@gmail.com")
+pub fn normalize_email(email: &str) -> Result<String, ApiError> {
+    let email = email.trim().to_lowercase();
+    let invalid = || ApiError::BadRequest { message: format!("Invalid email address: '{}'", email) };
+
+    let (local, domain) = email.rsplit_once('@').ok_or_else(invalid)?;
+    if local.is_empty() || domain.is_empty() || local.contains('@') || !domain.contains('.') {
+        return Err(invalid());
+    }
+
+    if DOT_INSENSITIVE_DOMAINS.contains(&domain) {
+        let local = local.split('+').next().unwrap_or_default().replace('.', "");
+        if local.is_empty() {
+            return Err(invalid());
+        }
+        return Ok(format!("{}@gmail.com", local));
+    }
+
+    Ok(email)
+}
+
+/// Hashes contacts with a shared salt and intersects them with stored hashes
+pub struct ContactMatcher {
+    salt: SecretString,
+}
+
+impl ContactMatcher {
+    /// `salt` must be the same in every service that matches against the same stored hashes
+    pub fn new(salt: SecretString) -> Self {
+        Self { salt }
+    }
+
+    fn hash(&self, kind: ContactKind, normalized: &str) -> String {
+        let digest = Sha256::new()
+            .chain_update(self.salt.expose_secret().as_bytes())
+            .chain_update(kind.as_str().as_bytes())
+            .chain_update(b":")
+            .chain_update(normalized.as_bytes())
+            .finalize();
+        hex::encode(digest)
+    }
+
+    /// Hash of a phone number, normalized to E.164; national numbers use `default_country`
+    pub fn hash_phone(&self, phone: &str, default_country: Option<&str>) -> Result<String, ApiError> {
+        let normalized = CountryService::normalize_phone_number_e164(phone, default_country)?;
+        Ok(self.hash(ContactKind::Phone, &normalized))
+    }
+
+    pub fn hash_email(&self, email: &str) -> Result<String, ApiError> {
+        Ok(self.hash(ContactKind::Email, &normalize_email(email)?))
+    }
+
+    pub fn hash_contact(&self, contact: &RawContact, default_country: Option<&str>) -> Result<String, ApiError> {
+        match contact.kind {
+            ContactKind::Phone => self.hash_phone(&contact.value, default_country),
+            ContactKind::Email => self.hash_email(&contact.value),
+        }
+    }
+
+    /// Unique hashes of an address book of at most `MAX_MATCH_BATCH` entries; entries that don't
+    /// parse are skipped
+    pub fn hash_contacts(
+        &self,
+        contacts: &[RawContact],
+        default_country: Option<&str>
+    ) -> Result<Vec<String>, ApiError> {
+        check_batch_size(contacts.len())?;
+
+        let mut seen = HashSet::new();
+        Ok(
+            contacts
+                .iter()
+                .filter_map(|contact| self.hash_contact(contact, default_country).ok())
+                .filter(|hash| seen.insert(hash.clone()))
+                .collect()
+        )
+    }
+
+    /// Candidate hashes that are also stored, in candidate order
+    pub fn intersect<'a>(
+        &self,
+        candidates: &[String],
+        stored: impl IntoIterator<Item = &'a str>
+    ) -> Result<Vec<String>, ApiError> {
+        check_batch_size(candidates.len())?;
+
+        let stored: HashSet<&str> = stored.into_iter().collect();
+        Ok(
+            candidates
+                .iter()
+                .filter(|hash| stored.contains(hash.as_str()))
+                .cloned()
+                .collect()
+        )
+    }
+}
+
+/// Filter for documents storing any of at most `MAX_MATCH_BATCH` hashes in an array field
+#[cfg(not(feature = "no_mongo"))]
+pub fn stored_hash_filter(field: &str, hashes: &[String]) -> Result<Document, ApiError> {
+    check_batch_size(hashes.len())?;
+    Ok(doc! { field: { "$in": hashes } })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashes_match_across_formats() {
+        let matcher = ContactMatcher::new(SecretString::from("salt"));
+
+        let international = matcher.hash_phone("+351 912 345 678", None).unwrap();
+        assert_eq!(matcher.hash_phone("912345678", Some("pt")).unwrap(), international);
+        assert_ne!(ContactMatcher::new(SecretString::from("other")).hash_phone("+351912345678", None).unwrap(), international);
+        assert!(matcher.hash_phone("123", Some("PT")).is_err());
+
+        assert_eq!(normalize_email(" J.Doe+news@GoogleMail.com ").unwrap(), "jdoe@gmail.com");
+        assert_eq!(normalize_email("Ana.Silva+x@example.pt").unwrap(), "ana.silva+x@example.pt");
+        assert!(normalize_email("not-an-email").is_err());
+
+        let contacts = vec![
+            RawContact { kind: ContactKind::Phone, value: "912 345 678".to_string() },
+            RawContact { kind: ContactKind::Phone, value: "+351912345678".to_string() },
+            RawContact { kind: ContactKind::Email, value: "jdoe@gmail.com".to_string() },
+            RawContact { kind: ContactKind::Email, value: "broken".to_string() },
+        ];
+        let hashes = matcher.hash_contacts(&contacts, Some("PT")).unwrap();
+        assert_eq!(hashes.len(), 2);
+        let oversized = vec![contacts[0].clone(); MAX_MATCH_BATCH + 1];
+        assert!(matches!(matcher.hash_contacts(&oversized, Some("PT")), Err(ApiError::BadRequest { .. })));
+        #[cfg(not(feature = "no_mongo"))]
+        assert!(stored_hash_filter("contact_hashes", &vec![international.clone(); MAX_MATCH_BATCH + 1]).is_err());
+
+        let stored = [international.clone(), matcher.hash_email("someone@else.com").unwrap()];
+        let matched = matcher.intersect(&hashes, stored.iter().map(String::as_str)).unwrap();
+        assert_eq!(matched, vec![international]);
+    }
+}
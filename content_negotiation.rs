@@ -0,0 +1,214 @@
+//! JSON, MessagePack and CBOR content negotiation, enabled with the `binary_formats` feature
+//!
+//! `Negotiated<T>` is both a data guard and a responder, like Rocket's `Json<T>`: request bodies are
+//! decoded according to `Content-Type` and responses are encoded according to `Accept`, falling back
+//! to JSON. Intended for internal high-throughput endpoints where payload size and encode cost matter.
+//!
+//! The request body limit is read from the `negotiated` limit (default 1 MiB).
+
+use std::io::Cursor;
+use rocket::data::{ self, Data, FromData, ToByteUnit };
+use rocket::http::{ ContentType, Header, MediaType, Status };
+use rocket::request::Request;
+use rocket::response::{ self, Responder, Response };
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::error;
+
+use crate::common_lib::error::ApiError;
+
+/// Serialization formats supported on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireFormat {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl WireFormat {
+    pub fn content_type(&self) -> ContentType {
+        match self {
+            WireFormat::Json => ContentType::JSON,
+            WireFormat::MessagePack => ContentType::new("application", "msgpack"),
+            WireFormat::Cbor => ContentType::new("application", "cbor"),
+        }
+    }
+
+    /// Format for a media type, accepting the common MessagePack aliases
+    pub fn from_media_type(media_type: &MediaType) -> Option<Self> {
+        if media_type.top() != "application" {
+            return None;
+        }
+
+        match media_type.sub().as_str() {
+            "json" => Some(WireFormat::Json),
+            "msgpack" | "x-msgpack" | "vnd.msgpack" => Some(WireFormat::MessagePack),
+            "cbor" => Some(WireFormat::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Highest-weighted supported format in the request's `Accept` header, JSON otherwise
+    pub fn from_accept(request: &Request<'_>) -> Self {
+        request
+            .accept()
+            .and_then(|accept| {
+                accept
+                    .iter()
+                    .filter_map(|media_type| {
+                        Self::from_media_type(media_type.media_type()).map(|format| {
+                            (format, media_type.weight_or(1.0))
+                        })
+                    })
+                    .filter(|(_, weight)| *weight > 0.0)
+                    .fold(None, |best: Option<(Self, f32)>, candidate| {
+                        match best {
+                            Some(best) if best.1 >= candidate.1 => Some(best),
+                            _ => Some(candidate),
+                        }
+                    })
+            })
+            .map(|(format, _)| format)
+            .unwrap_or(WireFormat::Json)
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, ApiError> {
+        match self {
+            WireFormat::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            WireFormat::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            WireFormat::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map(|_| bytes).map_err(|e| e.to_string())
+            }
+        }.map_err(|message| ApiError::InternalServerError {
+            message: format!("Failed to encode {:?} response: {}", self, message),
+        })
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, ApiError> {
+        match self {
+            WireFormat::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            WireFormat::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            WireFormat::Cbor => ciborium::from_reader(bytes).map_err(|e| e.to_string()),
+        }.map_err(|message| ApiError::BadRequest {
+            message: format!("Invalid {:?} body: {}", self, message),
+        })
+    }
+}
+
+/// Request body or response value in the negotiated wire format
+#[derive(Debug, Clone)]
+pub struct Negotiated<T>(pub T);
+
+impl<T> Negotiated<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned> FromData<'r> for Negotiated<T> {
+    type Error = ApiError;
+
+    async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let format = match request.content_type() {
+            None => WireFormat::Json,
+            Some(content_type) =>
+                match WireFormat::from_media_type(content_type.media_type()) {
+                    Some(format) => format,
+                    None => {
+                        return data::Outcome::Error((
+                            Status::UnsupportedMediaType,
+                            ApiError::BadRequest {
+                                message: format!("Unsupported content type: {}", content_type),
+                            },
+                        ));
+                    }
+                }
+        };
+
+        let limit = request.limits().get("negotiated").unwrap_or(1.mebibytes());
+        let bytes = match data.open(limit).into_bytes().await {
+            Ok(bytes) if bytes.is_complete() => bytes.into_inner(),
+            Ok(_) => {
+                return data::Outcome::Error((
+                    Status::PayloadTooLarge,
+                    ApiError::BadRequest {
+                        message: format!("Request body exceeds {}", limit),
+                    },
+                ));
+            }
+            Err(e) => {
+                return data::Outcome::Error((Status::BadRequest, ApiError::from(e)));
+            }
+        };
+
+        match format.decode(&bytes) {
+            Ok(value) => data::Outcome::Success(Negotiated(value)),
+            Err(e) => data::Outcome::Error((Status::BadRequest, e)),
+        }
+    }
+}
+
+impl<'r, T: Serialize> Responder<'r, 'static> for Negotiated<T> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let format = WireFormat::from_accept(request);
+        let body = format.encode(&self.0).map_err(|e| {
+            error!("NEGOTIATION:respond [ENCODE_ERROR] {}", e);
+            Status::InternalServerError
+        })?;
+
+        Response::build()
+            .header(format.content_type())
+            .header(Header::new("Vary", "Accept"))
+            .sized_body(body.len(), Cursor::new(body))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::{ post, routes };
+    use serde::Deserialize;
+    use crate::common_lib::test_client::{ test_client, test_rocket };
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Payload {
+        id: u32,
+        tags: Vec<String>,
+    }
+
+    #[post("/echo", data = "<payload>")]
+    fn echo(payload: Negotiated<Payload>) -> Negotiated<Payload> {
+        payload
+    }
+
+    #[rocket::async_test]
+    async fn test_negotiated_round_trip() {
+        let client = test_client(test_rocket(routes![echo])).await;
+        let payload = Payload { id: 7, tags: vec!["a".to_string()] };
+        let msgpack = WireFormat::MessagePack.content_type();
+        let cbor = WireFormat::Cbor.content_type();
+
+        let response = client
+            .post("/echo")
+            .header(msgpack.clone())
+            .header(Header::new("Accept", "application/json;q=0.5, application/cbor"))
+            .body(WireFormat::MessagePack.encode(&payload).unwrap())
+            .dispatch().await;
+        assert_eq!(response.content_type(), Some(cbor));
+        let body = response.into_bytes().await.unwrap();
+        assert_eq!(WireFormat::Cbor.decode::<Payload>(&body).unwrap(), payload);
+
+        let response = client
+            .post("/echo")
+            .header(ContentType::JSON)
+            .body(r#"{"id":7,"tags":["a"]}"#)
+            .dispatch().await;
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+
+        let response = client.post("/echo").header(ContentType::XML).body("<id/>").dispatch().await;
+        assert_eq!(response.status(), Status::UnsupportedMediaType);
+    }
+}
@@ -0,0 +1,117 @@
+//! Contract-testing fixtures for shared DTOs, enabled with the `test_support` feature
+//!
+//! Writes `<Name>.schema.json` (JSON Schema draft 7) and `<Name>.example.json` for every shared DTO
+//! so mobile and web teams can validate their models against the same contracts. Typically run from
+//! an ignored test or a small bin in the host service:
+//!
+//! ```ignore
+//! common_lib::contract_fixtures::write_contract_fixtures(Path::new("target/contracts"))?;
+//! ```
+
+use std::fs;
+use std::path::{ Path, PathBuf };
+use rocket_okapi::okapi::schemars::r#gen::SchemaSettings;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::info;
+
+use crate::common_lib::error::ApiError;
+use crate::common_lib::openapi::ErrorBody;
+use crate::common_lib::shared_models::{ IdNamePair, Money, PaginatedResponse };
+use crate::common_lib::test_support::{
+    fake_encrypted_message,
+    fake_notification_preference,
+    LocationInfoBuilder,
+    NotificationEventBuilder,
+};
+
+/// Schema and example payload for one DTO
+pub struct ContractFixture {
+    pub name: &'static str,
+    pub schema: Value,
+    pub example: Value,
+}
+
+impl ContractFixture {
+    pub fn new<T: JsonSchema + Serialize>(name: &'static str, example: &T) -> Self {
+        let schema = SchemaSettings::draft07().into_generator().into_root_schema_for::<T>();
+
+        Self {
+            name,
+            schema: serde_json::to_value(schema).unwrap_or_default(),
+            example: serde_json::to_value(example).unwrap_or_default(),
+        }
+    }
+}
+
+/// Fixtures for all shared DTOs
+pub fn contract_fixtures() -> Vec<ContractFixture> {
+    vec![
+        ContractFixture::new("LocationInfo", &LocationInfoBuilder::new().build()),
+        ContractFixture::new("ErrorBody", &ErrorBody {
+            error: ApiError::NotFound { message: "User not found".to_string() }.to_string(),
+        }),
+        ContractFixture::new("ApiError", &ApiError::registration_required("send sparks")),
+        ContractFixture::new("NotificationEvent", &NotificationEventBuilder::new().build()),
+        ContractFixture::new("NotificationPreference", &fake_notification_preference()),
+        ContractFixture::new("EncryptedMessage", &fake_encrypted_message()),
+        ContractFixture::new("Money", &Money::new(1299, "GBP")),
+        ContractFixture::new(
+            "PaginatedResponse",
+            &PaginatedResponse::new(
+                vec![IdNamePair { id: "1".to_string(), name: "Lisbon".to_string() }],
+                1,
+                20,
+                1
+            )
+        )
+    ]
+}
+
+/// Write all fixtures into `dir`, creating it if needed, and return the written paths
+pub fn write_contract_fixtures(dir: &Path) -> Result<Vec<PathBuf>, ApiError> {
+    fs::create_dir_all(dir).map_err(|e| ApiError::InternalServerError {
+        message: format!("Failed to create contract directory '{}': {}", dir.display(), e),
+    })?;
+
+    let mut written = Vec::new();
+
+    for fixture in contract_fixtures() {
+        for (suffix, value) in [("schema", &fixture.schema), ("example", &fixture.example)] {
+            let path = dir.join(format!("{}.{}.json", fixture.name, suffix));
+            let contents = serde_json::to_string_pretty(value).map_err(ApiError::from)?;
+
+            fs::write(&path, contents).map_err(|e| ApiError::InternalServerError {
+                message: format!("Failed to write contract fixture '{}': {}", path.display(), e),
+            })?;
+
+            written.push(path);
+        }
+    }
+
+    info!("CONTRACTS:write [SUCCESS] Wrote contract fixtures - dir: {}, files: {}", dir.display(), written.len());
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_contract_fixtures() {
+        let dir = std::env::temp_dir().join(format!("contracts-{}", uuid::Uuid::new_v4()));
+        let written = write_contract_fixtures(&dir).unwrap();
+
+        assert_eq!(written.len(), contract_fixtures().len() * 2);
+
+        let schema: Value = serde_json
+            ::from_str(&fs::read_to_string(dir.join("LocationInfo.schema.json")).unwrap())
+            .unwrap();
+        assert_eq!(schema["title"], "LocationInfo");
+        assert!(schema["required"].as_array().unwrap().contains(&Value::from("country_code")));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
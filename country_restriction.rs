@@ -0,0 +1,314 @@
+//! Country allow/deny lists for features we cannot offer in every jurisdiction, compiled out by the
+//! `no_web` or `no_geo` feature
+//!
+//! Manage a `CountryRestrictionConfig` and an `Arc<GeolocationService>`, add `CountryAccess` to the
+//! restricted handlers and register `catchers()` at the same base as those handlers, so blocked
+//! requests get the localized JSON error and the rest of the app keeps its own 403 handling:
+//!
+//! ```ignore
+//! rocket::build()
+//!     .manage(geolocation_service)
+//!     .manage(CountryRestrictionConfig::deny(&["KP", "IR"]))
+//!     .mount("/payments", routes![create_payment])
+//!     .register("/payments", country_restriction::catchers())
+//! ```
+
+use std::collections::{ HashMap, HashSet };
+use rocket::http::Status;
+use rocket::request::{ FromRequest, Outcome, Request };
+use rocket::{ catch, catchers, Catcher };
+use tracing::{ error, warn };
+
+use crate::common_lib::error::ApiError;
+use crate::common_lib::geolocation::ClientLocation;
+use crate::common_lib::request_context::preferred_language;
+
+const DEFAULT_MESSAGE: &str = "This feature is not available in your country";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountryListMode {
+    /// Only the listed countries are served
+    Allow,
+    /// Every country except the listed ones is served
+    Deny,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestrictionAction {
+    /// Reject restricted requests
+    Block,
+    /// Let restricted requests through with `CountryAccess::flagged` set
+    Flag,
+}
+
+/// Which countries a feature is offered in and how other requests are handled
+#[derive(Debug, Clone)]
+pub struct CountryRestrictionConfig {
+    pub mode: CountryListMode,
+    /// ISO 3166-1 alpha-2 codes
+    pub countries: HashSet<String>,
+    pub action: RestrictionAction,
+    /// Block with 451 Unavailable For Legal Reasons instead of 403 Forbidden
+    pub legal_block: bool,
+    /// Whether requests whose country cannot be resolved are treated as allowed
+    pub allow_unknown: bool,
+    /// Block messages keyed by locale, e.g. "en" or "pt-BR"
+    pub messages: HashMap<String, String>,
+    pub default_locale: String,
+}
+
+impl CountryRestrictionConfig {
+    /// Serve only the given countries
+    pub fn allow_only(countries: &[&str]) -> Self {
+        Self::with_countries(CountryListMode::Allow, countries)
+    }
+
+    /// Serve every country except the given ones
+    pub fn deny(countries: &[&str]) -> Self {
+        Self::with_countries(CountryListMode::Deny, countries)
+    }
+
+    fn with_countries(mode: CountryListMode, countries: &[&str]) -> Self {
+        Self {
+            mode,
+            countries: countries
+                .iter()
+                .map(|country| country.trim().to_uppercase())
+                .collect(),
+            action: RestrictionAction::Block,
+            legal_block: true,
+            allow_unknown: true,
+            messages: HashMap::new(),
+            default_locale: "en".to_string(),
+        }
+    }
+
+    pub fn with_action(mut self, action: RestrictionAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    pub fn with_message(mut self, locale: &str, message: &str) -> Self {
+        self.messages.insert(locale.to_string(), message.to_string());
+        self
+    }
+
+    /// Whether a client in this country is restricted
+    pub fn is_restricted(&self, country_code: Option<&str>) -> bool {
+        let Some(country_code) = country_code else {
+            return !self.allow_unknown;
+        };
+        let listed = self.countries.contains(&country_code.to_uppercase());
+
+        match self.mode {
+            CountryListMode::Allow => !listed,
+            CountryListMode::Deny => listed,
+        }
+    }
+
+    /// Block message for an `Accept-Language` header
+    /// e.g. "pt-BR,pt;q=0.9" tries "pt-BR", then "pt", then the default locale
+    pub fn message_for(&self, accept_language: Option<&str>) -> String {
+        let mut chain = Vec::new();
+        if let Some(requested) = accept_language.and_then(preferred_language) {
+            let language = requested.split_once('-').map(|(language, _)| language.to_string());
+            chain.push(requested);
+            chain.extend(language);
+        }
+        chain.push(self.default_locale.clone());
+
+        chain
+            .iter()
+            .find_map(|locale| self.messages.get(locale))
+            .cloned()
+            .unwrap_or_else(|| DEFAULT_MESSAGE.to_string())
+    }
+
+    /// Error returned for a blocked request
+    pub fn error(&self, accept_language: Option<&str>) -> ApiError {
+        let message = self.message_for(accept_language);
+
+        if self.legal_block {
+            ApiError::UnavailableForLegalReasons { message }
+        } else {
+            ApiError::Forbidden { message }
+        }
+    }
+}
+
+/// Request guard enforcing the managed `CountryRestrictionConfig`
+#[derive(Debug, Clone)]
+pub struct CountryAccess {
+    pub country_code: Option<String>,
+    /// Set when the country is restricted and the configured action is `Flag`
+    pub flagged: bool,
+}
+
+/// Message of the request's block error, read back by the catchers
+struct BlockedMessage(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CountryAccess {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(config) = request.rocket().state::<CountryRestrictionConfig>() else {
+            error!("GEO:country_access [CONFIG] CountryRestrictionConfig is not managed by Rocket");
+            return Outcome::Error((
+                Status::InternalServerError,
+                ApiError::InternalServerError {
+                    message: "Country restrictions are not configured".to_string(),
+                },
+            ));
+        };
+
+        let client_location = match request.guard::<ClientLocation>().await {
+            Outcome::Success(client_location) => client_location,
+            Outcome::Error(e) => {
+                return Outcome::Error(e);
+            }
+            Outcome::Forward(status) => {
+                return Outcome::Forward(status);
+            }
+        };
+        let country_code = client_location.location.map(|location| location.country_code);
+
+        if !config.is_restricted(country_code.as_deref()) {
+            return Outcome::Success(CountryAccess { country_code, flagged: false });
+        }
+
+        warn!(
+            "GEO:country_access [RESTRICTED] Request from restricted country - country: {:?}, ip: {:?}, action: {:?}",
+            country_code,
+            client_location.ip_address,
+            config.action
+        );
+
+        match config.action {
+            RestrictionAction::Flag => Outcome::Success(CountryAccess { country_code, flagged: true }),
+            RestrictionAction::Block => {
+                let accept_language = request.headers().get_one("Accept-Language");
+                request.local_cache(|| BlockedMessage(Some(config.message_for(accept_language))));
+                let error = config.error(accept_language);
+
+                match error.status_code() {
+                    451 => Outcome::Error((Status::UnavailableForLegalReasons, error)),
+                    _ => Outcome::Error((Status::Forbidden, error)),
+                }
+            }
+        }
+    }
+}
+
+/// The localized block message, or the status reason for errors `CountryAccess` didn't raise
+fn blocked_message(request: &Request<'_>, status: Status) -> String {
+    request
+        .local_cache(|| BlockedMessage(None))
+        .0.clone()
+        .unwrap_or_else(|| status.reason_lossy().to_string())
+}
+
+#[catch(403)]
+fn forbidden(request: &Request<'_>) -> ApiError {
+    ApiError::Forbidden { message: blocked_message(request, Status::Forbidden) }
+}
+
+#[catch(451)]
+fn unavailable_for_legal_reasons(request: &Request<'_>) -> ApiError {
+    ApiError::UnavailableForLegalReasons { message: blocked_message(request, Status::UnavailableForLegalReasons) }
+}
+
+/// Catchers rendering blocked requests as JSON errors
+/// They take over every 403 and 451 under the base they are registered at, so register them at
+/// the mount point of the restricted routes, e.g. `rocket.register("/payments", catchers())`.
+pub fn catchers() -> Vec<Catcher> {
+    catchers![forbidden, unavailable_for_legal_reasons]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use reqwest::Client;
+    use rocket::{ get, routes };
+    use rocket::http::Header;
+    use crate::common_lib::geolocation::{ GeolocationConfig, GeolocationService, LocationInfo };
+    use crate::common_lib::test_client::{ test_client, test_rocket };
+
+    #[test]
+    fn test_is_restricted() {
+        let deny = CountryRestrictionConfig::deny(&["kp"]);
+        assert!(deny.is_restricted(Some("KP")));
+        assert!(!deny.is_restricted(Some("PT")));
+        assert!(!deny.is_restricted(None));
+
+        let allow = CountryRestrictionConfig::allow_only(&["PT", "BR"]);
+        assert!(allow.is_restricted(Some("US")));
+        assert!(!allow.is_restricted(Some("br")));
+    }
+
+    #[get("/restricted")]
+    fn restricted(access: CountryAccess) -> String {
+        access.country_code.unwrap_or_default()
+    }
+
+    #[get("/forbidden")]
+    fn forbidden_route() -> Status {
+        Status::Forbidden
+    }
+
+    #[rocket::async_test]
+    async fn test_blocked_request_gets_localized_451() {
+        let service = Arc::new(GeolocationService::new(Arc::new(Client::new()), GeolocationConfig::default()).unwrap());
+        for (ip_address, country_code) in [("203.0.113.1", "PT"), ("203.0.113.2", "KP")] {
+            service.cache_location(ip_address, &LocationInfo {
+                country_code: country_code.to_string(),
+                country_name: String::new(),
+                continent_code: None,
+                city: None,
+                region: None,
+                latitude: None,
+                longitude: None,
+                timezone: None,
+                asn: None,
+                isp: None,
+                organization: None,
+            }).await;
+        }
+        let config = CountryRestrictionConfig::deny(&["KP"])
+            .with_message("en", "Not available in your country")
+            .with_message("pt", "Indisponível no seu país");
+        let rocket = test_rocket(routes![forbidden_route])
+            .mount("/geo", routes![restricted, forbidden_route])
+            .manage(service)
+            .manage(config)
+            .register("/geo", catchers());
+        let client = test_client(rocket).await;
+
+        let allowed = client
+            .get("/geo/restricted")
+            .header(Header::new("X-Forwarded-For", "203.0.113.1"))
+            .dispatch().await;
+        assert_eq!(allowed.status(), Status::Ok);
+        assert_eq!(allowed.into_string().await.unwrap(), "PT");
+
+        let blocked = client
+            .get("/geo/restricted")
+            .header(Header::new("X-Forwarded-For", "203.0.113.2"))
+            .header(Header::new("Accept-Language", "pt-BR,pt;q=0.9"))
+            .dispatch().await;
+        assert_eq!(blocked.status(), Status::UnavailableForLegalReasons);
+        let body: serde_json::Value = blocked.into_json().await.unwrap();
+        assert_eq!(body["error"], "Unavailable For Legal Reasons: Indisponível no seu país");
+
+        // Other 403s under the restricted mount don't get the country message, and the rest of
+        // the app keeps its own catchers
+        let scoped = client.get("/geo/forbidden").dispatch().await;
+        assert_eq!(scoped.status(), Status::Forbidden);
+        let body: serde_json::Value = scoped.into_json().await.unwrap();
+        assert_eq!(body["error"], "Forbidden: Forbidden");
+        let outside = client.get("/forbidden").dispatch().await;
+        assert_eq!(outside.status(), Status::Forbidden);
+        assert!(!outside.into_string().await.unwrap().contains("Forbidden: "));
+    }
+}
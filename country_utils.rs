@@ -1,6 +1,10 @@
+#[cfg(not(feature = "no_phone"))]
 use phonenumber::PhoneNumber;
-use crate::common_lib::logging::{ generate_correlation_id, OperationTimer, LogLevel, error_codes };
+#[cfg(not(feature = "no_phone"))]
+use crate::common_lib::logging::{ OperationTimer, LogLevel, RequestId, error_codes };
+#[cfg(not(feature = "no_phone"))]
 use crate::common_lib::error::ApiError;
+#[cfg(not(feature = "no_phone"))]
 use tracing::debug;
 
 /// Country utilities for phone number parsing and country code validation
@@ -10,9 +14,10 @@ impl CountryService {
     /// Parse phone number and extract country code
     /// Uses phonenumber library's built-in country code extraction
     /// Returns ISO 3166-1 alpha-2 country code (e.g., "US", "DE", "JP")
+    #[cfg(not(feature = "no_phone"))]
     pub fn parse_phone_number_to_country(phone: &str) -> Result<String, ApiError> {
-        let req_id = generate_correlation_id();
-        let timer = OperationTimer::new("COUNTRY:parse_phone_number_to_country", &req_id);
+        let req_id = RequestId::new();
+        let timer = OperationTimer::for_request("COUNTRY:parse_phone_number_to_country", req_id);
 
         debug!(
             "COUNTRY:parse_phone_number_to_country [VALIDATION] [req_id:{}] Starting phone number parsing for: '{}'",
@@ -53,6 +58,31 @@ impl CountryService {
         Ok(country_code)
     }
 
+    /// Normalize a phone number to E.164, e.g. "912 345 678" with default country "PT" -> "+351912345678"
+    /// Numbers without a leading "+" are read as national numbers of `default_country`.
+    #[cfg(not(feature = "no_phone"))]
+    pub fn normalize_phone_number_e164(phone: &str, default_country: Option<&str>) -> Result<String, ApiError> {
+        let country = match default_country {
+            Some(country_code) => Some(
+                country_code.to_uppercase().parse::<phonenumber::country::Id>().map_err(|_| ApiError::BadRequest {
+                    message: format!("Invalid country code format: '{}'", country_code),
+                })?
+            ),
+            None => None,
+        };
+
+        let parsed_phone_number = phonenumber::parse(country, phone).map_err(|e| ApiError::BadRequest {
+            message: format!("Invalid phone number format: {:?}", e),
+        })?;
+        if !phonenumber::is_valid(&parsed_phone_number) {
+            return Err(ApiError::BadRequest {
+                message: "Phone number is not valid for its country.".to_string(),
+            });
+        }
+
+        Ok(parsed_phone_number.format().mode(phonenumber::Mode::E164).to_string())
+    }
+
     /// Validate country code format and existence
     /// Returns true if the country code is a valid 2-letter ISO code
     pub fn is_valid_country_code(country_code: &str) -> bool {
@@ -76,6 +106,56 @@ impl CountryService {
             Err(format!("Invalid country code format: '{}'", country_code))
         }
     }
+
+    /// Default ISO 4217 currency for a country, `None` for countries not in the table
+    pub fn currency_for_country(country_code: &str) -> Option<&'static str> {
+        let currency = match country_code.to_uppercase().as_str() {
+            "AT" | "BE" | "CY" | "DE" | "EE" | "ES" | "FI" | "FR" | "GR" | "HR" |
+            "IE" | "IT" | "LT" | "LU" | "LV" | "MT" | "NL" | "PT" | "SI" | "SK" => "EUR",
+            "US" => "USD",
+            "GB" => "GBP",
+            "CH" => "CHF",
+            "SE" => "SEK",
+            "NO" => "NOK",
+            "DK" => "DKK",
+            "PL" => "PLN",
+            "CZ" => "CZK",
+            "HU" => "HUF",
+            "RO" => "RON",
+            "TR" => "TRY",
+            "CA" => "CAD",
+            "MX" => "MXN",
+            "BR" => "BRL",
+            "AR" => "ARS",
+            "CL" => "CLP",
+            "CO" => "COP",
+            "PE" => "PEN",
+            "AU" => "AUD",
+            "NZ" => "NZD",
+            "JP" => "JPY",
+            "CN" => "CNY",
+            "KR" => "KRW",
+            "IN" => "INR",
+            "ID" => "IDR",
+            "SG" => "SGD",
+            "TH" => "THB",
+            "PH" => "PHP",
+            "AE" => "AED",
+            "SA" => "SAR",
+            "IL" => "ILS",
+            "ZA" => "ZAR",
+            "NG" => "NGN",
+            "KE" => "KES",
+            "EG" => "EGP",
+            "AO" => "AOA",
+            "MZ" => "MZN",
+            _ => {
+                return None;
+            }
+        };
+
+        Some(currency)
+    }
 }
 
 #[cfg(test)]
@@ -83,6 +163,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[cfg(not(feature = "no_phone"))]
     fn test_parse_phone_number_to_country() {
         // Test US phone number
         let result = CountryService::parse_phone_number_to_country("+1 650 253 0000");
@@ -130,4 +211,11 @@ mod tests {
         assert!(CountryService::validate_and_normalize_country_code("1").is_err());
         assert!(CountryService::validate_and_normalize_country_code("").is_err());
     }
+
+    #[test]
+    fn test_currency_for_country() {
+        assert_eq!(CountryService::currency_for_country("PT"), Some("EUR"));
+        assert_eq!(CountryService::currency_for_country("br"), Some("BRL"));
+        assert_eq!(CountryService::currency_for_country("XX"), None);
+    }
 }
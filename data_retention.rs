@@ -0,0 +1,161 @@
+//! Shared data retention rules, so deletion jobs don't hardcode durations
+//!
+//! A `RetentionTable` holds a default period per data class and per-country overrides, e.g. a
+//! country's statutory bookkeeping period. Periods run from the end of the record's relevance
+//! (account closed, transaction completed, log written). The built-in table follows common EU
+//! practice and the statutory bookkeeping periods of the countries listed; services can load
+//! their own table from JSON config:
+//!
+//! ```json
+//! { "defaults": { "ACCESS_LOGS": { "days": 180 } }, "overrides": { "PT": { "FINANCIAL_RECORDS": { "years": 10 } } } }
+//! ```
+
+use std::collections::HashMap;
+use chrono::{ DateTime, Days, Months, Utc };
+use serde::{ Deserialize, Serialize };
+
+use crate::common_lib::error::ApiError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DataClass {
+    /// Profile and contact details, after the account is closed
+    AccountProfile,
+    /// Invoices, payments and other bookkeeping records
+    FinancialRecords,
+    /// Identity verification documents and results, after the customer relationship ends
+    KycRecords,
+    AccessLogs,
+    LocationHistory,
+    SupportTickets,
+}
+
+/// How long a record is kept, in calendar units so statutory years end on the same date
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionPeriod {
+    Days(u32),
+    Months(u32),
+    Years(u32),
+}
+
+impl RetentionPeriod {
+    /// The date `period` after `since`; `None` past chrono's range
+    pub fn after(&self, since: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match *self {
+            RetentionPeriod::Days(days) => since.checked_add_days(Days::new(u64::from(days))),
+            RetentionPeriod::Months(months) => since.checked_add_months(Months::new(months)),
+            RetentionPeriod::Years(years) => since.checked_add_months(Months::new(years.checked_mul(12)?)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionTable {
+    pub defaults: HashMap<DataClass, RetentionPeriod>,
+    /// ISO 3166-1 alpha-2 country code to the classes it overrides
+    #[serde(default)]
+    pub overrides: HashMap<String, HashMap<DataClass, RetentionPeriod>>,
+}
+
+impl Default for RetentionTable {
+    fn default() -> Self {
+        let defaults = HashMap::from([
+            (DataClass::AccountProfile, RetentionPeriod::Days(30)),
+            (DataClass::FinancialRecords, RetentionPeriod::Years(10)),
+            // EU anti-money-laundering directive
+            (DataClass::KycRecords, RetentionPeriod::Years(5)),
+            (DataClass::AccessLogs, RetentionPeriod::Days(180)),
+            (DataClass::LocationHistory, RetentionPeriod::Days(90)),
+            (DataClass::SupportTickets, RetentionPeriod::Years(2)),
+        ]);
+        let financial = |years: u32| HashMap::from([(DataClass::FinancialRecords, RetentionPeriod::Years(years))]);
+        let overrides = HashMap::from([
+            ("NL".to_string(), financial(7)),
+            ("ES".to_string(), financial(6)),
+            ("GB".to_string(), financial(6)),
+            ("AE".to_string(), financial(5)),
+        ]);
+
+        Self { defaults, overrides }
+    }
+}
+
+impl RetentionTable {
+    /// Parse a table from JSON config; override country codes are matched case-insensitively
+    pub fn from_json(json: &str) -> Result<Self, ApiError> {
+        let table: Self = serde_json::from_str(json).map_err(|e| ApiError::InternalServerError {
+            message: format!("Invalid retention table: {}", e),
+        })?;
+
+        let mut overrides: HashMap<String, HashMap<DataClass, RetentionPeriod>> = HashMap::new();
+        for (country_code, classes) in table.overrides {
+            overrides.entry(country_code.trim().to_uppercase()).or_default().extend(classes);
+        }
+        Ok(Self { overrides, ..table })
+    }
+
+    /// Set a country's period for one class
+    pub fn with_override(mut self, country_code: &str, data_class: DataClass, period: RetentionPeriod) -> Self {
+        self.overrides.entry(country_code.trim().to_uppercase()).or_default().insert(data_class, period);
+        self
+    }
+
+    /// The country's period for a class, or the default; `None` when the table has neither
+    pub fn retention_period_for(&self, country_code: &str, data_class: DataClass) -> Option<RetentionPeriod> {
+        self.overrides
+            .get(&country_code.trim().to_uppercase())
+            .and_then(|classes| classes.get(&data_class))
+            .or_else(|| self.defaults.get(&data_class))
+            .copied()
+    }
+
+    /// When a record that stopped being relevant at `since` must be deleted
+    pub fn delete_after(
+        &self,
+        country_code: &str,
+        data_class: DataClass,
+        since: DateTime<Utc>
+    ) -> Result<DateTime<Utc>, ApiError> {
+        let period = self.retention_period_for(country_code, data_class).ok_or_else(|| ApiError::InternalServerError {
+            message: format!("No retention period for {:?} in {}", data_class, country_code),
+        })?;
+        period.after(since).ok_or_else(|| ApiError::InternalServerError {
+            message: format!("Retention period {:?} from {} is out of range", period, since),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_country_overrides_fall_back_to_defaults() {
+        let table = RetentionTable::default().with_override("pt", DataClass::AccessLogs, RetentionPeriod::Months(12));
+
+        assert_eq!(table.retention_period_for("DE", DataClass::FinancialRecords), Some(RetentionPeriod::Years(10)));
+        assert_eq!(table.retention_period_for("nl", DataClass::FinancialRecords), Some(RetentionPeriod::Years(7)));
+        assert_eq!(table.retention_period_for("PT", DataClass::AccessLogs), Some(RetentionPeriod::Months(12)));
+        assert_eq!(table.retention_period_for("PT", DataClass::LocationHistory), Some(RetentionPeriod::Days(90)));
+
+        let closed = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        let deletion = table.delete_after("FR", DataClass::AccountProfile, closed).unwrap();
+        assert_eq!(deletion, Utc.with_ymd_and_hms(2025, 1, 31, 0, 0, 0).unwrap());
+        // Calendar years, across the 2028 and 2032 leap days
+        let deletion = table.delete_after("FR", DataClass::FinancialRecords, closed).unwrap();
+        assert_eq!(deletion, Utc.with_ymd_and_hms(2035, 1, 1, 0, 0, 0).unwrap());
+
+        let configured = RetentionTable::from_json(
+            r#"{
+                "defaults": { "ACCESS_LOGS": { "days": 30 } },
+                "overrides": { "pt": { "ACCESS_LOGS": { "months": 6 } } }
+            }"#
+        ).unwrap();
+        assert_eq!(configured.retention_period_for("GB", DataClass::AccessLogs), Some(RetentionPeriod::Days(30)));
+        assert_eq!(configured.retention_period_for("PT", DataClass::AccessLogs), Some(RetentionPeriod::Months(6)));
+        assert!(configured.delete_after("GB", DataClass::KycRecords, closed).is_err());
+        assert!(RetentionTable::from_json(r#"{ "defaults": { "ACCESS_LOGS": 30 } }"#).is_err(), "unit is required");
+    }
+}
@@ -0,0 +1,307 @@
+//! `/debug/cache`, `/debug/config` and `/debug/stats` routes for on-call inspection, compiled out by
+//! the `no_web` feature
+//!
+//! All routes require the internal API key in `X-Internal-API-Key`. Configuration is captured
+//! through `Serialize` when registered, so `SecretString` fields come out as `[REDACTED]`:
+//!
+//! ```ignore
+//! let debug = DebugRegistry::new(internal_api_key)
+//!     .cache("geolocation", geolocation_service.clone())
+//!     .config("geolocation", &geolocation_config)
+//!     .counters("errors", error_counters.clone());
+//! rocket::build().manage(debug).mount("/", debug_routes::routes())
+//! ```
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use rocket::http::Status;
+use rocket::request::{ FromRequest, Outcome, Request };
+use rocket::{ get, routes, serde::json::Json, Route, State };
+use rocket_okapi::okapi::schemars::JsonSchema;
+use rocket_okapi::okapi::schemars::{ self };
+use serde::Serialize;
+use serde_json::Value;
+use tracing::error;
+
+use crate::common_lib::build_info::BuildInfo;
+use crate::common_lib::constants::X_INTERNAL_API_KEY;
+use crate::common_lib::error::ApiError;
+#[cfg(not(feature = "no_geo"))]
+use crate::common_lib::geolocation::GeolocationService;
+use crate::common_lib::logging::RequestId;
+use crate::common_lib::secret::SecretKeySet;
+use crate::common_lib::windowed_metrics::{ WindowedCount, WindowedCounters };
+use crate::log_security;
+
+pub type StatsFuture<'a> = Pin<Box<dyn Future<Output = Value> + Send + 'a>>;
+
+/// Anything that can report cache statistics as JSON
+///
+/// Implemented for `GeolocationService` and for closures, e.g. `move || json!({ "len": cache.len() })`.
+pub trait CacheStatsSource: Send + Sync {
+    fn cache_stats(&self) -> StatsFuture<'_>;
+}
+
+impl<F: Fn() -> Value + Send + Sync> CacheStatsSource for F {
+    fn cache_stats(&self) -> StatsFuture<'_> {
+        let stats = self();
+        Box::pin(async move { stats })
+    }
+}
+
+#[cfg(not(feature = "no_geo"))]
+impl CacheStatsSource for GeolocationService {
+    fn cache_stats(&self) -> StatsFuture<'_> {
+        Box::pin(async move { serde_json::to_value(self.get_cache_stats().await).unwrap_or_default() })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugCacheReport {
+    pub caches: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugConfigReport {
+    pub build: BuildInfo,
+    /// Cargo features common-lib declares and whether each is enabled; the `no_*` ones compile
+    /// base functionality out
+    pub features: BTreeMap<String, bool>,
+    pub config: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugStatsReport {
+    pub window_seconds: u64,
+    pub counters: BTreeMap<String, Vec<WindowedCount>>,
+}
+
+/// Window of `/debug/stats` when the caller doesn't pass `window_seconds`
+pub const DEFAULT_STATS_WINDOW_SECONDS: u64 = 300;
+
+/// Sources shown by the debug routes and the key protecting them, managed by Rocket
+pub struct DebugRegistry {
+    api_key: SecretKeySet,
+    caches: Vec<(String, Arc<dyn CacheStatsSource>)>,
+    config: BTreeMap<String, Value>,
+    counters: Vec<(String, Arc<WindowedCounters>)>,
+}
+
+impl DebugRegistry {
+    pub fn new(api_key: impl Into<SecretKeySet>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            caches: Vec::new(),
+            config: BTreeMap::new(),
+            counters: Vec::new(),
+        }
+    }
+
+    pub fn cache(mut self, name: &str, source: Arc<dyn CacheStatsSource>) -> Self {
+        self.caches.push((name.to_string(), source));
+        self
+    }
+
+    /// Snapshot of a configuration section; secrets must be `SecretString` to be redacted
+    pub fn config(mut self, name: &str, config: &impl Serialize) -> Self {
+        let value = serde_json::to_value(config).unwrap_or_else(|e| Value::String(format!("unserializable: {}", e)));
+        self.config.insert(name.to_string(), value);
+        self
+    }
+
+    pub fn counters(mut self, name: &str, counters: Arc<WindowedCounters>) -> Self {
+        self.counters.push((name.to_string(), counters));
+        self
+    }
+
+    pub async fn cache_report(&self) -> DebugCacheReport {
+        let mut caches = BTreeMap::new();
+        for (name, source) in &self.caches {
+            caches.insert(name.clone(), source.cache_stats().await);
+        }
+        DebugCacheReport { caches }
+    }
+
+    pub fn config_report(&self) -> DebugConfigReport {
+        DebugConfigReport {
+            build: BuildInfo::current(),
+            features: compiled_features(),
+            config: self.config.clone(),
+        }
+    }
+
+    pub fn stats_report(&self, window: Duration) -> DebugStatsReport {
+        DebugStatsReport {
+            window_seconds: window.as_secs(),
+            counters: self.counters
+                .iter()
+                .map(|(name, counters)| (name.clone(), counters.snapshot(window)))
+                .collect(),
+        }
+    }
+}
+
+/// Every feature the crate declares, each name spelled once so the report can't drift from the
+/// `cfg!` it reads
+macro_rules! compiled_features {
+    ($($feature:literal),* $(,)?) => {
+        BTreeMap::from([$(($feature.to_string(), cfg!(feature = $feature))),*])
+    };
+}
+
+fn compiled_features() -> BTreeMap<String, bool> {
+    compiled_features![
+        "no_web",
+        "no_aws",
+        "no_mongo",
+        "no_http",
+        "no_geo",
+        "no_phone",
+        "geoip_db",
+        "redis",
+        "axum",
+        "graphql",
+        "grpc",
+        "lambda",
+        "request_signing",
+        "breach_check",
+        "text",
+        "contact_matching",
+        "binary_formats",
+        "test_support",
+        "benchmarks",
+        "test_containers",
+    ]
+}
+
+/// Request guard admitting callers with the internal API key
+pub struct DebugAccess;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DebugAccess {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(registry) = request.rocket().state::<DebugRegistry>() else {
+            error!("DEBUG:access [CONFIG] DebugRegistry is not managed by Rocket");
+            return Outcome::Error((
+                Status::InternalServerError,
+                ApiError::InternalServerError {
+                    message: "Debug routes are not configured".to_string(),
+                },
+            ));
+        };
+
+        let provided = request.headers().get_one(X_INTERNAL_API_KEY).unwrap_or_default();
+        if !provided.is_empty() && registry.api_key.matches(provided, chrono::Utc::now()) {
+            return Outcome::Success(DebugAccess);
+        }
+
+        let req_id = request.guard::<RequestId>().await.succeeded().unwrap_or_default();
+        log_security!(warn, "debug_access", "REJECTED", req_id, "Debug route called without a valid key - path: {}", request.uri());
+        let error = ApiError::Unauthorized { message: "Invalid internal API key".to_string() };
+        Outcome::Error((error.http_status(), error))
+    }
+}
+
+#[get("/debug/cache")]
+pub async fn debug_cache(_access: DebugAccess, registry: &State<DebugRegistry>) -> Json<DebugCacheReport> {
+    Json(registry.cache_report().await)
+}
+
+#[get("/debug/config")]
+pub fn debug_config(_access: DebugAccess, registry: &State<DebugRegistry>) -> Json<DebugConfigReport> {
+    Json(registry.config_report())
+}
+
+#[get("/debug/stats?<window_seconds>")]
+pub fn debug_stats(
+    _access: DebugAccess,
+    registry: &State<DebugRegistry>,
+    window_seconds: Option<u64>
+) -> Json<DebugStatsReport> {
+    let window = Duration::from_secs(window_seconds.unwrap_or(DEFAULT_STATS_WINDOW_SECONDS));
+    Json(registry.stats_report(window))
+}
+
+/// Routes to mount, e.g. `rocket.mount("/", debug_routes::routes())`
+pub fn routes() -> Vec<Route> {
+    routes![debug_cache, debug_config, debug_stats]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::Header;
+    use crate::common_lib::secret::SecretString;
+    use crate::common_lib::test_client::{ test_client, test_rocket };
+
+    #[derive(Serialize)]
+    struct ProviderConfig {
+        url: String,
+        api_key: SecretString,
+    }
+
+    #[rocket::async_test]
+    async fn test_debug_routes_require_key_and_redact_secrets() {
+        let registry = DebugRegistry::new(SecretString::from("internal-key"))
+            .cache("templates", Arc::new(|| serde_json::json!({ "entries": 3 })))
+            .config("provider", &ProviderConfig {
+                url: "https://provider.example".to_string(),
+                api_key: SecretString::from("sk_live_123"),
+            });
+        let client = test_client(test_rocket(routes()).manage(registry)).await;
+
+        assert_eq!(client.get("/debug/config").dispatch().await.status(), Status::Unauthorized);
+        let response = client
+            .get("/debug/config")
+            .header(Header::new(X_INTERNAL_API_KEY, "wrong"))
+            .dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let config: Value = client
+            .get("/debug/config")
+            .header(Header::new(X_INTERNAL_API_KEY, "internal-key"))
+            .dispatch().await
+            .into_json().await
+            .unwrap();
+        assert_eq!(config["config"]["provider"]["api_key"], "[REDACTED]");
+        assert_eq!(config["features"]["no_web"], false);
+        assert_eq!(config["features"].as_object().unwrap().len(), 20);
+
+        let caches: Value = client
+            .get("/debug/cache")
+            .header(Header::new(X_INTERNAL_API_KEY, "internal-key"))
+            .dispatch().await
+            .into_json().await
+            .unwrap();
+        assert_eq!(caches["caches"]["templates"]["entries"], 3);
+    }
+
+    #[rocket::async_test]
+    async fn test_debug_stats_reports_windowed_counters() {
+        let errors = Arc::new(WindowedCounters::new(Duration::from_secs(10), Duration::from_secs(900)));
+        errors.record_many("GEO:get_location", 3);
+        errors.record("SMS:send");
+        let registry = DebugRegistry::new(SecretString::from("internal-key")).counters("errors", errors);
+        let client = test_client(test_rocket(routes()).manage(registry)).await;
+
+        assert_eq!(client.get("/debug/stats").dispatch().await.status(), Status::Unauthorized);
+        let stats: Value = client
+            .get("/debug/stats?window_seconds=60")
+            .header(Header::new(X_INTERNAL_API_KEY, "internal-key"))
+            .dispatch().await
+            .into_json().await
+            .unwrap();
+        assert_eq!(stats["windowSeconds"], 60);
+        assert_eq!(stats["counters"]["errors"][0]["key"], "GEO:get_location");
+        assert_eq!(stats["counters"]["errors"][0]["count"], 3);
+        assert_eq!(stats["counters"]["errors"][1]["key"], "SMS:send");
+    }
+}
@@ -0,0 +1,282 @@
+//! Delta-sync protocol for mobile offline sync, compiled out by the `no_mongo` feature
+//!
+//! A client sends the `SyncToken` from its previous response and receives everything changed since:
+//!
+//! ```ignore
+//! let token = SyncToken::decode_or_initial(params.token.as_deref(), "contacts")?;
+//! let records = collection
+//!     .find(change_feed_filter(&token), change_feed_options(limit))
+//!     .await?
+//!     .try_collect::<Vec<Contact>>()
+//!     .await?;
+//! Ok(Json(SyncResponse::from_page(&token, records, limit)))
+//! ```
+//!
+//! Collections must maintain `updated_at` on every write (including soft deletes) and set
+//! `deleted_at` instead of removing documents, with an index on `{ updated_at: 1, _id: 1 }`.
+
+use chrono::{ DateTime, TimeZone, Utc };
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{ self, doc, Bson, Document };
+use mongodb::options::FindOptions;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
+use serde::{ Deserialize, Serialize };
+
+use crate::common_lib::conflict_resolution::VersionVector;
+use crate::common_lib::error::ApiError;
+
+pub const ID_FIELD: &str = "_id";
+pub const UPDATED_AT_FIELD: &str = "updated_at";
+pub const DELETED_AT_FIELD: &str = "deleted_at";
+
+/// A synced document: stable ID, last modification time and soft-delete marker
+pub trait Versioned {
+    fn sync_id(&self) -> String;
+
+    fn updated_at(&self) -> DateTime<Utc>;
+
+    fn deleted_at(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    /// Per-device edit counters used by `conflict_resolution`; empty for documents that are never merged
+    fn version_vector(&self) -> VersionVector {
+        VersionVector::default()
+    }
+
+    /// Store the vector `conflict_resolution` computed for a merged document
+    /// Documents that override `version_vector` must override this too.
+    fn set_version_vector(&mut self, _version_vector: VersionVector) {}
+}
+
+/// High-water mark of what a client has seen in one collection
+///
+/// Changes are ordered by `(updated_at, _id)`; `last_id` breaks ties between documents
+/// updated in the same millisecond so pages never skip or repeat records.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncToken {
+    #[serde(rename = "c")]
+    pub collection: String,
+    #[serde(rename = "t")]
+    pub updated_at_millis: i64,
+    #[serde(rename = "i")]
+    pub last_id: Option<String>,
+}
+
+impl SyncToken {
+    /// Token for a client that has never synced
+    pub fn initial(collection: &str) -> Self {
+        Self {
+            collection: collection.to_string(),
+            updated_at_millis: 0,
+            last_id: None,
+        }
+    }
+
+    /// Opaque string form for clients
+    pub fn encode(&self) -> String {
+        hex::encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    /// Parse a client token, rejecting tokens issued for another collection
+    pub fn decode(token: &str, collection: &str) -> Result<Self, ApiError> {
+        let invalid = || ApiError::BadRequest {
+            message: "Invalid sync token".to_string(),
+        };
+
+        let bytes = hex::decode(token.trim()).map_err(|_| invalid())?;
+        let decoded: Self = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+
+        if decoded.collection != collection {
+            return Err(ApiError::BadRequest {
+                message: format!("Sync token was issued for '{}', not '{}'", decoded.collection, collection),
+            });
+        }
+
+        Ok(decoded)
+    }
+
+    /// Decode the client's token, or start from the beginning when it has none
+    pub fn decode_or_initial(token: Option<&str>, collection: &str) -> Result<Self, ApiError> {
+        match token.filter(|token| !token.trim().is_empty()) {
+            Some(token) => Self::decode(token, collection),
+            None => Ok(Self::initial(collection)),
+        }
+    }
+
+    pub fn updated_at(&self) -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(self.updated_at_millis).single().unwrap_or_default()
+    }
+}
+
+/// Filter for documents changed after the token's high-water mark
+pub fn change_feed_filter(token: &SyncToken) -> Document {
+    let updated_at = bson::DateTime::from_millis(token.updated_at_millis);
+
+    match &token.last_id {
+        Some(last_id) => {
+            // Collections keyed by ObjectId compare against ObjectId, others against the raw string
+            let last_id = ObjectId::parse_str(last_id)
+                .map(Bson::ObjectId)
+                .unwrap_or_else(|_| Bson::String(last_id.clone()));
+
+            doc! {
+                "$or": [
+                    { UPDATED_AT_FIELD: { "$gt": updated_at } },
+                    { UPDATED_AT_FIELD: updated_at, ID_FIELD: { "$gt": last_id } },
+                ]
+            }
+        }
+        // Nothing at the mark itself has been seen yet
+        None => doc! { UPDATED_AT_FIELD: { "$gte": updated_at } },
+    }
+}
+
+/// Page size actually used for a requested limit; a page of zero records would never advance the token
+pub fn page_limit(limit: usize) -> usize {
+    limit.max(1)
+}
+
+/// Change feed ordering; fetches one extra record so `SyncResponse::from_page` can report `has_more`
+pub fn change_feed_options(limit: usize) -> FindOptions {
+    FindOptions::builder()
+        .sort(doc! { UPDATED_AT_FIELD: 1, ID_FIELD: 1 })
+        .limit((page_limit(limit) as i64) + 1)
+        .build()
+}
+
+/// A record deleted since the client's last sync
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Tombstone {
+    pub id: String,
+    #[cfg_attr(not(feature = "no_web"), schemars(with = "String"))]
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// One page of changes: records to insert or replace, records to delete, and the next token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct SyncResponse<T> {
+    pub upserts: Vec<T>,
+    pub tombstones: Vec<Tombstone>,
+    pub next_token: String,
+    /// The client should request again immediately with `next_token`
+    pub has_more: bool,
+}
+
+impl<T: Versioned> SyncResponse<T> {
+    /// Split a change feed page (fetched with `change_feed_options(limit)`) into upserts and tombstones
+    pub fn from_page(token: &SyncToken, mut records: Vec<T>, limit: usize) -> Self {
+        let limit = page_limit(limit);
+        let has_more = records.len() > limit;
+        records.truncate(limit);
+
+        let next_token = match records.last() {
+            Some(last) =>
+                SyncToken {
+                    collection: token.collection.clone(),
+                    updated_at_millis: last.updated_at().timestamp_millis(),
+                    last_id: Some(last.sync_id()),
+                },
+            None => token.clone(),
+        };
+
+        let mut upserts = Vec::new();
+        let mut tombstones = Vec::new();
+        for record in records {
+            match record.deleted_at() {
+                Some(deleted_at) => tombstones.push(Tombstone { id: record.sync_id(), deleted_at }),
+                None => upserts.push(record),
+            }
+        }
+
+        Self {
+            upserts,
+            tombstones,
+            next_token: next_token.encode(),
+            has_more,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Contact {
+        id: String,
+        updated_at: DateTime<Utc>,
+        deleted_at: Option<DateTime<Utc>>,
+    }
+
+    impl Versioned for Contact {
+        fn sync_id(&self) -> String {
+            self.id.clone()
+        }
+
+        fn updated_at(&self) -> DateTime<Utc> {
+            self.updated_at
+        }
+
+        fn deleted_at(&self) -> Option<DateTime<Utc>> {
+            self.deleted_at
+        }
+    }
+
+    #[test]
+    fn test_sync_token_round_trip() {
+        let token = SyncToken {
+            collection: "contacts".to_string(),
+            updated_at_millis: 1_700_000_000_000,
+            last_id: Some("65a1f0c2e4b0a1b2c3d4e5f6".to_string()),
+        };
+
+        assert_eq!(SyncToken::decode(&token.encode(), "contacts").unwrap(), token);
+        assert!(SyncToken::decode(&token.encode(), "messages").is_err());
+        assert!(SyncToken::decode("not-a-token", "contacts").is_err());
+        assert_eq!(SyncToken::decode_or_initial(None, "contacts").unwrap(), SyncToken::initial("contacts"));
+
+        let filter = change_feed_filter(&token);
+        assert_eq!(filter.get_array("$or").unwrap().len(), 2);
+        let initial = change_feed_filter(&SyncToken::initial("contacts"));
+        assert!(initial.get_document(UPDATED_AT_FIELD).unwrap().contains_key("$gte"));
+    }
+
+    #[test]
+    fn test_sync_response_from_page() {
+        let now = Utc::now();
+        let contact = |id: &str, deleted: bool| Contact {
+            id: id.to_string(),
+            updated_at: now,
+            deleted_at: deleted.then_some(now),
+        };
+        let token = SyncToken::initial("contacts");
+
+        let records = vec![contact("a", false), contact("b", true), contact("c", false)];
+
+        let page = SyncResponse::from_page(&token, records, 2);
+        assert!(page.has_more);
+        assert_eq!(page.upserts.len(), 1);
+        assert_eq!(page.tombstones, vec![Tombstone { id: "b".to_string(), deleted_at: now }]);
+
+        let next = SyncToken::decode(&page.next_token, "contacts").unwrap();
+        assert_eq!(next.last_id.as_deref(), Some("b"));
+        assert_eq!(next.updated_at_millis, now.timestamp_millis());
+
+        let empty = SyncResponse::<Contact>::from_page(&next, Vec::new(), 2);
+        assert!(!empty.has_more);
+        assert_eq!(empty.next_token, page.next_token);
+
+        // A zero limit still advances, so clients following `has_more` can't loop forever
+        let single = SyncResponse::from_page(&token, vec![contact("a", false), contact("b", false)], 0);
+        assert_eq!(single.upserts.len(), 1);
+        assert_ne!(single.next_token, token.encode());
+        assert_eq!(change_feed_options(0).limit, Some(2));
+    }
+}
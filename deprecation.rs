@@ -0,0 +1,211 @@
+//! Deprecation and sunset headers for old routes, compiled out by the `no_web` feature
+//!
+//! Register each deprecated route once; the fairing adds `Deprecation` (RFC 9745), `Sunset`
+//! (RFC 8594) and a `successor-version` `Link` to its responses and counts the calls, so the
+//! usage can be checked before the route is removed:
+//!
+//! ```ignore
+//! let deprecations = Arc::new(
+//!     DeprecationRegistry::new().route(
+//!         DeprecatedRoute::new(Method::Get, "/v1/users/<id>", announced)
+//!             .sunset(sunset)
+//!             .replacement("/v2/users/{id}")
+//!     )
+//! );
+//! rocket::build().attach(deprecations.clone()).manage(deprecations)
+//! ```
+
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use chrono::{ DateTime, Utc };
+use rocket::fairing::{ Fairing, Info, Kind };
+use rocket::http::{ Header, Method };
+use rocket::request::Request;
+use rocket::response::Response;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use rocket_okapi::okapi::schemars::{ self };
+use serde::Serialize;
+use tracing::warn;
+
+use crate::common_lib::clock::{ system_clock, Clock };
+
+/// Deprecation metadata of one route
+#[derive(Debug, Clone)]
+pub struct DeprecatedRoute {
+    pub method: Method,
+    /// Route URI as mounted, e.g. "/v1/users/<id>"
+    pub path: String,
+    /// When the deprecation was announced
+    pub announced: DateTime<Utc>,
+    /// When the route stops being served
+    pub sunset: Option<DateTime<Utc>>,
+    /// Where clients should move to
+    pub replacement: Option<String>,
+}
+
+impl DeprecatedRoute {
+    pub fn new(method: Method, path: &str, announced: DateTime<Utc>) -> Self {
+        Self {
+            method,
+            path: path.to_string(),
+            announced,
+            sunset: None,
+            replacement: None,
+        }
+    }
+
+    pub fn sunset(mut self, sunset: DateTime<Utc>) -> Self {
+        self.sunset = Some(sunset);
+        self
+    }
+
+    pub fn replacement(mut self, replacement: &str) -> Self {
+        self.replacement = Some(replacement.to_string());
+        self
+    }
+
+    /// Response headers announcing the deprecation
+    pub fn headers(&self) -> Vec<Header<'static>> {
+        let mut headers = vec![Header::new("Deprecation", format!("@{}", self.announced.timestamp()))];
+        if let Some(sunset) = self.sunset {
+            headers.push(Header::new("Sunset", sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string()));
+        }
+        if let Some(replacement) = &self.replacement {
+            headers.push(Header::new("Link", format!("<{}>; rel=\"successor-version\"", replacement)));
+        }
+        headers
+    }
+}
+
+/// Calls to a deprecated route since startup
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeprecatedRouteUsage {
+    pub method: String,
+    pub path: String,
+    #[schemars(with = "Option<String>")]
+    pub sunset: Option<DateTime<Utc>>,
+    pub calls: u64,
+}
+
+struct Entry {
+    route: DeprecatedRoute,
+    calls: AtomicU64,
+}
+
+/// Deprecated routes of a service; attach it (behind an `Arc`) as a fairing
+pub struct DeprecationRegistry {
+    entries: Vec<Entry>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for DeprecationRegistry {
+    fn default() -> Self {
+        Self::with_clock(system_clock())
+    }
+}
+
+impl DeprecationRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry using the given clock to detect calls past the sunset date
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self { entries: Vec::new(), clock }
+    }
+
+    pub fn route(mut self, route: DeprecatedRoute) -> Self {
+        self.entries.push(Entry { route, calls: AtomicU64::new(0) });
+        self
+    }
+
+    fn find(&self, method: Method, path: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.route.method == method && entry.route.path == path)
+    }
+
+    /// Call counts per deprecated route, e.g. for an internal metrics endpoint
+    pub fn usage(&self) -> Vec<DeprecatedRouteUsage> {
+        self.entries
+            .iter()
+            .map(|entry| DeprecatedRouteUsage {
+                method: entry.route.method.to_string(),
+                path: entry.route.path.clone(),
+                sunset: entry.route.sunset,
+                calls: entry.calls.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for DeprecationRegistry {
+    fn info(&self) -> Info {
+        Info { name: "API deprecation headers", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(route) = request.route() else {
+            return;
+        };
+        let Some(entry) = self.find(route.method, route.uri.as_str()) else {
+            return;
+        };
+
+        entry.calls.fetch_add(1, Ordering::Relaxed);
+        if entry.route.sunset.is_some_and(|sunset| sunset <= self.clock.now_utc()) {
+            warn!(
+                "DEPRECATION:on_response [PAST_SUNSET] Route called after its sunset date - method: {}, path: {}",
+                route.method,
+                entry.route.path
+            );
+        }
+
+        for header in entry.route.headers() {
+            response.set_header(header);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rocket::get;
+    use crate::common_lib::test_client::{ test_client, test_rocket };
+
+    #[get("/v1/users/<id>")]
+    fn user_v1(id: u32) -> String {
+        id.to_string()
+    }
+
+    #[get("/v2/users/<id>")]
+    fn user_v2(id: u32) -> String {
+        id.to_string()
+    }
+
+    #[rocket::async_test]
+    async fn test_deprecated_route_headers_and_usage() {
+        let announced = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let sunset = Utc.with_ymd_and_hms(2099, 7, 1, 0, 0, 0).unwrap();
+        let registry = Arc::new(
+            DeprecationRegistry::new().route(
+                DeprecatedRoute::new(Method::Get, "/v1/users/<id>", announced)
+                    .sunset(sunset)
+                    .replacement("/v2/users/{id}")
+            )
+        );
+        let client = test_client(test_rocket(rocket::routes![user_v1, user_v2]).attach(registry.clone())).await;
+
+        let response = client.get("/v1/users/7").dispatch().await;
+        assert_eq!(response.headers().get_one("Deprecation"), Some("@1767225600"));
+        assert_eq!(response.headers().get_one("Sunset"), Some("Wed, 01 Jul 2099 00:00:00 GMT"));
+        assert_eq!(response.headers().get_one("Link"), Some("</v2/users/{id}>; rel=\"successor-version\""));
+
+        let current = client.get("/v2/users/7").dispatch().await;
+        assert!(current.headers().get_one("Deprecation").is_none());
+
+        client.get("/v1/users/8").dispatch().await;
+        assert_eq!(registry.usage()[0].calls, 2);
+    }
+}
@@ -0,0 +1,319 @@
+//! Cached DNS resolution for outbound requests, e.g. webhook delivery and SSRF checks
+//!
+//! Answers are cached for `ttl_seconds` and failures for `negative_ttl_seconds`, so repeated
+//! deliveries to the same host don't re-resolve and a dead host isn't hammered. When a lookup
+//! fails after an answer expired, the old answer is served for up to `stale_ttl_seconds` more.
+//! Concurrent lookups are capped by `max_concurrent_lookups`:
+//!
+//! ```ignore
+//! let resolver = DnsResolver::new(DnsCacheConfig::default());
+//! let addresses = resolver.resolve_public(webhook_url.host_str().unwrap_or_default()).await?;
+//! ```
+
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::time::{ Duration, Instant };
+use serde::{ Deserialize, Serialize };
+use tokio::sync::{ Mutex, Semaphore };
+use tracing::warn;
+
+use crate::common_lib::clock::{ system_clock, Clock };
+use crate::common_lib::error::ApiError;
+#[cfg(not(feature = "no_geo"))]
+use crate::common_lib::geolocation::is_public_ip;
+use crate::common_lib::lru_cache::LruCache;
+
+pub type LookupFuture<'a> = Pin<Box<dyn Future<Output = io::Result<Vec<IpAddr>>> + Send + 'a>>;
+
+/// Source of DNS answers
+pub trait DnsLookup: Send + Sync {
+    fn lookup<'a>(&'a self, host: &'a str) -> LookupFuture<'a>;
+}
+
+/// The operating system resolver, through tokio
+pub struct SystemDnsLookup;
+
+impl DnsLookup for SystemDnsLookup {
+    fn lookup<'a>(&'a self, host: &'a str) -> LookupFuture<'a> {
+        Box::pin(async move {
+            let addresses = tokio::net::lookup_host((host, 0)).await?;
+            Ok(addresses.map(|address| address.ip()).collect())
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsCacheConfig {
+    pub ttl_seconds: u64,
+    pub negative_ttl_seconds: u64,
+    /// How long past `ttl_seconds` an answer may still be served when lookups fail
+    pub stale_ttl_seconds: u64,
+    pub capacity: usize,
+    pub max_concurrent_lookups: usize,
+    pub timeout_seconds: u64,
+}
+
+impl Default for DnsCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_seconds: 60,
+            negative_ttl_seconds: 10,
+            stale_ttl_seconds: 300,
+            capacity: 1024,
+            max_concurrent_lookups: 16,
+            timeout_seconds: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DnsCacheStats {
+    pub hits: u64,
+    pub negative_hits: u64,
+    pub lookups: u64,
+    pub failures: u64,
+    pub stale_served: u64,
+}
+
+struct DnsCacheEntry {
+    /// Addresses, or the lookup error
+    answer: Result<Vec<IpAddr>, String>,
+    resolved_at: Instant,
+}
+
+#[derive(Default)]
+struct DnsCounters {
+    hits: AtomicU64,
+    negative_hits: AtomicU64,
+    lookups: AtomicU64,
+    failures: AtomicU64,
+    stale_served: AtomicU64,
+}
+
+pub struct DnsResolver {
+    config: DnsCacheConfig,
+    lookup: Arc<dyn DnsLookup>,
+    cache: Mutex<LruCache<String, DnsCacheEntry>>,
+    permits: Semaphore,
+    clock: Arc<dyn Clock>,
+    counters: DnsCounters,
+}
+
+fn lookup_error(host: &str, message: &str) -> ApiError {
+    ApiError::InternalServerError {
+        message: format!("DNS lookup failed for '{}': {}", host, message),
+    }
+}
+
+impl DnsResolver {
+    pub fn new(config: DnsCacheConfig) -> Self {
+        Self::with_lookup(config, Arc::new(SystemDnsLookup), system_clock())
+    }
+
+    /// Resolver using the given lookup and clock, e.g. fakes in tests
+    pub fn with_lookup(config: DnsCacheConfig, lookup: Arc<dyn DnsLookup>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            cache: Mutex::new(LruCache::new(config.capacity.max(1))),
+            permits: Semaphore::new(config.max_concurrent_lookups.max(1)),
+            lookup,
+            clock,
+            counters: DnsCounters::default(),
+            config,
+        }
+    }
+
+    /// Addresses of a host; IP literals, bracketed or not, are returned without a lookup
+    ///
+    /// IPv4-mapped IPv6 addresses (`::ffff:10.0.0.1`), literal or resolved, come back as IPv4.
+    pub async fn resolve(&self, host: &str) -> Result<Vec<IpAddr>, ApiError> {
+        let host = host.trim();
+        let unbracketed = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+        if let Ok(ip) = unbracketed.parse::<IpAddr>() {
+            return Ok(vec![ip.to_canonical()]);
+        }
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        if host.is_empty() {
+            return Err(ApiError::BadRequest { message: "Host name is empty".to_string() });
+        }
+
+        let ttl = Duration::from_secs(self.config.ttl_seconds);
+        let negative_ttl = Duration::from_secs(self.config.negative_ttl_seconds);
+        let mut stale = None;
+
+        if let Some(entry) = self.cache.lock().await.get(&host) {
+            let age = self.clock.now().duration_since(entry.resolved_at);
+            match &entry.answer {
+                Ok(addresses) if age < ttl => {
+                    self.counters.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(addresses.clone());
+                }
+                Ok(addresses) if age < ttl + Duration::from_secs(self.config.stale_ttl_seconds) => {
+                    stale = Some(addresses.clone());
+                }
+                Err(message) if age < negative_ttl => {
+                    self.counters.negative_hits.fetch_add(1, Ordering::Relaxed);
+                    return Err(lookup_error(&host, message));
+                }
+                _ => {}
+            }
+        }
+
+        let answer = self.lookup_uncached(&host).await;
+        match (answer, stale) {
+            (Ok(addresses), _) => {
+                self.store(&host, Ok(addresses.clone())).await;
+                Ok(addresses)
+            }
+            (Err(message), Some(addresses)) => {
+                warn!("DNS:resolve [STALE] Lookup failed, serving expired answer - host: {}, error: {}", host, message);
+                self.counters.stale_served.fetch_add(1, Ordering::Relaxed);
+                Ok(addresses)
+            }
+            (Err(message), None) => {
+                self.store(&host, Err(message.clone())).await;
+                Err(lookup_error(&host, &message))
+            }
+        }
+    }
+
+    /// Addresses of a host that may be called from inside the network; fails if any address
+    /// isn't public (see `is_public_ip`), so a public name pointing at a private address can't
+    /// reach internal services
+    #[cfg(not(feature = "no_geo"))]
+    pub async fn resolve_public(&self, host: &str) -> Result<Vec<IpAddr>, ApiError> {
+        let addresses = self.resolve(host).await?;
+        if let Some(blocked) = addresses.iter().find(|ip| !is_public_ip(ip)) {
+            return Err(ApiError::BadRequest {
+                message: format!("Host '{}' resolves to non-public address {}", host, blocked),
+            });
+        }
+        Ok(addresses)
+    }
+
+    async fn lookup_uncached(&self, host: &str) -> Result<Vec<IpAddr>, String> {
+        let timeout = Duration::from_secs(self.config.timeout_seconds);
+        self.counters.lookups.fetch_add(1, Ordering::Relaxed);
+
+        // Waiting for a permit counts towards the timeout
+        let answer = tokio::time::timeout(timeout, async {
+            let _permit = self.permits.acquire().await.map_err(|e| e.to_string())?;
+            self.lookup.lookup(host).await.map_err(|e| e.to_string())
+        }).await;
+
+        let answer = match answer {
+            Ok(Ok(addresses)) if addresses.is_empty() => Err("no addresses".to_string()),
+            Ok(Ok(addresses)) => Ok(addresses.iter().map(IpAddr::to_canonical).collect()),
+            Ok(Err(message)) => Err(message),
+            Err(_) => Err(format!("timed out after {}s", timeout.as_secs())),
+        };
+        if answer.is_err() {
+            self.counters.failures.fetch_add(1, Ordering::Relaxed);
+        }
+        answer
+    }
+
+    async fn store(&self, host: &str, answer: Result<Vec<IpAddr>, String>) {
+        let entry = DnsCacheEntry { answer, resolved_at: self.clock.now() };
+        self.cache.lock().await.insert(host.to_string(), entry);
+    }
+
+    /// Drop a cached answer, e.g. after connecting to its addresses failed
+    pub async fn invalidate(&self, host: &str) {
+        let host = host.trim().trim_end_matches('.').to_ascii_lowercase();
+        self.cache.lock().await.remove(&host);
+    }
+
+    pub fn stats(&self) -> DnsCacheStats {
+        DnsCacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            negative_hits: self.counters.negative_hits.load(Ordering::Relaxed),
+            lookups: self.counters.lookups.load(Ordering::Relaxed),
+            failures: self.counters.failures.load(Ordering::Relaxed),
+            stale_served: self.counters.stale_served.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use crate::common_lib::clock::MockClock;
+
+    struct FakeLookup {
+        failing: AtomicBool,
+    }
+
+    impl DnsLookup for FakeLookup {
+        fn lookup<'a>(&'a self, host: &'a str) -> LookupFuture<'a> {
+            let answer = match (host, self.failing.load(Ordering::SeqCst)) {
+                (_, true) => Err(io::Error::other("SERVFAIL")),
+                ("hooks.example.com", _) => Ok(vec!["93.184.216.34".parse().unwrap()]),
+                ("internal.example.com", _) => Ok(vec!["10.0.0.5".parse().unwrap()]),
+                ("mapped.example.com", _) => Ok(vec!["::ffff:10.0.0.5".parse().unwrap()]),
+                _ => Err(io::Error::new(io::ErrorKind::NotFound, "NXDOMAIN")),
+            };
+            Box::pin(std::future::ready(answer))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_caching_and_graceful_failures() {
+        let lookup = Arc::new(FakeLookup { failing: AtomicBool::new(false) });
+        let clock = Arc::new(MockClock::default());
+        let resolver = DnsResolver::with_lookup(DnsCacheConfig::default(), lookup.clone(), clock.clone());
+
+        assert_eq!(resolver.resolve("[::1]").await.unwrap(), vec!["::1".parse::<IpAddr>().unwrap()]);
+        let addresses = resolver.resolve("hooks.example.com").await.unwrap();
+        assert_eq!(resolver.resolve("Hooks.Example.com.").await.unwrap(), addresses);
+        assert!(resolver.resolve("missing.example.com").await.is_err());
+        assert!(resolver.resolve("missing.example.com").await.is_err());
+        #[cfg(not(feature = "no_geo"))]
+        assert!(resolver.resolve_public("internal.example.com").await.is_err());
+
+        // Expired answers are served while the resolver fails
+        lookup.failing.store(true, Ordering::SeqCst);
+        clock.advance(Duration::from_secs(120));
+        assert_eq!(resolver.resolve("hooks.example.com").await.unwrap(), addresses);
+        clock.advance(Duration::from_secs(300));
+        assert!(resolver.resolve("hooks.example.com").await.is_err());
+
+        let stats = resolver.stats();
+        assert_eq!((stats.hits, stats.negative_hits, stats.stale_served), (1, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_literal_and_mapped_addresses() {
+        let lookup = Arc::new(FakeLookup { failing: AtomicBool::new(false) });
+        let resolver = DnsResolver::with_lookup(DnsCacheConfig::default(), lookup, Arc::new(MockClock::default()));
+        let ten: IpAddr = "10.0.0.5".parse().unwrap();
+
+        assert_eq!(resolver.resolve("[::ffff:10.0.0.5]").await.unwrap(), vec![ten]);
+        assert_eq!(resolver.resolve("::ffff:10.0.0.5").await.unwrap(), vec![ten]);
+        assert_eq!(resolver.resolve("mapped.example.com").await.unwrap(), vec![ten]);
+        assert!(resolver.resolve("[[::1]]").await.is_err(), "not a literal, nor a host name");
+
+        #[cfg(not(feature = "no_geo"))]
+        {
+            for host in [
+                "[::ffff:10.0.0.5]",
+                "[::ffff:169.254.169.254]",
+                "169.254.169.254",
+                "[64:ff9b::a00:5]",
+                "[::1]",
+                "0.0.0.0",
+                "198.51.100.7",
+                "224.0.0.1",
+                "mapped.example.com",
+            ] {
+                assert!(resolver.resolve_public(host).await.is_err(), "{} should be rejected", host);
+            }
+            assert!(resolver.resolve_public("93.184.216.34").await.is_ok());
+            assert!(resolver.resolve_public("hooks.example.com").await.is_ok());
+        }
+    }
+}
@@ -0,0 +1,457 @@
+//! Email deliverability checks before sending OTP emails
+//!
+//! `EmailValidator` rejects addresses with invalid syntax, on disposable-mail domains, or on
+//! domains without a mail server. The disposable list is a newline-separated file of domains
+//! (the format of the public disposable-email-domains lists), embedded or loaded from S3 and
+//! refreshed in the background. Mail servers are looked up through an `MxLookup`; `DohMxLookup`
+//! (left out by the `no_http` feature) asks a DNS-over-HTTPS resolver. A failed lookup lets the
+//! address through rather than blocking registration on a DNS outage:
+//!
+//! ```ignore
+//! let validator = Arc::new(
+//!     EmailValidator::new(EmailValidatorConfig::default()).await?
+//!         .with_mx_lookup(Arc::new(DohMxLookup::new(client)))
+//! );
+//! validator.spawn_refresh();
+//! let email = validator.validate(&request.email, &req_id).await?;
+//! ```
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(not(feature = "no_http"))]
+use reqwest::Client;
+#[cfg(not(feature = "no_http"))]
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::RwLock;
+use tracing::{ error, info, warn };
+
+use crate::common_lib::error::ApiError;
+#[cfg(not(feature = "no_http"))]
+use crate::common_lib::secret::redact_uris;
+#[cfg(not(feature = "no_aws"))]
+use crate::common_lib::utils::download_file_from_s3;
+
+pub type MxFuture<'a> = Pin<Box<dyn Future<Output = Result<bool, ApiError>> + Send + 'a>>;
+
+/// Well-known throwaway domains, used until a full list is configured
+pub const DEFAULT_DISPOSABLE_DOMAINS: &[&str] = &[
+    "10minutemail.com",
+    "dispostable.com",
+    "getnada.com",
+    "guerrillamail.com",
+    "mailinator.com",
+    "maildrop.cc",
+    "sharklasers.com",
+    "temp-mail.org",
+    "trashmail.com",
+    "yopmail.com",
+];
+
+/// Whether a domain accepts mail
+pub trait MxLookup: Send + Sync {
+    /// `false` when the domain doesn't exist, has no MX or address records, or publishes a
+    /// null MX (RFC 7505); errors mean the answer is unknown
+    fn accepts_mail<'a>(&'a self, domain: &'a str) -> MxFuture<'a>;
+}
+
+/// Where the disposable domain list is loaded from
+#[derive(Debug, Clone)]
+pub enum DisposableDomainSource {
+    Embedded(Vec<String>),
+    /// A newline-separated list; blank lines and `#` comments are skipped
+    #[cfg(not(feature = "no_aws"))]
+    S3 {
+        bucket: String,
+        key: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailValidatorConfig {
+    pub disposable_domains: DisposableDomainSource,
+    pub refresh_interval_seconds: u64,
+}
+
+impl Default for EmailValidatorConfig {
+    fn default() -> Self {
+        Self {
+            disposable_domains: DisposableDomainSource::Embedded(
+                DEFAULT_DISPOSABLE_DOMAINS.iter().map(|domain| domain.to_string()).collect()
+            ),
+            refresh_interval_seconds: 3600,
+        }
+    }
+}
+
+/// Outcome of checking an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EmailVerdict {
+    Deliverable,
+    InvalidSyntax,
+    Disposable,
+    /// The domain has no mail server
+    Undeliverable,
+}
+
+fn valid_local_part(local: &str) -> bool {
+    const SPECIALS: &str = "!#$%&'*+/=?^_`{|}~.-";
+
+    !local.is_empty() &&
+        local.len() <= 64 &&
+        !local.starts_with('.') &&
+        !local.ends_with('.') &&
+        !local.contains("..") &&
+        local.chars().all(|c| c.is_ascii_alphanumeric() || SPECIALS.contains(c))
+}
+
+fn valid_domain(domain: &str) -> bool {
+    let labels: Vec<&str> = domain.split('.').collect();
+    let tld = labels.last().copied().unwrap_or_default();
+
+    domain.len() <= 253 &&
+        labels.len() >= 2 &&
+        labels.iter().all(|label| {
+            !label.is_empty() &&
+                label.len() <= 63 &&
+                !label.starts_with('-') &&
+                !label.ends_with('-') &&
+                label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        }) &&
+        (tld.starts_with("xn--") || (tld.len() >= 2 && tld.chars().all(|c| c.is_ascii_alphabetic())))
+}
+
+/// Trimmed address with a lowercase domain, if the syntax is valid (ASCII addresses only)
+pub fn normalize_email_syntax(email: &str) -> Option<String> {
+    let email = email.trim();
+    let (local, domain) = email.rsplit_once('@')?;
+    let domain = domain.to_ascii_lowercase();
+
+    (valid_local_part(local) && valid_domain(&domain)).then(|| format!("{}@{}", local, domain))
+}
+
+fn parse_domain_list(list: &str) -> HashSet<String> {
+    list.lines()
+        .map(|line| line.trim().trim_end_matches('.').to_ascii_lowercase())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// Syntax, disposable-domain and mail server checks for registration emails
+pub struct EmailValidator {
+    config: EmailValidatorConfig,
+    disposable: RwLock<Arc<HashSet<String>>>,
+    mx_lookup: Option<Arc<dyn MxLookup>>,
+}
+
+impl EmailValidator {
+    /// Create the validator and load the disposable domain list
+    pub async fn new(config: EmailValidatorConfig) -> Result<Self, ApiError> {
+        let disposable = Self::load_domains(&config.disposable_domains).await?;
+
+        Ok(Self {
+            config,
+            disposable: RwLock::new(Arc::new(disposable)),
+            mx_lookup: None,
+        })
+    }
+
+    /// Check that domains accept mail; without a lookup the check is skipped
+    pub fn with_mx_lookup(mut self, mx_lookup: Arc<dyn MxLookup>) -> Self {
+        self.mx_lookup = Some(mx_lookup);
+        self
+    }
+
+    async fn load_domains(source: &DisposableDomainSource) -> Result<HashSet<String>, ApiError> {
+        match source {
+            DisposableDomainSource::Embedded(domains) => Ok(parse_domain_list(&domains.join("\n"))),
+            #[cfg(not(feature = "no_aws"))]
+            DisposableDomainSource::S3 { bucket, key } => {
+                let list = download_file_from_s3(bucket, key).await.map_err(|e| {
+                    error!("EMAIL_VALIDATION:load [S3_ERROR] Failed to download domain list - key: {}, error: {}", key, e);
+                    ApiError::InternalServerError {
+                        message: format!("Failed to download disposable domain list: {e}"),
+                    }
+                })?;
+                Ok(parse_domain_list(&list))
+            }
+        }
+    }
+
+    /// Reload the disposable domain list, keeping the current one if loading fails
+    pub async fn reload(&self) -> Result<(), ApiError> {
+        let domains = Self::load_domains(&self.config.disposable_domains).await?;
+        info!("EMAIL_VALIDATION:reload [LOADED] Disposable domains reloaded - count: {}", domains.len());
+        *self.disposable.write().await = Arc::new(domains);
+        Ok(())
+    }
+
+    /// Spawn a background task that periodically reloads the disposable domain list
+    pub fn spawn_refresh(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let validator = Arc::clone(self);
+        let interval = Duration::from_secs(validator.config.refresh_interval_seconds.max(1));
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // The first tick completes immediately and the list is already loaded
+            ticker.tick().await;
+
+            loop {
+                ticker.tick().await;
+                if let Err(e) = validator.reload().await {
+                    error!("EMAIL_VALIDATION:refresh [RELOAD_ERROR] Keeping previous list - error: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Whether the domain or one of its parents is on the disposable list
+    pub async fn is_disposable(&self, domain: &str) -> bool {
+        let disposable = Arc::clone(&*self.disposable.read().await);
+        let domain = domain.to_ascii_lowercase();
+        let mut candidate = domain.as_str();
+
+        loop {
+            if disposable.contains(candidate) {
+                return true;
+            }
+            match candidate.split_once('.') {
+                Some((_, parent)) if parent.contains('.') => candidate = parent,
+                _ => return false,
+            }
+        }
+    }
+
+    pub async fn check(&self, email: &str, req_id: &str) -> EmailVerdict {
+        let Some(normalized) = normalize_email_syntax(email) else {
+            return EmailVerdict::InvalidSyntax;
+        };
+        let domain = normalized.rsplit_once('@').map(|(_, domain)| domain).unwrap_or_default();
+
+        if self.is_disposable(domain).await {
+            return EmailVerdict::Disposable;
+        }
+        if let Some(mx_lookup) = &self.mx_lookup {
+            match mx_lookup.accepts_mail(domain).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    return EmailVerdict::Undeliverable;
+                }
+                Err(e) => {
+                    warn!(
+                        "EMAIL_VALIDATION:check [MX_ERROR] [req_id:{}] Mail server lookup failed, accepting - domain: {}, error: {}",
+                        req_id,
+                        domain,
+                        e
+                    );
+                }
+            }
+        }
+
+        EmailVerdict::Deliverable
+    }
+
+    /// Normalized address, or `BadRequest` explaining why it can't be used
+    pub async fn validate(&self, email: &str, req_id: &str) -> Result<String, ApiError> {
+        let message = match self.check(email, req_id).await {
+            EmailVerdict::Deliverable => {
+                return normalize_email_syntax(email).ok_or_else(|| ApiError::BadRequest {
+                    message: "Invalid email address".to_string(),
+                });
+            }
+            EmailVerdict::InvalidSyntax => "Invalid email address",
+            EmailVerdict::Disposable => "Disposable email addresses are not allowed",
+            EmailVerdict::Undeliverable => "This email domain does not accept mail",
+        };
+
+        Err(ApiError::BadRequest { message: message.to_string() })
+    }
+}
+
+/// DNS-over-HTTPS JSON answer (RFC 8484 JSON flavour used by Cloudflare and Google)
+#[cfg(not(feature = "no_http"))]
+#[derive(Debug, Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Status")]
+    status: u32,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[cfg(not(feature = "no_http"))]
+#[derive(Debug, Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    data: String,
+}
+
+/// MX lookups through a DNS-over-HTTPS resolver, compiled out by the `no_http` feature
+#[cfg(not(feature = "no_http"))]
+pub struct DohMxLookup {
+    client: Arc<Client>,
+    base_url: String,
+}
+
+#[cfg(not(feature = "no_http"))]
+impl DohMxLookup {
+    pub const DEFAULT_URL: &'static str = "https://cloudflare-dns.com/dns-query";
+    const NOERROR: u32 = 0;
+    const NXDOMAIN: u32 = 3;
+    const TYPE_A: u16 = 1;
+    const TYPE_MX: u16 = 15;
+    const TYPE_AAAA: u16 = 28;
+
+    pub fn new(client: Arc<Client>) -> Self {
+        Self::with_base_url(client, Self::DEFAULT_URL)
+    }
+
+    pub fn with_base_url(client: Arc<Client>, base_url: &str) -> Self {
+        Self { client, base_url: base_url.trim_end_matches('/').to_string() }
+    }
+
+    /// Answer for a NOERROR or NXDOMAIN response; any other status (SERVFAIL, REFUSED, ...) means
+    /// the resolver couldn't tell, which is an error rather than a missing record
+    async fn query(&self, domain: &str, record_type: &str) -> Result<DohResponse, ApiError> {
+        let response: DohResponse = self.client
+            .get(&self.base_url)
+            .query(&[("name", domain), ("type", record_type)])
+            .header("Accept", "application/dns-json")
+            .timeout(Duration::from_secs(5))
+            .send().await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ApiError::InternalServerError {
+                message: format!("DNS-over-HTTPS request failed: {}", redact_uris(&e.to_string())),
+            })?
+            .json().await
+            .map_err(|e| ApiError::InternalServerError {
+                message: format!("Failed to parse DNS-over-HTTPS response: {}", redact_uris(&e.to_string())),
+            })?;
+
+        if response.status != Self::NOERROR && response.status != Self::NXDOMAIN {
+            return Err(ApiError::InternalServerError {
+                message: format!(
+                    "DNS-over-HTTPS {} lookup for {} failed with status {}",
+                    record_type,
+                    domain,
+                    response.status
+                ),
+            });
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(not(feature = "no_http"))]
+impl MxLookup for DohMxLookup {
+    fn accepts_mail<'a>(&'a self, domain: &'a str) -> MxFuture<'a> {
+        Box::pin(async move {
+            let mx = self.query(domain, "MX").await?;
+            if mx.status == Self::NXDOMAIN {
+                return Ok(false);
+            }
+            let exchanges: Vec<&str> = mx.answer
+                .iter()
+                .filter(|answer| answer.record_type == Self::TYPE_MX)
+                .filter_map(|answer| answer.data.split_whitespace().nth(1))
+                .collect();
+            if !exchanges.is_empty() {
+                // Null MX: "0 ." means the domain accepts no mail
+                return Ok(exchanges.iter().any(|exchange| *exchange != "."));
+            }
+
+            // Without MX records, mail goes to the domain's own address (RFC 5321 implicit MX)
+            for (record_type, type_code) in [("A", Self::TYPE_A), ("AAAA", Self::TYPE_AAAA)] {
+                let address = self.query(domain, record_type).await?;
+                if address.answer.iter().any(|answer| answer.record_type == type_code) {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeMx;
+
+    impl MxLookup for FakeMx {
+        fn accepts_mail<'a>(&'a self, domain: &'a str) -> MxFuture<'a> {
+            Box::pin(async move {
+                match domain {
+                    "example.com" => Ok(true),
+                    "flaky.example" => Err(ApiError::InternalServerError { message: "timeout".to_string() }),
+                    _ => Ok(false),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_email_checks() {
+        let config = EmailValidatorConfig {
+            disposable_domains: DisposableDomainSource::Embedded(vec!["# throwaway".to_string(), "Mailinator.com".to_string()]),
+            ..EmailValidatorConfig::default()
+        };
+        let validator = EmailValidator::new(config).await.unwrap().with_mx_lookup(Arc::new(FakeMx));
+
+        assert_eq!(validator.validate(" Ana.Silva+otp@Example.COM ", "req").await.unwrap(), "Ana.Silva+otp@example.com");
+        for (email, verdict) in [
+            ("ana@", EmailVerdict::InvalidSyntax),
+            ("ana..silva@example.com", EmailVerdict::InvalidSyntax),
+            ("ana@localhost", EmailVerdict::InvalidSyntax),
+            ("ana@-bad.com", EmailVerdict::InvalidSyntax),
+            ("ana@mailinator.com", EmailVerdict::Disposable),
+            ("ana@eu.mailinator.com", EmailVerdict::Disposable),
+            ("ana@no-mail.org", EmailVerdict::Undeliverable),
+            ("ana@flaky.example", EmailVerdict::Deliverable),
+        ] {
+            assert_eq!(validator.check(email, "req").await, verdict, "email: {email}");
+        }
+    }
+
+    #[cfg(not(feature = "no_http"))]
+    #[tokio::test]
+    async fn test_doh_lookup_statuses_and_implicit_mx() {
+        use serde_json::json;
+        use wiremock::matchers::query_param;
+        use wiremock::{ Mock, MockServer, ResponseTemplate };
+
+        let server = MockServer::start().await;
+        let answer = |name: &str, record_type: &str, body: serde_json::Value| {
+            Mock::given(query_param("name", name))
+                .and(query_param("type", record_type))
+                .respond_with(ResponseTemplate::new(200).set_body_json(body))
+        };
+        let no_answer = json!({ "Status": 0 });
+        for mock in [
+            answer("mx.example", "MX", json!({ "Status": 0, "Answer": [{ "type": 15, "data": "10 mail.mx.example." }] })),
+            answer("null-mx.example", "MX", json!({ "Status": 0, "Answer": [{ "type": 15, "data": "0 ." }] })),
+            answer("missing.example", "MX", json!({ "Status": 3 })),
+            answer("servfail.example", "MX", json!({ "Status": 2 })),
+            answer("v6.example", "MX", no_answer.clone()),
+            answer("v6.example", "A", no_answer.clone()),
+            answer("v6.example", "AAAA", json!({ "Status": 0, "Answer": [{ "type": 28, "data": "2001:db8::25" }] })),
+            answer("bare.example", "MX", no_answer.clone()),
+            answer("bare.example", "A", no_answer.clone()),
+            answer("bare.example", "AAAA", no_answer),
+        ] {
+            mock.mount(&server).await;
+        }
+        let lookup = DohMxLookup::with_base_url(Arc::new(Client::new()), &server.uri());
+
+        assert!(lookup.accepts_mail("mx.example").await.unwrap());
+        assert!(!lookup.accepts_mail("null-mx.example").await.unwrap());
+        assert!(!lookup.accepts_mail("missing.example").await.unwrap());
+        assert!(lookup.accepts_mail("servfail.example").await.is_err(), "SERVFAIL is unknown, not undeliverable");
+        assert!(lookup.accepts_mail("v6.example").await.unwrap(), "implicit MX through AAAA");
+        assert!(!lookup.accepts_mail("bare.example").await.unwrap());
+    }
+}
@@ -1,22 +1,36 @@
+#[cfg(not(feature = "no_web"))]
 use rocket::{
     http::{ ContentType, Status },
     request::Request,
     response::{ self, Responder, Response },
 };
+#[cfg(not(feature = "no_web"))]
 use rocket_okapi::{
     r#gen::OpenApiGenerator,
     okapi::openapi3::Responses,
     response::OpenApiResponderInner,
     OpenApiError,
 };
+#[cfg(not(feature = "no_web"))]
 use rocket_okapi::okapi::schemars::Map;
+#[cfg(feature = "axum")]
+use axum::{ http::{ header, StatusCode }, response::IntoResponse };
 use serde::{ Deserialize, Serialize };
-use serde_json::json;
+use serde_json::{ json, Value };
 use std::{ error::Error, fmt::{ Display, Formatter } };
+#[cfg(not(feature = "no_web"))]
 use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
 use rocket_okapi::okapi::schemars::{ self };
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+/// Framework-agnostic name for `ApiError`
+///
+/// The enum itself has no web framework dependency; the Rocket responder is compiled out by the
+/// `no_web` feature and the axum one is enabled by the `axum` feature.
+pub type CoreError = ApiError;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
 #[serde(tag = "type", content = "details")]
 pub enum ApiError {
     NotFound {
@@ -31,6 +45,13 @@ pub enum ApiError {
     Unauthorized {
         message: String,
     },
+    Forbidden {
+        message: String,
+    },
+    /// 451: the feature cannot legally be offered in the client's jurisdiction
+    UnavailableForLegalReasons {
+        message: String,
+    },
     PaymentRequired {
         message: String,
     },
@@ -46,18 +67,35 @@ pub enum ApiError {
         reason: String,
         suggested_action: String,
     },
+    /// 429: too many attempts; the client may retry after the given delay
+    TooManyRequests {
+        message: String,
+        retry_after_seconds: u64,
+    },
+    /// 426: the client app is older than the minimum supported version for its platform
+    #[serde(rename = "UPGRADE_REQUIRED")] UpgradeRequired {
+        message: String,
+        platform: String,
+        minimum_version: String,
+        update_url: Option<String>,
+    },
 }
 
 impl ApiError {
+    #[cfg(not(feature = "no_web"))]
     pub fn http_status(&self) -> Status {
         match self {
             ApiError::NotFound { .. } => Status::NotFound,
             ApiError::InternalServerError { .. } => Status::InternalServerError,
             ApiError::BadRequest { .. } => Status::BadRequest,
             ApiError::Unauthorized { .. } => Status::Unauthorized,
+            ApiError::Forbidden { .. } => Status::Forbidden,
+            ApiError::UnavailableForLegalReasons { .. } => Status::UnavailableForLegalReasons,
             ApiError::PaymentRequired { .. } => Status::PaymentRequired,
             ApiError::QuotaExceeded { .. } => Status::PaymentRequired,
             ApiError::RegistrationRequired { .. } => Status::PreconditionRequired, // 428
+            ApiError::UpgradeRequired { .. } => Status::UpgradeRequired, // 426
+            ApiError::TooManyRequests { .. } => Status::TooManyRequests, // 429
         }
     }
 
@@ -75,9 +113,47 @@ impl ApiError {
             ApiError::InternalServerError { .. } => 500,
             ApiError::BadRequest { .. } => 400,
             ApiError::Unauthorized { .. } => 401,
+            ApiError::Forbidden { .. } => 403,
+            ApiError::UnavailableForLegalReasons { .. } => 451,
             ApiError::PaymentRequired { .. } => 402,
             ApiError::QuotaExceeded { .. } => 402,
             ApiError::RegistrationRequired { .. } => 428, // 428 Precondition Required
+            ApiError::UpgradeRequired { .. } => 426, // 426 Upgrade Required
+            ApiError::TooManyRequests { .. } => 429, // 429 Too Many Requests
+        }
+    }
+
+    /// Stable machine-readable error type, matching the serialized `type` tag
+    pub fn error_type(&self) -> &'static str {
+        match self {
+            ApiError::NotFound { .. } => "NotFound",
+            ApiError::InternalServerError { .. } => "InternalServerError",
+            ApiError::BadRequest { .. } => "BadRequest",
+            ApiError::Unauthorized { .. } => "Unauthorized",
+            ApiError::Forbidden { .. } => "Forbidden",
+            ApiError::UnavailableForLegalReasons { .. } => "UnavailableForLegalReasons",
+            ApiError::PaymentRequired { .. } => "PaymentRequired",
+            ApiError::QuotaExceeded { .. } => "QuotaExceeded",
+            ApiError::RegistrationRequired { .. } => "REGISTRATION_REQUIRED",
+            ApiError::UpgradeRequired { .. } => "UPGRADE_REQUIRED",
+            ApiError::TooManyRequests { .. } => "TooManyRequests",
+        }
+    }
+
+    /// JSON body returned to clients by every framework adapter
+    pub fn error_body(&self) -> Value {
+        match self {
+            // Clients need these to render the forced-upgrade screen
+            ApiError::UpgradeRequired { platform, minimum_version, update_url, .. } =>
+                json!({
+                    "error": self.to_string(),
+                    "platform": platform,
+                    "minimumVersion": minimum_version,
+                    "updateUrl": update_url,
+                }),
+            ApiError::TooManyRequests { retry_after_seconds, .. } =>
+                json!({ "error": self.to_string(), "retryAfterSeconds": retry_after_seconds }),
+            _ => json!({ "error": self.to_string() }),
         }
     }
 }
@@ -91,6 +167,10 @@ impl Display for ApiError {
             }
             ApiError::BadRequest { message } => { write!(f, "Bad Request Error: {message}") }
             ApiError::Unauthorized { message } => { write!(f, "Unauthorized Error: {message}") }
+            ApiError::Forbidden { message } => { write!(f, "Forbidden: {message}") }
+            ApiError::UnavailableForLegalReasons { message } => {
+                write!(f, "Unavailable For Legal Reasons: {message}")
+            }
             ApiError::PaymentRequired { message } => { write!(f, "Payment Required: {message}") }
             ApiError::QuotaExceeded {
                 resource,
@@ -107,6 +187,8 @@ impl Display for ApiError {
             ApiError::RegistrationRequired { message, reason, suggested_action } => {
                 write!(f, "Registration Required: {message} - {reason} - {suggested_action}")
             }
+            ApiError::UpgradeRequired { message, .. } => { write!(f, "Upgrade Required: {message}") }
+            ApiError::TooManyRequests { message, .. } => { write!(f, "Too Many Requests: {message}") }
         }
     }
 }
@@ -136,6 +218,7 @@ impl From<Box<dyn std::error::Error>> for ApiError {
     }
 }
 
+#[cfg(not(feature = "no_web"))]
 impl OpenApiResponderInner for ApiError {
     fn responses(_generator: &mut OpenApiGenerator) -> Result<Responses, OpenApiError> {
         use rocket_okapi::okapi::openapi3::{ RefOr, Response as OpenApiResponse };
@@ -161,6 +244,16 @@ impl OpenApiResponderInner for ApiError {
                 ..Default::default()
             })
         );
+        responses.insert(
+            "403".to_string(),
+            RefOr::Object(OpenApiResponse {
+                description: "\
+                # [403 Forbidden](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/403)\n\
+                This response is given when you are not allowed to access this resource.\
+                ".to_string(),
+                ..Default::default()
+            })
+        );
         responses.insert(
             "404".to_string(),
             RefOr::Object(OpenApiResponse {
@@ -181,6 +274,36 @@ impl OpenApiResponderInner for ApiError {
                 ..Default::default()
             })
         );
+        responses.insert(
+            "426".to_string(),
+            RefOr::Object(OpenApiResponse {
+                description: "\
+                # [426 Upgrade Required](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/426)\n\
+                This response is given when your app version is no longer supported.\
+                ".to_string(),
+                ..Default::default()
+            })
+        );
+        responses.insert(
+            "429".to_string(),
+            RefOr::Object(OpenApiResponse {
+                description: "\
+                # [429 Too Many Requests](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/429)\n\
+                This response is given when too many attempts were made; retry after the `Retry-After` delay.\
+                ".to_string(),
+                ..Default::default()
+            })
+        );
+        responses.insert(
+            "451".to_string(),
+            RefOr::Object(OpenApiResponse {
+                description: "\
+                # [451 Unavailable For Legal Reasons](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/451)\n\
+                This response is given when the feature is not offered in your country.\
+                ".to_string(),
+                ..Default::default()
+            })
+        );
         responses.insert(
             "500".to_string(),
             RefOr::Object(OpenApiResponse {
@@ -198,17 +321,41 @@ impl OpenApiResponderInner for ApiError {
     }
 }
 
+#[cfg(not(feature = "no_web"))]
 impl<'r> Responder<'r, 'static> for ApiError {
     fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
         let status_code = self.http_status();
-        let error_response = json!({ "error": self.to_string() });
-        let body = serde_json::to_string(&error_response).unwrap();
+        let body = self.error_body().to_string();
 
-        Response::build()
+        let mut response = Response::build();
+        response
             .sized_body(body.len(), std::io::Cursor::new(body))
             .header(ContentType::JSON)
-            .status(status_code)
-            .ok()
+            .status(status_code);
+        if let ApiError::TooManyRequests { retry_after_seconds, .. } = &self {
+            response.raw_header("Retry-After", retry_after_seconds.to_string());
+        }
+        response.ok()
+    }
+}
+
+#[cfg(feature = "axum")]
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        let status_code = StatusCode::from_u16(self.status_code()).unwrap_or(
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        let body = self.error_body().to_string();
+        let retry_after = match &self {
+            ApiError::TooManyRequests { retry_after_seconds, .. } => Some(*retry_after_seconds),
+            _ => None,
+        };
+
+        let mut response = (status_code, [(header::CONTENT_TYPE, "application/json")], body).into_response();
+        if let Some(retry_after) = retry_after {
+            response.headers_mut().insert(header::RETRY_AFTER, retry_after.into());
+        }
+        response
     }
 }
 
@@ -218,3 +365,25 @@ impl From<String> for ApiError {
         ApiError::InternalServerError { message: format!("Generic conversion error: {message}") }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_body() {
+        let error = ApiError::NotFound { message: "user".to_string() };
+
+        assert_eq!(error.error_body(), json!({ "error": "Not Found: user" }));
+        assert_eq!(serde_json::to_value(&error).unwrap()["type"], error.error_type());
+    }
+
+    #[cfg(feature = "axum")]
+    #[test]
+    fn test_axum_response() {
+        let response = ApiError::registration_required("send sparks").into_response();
+
+        assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    }
+}
@@ -0,0 +1,174 @@
+//! Precise-location consent for geolocation results
+//! Users who have not opted in only ever receive country-level locations.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+#[cfg(feature = "redis")]
+use redis::AsyncCommands;
+use tokio::sync::RwLock;
+use tracing::{ debug, warn };
+
+use crate::common_lib::error::ApiError;
+use crate::common_lib::geolocation::{ GeolocationService, LocationInfo };
+
+pub type ConsentFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, ApiError>> + Send + 'a>>;
+
+/// Storage for per-user precise location opt-in
+pub trait LocationConsentStore: Send + Sync {
+    fn has_precise_consent<'a>(&'a self, user_id: &'a str) -> ConsentFuture<'a, bool>;
+    fn set_precise_consent<'a>(&'a self, user_id: &'a str, granted: bool) -> ConsentFuture<'a, ()>;
+}
+
+/// In-process consent store for tests and single-instance tools
+#[derive(Default)]
+pub struct InMemoryConsentStore {
+    consents: RwLock<HashMap<String, bool>>,
+}
+
+impl LocationConsentStore for InMemoryConsentStore {
+    fn has_precise_consent<'a>(&'a self, user_id: &'a str) -> ConsentFuture<'a, bool> {
+        Box::pin(async move { Ok(self.consents.read().await.get(user_id).copied().unwrap_or(false)) })
+    }
+
+    fn set_precise_consent<'a>(&'a self, user_id: &'a str, granted: bool) -> ConsentFuture<'a, ()> {
+        Box::pin(async move {
+            self.consents.write().await.insert(user_id.to_string(), granted);
+            Ok(())
+        })
+    }
+}
+
+/// Consent store shared across instances through Redis, enabled with the `redis` feature
+#[cfg(feature = "redis")]
+pub struct RedisConsentStore {
+    connection: redis::aio::MultiplexedConnection,
+}
+
+#[cfg(feature = "redis")]
+impl RedisConsentStore {
+    const KEY_PREFIX: &'static str = "geo_consent:";
+
+    pub async fn connect(redis_url: &str) -> Result<Self, ApiError> {
+        let client = redis::Client::open(redis_url).map_err(Self::redis_error)?;
+        let connection = client.get_multiplexed_async_connection().await.map_err(Self::redis_error)?;
+
+        Ok(Self { connection })
+    }
+
+    fn key(user_id: &str) -> String {
+        format!("{}{}", Self::KEY_PREFIX, user_id)
+    }
+
+    fn redis_error(e: redis::RedisError) -> ApiError {
+        ApiError::InternalServerError {
+            message: format!("Consent store error: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+impl LocationConsentStore for RedisConsentStore {
+    fn has_precise_consent<'a>(&'a self, user_id: &'a str) -> ConsentFuture<'a, bool> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let value: Option<String> = connection
+                .get(Self::key(user_id)).await
+                .map_err(Self::redis_error)?;
+
+            Ok(value.as_deref() == Some("1"))
+        })
+    }
+
+    fn set_precise_consent<'a>(&'a self, user_id: &'a str, granted: bool) -> ConsentFuture<'a, ()> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let value = if granted { "1" } else { "0" };
+
+            connection
+                .set::<_, _, ()>(Self::key(user_id), value).await
+                .map_err(Self::redis_error)
+        })
+    }
+}
+
+/// Geolocation lookups that respect the user's precise location consent
+pub struct ConsentAwareGeolocation {
+    service: Arc<GeolocationService>,
+    store: Arc<dyn LocationConsentStore>,
+}
+
+impl ConsentAwareGeolocation {
+    pub fn new(service: Arc<GeolocationService>, store: Arc<dyn LocationConsentStore>) -> Self {
+        Self { service, store }
+    }
+
+    /// Location for a user's request, reduced to country level without consent
+    /// If the consent store is unavailable the result is degraded rather than failing the request.
+    pub async fn get_location_for_user(
+        &self,
+        user_id: &str,
+        ip_address: &str
+    ) -> Result<LocationInfo, ApiError> {
+        let location = self.service.get_location(ip_address).await?;
+
+        let has_consent = match self.store.has_precise_consent(user_id).await {
+            Ok(has_consent) => has_consent,
+            Err(e) => {
+                warn!(
+                    "GEO:consent [STORE_ERROR] Consent lookup failed, degrading to country - user_id: {}, error: {}",
+                    user_id,
+                    e
+                );
+                false
+            }
+        };
+
+        if has_consent {
+            return Ok(location);
+        }
+
+        debug!("GEO:consent [DEGRADED] No precise location consent - user_id: {}", user_id);
+        Ok(location.country_level())
+    }
+
+    pub async fn set_precise_consent(&self, user_id: &str, granted: bool) -> Result<(), ApiError> {
+        self.store.set_precise_consent(user_id, granted).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+    use crate::common_lib::geolocation::GeolocationConfig;
+
+    #[tokio::test]
+    async fn test_location_degraded_without_consent() {
+        let service = Arc::new(GeolocationService::new(Arc::new(Client::new()), GeolocationConfig::default()).unwrap());
+        service.cache_location("203.0.113.7", &LocationInfo {
+            country_code: "PT".to_string(),
+            country_name: "Portugal".to_string(),
+            continent_code: Some("EU".to_string()),
+            city: Some("Lisbon".to_string()),
+            region: Some("Lisboa".to_string()),
+            latitude: Some(38.72),
+            longitude: Some(-9.14),
+            timezone: Some("Europe/Lisbon".to_string()),
+            asn: None,
+            isp: None,
+            organization: None,
+        }).await;
+        let geo = ConsentAwareGeolocation::new(service, Arc::new(InMemoryConsentStore::default()));
+
+        let degraded = geo.get_location_for_user("user-1", "203.0.113.7").await.unwrap();
+        assert_eq!(degraded.country_code, "PT");
+        assert!(degraded.city.is_none() && degraded.latitude.is_none() && degraded.longitude.is_none());
+        assert_eq!(degraded.timezone.as_deref(), Some("Europe/Lisbon"));
+
+        geo.set_precise_consent("user-1", true).await.unwrap();
+        let precise = geo.get_location_for_user("user-1", "203.0.113.7").await.unwrap();
+        assert_eq!(precise.city.as_deref(), Some("Lisbon"));
+    }
+}
@@ -0,0 +1,162 @@
+//! Coordinates, great-circle distances and radius bounding boxes
+//!
+//! Distances use the haversine formula on a spherical Earth, which is within 0.5% of the
+//! ellipsoidal distance: plenty for "nearby" searches and travel checks, not for surveying.
+
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
+use serde::{ Deserialize, Serialize };
+
+use crate::common_lib::error::ApiError;
+
+/// Mean Earth radius in kilometres
+pub const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// WGS84 coordinates in degrees
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct GeoPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl GeoPoint {
+    /// Validated point; latitude must be within ±90 and longitude within ±180
+    pub fn new(latitude: f64, longitude: f64) -> Result<Self, ApiError> {
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return Err(ApiError::BadRequest {
+                message: format!("Invalid coordinates: {}, {}", latitude, longitude),
+            });
+        }
+
+        Ok(Self { latitude, longitude })
+    }
+
+    /// Great-circle distance in kilometres
+    pub fn distance_km(&self, other: &GeoPoint) -> f64 {
+        let d_lat = (other.latitude - self.latitude).to_radians();
+        let d_lon = (other.longitude - self.longitude).to_radians();
+        let a =
+            (d_lat / 2.0).sin().powi(2) +
+            self.latitude.to_radians().cos() * other.latitude.to_radians().cos() * (d_lon / 2.0).sin().powi(2);
+
+        2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+    }
+
+    pub fn is_within_km(&self, other: &GeoPoint, radius_km: f64) -> bool {
+        self.distance_km(other) <= radius_km
+    }
+
+    /// Smallest box containing every point within `radius_km`, for prefiltering database queries
+    /// before the exact `is_within_km` check
+    pub fn bounding_box(&self, radius_km: f64) -> BoundingBox {
+        let angular_radius = (radius_km / EARTH_RADIUS_KM).to_degrees();
+        let min_latitude = self.latitude - angular_radius;
+        let max_latitude = self.latitude + angular_radius;
+
+        // Boxes reaching a pole span every longitude
+        if min_latitude <= -90.0 || max_latitude >= 90.0 {
+            return BoundingBox {
+                min_latitude: min_latitude.max(-90.0),
+                max_latitude: max_latitude.min(90.0),
+                min_longitude: -180.0,
+                max_longitude: 180.0,
+            };
+        }
+
+        // Widest longitude offset, reached at the latitude where the circle touches its meridians
+        let latitude = self.latitude.to_radians();
+        let delta_longitude = (angular_radius.to_radians().sin() / latitude.cos()).asin().to_degrees();
+
+        BoundingBox {
+            min_latitude,
+            max_latitude,
+            min_longitude: wrap_longitude(self.longitude - delta_longitude),
+            max_longitude: wrap_longitude(self.longitude + delta_longitude),
+        }
+    }
+}
+
+/// Latitude/longitude box; `min_longitude > max_longitude` when it crosses the antimeridian
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct BoundingBox {
+    pub min_latitude: f64,
+    pub max_latitude: f64,
+    pub min_longitude: f64,
+    pub max_longitude: f64,
+}
+
+impl BoundingBox {
+    pub fn crosses_antimeridian(&self) -> bool {
+        self.min_longitude > self.max_longitude
+    }
+
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        let within_latitude = (self.min_latitude..=self.max_latitude).contains(&point.latitude);
+        let within_longitude = if self.crosses_antimeridian() {
+            point.longitude >= self.min_longitude || point.longitude <= self.max_longitude
+        } else {
+            (self.min_longitude..=self.max_longitude).contains(&point.longitude)
+        };
+
+        within_latitude && within_longitude
+    }
+}
+
+fn wrap_longitude(longitude: f64) -> f64 {
+    if longitude > 180.0 {
+        longitude - 360.0
+    } else if longitude < -180.0 {
+        longitude + 360.0
+    } else {
+        longitude
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance() {
+        let lisbon = GeoPoint::new(38.7223, -9.1393).unwrap();
+        let madrid = GeoPoint::new(40.4168, -3.7038).unwrap();
+
+        assert!((lisbon.distance_km(&madrid) - 503.0).abs() < 2.0);
+        assert_eq!(lisbon.distance_km(&lisbon), 0.0);
+        assert!(lisbon.is_within_km(&madrid, 510.0) && !lisbon.is_within_km(&madrid, 500.0));
+        assert!(matches!(GeoPoint::new(0.0, 181.0), Err(ApiError::BadRequest { .. })));
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let lisbon = GeoPoint::new(38.7223, -9.1393).unwrap();
+        let bounds = lisbon.bounding_box(50.0);
+        for bearing in (0..360).step_by(15) {
+            // Points just inside the radius in every direction are inside the box
+            let bearing = (bearing as f64).to_radians();
+            let angular = 49.9 / EARTH_RADIUS_KM;
+            let latitude = lisbon.latitude.to_radians();
+            let point_latitude = (latitude.sin() * angular.cos() + latitude.cos() * angular.sin() * bearing.cos()).asin();
+            let point_longitude = lisbon.longitude.to_radians() +
+                (bearing.sin() * angular.sin() * latitude.cos()).atan2(angular.cos() - latitude.sin() * point_latitude.sin());
+            let point = GeoPoint { latitude: point_latitude.to_degrees(), longitude: point_longitude.to_degrees() };
+
+            assert!(lisbon.is_within_km(&point, 50.0));
+            assert!(bounds.contains(&point), "{point:?} outside {bounds:?}");
+        }
+        assert!(!bounds.contains(&GeoPoint::new(40.4168, -3.7038).unwrap()));
+
+        let fiji = GeoPoint::new(-17.7134, 179.9).unwrap().bounding_box(100.0);
+        assert!(fiji.crosses_antimeridian());
+        assert!(fiji.contains(&GeoPoint::new(-17.7, -179.8).unwrap()));
+
+        let north_pole = GeoPoint::new(89.9, 0.0).unwrap().bounding_box(50.0);
+        assert_eq!((north_pole.max_latitude, north_pole.min_longitude), (90.0, -180.0));
+    }
+}
@@ -0,0 +1,321 @@
+//! Additional IP geolocation providers, compiled out by the `no_geo` feature
+//!
+//! `GeolocationService` asks providers added with `with_provider` in order after MaxMind and
+//! before the ip-api.com fallback, so services without a MaxMind license can run on ipinfo.io
+//! or IP2Location.io instead:
+//!
+//! ```ignore
+//! let service = GeolocationService::new(client.clone(), config)?
+//!     .with_provider(Arc::new(IpInfoProvider::new(client.clone(), ipinfo_token)))
+//!     .with_provider(Arc::new(Ip2LocationProvider::new(client, ip2location_key)));
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use reqwest::{ Client, StatusCode };
+use serde::Deserialize;
+
+use crate::common_lib::error::ApiError;
+use crate::common_lib::geolocation::{ non_empty, parse_asn, ping_url, LocationInfo };
+use crate::common_lib::secret::{ redact_uris, SecretString };
+
+pub type ProviderFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<LocationInfo>, ApiError>> + Send + 'a>>;
+pub type PingFuture<'a> = Pin<Box<dyn Future<Output = Result<(), ApiError>> + Send + 'a>>;
+
+/// An IP geolocation API
+pub trait LocationProvider: Send + Sync {
+    /// Short name for logs and metrics, e.g. "ipinfo"
+    fn name(&self) -> &str;
+
+    /// Location of a normalized public address; `None` when the provider doesn't know it
+    fn lookup<'a>(&'a self, ip_address: &'a str) -> ProviderFuture<'a>;
+
+    /// Reachability check for deep health checks, without using lookup quota
+    fn ping(&self) -> PingFuture<'_>;
+}
+
+fn request_error(provider: &str, e: reqwest::Error) -> ApiError {
+    ApiError::InternalServerError {
+        message: format!("{} request failed: {}", provider, redact_uris(&e.to_string())),
+    }
+}
+
+fn parse_error(provider: &str, e: reqwest::Error) -> ApiError {
+    ApiError::InternalServerError {
+        message: format!("Failed to parse {} response: {}", provider, redact_uris(&e.to_string())),
+    }
+}
+
+/// ipinfo.io response; `bogon` is set for reserved ranges
+#[derive(Debug, Deserialize)]
+struct IpInfoResponse {
+    #[serde(default)]
+    bogon: bool,
+    country: Option<String>,
+    city: Option<String>,
+    region: Option<String>,
+    /// "latitude,longitude"
+    loc: Option<String>,
+    /// "AS15169 Google LLC"
+    org: Option<String>,
+    timezone: Option<String>,
+}
+
+/// ipinfo.io, authenticated with a bearer token
+pub struct IpInfoProvider {
+    client: Arc<Client>,
+    token: SecretString,
+    base_url: String,
+    timeout: Duration,
+}
+
+impl IpInfoProvider {
+    pub const DEFAULT_URL: &'static str = "https://ipinfo.io";
+
+    pub fn new(client: Arc<Client>, token: SecretString) -> Self {
+        Self {
+            client,
+            token,
+            base_url: Self::DEFAULT_URL.to_string(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn to_location(response: IpInfoResponse) -> Option<LocationInfo> {
+        let country_code = response.country.filter(|_| !response.bogon)?;
+        let (latitude, longitude) = response.loc
+            .as_deref()
+            .and_then(|loc| loc.split_once(','))
+            .map(|(latitude, longitude)| (latitude.trim().parse().ok(), longitude.trim().parse().ok()))
+            .unwrap_or((None, None));
+        let org = response.org.and_then(non_empty);
+        // The org field is "AS<number> <name>"
+        let organization = org
+            .as_deref()
+            .map(|org| org.split_once(' ').map_or(org, |(_, name)| name).to_string());
+
+        Some(LocationInfo {
+            // Names need a paid plan; the code is what every consumer keys on
+            country_name: country_code.clone(),
+            continent_code: None,
+            country_code,
+            city: response.city.and_then(non_empty),
+            region: response.region.and_then(non_empty),
+            latitude,
+            longitude,
+            timezone: response.timezone.and_then(non_empty),
+            asn: org.as_deref().and_then(parse_asn),
+            isp: None,
+            organization,
+        })
+    }
+}
+
+impl LocationProvider for IpInfoProvider {
+    fn name(&self) -> &str {
+        "ipinfo"
+    }
+
+    fn lookup<'a>(&'a self, ip_address: &'a str) -> ProviderFuture<'a> {
+        Box::pin(async move {
+            let response = self.client
+                .get(format!("{}/{}/json", self.base_url, ip_address))
+                .bearer_auth(self.token.expose_secret())
+                .timeout(self.timeout)
+                .send().await
+                .map_err(|e| request_error("ipinfo.io", e))?;
+
+            match response.status() {
+                StatusCode::NOT_FOUND => {
+                    return Ok(None);
+                }
+                status if !status.is_success() => {
+                    return Err(ApiError::InternalServerError {
+                        message: format!("ipinfo.io service error: {}", status),
+                    });
+                }
+                _ => {}
+            }
+
+            let response: IpInfoResponse = response.json().await.map_err(|e| parse_error("ipinfo.io", e))?;
+            Ok(Self::to_location(response))
+        })
+    }
+
+    fn ping(&self) -> PingFuture<'_> {
+        Box::pin(ping_url(&self.client, &self.base_url, self.timeout))
+    }
+}
+
+/// IP2Location.io response; failures come back as `{"error": {...}}`
+#[derive(Debug, Deserialize)]
+struct Ip2LocationResponse {
+    error: Option<Ip2LocationError>,
+    country_code: Option<String>,
+    country_name: Option<String>,
+    region_name: Option<String>,
+    city_name: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    /// UTC offset such as "-07:00", not an IANA zone
+    time_zone: Option<String>,
+    asn: Option<String>,
+    #[serde(rename = "as")]
+    as_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Ip2LocationError {
+    error_code: i64,
+    error_message: String,
+}
+
+/// IP2Location.io, authenticated with an API key query parameter
+pub struct Ip2LocationProvider {
+    client: Arc<Client>,
+    api_key: SecretString,
+    base_url: String,
+    timeout: Duration,
+}
+
+impl Ip2LocationProvider {
+    pub const DEFAULT_URL: &'static str = "https://api.ip2location.io";
+
+    pub fn new(client: Arc<Client>, api_key: SecretString) -> Self {
+        Self {
+            client,
+            api_key,
+            base_url: Self::DEFAULT_URL.to_string(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn to_location(response: Ip2LocationResponse) -> Option<LocationInfo> {
+        // "-" marks unknown fields, e.g. for reserved ranges
+        let known = |value: Option<String>| value.filter(|value| value != "-").and_then(non_empty);
+        let country_code = known(response.country_code)?;
+
+        Some(LocationInfo {
+            country_name: known(response.country_name).unwrap_or_else(|| country_code.clone()),
+            continent_code: None,
+            country_code,
+            city: known(response.city_name),
+            region: known(response.region_name),
+            latitude: response.latitude,
+            longitude: response.longitude,
+            timezone: known(response.time_zone).filter(|zone| zone.contains('/')),
+            asn: known(response.asn).and_then(|asn| asn.parse().ok()),
+            isp: None,
+            organization: known(response.as_name),
+        })
+    }
+}
+
+impl LocationProvider for Ip2LocationProvider {
+    fn name(&self) -> &str {
+        "ip2location"
+    }
+
+    fn lookup<'a>(&'a self, ip_address: &'a str) -> ProviderFuture<'a> {
+        Box::pin(async move {
+            let response = self.client
+                .get(format!("{}/", self.base_url))
+                .query(&[("key", self.api_key.expose_secret().as_str()), ("ip", ip_address), ("format", "json")])
+                .timeout(self.timeout)
+                .send().await
+                .map_err(|e| request_error("IP2Location.io", e))?;
+            let status = response.status();
+
+            // Errors come with 4xx statuses and a JSON body explaining them
+            let response: Ip2LocationResponse = response.json().await.map_err(|e| parse_error("IP2Location.io", e))?;
+            if let Some(error) = response.error {
+                return Err(ApiError::InternalServerError {
+                    message: format!(
+                        "IP2Location.io error {} ({}): {}",
+                        error.error_code,
+                        status,
+                        error.error_message
+                    ),
+                });
+            }
+
+            Ok(Self::to_location(response))
+        })
+    }
+
+    fn ping(&self) -> PingFuture<'_> {
+        Box::pin(ping_url(&self.client, &self.base_url, self.timeout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_lib::geolocation::{ GeolocationConfig, GeolocationService };
+    use crate::common_lib::test_http_stubs::{ fixtures, ProviderStubServer };
+
+    #[tokio::test]
+    async fn test_provider_response_mappings() {
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_path("GET", "/ipinfo/8.8.8.8/json", 200, fixtures::ipinfo_lookup("8.8.8.8")).await;
+        stubs.stub_path("GET", "/ipinfo/10.0.0.1/json", 200, serde_json::json!({ "ip": "10.0.0.1", "bogon": true })).await;
+        stubs.stub_path("GET", "/", 200, fixtures::ip2location_lookup("8.8.8.8")).await;
+        let client = Arc::new(Client::new());
+
+        let ipinfo = IpInfoProvider::new(client.clone(), SecretString::from("token")).with_base_url(
+            &format!("{}/ipinfo", stubs.uri())
+        );
+        let location = ipinfo.lookup("8.8.8.8").await.unwrap().unwrap();
+        assert_eq!((location.country_code.as_str(), location.city.as_deref()), ("US", Some("Mountain View")));
+        assert_eq!((location.latitude, location.longitude), (Some(37.4056), Some(-122.0775)));
+        assert_eq!((location.asn, location.organization.as_deref()), (Some(15169), Some("Google LLC")));
+        assert!(ipinfo.lookup("10.0.0.1").await.unwrap().is_none(), "bogon");
+
+        let ip2location = Ip2LocationProvider::new(client, SecretString::from("key")).with_base_url(&stubs.uri());
+        let location = ip2location.lookup("8.8.8.8").await.unwrap().unwrap();
+        assert_eq!(location.country_name, "United States of America");
+        assert_eq!((location.asn, location.timezone), (Some(15169), None));
+    }
+
+    #[tokio::test]
+    async fn test_service_uses_providers_before_fallback() {
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_path("GET", "/ipinfo/8.8.8.8/json", 200, fixtures::ipinfo_lookup("8.8.8.8")).await;
+        stubs.stub_ip_api(200, fixtures::ip_api_success("8.8.8.8")).await;
+        let client = Arc::new(Client::new());
+        let config = GeolocationConfig {
+            api_key: SecretString::default(),
+            ..stubs.geolocation_config()
+        };
+        let ipinfo = IpInfoProvider::new(client.clone(), SecretString::from("token")).with_base_url(
+            &format!("{}/ipinfo", stubs.uri())
+        );
+        let service = GeolocationService::new(client, config).unwrap().with_provider(Arc::new(ipinfo));
+
+        assert_eq!(service.get_location("8.8.8.8").await.unwrap().country_code, "US");
+        assert_eq!(stubs.ip_api_request_count().await, 0);
+        assert_eq!(service.get_cache_stats().await.provider_calls, 1);
+    }
+}
@@ -0,0 +1,177 @@
+//! Impossible travel detection between consecutive user locations, compiled out by the `no_geo` feature
+
+use chrono::{ DateTime, Utc };
+use serde::{ Deserialize, Serialize };
+
+use crate::common_lib::geo_point::GeoPoint;
+use crate::common_lib::geolocation::LocationInfo;
+use crate::log_security;
+
+/// A location observed for a user at a point in time (e.g. at login)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocationPoint {
+    pub timestamp: DateTime<Utc>,
+    pub location: LocationInfo,
+}
+
+/// Thresholds for impossible travel detection
+#[derive(Debug, Clone)]
+pub struct GeoVelocityConfig {
+    /// Speeds above this are physically implausible (commercial flights cruise around 900 km/h)
+    pub max_speed_kmh: f64,
+    /// IP geolocation is imprecise; shorter jumps are never flagged
+    pub min_distance_km: f64,
+    /// Whether impossible travel should require step-up authentication
+    pub require_step_up: bool,
+}
+
+impl Default for GeoVelocityConfig {
+    fn default() -> Self {
+        Self {
+            max_speed_kmh: 1000.0,
+            min_distance_km: 100.0,
+            require_step_up: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum VelocityVerdict {
+    Plausible,
+    ImpossibleTravel,
+    /// One of the points has no coordinates
+    Unknown,
+}
+
+/// Outcome of comparing two location points
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VelocityAssessment {
+    pub verdict: VelocityVerdict,
+    pub distance_km: Option<f64>,
+    pub speed_kmh: Option<f64>,
+    pub requires_step_up: bool,
+}
+
+/// Great-circle distance between two coordinates in kilometres
+pub fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    GeoPoint { latitude: lat1, longitude: lon1 }.distance_km(&GeoPoint { latitude: lat2, longitude: lon2 })
+}
+
+/// Compare two points for a user and flag impossible travel
+/// Flagged travel is logged as a security event so it reaches the security dashboards.
+pub fn check_geo_velocity(
+    previous: &LocationPoint,
+    current: &LocationPoint,
+    config: &GeoVelocityConfig,
+    user_id: &str,
+    req_id: &str
+) -> VelocityAssessment {
+    let (from, to) = match (previous.location.point(), current.location.point()) {
+        (Some(from), Some(to)) => (from, to),
+        _ => {
+            return VelocityAssessment {
+                verdict: VelocityVerdict::Unknown,
+                distance_km: None,
+                speed_kmh: None,
+                requires_step_up: false,
+            };
+        }
+    };
+
+    let distance_km = from.distance_km(&to);
+    let elapsed_hours = ((current.timestamp - previous.timestamp).num_seconds().abs() as f64) / 3600.0;
+    // Simultaneous logins from distant places count as infinitely fast
+    let speed_kmh = if elapsed_hours > 0.0 { distance_km / elapsed_hours } else { f64::INFINITY };
+
+    if distance_km < config.min_distance_km || speed_kmh <= config.max_speed_kmh {
+        return VelocityAssessment {
+            verdict: VelocityVerdict::Plausible,
+            distance_km: Some(distance_km),
+            speed_kmh: Some(speed_kmh),
+            requires_step_up: false,
+        };
+    }
+
+    log_security!(
+        warn,
+        "geo_velocity",
+        "IMPOSSIBLE_TRAVEL",
+        req_id,
+        "user_id: {}, from: {}, to: {}, distance_km: {:.0}, speed_kmh: {:.0}, step_up: {}",
+        user_id,
+        previous.location.country_code,
+        current.location.country_code,
+        distance_km,
+        speed_kmh,
+        config.require_step_up
+    );
+
+    VelocityAssessment {
+        verdict: VelocityVerdict::ImpossibleTravel,
+        distance_km: Some(distance_km),
+        speed_kmh: Some(speed_kmh),
+        requires_step_up: config.require_step_up,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn point(minutes_ago: i64, latitude: f64, longitude: f64) -> LocationPoint {
+        LocationPoint {
+            timestamp: Utc::now() - Duration::minutes(minutes_ago),
+            location: LocationInfo {
+                country_code: "XX".to_string(),
+                country_name: "Test".to_string(),
+                continent_code: None,
+                city: None,
+                region: None,
+                latitude: Some(latitude),
+                longitude: Some(longitude),
+                timezone: None,
+                asn: None,
+                isp: None,
+                organization: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_geo_velocity() {
+        let config = GeoVelocityConfig::default();
+        let london = (51.5074, -0.1278);
+        let sydney = (-33.8688, 151.2093);
+
+        // London -> Sydney (~17,000 km) in one hour
+        let impossible = check_geo_velocity(
+            &point(60, london.0, london.1),
+            &point(0, sydney.0, sydney.1),
+            &config,
+            "user-1",
+            "req-1"
+        );
+        assert_eq!(impossible.verdict, VelocityVerdict::ImpossibleTravel);
+        assert!(impossible.requires_step_up);
+        assert!((impossible.distance_km.unwrap() - 16_990.0).abs() < 50.0);
+
+        // Same trip over a day is a plausible flight
+        let plausible = check_geo_velocity(
+            &point(24 * 60, london.0, london.1),
+            &point(0, sydney.0, sydney.1),
+            &config,
+            "user-1",
+            "req-2"
+        );
+        assert_eq!(plausible.verdict, VelocityVerdict::Plausible);
+
+        let mut unknown = point(0, 0.0, 0.0);
+        unknown.location.latitude = None;
+        let result = check_geo_velocity(&point(5, london.0, london.1), &unknown, &config, "user-1", "req-3");
+        assert_eq!(result.verdict, VelocityVerdict::Unknown);
+    }
+}
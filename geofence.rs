@@ -0,0 +1,186 @@
+//! Geofences for location-restricted features
+//!
+//! A geofence is a circle (centre and radius) or a polygon of latitude/longitude vertices, and is
+//! usually loaded from configuration:
+//!
+//! ```json
+//! { "type": "circle", "center": { "latitude": 38.72, "longitude": -9.14 }, "radiusKm": 25 }
+//! { "type": "polygon", "vertices": [{ "latitude": 38.8, "longitude": -9.3 }, ...] }
+//! ```
+//!
+//! Polygon edges are straight lines in latitude/longitude, which is accurate for city and
+//! region sized areas; polygons must not cross the antimeridian or contain a pole.
+
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
+use serde::{ Deserialize, Serialize };
+
+use crate::common_lib::error::ApiError;
+use crate::common_lib::geo_point::{ BoundingBox, GeoPoint };
+#[cfg(not(feature = "no_geo"))]
+use crate::common_lib::geolocation::LocationInfo;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Geofence {
+    #[serde(rename_all = "camelCase")]
+    Circle {
+        center: GeoPoint,
+        radius_km: f64,
+    },
+    Polygon {
+        /// Boundary in order, without repeating the first vertex
+        vertices: Vec<GeoPoint>,
+    },
+}
+
+impl Geofence {
+    pub fn circle(center: GeoPoint, radius_km: f64) -> Self {
+        Geofence::Circle { center, radius_km }
+    }
+
+    pub fn polygon(vertices: Vec<GeoPoint>) -> Self {
+        Geofence::Polygon { vertices }
+    }
+
+    /// Check a geofence read from configuration
+    pub fn validate(&self) -> Result<(), ApiError> {
+        let invalid = |message: &str| Err(ApiError::BadRequest { message: message.to_string() });
+
+        match self {
+            Geofence::Circle { center, radius_km } => {
+                GeoPoint::new(center.latitude, center.longitude)?;
+                if !radius_km.is_finite() || *radius_km <= 0.0 {
+                    return invalid("Geofence radius must be greater than zero");
+                }
+            }
+            Geofence::Polygon { vertices } => {
+                if vertices.len() < 3 {
+                    return invalid("Geofence polygon needs at least 3 vertices");
+                }
+                for vertex in vertices {
+                    GeoPoint::new(vertex.latitude, vertex.longitude)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        self.contains_point(&GeoPoint { latitude, longitude })
+    }
+
+    pub fn contains_point(&self, point: &GeoPoint) -> bool {
+        match self {
+            Geofence::Circle { center, radius_km } => center.is_within_km(point, *radius_km),
+            Geofence::Polygon { vertices } => polygon_contains(vertices, point),
+        }
+    }
+
+    /// Whether a resolved location is inside, `None` when it has no coordinates
+    #[cfg(not(feature = "no_geo"))]
+    pub fn contains_location(&self, location: &LocationInfo) -> Option<bool> {
+        location.point().map(|point| self.contains_point(&point))
+    }
+
+    /// Box around the geofence, for prefiltering database queries
+    pub fn bounding_box(&self) -> BoundingBox {
+        match self {
+            Geofence::Circle { center, radius_km } => center.bounding_box(*radius_km),
+            Geofence::Polygon { vertices } => {
+                let fold = |f: fn(f64, f64) -> f64, start: f64, coordinate: fn(&GeoPoint) -> f64| {
+                    vertices.iter().map(coordinate).fold(start, f)
+                };
+
+                BoundingBox {
+                    min_latitude: fold(f64::min, 90.0, |vertex| vertex.latitude),
+                    max_latitude: fold(f64::max, -90.0, |vertex| vertex.latitude),
+                    min_longitude: fold(f64::min, 180.0, |vertex| vertex.longitude),
+                    max_longitude: fold(f64::max, -180.0, |vertex| vertex.longitude),
+                }
+            }
+        }
+    }
+}
+
+/// Even-odd ray casting; points exactly on an edge may fall either way
+fn polygon_contains(vertices: &[GeoPoint], point: &GeoPoint) -> bool {
+    let mut inside = false;
+    let mut previous = match vertices.last() {
+        Some(vertex) => vertex,
+        None => {
+            return false;
+        }
+    };
+
+    for vertex in vertices {
+        let crosses = (vertex.latitude > point.latitude) != (previous.latitude > point.latitude);
+        if crosses {
+            let longitude_at_crossing =
+                vertex.longitude +
+                ((point.latitude - vertex.latitude) / (previous.latitude - vertex.latitude)) *
+                    (previous.longitude - vertex.longitude);
+            if point.longitude < longitude_at_crossing {
+                inside = !inside;
+            }
+        }
+        previous = vertex;
+    }
+
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(latitude: f64, longitude: f64) -> GeoPoint {
+        GeoPoint { latitude, longitude }
+    }
+
+    #[test]
+    fn test_circle_and_polygon() {
+        let lisbon = Geofence::circle(point(38.7223, -9.1393), 25.0);
+        assert!(lisbon.contains(38.6979, -9.2068), "Belém");
+        assert!(!lisbon.contains(41.1579, -8.6291), "Porto");
+
+        // Concave "L" shape: the notch at the top right is outside
+        let shape = Geofence::polygon(vec![
+            point(0.0, 0.0),
+            point(0.0, 2.0),
+            point(1.0, 2.0),
+            point(1.0, 1.0),
+            point(2.0, 1.0),
+            point(2.0, 0.0),
+        ]);
+        assert!(shape.contains(0.5, 1.5));
+        assert!(shape.contains(1.5, 0.5));
+        assert!(!shape.contains(1.5, 1.5));
+        assert!(!shape.contains(-0.5, 0.5));
+        assert_eq!(shape.bounding_box(), BoundingBox {
+            min_latitude: 0.0,
+            max_latitude: 2.0,
+            min_longitude: 0.0,
+            max_longitude: 2.0,
+        });
+    }
+
+    #[test]
+    fn test_serde_and_validation() {
+        let geofence: Geofence = serde_json::from_str(
+            r#"{ "type": "circle", "center": { "latitude": 38.72, "longitude": -9.14 }, "radiusKm": 25 }"#
+        ).unwrap();
+        assert_eq!(geofence, Geofence::circle(point(38.72, -9.14), 25.0));
+        assert!(geofence.validate().is_ok());
+
+        let json = serde_json::to_value(Geofence::polygon(vec![point(0.0, 0.0), point(1.0, 1.0)])).unwrap();
+        assert_eq!(json["type"], "polygon");
+        let degenerate: Geofence = serde_json::from_value(json).unwrap();
+        assert!(matches!(degenerate.validate(), Err(ApiError::BadRequest { .. })));
+        assert!(Geofence::circle(point(0.0, 0.0), 0.0).validate().is_err());
+    }
+}
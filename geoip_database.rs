@@ -0,0 +1,194 @@
+//! Local MaxMind GeoLite2 / GeoIP2 City database, enabled with the `geoip_db` feature
+//! Lets `GeolocationService` answer lookups without outbound HTTP while the database is fresh.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::Utc;
+use maxminddb::{ geoip2, Reader };
+use tokio::sync::RwLock;
+use tracing::{ debug, error, warn };
+
+use crate::common_lib::error::ApiError;
+use crate::common_lib::geolocation::{ localized_name, LocationInfo };
+use crate::common_lib::logging::{ OperationTimer, LogLevel, RequestId };
+
+/// Configuration for the local database
+#[derive(Debug, Clone)]
+pub struct GeoIpDatabaseConfig {
+    /// Path to the `.mmdb` file, e.g. `/data/GeoLite2-City.mmdb`
+    pub path: String,
+    /// Databases built longer ago than this are stale and lookups fall back to HTTP providers
+    pub max_age_seconds: u64,
+    pub reload_interval_seconds: u64,
+}
+
+impl Default for GeoIpDatabaseConfig {
+    fn default() -> Self {
+        Self {
+            path: "GeoLite2-City.mmdb".to_string(),
+            max_age_seconds: 30 * 24 * 3600, // 30 days; GeoLite2 is published twice weekly
+            reload_interval_seconds: 6 * 3600, // 6 hours
+        }
+    }
+}
+
+/// Hot-reloadable reader for a City database
+pub struct GeoIpDatabase {
+    config: GeoIpDatabaseConfig,
+    reader: RwLock<Option<Arc<Reader<Vec<u8>>>>>,
+}
+
+impl GeoIpDatabase {
+    /// Open the database; a missing or invalid file is logged and lookups fall back until a reload succeeds
+    pub async fn open(config: GeoIpDatabaseConfig) -> Self {
+        let database = Self {
+            config,
+            reader: RwLock::new(None),
+        };
+
+        if let Err(e) = database.reload().await {
+            warn!("GEO_DB:open [UNAVAILABLE] Starting without local database - error: {}", e);
+        }
+
+        database
+    }
+
+    /// Re-read the database file, keeping the current reader if loading fails
+    pub async fn reload(&self) -> Result<(), ApiError> {
+        let req_id = RequestId::new();
+        let timer = OperationTimer::for_request("GEO_DB:reload", req_id);
+
+        let bytes = tokio::fs::read(&self.config.path).await.map_err(|e| {
+            ApiError::InternalServerError {
+                message: format!("Failed to read GeoIP database '{}': {}", self.config.path, e),
+            }
+        })?;
+        let reader = Reader::from_source(bytes).map_err(|e| ApiError::InternalServerError {
+            message: format!("Invalid GeoIP database '{}': {}", self.config.path, e),
+        })?;
+        let build_epoch = reader.metadata.build_epoch;
+
+        *self.reader.write().await = Some(Arc::new(reader));
+
+        timer.log_completion(
+            LogLevel::Info,
+            "SUCCESS",
+            &format!("GeoIP database loaded - path: {}, build_epoch: {}", self.config.path, build_epoch)
+        );
+
+        Ok(())
+    }
+
+    /// Spawn a background task that periodically reloads the database file
+    pub fn spawn_reload(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let database = Arc::clone(self);
+        let interval = Duration::from_secs(database.config.reload_interval_seconds.max(1));
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // The first tick completes immediately and the database was loaded on open
+            ticker.tick().await;
+
+            loop {
+                ticker.tick().await;
+                if let Err(e) = database.reload().await {
+                    error!("GEO_DB:reload [RELOAD_ERROR] Keeping previous database - error: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Whether a fresh database is loaded
+    pub async fn is_available(&self) -> bool {
+        match self.reader.read().await.as_ref() {
+            Some(reader) => !self.is_stale(reader.metadata.build_epoch),
+            None => false,
+        }
+    }
+
+    /// Look up an IP; `None` when the database is missing or stale, the IP is invalid or has no record
+    /// Place names are returned in the first of `preferred_languages` the database has, else English.
+    pub async fn lookup(&self, ip_address: &str, preferred_languages: &[String]) -> Option<LocationInfo> {
+        let reader = self.reader.read().await.clone()?;
+
+        if self.is_stale(reader.metadata.build_epoch) {
+            warn!(
+                "GEO_DB:lookup [STALE] Database older than max age - build_epoch: {}",
+                reader.metadata.build_epoch
+            );
+            return None;
+        }
+
+        let ip: IpAddr = ip_address.parse().ok()?;
+        let city: geoip2::City = match reader.lookup(ip) {
+            Ok(city) => city,
+            Err(e) => {
+                debug!("GEO_DB:lookup [NO_RECORD] No database record - ip: {}, error: {}", ip_address, e);
+                return None;
+            }
+        };
+
+        let localized = |names: Option<&std::collections::BTreeMap<&str, &str>>| {
+            let names = names?;
+            localized_name(preferred_languages, |language| names.get(language).map(|name| name.to_string()))
+        };
+
+        let country = city.country?;
+        let location = city.location;
+
+        Some(LocationInfo {
+            country_code: country.iso_code?.to_string(),
+            country_name: localized(country.names.as_ref()).unwrap_or_default(),
+            continent_code: city.continent.and_then(|continent| continent.code).map(str::to_string),
+            city: city.city.and_then(|c| localized(c.names.as_ref())),
+            region: city.subdivisions
+                .as_ref()
+                .and_then(|subdivisions| subdivisions.first())
+                .and_then(|subdivision| localized(subdivision.names.as_ref())),
+            latitude: location.as_ref().and_then(|l| l.latitude),
+            longitude: location.as_ref().and_then(|l| l.longitude),
+            timezone: location.as_ref().and_then(|l| l.time_zone.map(str::to_string)),
+            asn: None,
+            isp: None,
+            organization: None,
+        })
+    }
+
+    fn is_stale(&self, build_epoch: u64) -> bool {
+        let age_seconds = (Utc::now().timestamp() as u64).saturating_sub(build_epoch);
+        age_seconds > self.config.max_age_seconds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_missing_database_is_unavailable() {
+        let database = GeoIpDatabase::open(GeoIpDatabaseConfig {
+            path: "/nonexistent/GeoLite2-City.mmdb".to_string(),
+            ..GeoIpDatabaseConfig::default()
+        }).await;
+
+        assert!(!database.is_available().await);
+        assert!(database.lookup("8.8.8.8", &["en".to_string()]).await.is_none());
+        assert!(database.reload().await.is_err());
+    }
+
+    #[test]
+    fn test_staleness() {
+        let database = GeoIpDatabase {
+            config: GeoIpDatabaseConfig {
+                max_age_seconds: 3600,
+                ..GeoIpDatabaseConfig::default()
+            },
+            reader: RwLock::new(None),
+        };
+        let now = Utc::now().timestamp() as u64;
+
+        assert!(!database.is_stale(now - 60));
+        assert!(database.is_stale(now - 7200));
+    }
+}
@@ -1,55 +1,367 @@
 use std::collections::HashMap;
+use std::net::{ IpAddr, Ipv4Addr, Ipv6Addr };
+use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{ AtomicU64, Ordering };
 use std::time::{ Duration, Instant };
+use chrono::{ DateTime, Utc };
+use rand::Rng;
 use reqwest::Client;
+#[cfg(not(feature = "no_web"))]
+use rocket::request::{ FromRequest, Outcome, Request };
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
 use serde::{ Deserialize, Serialize };
-use tokio::sync::RwLock;
-use tracing::{ debug, error, info };
+use tokio::sync::{ Mutex, Notify };
+use tokio::task::JoinHandle;
+use tracing::{ debug, error, info, warn };
 
+use crate::common_lib::build_info::BuildInfo;
+use crate::common_lib::clock::{ system_clock, Clock };
 use crate::common_lib::error::ApiError;
-use crate::common_lib::logging::{ generate_correlation_id, OperationTimer, LogLevel };
+use crate::common_lib::geo_point::GeoPoint;
+use crate::common_lib::geo_providers::LocationProvider;
+#[cfg(feature = "geoip_db")]
+use crate::common_lib::geoip_database::GeoIpDatabase;
+use crate::common_lib::health::{ HealthReport, HealthState, HealthStatus };
+use crate::common_lib::logging::{ OperationTimer, LogLevel, RequestId };
+use crate::common_lib::lru_cache::LruCache;
+use crate::common_lib::secret::{ redact_uris, SecretString };
 
 /// Geolocation information extracted from IP address
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
 pub struct LocationInfo {
     pub country_code: String,
     pub country_name: String,
+    /// Two-letter continent code, e.g. "EU" or "SA"; not every provider returns it
+    #[serde(default)]
+    pub continent_code: Option<String>,
     pub city: Option<String>,
     pub region: Option<String>,
     pub latitude: Option<f64>,
     pub longitude: Option<f64>,
     pub timezone: Option<String>,
+    /// Autonomous system number of the network, e.g. 3320
+    #[serde(default)]
+    pub asn: Option<u32>,
+    #[serde(default)]
+    pub isp: Option<String>,
+    #[serde(default)]
+    pub organization: Option<String>,
 }
 
+/// How an address reaches us, for abuse prevention
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum IpRiskCategory {
+    Residential,
+    /// Cloud and hosting providers
+    Datacenter,
+    /// VPNs, open proxies and relays such as iCloud Private Relay
+    Vpn,
+    Tor,
+    /// Internal address, or no risk provider configured
+    Unknown,
+}
+
+impl std::str::FromStr for IpRiskCategory {
+    type Err = ApiError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "residential" => Ok(IpRiskCategory::Residential),
+            "datacenter" | "hosting" => Ok(IpRiskCategory::Datacenter),
+            "vpn" | "proxy" | "relay" => Ok(IpRiskCategory::Vpn),
+            "tor" => Ok(IpRiskCategory::Tor),
+            other =>
+                Err(ApiError::BadRequest {
+                    message: format!("Unknown IP risk category '{}'", other),
+                }),
+        }
+    }
+}
+
+/// Risk classification of an IP address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+pub struct IpRisk {
+    pub ip_address: String,
+    pub category: IpRiskCategory,
+    /// VPN or hosting provider name when the provider knows it
+    pub service: Option<String>,
+}
+
+impl IpRisk {
+    /// Whether the real client address is hidden behind a VPN, proxy or Tor
+    pub fn is_anonymized(&self) -> bool {
+        matches!(self.category, IpRiskCategory::Vpn | IpRiskCategory::Tor)
+    }
+}
+
+/// Local list of classified ranges (Tor exit nodes, cloud provider ranges, ...), checked before the provider
+#[derive(Debug, Clone, Default)]
+pub struct IpRiskDataset {
+    ranges: Vec<(IpAddr, u8, IpRiskCategory)>,
+}
+
+impl IpRiskDataset {
+    /// Parse `cidr,category` lines, e.g. `185.220.101.0/24,tor`; blank lines and `#` comments are skipped
+    pub fn from_csv(contents: &str) -> Result<Self, ApiError> {
+        let mut ranges = Vec::new();
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || ApiError::BadRequest {
+                message: format!("Invalid IP risk dataset line {}: '{}'", index + 1, line),
+            };
+            let (cidr, category) = line.split_once(',').ok_or_else(invalid)?;
+            let (network, prefix) = cidr.trim().split_once('/').unwrap_or((cidr.trim(), ""));
+            let network: IpAddr = network.parse().map_err(|_| invalid())?;
+            let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+            let prefix = if prefix.is_empty() { max_prefix } else { prefix.parse().map_err(|_| invalid())? };
+            if prefix > max_prefix {
+                return Err(invalid());
+            }
+
+            ranges.push((network, prefix, category.parse()?));
+        }
+
+        Ok(Self { ranges })
+    }
+
+    /// Category of the most specific range containing the address
+    pub fn lookup(&self, ip: &IpAddr) -> Option<IpRiskCategory> {
+        self.ranges
+            .iter()
+            .filter(|(network, prefix, _)| cidr_contains(network, *prefix, ip))
+            .max_by_key(|(_, prefix, _)| *prefix)
+            .map(|(_, _, category)| *category)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+fn cidr_contains(network: &IpAddr, prefix: u8, ip: &IpAddr) -> bool {
+    let (network, ip, bits) = match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => (u32::from(*network) as u128, u32::from(*ip) as u128, 32),
+        (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(*network), u128::from(*ip), 128),
+        _ => {
+            return false;
+        }
+    };
+    let host_bits = bits - (prefix as u32);
+
+    host_bits >= bits || network >> host_bits == ip >> host_bits
+}
+
+impl LocationInfo {
+    /// Coordinates, when the provider returned them
+    pub fn point(&self) -> Option<GeoPoint> {
+        Some(GeoPoint { latitude: self.latitude?, longitude: self.longitude? })
+    }
+
+    /// Distance to another location in kilometres, `None` when either lacks coordinates
+    pub fn distance_km(&self, other: &LocationInfo) -> Option<f64> {
+        Some(self.point()?.distance_km(&other.point()?))
+    }
+
+    /// Copy without city, region or coordinates, for users who have not opted into precise location
+    /// Network details (ASN, ISP, organization) are kept since they do not locate the user.
+    pub fn country_level(&self) -> Self {
+        Self {
+            country_code: self.country_code.clone(),
+            country_name: self.country_name.clone(),
+            continent_code: self.continent_code.clone(),
+            city: None,
+            region: None,
+            latitude: None,
+            longitude: None,
+            timezone: self.timezone.clone(),
+            asn: self.asn,
+            isp: self.isp.clone(),
+            organization: self.organization.clone(),
+        }
+    }
+}
+
+/// Fields requested from ip-api.com, limited to what `LocationInfo` needs
+const IP_API_FIELDS: &str = "status,message,continentCode,country,countryCode,regionName,city,lat,lon,timezone,isp,org,as";
+
 /// Response structure for ip-api.com fallback service
-#[derive(Debug, Deserialize)]
+/// Failure responses only carry `status` and `message`, so every field defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
 struct FallbackApiResponse {
     status: String,
+    #[serde(rename = "continentCode")]
+    continent_code: String,
     country: String,
     #[serde(rename = "countryCode")]
     country_code: String,
-    #[allow(dead_code)]
-    region: String,
     #[serde(rename = "regionName")]
     region_name: String,
     city: String,
-    #[allow(dead_code)]
-    zip: String,
     lat: f64,
     lon: f64,
     timezone: String,
-    #[allow(dead_code)]
     isp: String,
-    #[allow(dead_code)]
     org: String,
     #[serde(rename = "as")]
-    #[allow(dead_code)]
     as_name: String,
-    #[allow(dead_code)]
-    query: String,
     message: Option<String>, // Error message when status != "success"
 }
 
+/// Response of the ipinfo.io privacy detection API
+#[derive(Debug, Deserialize)]
+struct IpInfoPrivacyResponse {
+    #[serde(default)]
+    vpn: bool,
+    #[serde(default)]
+    proxy: bool,
+    #[serde(default)]
+    tor: bool,
+    #[serde(default)]
+    relay: bool,
+    #[serde(default)]
+    hosting: bool,
+    #[serde(default)]
+    service: String,
+}
+
+/// Nominatim reverse geocoding response; `address` is missing for coordinates at sea
+#[derive(Debug, Deserialize)]
+struct NominatimReverseResponse {
+    address: Option<NominatimAddress>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NominatimAddress {
+    country_code: Option<String>,
+}
+
+/// Country code (`None` at sea) and when it was resolved
+type ReverseGeocodeEntry = (Option<String>, Instant);
+
+/// Result of the lookup in flight for an address, `None` until the leading request finishes
+type InFlightLookup = Arc<Mutex<Option<Result<Option<LocationInfo>, ApiError>>>>;
+
+/// Leaves the in-flight map entry once the last request waiting on it is done, even if cancelled
+struct InFlightGuard<'a> {
+    in_flight: &'a std::sync::Mutex<HashMap<String, InFlightLookup>>,
+    ip_address: &'a str,
+    lookup: InFlightLookup,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        // Released under the lock, so guards dropping together can't each still count the other
+        let lookup = std::mem::take(&mut self.lookup);
+        let is_last = in_flight
+            .get(self.ip_address)
+            .is_some_and(|entry| Arc::ptr_eq(entry, &lookup) && Arc::strong_count(entry) == 2);
+        drop(lookup);
+        if is_last {
+            in_flight.remove(self.ip_address);
+        }
+    }
+}
+
+/// Copy of a lookup result for the requests that waited on it; `ApiError` is not `Clone`
+fn share_lookup(result: &Result<Option<LocationInfo>, ApiError>) -> Result<Option<LocationInfo>, ApiError> {
+    match result {
+        Ok(location) => Ok(location.clone()),
+        Err(ApiError::TooManyRequests { message, retry_after_seconds }) => {
+            Err(ApiError::TooManyRequests { message: message.clone(), retry_after_seconds: *retry_after_seconds })
+        }
+        Err(ApiError::InternalServerError { message }) => Err(ApiError::InternalServerError { message: message.clone() }),
+        Err(e) => Err(ApiError::InternalServerError { message: e.to_string() }),
+    }
+}
+
+/// Serializable copy of the location cache, for carrying it across restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheSnapshot {
+    pub entries: Vec<CacheSnapshotEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheSnapshotEntry {
+    pub ip_address: String,
+    pub location: LocationInfo,
+    /// Wall-clock time the entry was cached, so its remaining TTL survives the restart
+    pub cached_at: DateTime<Utc>,
+}
+
+/// Cache and provider metrics for dashboards, from `get_cache_stats`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct GeoCacheMetrics {
+    pub total_entries: usize,
+    /// Entries still within the TTL
+    pub valid_entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Lookups answered from the negative cache of unknown and failed addresses
+    pub negative_hits: u64,
+    /// Cache misses that waited for a concurrent lookup of the same address instead of calling a provider
+    pub coalesced: u64,
+    /// Entries dropped to make room for new ones
+    pub evictions: u64,
+    pub database_lookups: u64,
+    pub maxmind_calls: u64,
+    /// Calls to providers added with `with_provider`
+    pub provider_calls: u64,
+    pub fallback_calls: u64,
+    /// Provider calls that failed; a MaxMind failure that the fallback recovered still counts
+    pub provider_errors: u64,
+    /// Mean duration of successful `get_location` calls, cache hits included
+    pub average_lookup_latency_ms: f64,
+}
+
+/// Counters behind `GeoCacheMetrics`, updated without taking the cache lock
+#[derive(Debug, Default)]
+struct GeoCacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    negative_hits: AtomicU64,
+    coalesced: AtomicU64,
+    evictions: AtomicU64,
+    database_lookups: AtomicU64,
+    maxmind_calls: AtomicU64,
+    provider_calls: AtomicU64,
+    fallback_calls: AtomicU64,
+    provider_errors: AtomicU64,
+    lookups: AtomicU64,
+    lookup_micros: AtomicU64,
+}
+
+impl GeoCacheCounters {
+    fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_lookup(&self, elapsed: Duration) {
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        self.lookup_micros.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
 /// Cache entry for geolocation results
 #[derive(Debug, Clone)]
 struct CacheEntry {
@@ -57,35 +369,340 @@ struct CacheEntry {
     timestamp: Instant,
 }
 
-/// Configuration for geolocation service
+/// Recently unknown or failed address, kept for `negative_cache_ttl_seconds`
 #[derive(Debug, Clone)]
+struct NegativeCacheEntry {
+    /// `None` when the providers don't know the address, otherwise the lookup error
+    error: Option<String>,
+    timestamp: Instant,
+}
+
+/// How far `GeolocationService::health_check` goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthCheckMode {
+    /// Configuration and cache only, no network calls
+    Shallow,
+    /// Also checks that every configured provider is reachable
+    Deep,
+}
+
+/// What a fallback lookup does once the ip-api.com rate limit is used up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum RateLimitBehavior {
+    /// Wait for capacity, failing only if that would take longer than `max_wait`
+    Queue {
+        max_wait: Duration,
+    },
+    /// Fail the lookup immediately with `TooManyRequests`
+    FailFast,
+}
+
+/// Token bucket refilled continuously at `requests_per_minute`
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    /// Goes negative while queued callers hold reservations
+    tokens: f64,
+    refill_per_second: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(requests_per_minute: u32, now: Instant) -> Self {
+        Self {
+            capacity: requests_per_minute as f64,
+            tokens: requests_per_minute as f64,
+            refill_per_second: (requests_per_minute as f64) / 60.0,
+            last_refill: now,
+        }
+    }
+
+    /// Take a token, returning how long to wait before using it; `None` if that exceeds `max_wait`
+    fn reserve(&mut self, now: Instant, max_wait: Duration) -> Option<Duration> {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.last_refill = now;
+
+        let wait = Duration::from_secs_f64((1.0 - self.tokens).max(0.0) / self.refill_per_second);
+        if wait > max_wait {
+            return None;
+        }
+        self.tokens -= 1.0;
+        Some(wait)
+    }
+}
+
+/// Configuration for geolocation service
+/// Serializes with keys and tokens redacted, e.g. for debug routes.
+#[derive(Debug, Clone, Serialize)]
 pub struct GeolocationConfig {
-    pub api_key: String,
+    pub api_key: SecretString,
     pub service_url: String,
+    pub fallback_service_url: String,
+    /// ip-api.com pro key, sent with every fallback request when set
+    pub fallback_api_key: SecretString,
+    /// Client-side limit for the free fallback endpoint, which bans clients going over 45 per
+    /// minute; not applied with a pro key, 0 disables it
+    pub fallback_requests_per_minute: u32,
+    pub fallback_rate_limit: RateLimitBehavior,
     pub timeout_seconds: u64,
     pub cache_ttl_seconds: u64,
+    /// How long unknown addresses and failed lookups are remembered; 0 disables negative caching
+    pub negative_cache_ttl_seconds: u64,
+    /// How often the task from `spawn_eviction_task` sweeps expired entries out of the caches
+    pub cache_eviction_interval_seconds: u64,
     pub max_cache_entries: usize,
+    /// Returned for private, loopback, link-local and CGNAT addresses without calling any provider
+    pub internal_location: LocationInfo,
+    /// Extra attempts per provider after connection errors and 5xx responses
+    pub retry_attempts: u32,
+    /// Backoff before the first retry, doubled for each further retry
+    pub retry_base_delay_ms: u64,
+    /// Randomize each backoff between half and the full delay so instances don't retry in lockstep
+    pub retry_jitter: bool,
+    /// Base URL of the ipinfo.io privacy detection API
+    pub ip_risk_url: String,
+    /// ipinfo.io token; `get_ip_risk` only consults the local dataset when empty
+    pub ip_risk_token: SecretString,
+    /// Languages for place names, most preferred first; English is always the last resort
+    pub preferred_languages: Vec<String>,
+    /// Nominatim-compatible `/reverse` endpoint used by `reverse_geocode`
+    pub reverse_geocode_url: String,
 }
 
 impl Default for GeolocationConfig {
     fn default() -> Self {
         Self {
-            api_key: String::new(),
+            api_key: SecretString::default(),
             service_url: "https://api.maxmind.com/geoip/v2.1/city".to_string(),
+            fallback_service_url: Self::IP_API_FREE_URL.to_string(),
+            fallback_api_key: SecretString::default(),
+            fallback_requests_per_minute: Self::IP_API_FREE_REQUESTS_PER_MINUTE,
+            fallback_rate_limit: RateLimitBehavior::Queue { max_wait: Duration::from_secs(5) },
             timeout_seconds: 5,
             cache_ttl_seconds: 3600, // 1 hour
+            negative_cache_ttl_seconds: 60,
+            cache_eviction_interval_seconds: 300,
             max_cache_entries: 10000,
+            internal_location: LocationInfo {
+                country_code: "ZZ".to_string(), // ISO 3166 user-assigned "unknown"
+                country_name: "Internal Network".to_string(),
+                continent_code: None,
+                city: None,
+                region: None,
+                latitude: None,
+                longitude: None,
+                timezone: None,
+                asn: None,
+                isp: None,
+                organization: None,
+            },
+            retry_attempts: 2,
+            retry_base_delay_ms: 100,
+            retry_jitter: true,
+            ip_risk_url: "https://ipinfo.io".to_string(),
+            ip_risk_token: SecretString::default(),
+            preferred_languages: vec!["en".to_string()],
+            reverse_geocode_url: "https://nominatim.openstreetmap.org/reverse".to_string(),
+        }
+    }
+}
+
+impl GeolocationConfig {
+    pub const MAX_TIMEOUT_SECONDS: u64 = 60;
+    pub const MAX_CACHE_ENTRIES: usize = 1_000_000;
+    pub const MAX_RETRY_ATTEMPTS: u32 = 5;
+    /// Free ip-api.com endpoint; HTTP only and limited to 45 requests per minute
+    pub const IP_API_FREE_URL: &'static str = "http://ip-api.com/json";
+    /// ip-api.com pro endpoint, HTTPS and keyed
+    pub const IP_API_PRO_URL: &'static str = "https://pro.ip-api.com/json";
+    pub const IP_API_FREE_REQUESTS_PER_MINUTE: u32 = 45;
+
+    /// Start from the defaults and override individual settings
+    pub fn builder() -> GeolocationConfigBuilder {
+        GeolocationConfigBuilder::default()
+    }
+
+    /// Check configuration invariants
+    pub fn validate(&self) -> Result<(), ApiError> {
+        let invalid = |message: String| Err(ApiError::BadRequest { message });
+
+        if self.timeout_seconds == 0 || self.timeout_seconds > Self::MAX_TIMEOUT_SECONDS {
+            return invalid(
+                format!(
+                    "Geolocation timeout must be between 1 and {} seconds, got {}",
+                    Self::MAX_TIMEOUT_SECONDS,
+                    self.timeout_seconds
+                )
+            );
+        }
+        if self.cache_ttl_seconds == 0 {
+            return invalid("Geolocation cache TTL must be greater than zero".to_string());
+        }
+        if self.cache_eviction_interval_seconds == 0 {
+            return invalid("Geolocation cache eviction interval must be greater than zero".to_string());
+        }
+        if self.max_cache_entries == 0 || self.max_cache_entries > Self::MAX_CACHE_ENTRIES {
+            return invalid(
+                format!(
+                    "Geolocation cache size must be between 1 and {}, got {}",
+                    Self::MAX_CACHE_ENTRIES,
+                    self.max_cache_entries
+                )
+            );
+        }
+        if self.retry_attempts > Self::MAX_RETRY_ATTEMPTS {
+            return invalid(
+                format!(
+                    "Geolocation retry attempts must be at most {}, got {}",
+                    Self::MAX_RETRY_ATTEMPTS,
+                    self.retry_attempts
+                )
+            );
+        }
+        for (name, url) in [
+            ("service_url", &self.service_url),
+            ("fallback_service_url", &self.fallback_service_url),
+            ("ip_risk_url", &self.ip_risk_url),
+            ("reverse_geocode_url", &self.reverse_geocode_url),
+        ] {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return invalid(format!("Geolocation {} must be an http(s) URL, got '{}'", name, url));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Validated builder for `GeolocationConfig`
+#[derive(Debug, Clone, Default)]
+pub struct GeolocationConfigBuilder {
+    config: GeolocationConfig,
+}
+
+impl GeolocationConfigBuilder {
+    /// MaxMind API key; leave empty to use only the fallback service
+    pub fn api_key(mut self, api_key: &str) -> Self {
+        self.config.api_key = SecretString::from(api_key);
+        self
+    }
+
+    pub fn service_url(mut self, service_url: &str) -> Self {
+        self.config.service_url = service_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn fallback_service_url(mut self, fallback_service_url: &str) -> Self {
+        self.config.fallback_service_url = fallback_service_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// ip-api.com pro key; switches the fallback to the HTTPS pro endpoint unless a custom URL is set
+    pub fn fallback_api_key(mut self, fallback_api_key: &str) -> Self {
+        self.config.fallback_api_key = SecretString::from(fallback_api_key);
+        if self.config.fallback_service_url == GeolocationConfig::IP_API_FREE_URL {
+            self.config.fallback_service_url = GeolocationConfig::IP_API_PRO_URL.to_string();
         }
+        self
+    }
+
+    /// Rate limit for the free fallback endpoint; 0 requests per minute disables it
+    pub fn fallback_rate_limit(mut self, requests_per_minute: u32, behavior: RateLimitBehavior) -> Self {
+        self.config.fallback_requests_per_minute = requests_per_minute;
+        self.config.fallback_rate_limit = behavior;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout_seconds = timeout.as_secs();
+        self
+    }
+
+    pub fn cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.config.cache_ttl_seconds = cache_ttl.as_secs();
+        self
+    }
+
+    /// TTL for unknown and failed lookups; zero disables negative caching
+    pub fn negative_cache_ttl(mut self, negative_cache_ttl: Duration) -> Self {
+        self.config.negative_cache_ttl_seconds = negative_cache_ttl.as_secs();
+        self
+    }
+
+    pub fn cache_eviction_interval(mut self, cache_eviction_interval: Duration) -> Self {
+        self.config.cache_eviction_interval_seconds = cache_eviction_interval.as_secs();
+        self
+    }
+
+    pub fn max_cache_entries(mut self, max_cache_entries: usize) -> Self {
+        self.config.max_cache_entries = max_cache_entries;
+        self
+    }
+
+    pub fn internal_location(mut self, internal_location: LocationInfo) -> Self {
+        self.config.internal_location = internal_location;
+        self
+    }
+
+    /// Retries per provider call; zero attempts disables retrying
+    pub fn retry(mut self, attempts: u32, base_delay: Duration) -> Self {
+        self.config.retry_attempts = attempts;
+        self.config.retry_base_delay_ms = base_delay.as_millis() as u64;
+        self
+    }
+
+    pub fn retry_jitter(mut self, retry_jitter: bool) -> Self {
+        self.config.retry_jitter = retry_jitter;
+        self
+    }
+
+    /// ipinfo.io privacy API token for `get_ip_risk`
+    pub fn ip_risk_token(mut self, ip_risk_token: &str) -> Self {
+        self.config.ip_risk_token = SecretString::from(ip_risk_token);
+        self
+    }
+
+    pub fn ip_risk_url(mut self, ip_risk_url: &str) -> Self {
+        self.config.ip_risk_url = ip_risk_url.to_string();
+        self
+    }
+
+    /// Languages for city, region and country names, e.g. `&["pt-BR", "es"]`
+    pub fn preferred_languages(mut self, preferred_languages: &[&str]) -> Self {
+        self.config.preferred_languages = preferred_languages
+            .iter()
+            .map(|language| language.to_string())
+            .collect();
+        self
+    }
+
+    /// Reverse geocoding endpoint, e.g. a self-hosted Nominatim for higher volumes
+    pub fn reverse_geocode_url(mut self, reverse_geocode_url: &str) -> Self {
+        self.config.reverse_geocode_url = reverse_geocode_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn build(self) -> Result<GeolocationConfig, ApiError> {
+        self.config.validate()?;
+        Ok(self.config)
     }
 }
 
 /// MaxMind GeoIP2 API response structure
 #[derive(Debug, Deserialize)]
 struct MaxMindResponse {
+    continent: Option<MaxMindContinent>,
     country: MaxMindCountry,
     city: Option<MaxMindCity>,
     location: Option<MaxMindLocation>,
     subdivisions: Option<Vec<MaxMindSubdivision>>,
+    traits: Option<MaxMindTraits>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MaxMindContinent {
+    code: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -111,27 +728,262 @@ struct MaxMindSubdivision {
     names: HashMap<String, String>,
 }
 
+/// Network traits; ISP and organization are only returned by the Insights and City Plus tiers
+#[derive(Debug, Deserialize)]
+struct MaxMindTraits {
+    autonomous_system_number: Option<u32>,
+    autonomous_system_organization: Option<String>,
+    isp: Option<String>,
+    organization: Option<String>,
+}
+
 /// High-performance geolocation service with caching
 pub struct GeolocationService {
     client: Arc<Client>,
     config: GeolocationConfig,
-    cache: Arc<RwLock<HashMap<String, CacheEntry>>>,
+    /// Hits promote entries, so lookups take the lock exclusively; every operation on it is O(1)
+    cache: Arc<Mutex<LruCache<String, CacheEntry>>>,
+    negative_cache: Arc<Mutex<LruCache<String, NegativeCacheEntry>>>,
+    risk_cache: Arc<Mutex<LruCache<String, (IpRisk, Instant)>>>,
+    /// Country per coordinates rounded to `REVERSE_GEOCODE_PRECISION` decimals
+    reverse_cache: Arc<Mutex<LruCache<String, ReverseGeocodeEntry>>>,
+    risk_dataset: Option<Arc<IpRiskDataset>>,
+    /// Asked in order between MaxMind and the ip-api fallback
+    providers: Vec<Arc<dyn LocationProvider>>,
+    /// Lookups in progress per normalized address, so concurrent misses make one provider call
+    in_flight: std::sync::Mutex<HashMap<String, InFlightLookup>>,
+    counters: GeoCacheCounters,
+    /// `None` when the fallback is keyed or unlimited
+    fallback_limiter: Option<Mutex<TokenBucket>>,
+    clock: Arc<dyn Clock>,
+    /// Background sweeper and its stop signal, see `spawn_eviction_task`
+    eviction_task: std::sync::Mutex<Option<(JoinHandle<()>, Arc<Notify>)>>,
+    #[cfg(feature = "geoip_db")]
+    database: Option<Arc<GeoIpDatabase>>,
 }
 
 impl GeolocationService {
+    /// Decimals kept from reverse geocoded coordinates; 2 decimals is about 1.1 km
+    const REVERSE_GEOCODE_PRECISION: usize = 2;
+    const REVERSE_GEOCODE_USER_AGENT: &'static str = "common-lib-geolocation";
+
     /// Create new geolocation service with configuration
-    pub fn new(client: Arc<Client>, config: GeolocationConfig) -> Self {
-        Self {
+    /// Fails with `BadRequest` when the configuration breaks an invariant, see `GeolocationConfig::validate`.
+    pub fn new(client: Arc<Client>, config: GeolocationConfig) -> Result<Self, ApiError> {
+        Self::with_clock(client, config, system_clock())
+    }
+
+    /// Create new geolocation service using the given clock for cache TTLs
+    pub fn with_clock(client: Arc<Client>, config: GeolocationConfig, clock: Arc<dyn Clock>) -> Result<Self, ApiError> {
+        config.validate()?;
+
+        let capacity = config.max_cache_entries;
+        let fallback_limiter = (config.fallback_api_key.is_empty() && config.fallback_requests_per_minute > 0).then(||
+            Mutex::new(TokenBucket::new(config.fallback_requests_per_minute, clock.now()))
+        );
+
+        Ok(Self {
             client,
             config,
-            cache: Arc::new(RwLock::new(HashMap::new())),
+            cache: Arc::new(Mutex::new(LruCache::new(capacity))),
+            negative_cache: Arc::new(Mutex::new(LruCache::new(capacity))),
+            risk_cache: Arc::new(Mutex::new(LruCache::new(capacity))),
+            reverse_cache: Arc::new(Mutex::new(LruCache::new(capacity))),
+            risk_dataset: None,
+            providers: Vec::new(),
+            in_flight: std::sync::Mutex::new(HashMap::new()),
+            counters: GeoCacheCounters::default(),
+            fallback_limiter,
+            clock,
+            eviction_task: std::sync::Mutex::new(None),
+            #[cfg(feature = "geoip_db")]
+            database: None,
+        })
+    }
+
+    /// Serve lookups from a local GeoIP database, using HTTP providers only when it is missing or stale
+    #[cfg(feature = "geoip_db")]
+    pub fn with_database(mut self, database: Arc<GeoIpDatabase>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Ask another provider (ipinfo.io, IP2Location.io, ...) when MaxMind is not configured or fails
+    pub fn with_provider(mut self, provider: Arc<dyn LocationProvider>) -> Self {
+        self.providers.push(provider);
+        self
+    }
+
+    /// Classify addresses from a local dataset before asking the risk provider
+    pub fn with_risk_dataset(mut self, risk_dataset: Arc<IpRiskDataset>) -> Self {
+        self.risk_dataset = Some(risk_dataset);
+        self
+    }
+
+    /// Classify an address as residential, datacenter, VPN or Tor
+    /// Internal addresses, and addresses outside the dataset when no provider token is set, are `Unknown`.
+    pub async fn get_ip_risk(&self, ip_address: &str) -> Result<IpRisk, ApiError> {
+        let req_id = RequestId::new();
+        let ip = parse_ip_address(ip_address)?;
+        let ip_address = ip.to_string();
+        let risk = |category: IpRiskCategory, service: Option<String>| IpRisk {
+            ip_address: ip_address.clone(),
+            category,
+            service,
+        };
+
+        if is_internal_ip(&ip) {
+            return Ok(risk(IpRiskCategory::Unknown, None));
+        }
+        if let Some(category) = self.risk_dataset.as_ref().and_then(|dataset| dataset.lookup(&ip)) {
+            debug!(
+                "GEO:get_ip_risk [DATASET_HIT] [req_id:{}] Address found in local dataset - ip: {}, category: {:?}",
+                req_id,
+                ip_address,
+                category
+            );
+            return Ok(risk(category, None));
+        }
+        if self.config.ip_risk_token.is_empty() {
+            return Ok(risk(IpRiskCategory::Unknown, None));
+        }
+
+        let ttl = Duration::from_secs(self.config.cache_ttl_seconds);
+        if let Some((cached, timestamp)) = self.risk_cache.lock().await.get(&ip_address) {
+            if self.clock.now().duration_since(*timestamp) < ttl {
+                return Ok(cached.clone());
+            }
+        }
+
+        let url = format!("{}/{}/privacy", self.config.ip_risk_url, ip_address);
+        let response = self
+            .send_with_retry("GEO:get_ip_risk", req_id.as_str(), || {
+                self.client
+                    .get(&url)
+                    .bearer_auth(self.config.ip_risk_token.expose_secret())
+                    .timeout(Duration::from_secs(self.config.timeout_seconds))
+            }).await
+            .map_err(|e| ApiError::InternalServerError {
+                message: format!("IP risk request failed: {}", redact_uris(&e.to_string())),
+            })?;
+
+        if !response.status().is_success() {
+            error!(
+                "GEO:get_ip_risk [API_ERROR] [req_id:{}] Non-success status - ip: {}, status: {}",
+                req_id,
+                ip_address,
+                response.status()
+            );
+            return Err(ApiError::InternalServerError {
+                message: format!("IP risk service error: {}", response.status()),
+            });
         }
+
+        let privacy: IpInfoPrivacyResponse = response.json().await.map_err(|e| ApiError::InternalServerError {
+            message: format!("Failed to parse IP risk response: {e}"),
+        })?;
+        let category = if privacy.tor {
+            IpRiskCategory::Tor
+        } else if privacy.vpn || privacy.proxy || privacy.relay {
+            IpRiskCategory::Vpn
+        } else if privacy.hosting {
+            IpRiskCategory::Datacenter
+        } else {
+            IpRiskCategory::Residential
+        };
+        let result = risk(category, non_empty(privacy.service));
+
+        self.risk_cache.lock().await.insert(ip_address.clone(), (result.clone(), self.clock.now()));
+
+        debug!(
+            "GEO:get_ip_risk [SUCCESS] [req_id:{}] Address classified - ip: {}, category: {:?}",
+            req_id,
+            ip_address,
+            category
+        );
+        Ok(result)
+    }
+
+    /// ISO 3166-1 alpha-2 country at GPS coordinates, `None` at sea or outside any country
+    ///
+    /// Results are cached per ~1 km cell, so clients reporting nearby positions share one provider call.
+    pub async fn reverse_geocode(&self, latitude: f64, longitude: f64) -> Result<Option<String>, ApiError> {
+        let req_id = RequestId::new();
+
+        GeoPoint::new(latitude, longitude).inspect_err(|_| {
+            error!(
+                "GEO:reverse_geocode [VALIDATION] [req_id:{}] Coordinates out of range - lat: {}, lon: {}",
+                req_id,
+                latitude,
+                longitude
+            );
+        })?;
+
+        let precision = Self::REVERSE_GEOCODE_PRECISION;
+        let cache_key = format!("{:.*},{:.*}", precision, latitude, precision, longitude);
+        let ttl = Duration::from_secs(self.config.cache_ttl_seconds);
+        if let Some((country_code, timestamp)) = self.reverse_cache.lock().await.get(&cache_key) {
+            if self.clock.now().duration_since(*timestamp) < ttl {
+                return Ok(country_code.clone());
+            }
+        }
+
+        let latitude = latitude.to_string();
+        let longitude = longitude.to_string();
+        let query = [
+            ("format", "jsonv2"),
+            ("lat", latitude.as_str()),
+            ("lon", longitude.as_str()),
+            // Country level; finer zoom levels only add work for the provider
+            ("zoom", "3"),
+        ];
+        let response = self
+            .send_with_retry("GEO:reverse_geocode", req_id.as_str(), || {
+                self.client
+                    .get(&self.config.reverse_geocode_url)
+                    .query(&query)
+                    // Nominatim's usage policy requires an identifying User-Agent
+                    .header(reqwest::header::USER_AGENT, Self::REVERSE_GEOCODE_USER_AGENT)
+                    .timeout(Duration::from_secs(self.config.timeout_seconds))
+            }).await
+            .map_err(|e| ApiError::InternalServerError {
+                message: format!("Reverse geocoding request failed: {}", redact_uris(&e.to_string())),
+            })?;
+
+        if !response.status().is_success() {
+            error!(
+                "GEO:reverse_geocode [API_ERROR] [req_id:{}] Non-success status - cell: {}, status: {}",
+                req_id,
+                cache_key,
+                response.status()
+            );
+            return Err(ApiError::InternalServerError {
+                message: format!("Reverse geocoding service error: {}", response.status()),
+            });
+        }
+
+        let reverse: NominatimReverseResponse = response.json().await.map_err(|e| ApiError::InternalServerError {
+            message: format!("Failed to parse reverse geocoding response: {e}"),
+        })?;
+        let country_code = reverse.address
+            .and_then(|address| address.country_code)
+            .map(|country_code| country_code.to_uppercase());
+
+        self.reverse_cache.lock().await.insert(cache_key.clone(), (country_code.clone(), self.clock.now()));
+
+        debug!(
+            "GEO:reverse_geocode [SUCCESS] [req_id:{}] Coordinates resolved - cell: {}, country: {:?}",
+            req_id,
+            cache_key,
+            country_code
+        );
+        Ok(country_code)
     }
 
     /// Get location information for IP address with caching
     pub async fn get_location(&self, ip_address: &str) -> Result<LocationInfo, ApiError> {
-        let req_id = generate_correlation_id();
-        let timer = OperationTimer::new("GEO:get_location", &req_id);
+        let req_id = RequestId::new();
+        let timer = OperationTimer::for_request_with_clock("GEO:get_location", req_id, Arc::clone(&self.clock));
 
         debug!(
             "GEO:get_location [START] [req_id:{}] Processing IP lookup - ip: {}",
@@ -147,8 +999,25 @@ impl GeolocationService {
             });
         }
 
+        let ip = parse_ip_address(ip_address).inspect_err(|_| {
+            error!("GEO:get_location [VALIDATION] [req_id:{}] Malformed IP address - ip: {}", req_id, ip_address);
+        })?;
+
+        // Dev and VPC traffic never reaches the providers
+        if is_internal_ip(&ip) {
+            debug!("GEO:get_location [INTERNAL] [req_id:{}] Internal address, skipping lookup - ip: {}", req_id, ip);
+            return Ok(self.config.internal_location.clone());
+        }
+
+        let normalized_ip = ip.to_string();
+        let ip_address = normalized_ip.as_str();
+        let started = self.clock.now();
+
         // 2. Check cache first
         if let Some(cached_location) = self.get_from_cache(ip_address).await {
+            GeoCacheCounters::increment(&self.counters.hits);
+            self.counters.record_lookup(self.clock.now().duration_since(started));
+
             debug!(
                 "GEO:get_location [CACHE_HIT] [req_id:{}] Found cached location - ip: {}, country: {}",
                 req_id,
@@ -169,17 +1038,55 @@ impl GeolocationService {
             return Ok(cached_location);
         }
 
-        // 3. Call external geolocation API
-        debug!(
-            "GEO:get_location [API_CALL] [req_id:{}] Cache miss, calling external API - ip: {}",
-            req_id,
-            ip_address
-        );
+        // 3. Addresses that recently came back unknown or failed
+        if let Some(negative) = self.get_from_negative_cache(ip_address).await {
+            GeoCacheCounters::increment(&self.counters.negative_hits);
+            debug!(
+                "GEO:get_location [NEGATIVE_HIT] [req_id:{}] Recently unknown or failed address - ip: {}",
+                req_id,
+                ip_address
+            );
 
-        let location = self.fetch_from_api(ip_address, &req_id).await?;
+            return match negative {
+                Some(message) => Err(ApiError::InternalServerError { message }),
+                None => Ok(self.default_location()),
+            };
+        }
 
-        // 4. Cache the result
-        self.cache_location(ip_address, &location).await;
+        // 4. One lookup per address at a time; concurrent misses wait for its result
+        let flight = {
+            let mut in_flight = self.in_flight.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            InFlightGuard {
+                in_flight: &self.in_flight,
+                ip_address,
+                lookup: Arc::clone(in_flight.entry(ip_address.to_string()).or_default()),
+            }
+        };
+        // A leader cancelled mid-lookup releases the lock with no result and the next waiter takes over
+        let mut shared = flight.lookup.lock().await;
+        let location = match shared.as_ref() {
+            Some(result) => {
+                GeoCacheCounters::increment(&self.counters.coalesced);
+                debug!(
+                    "GEO:get_location [COALESCED] [req_id:{}] Used concurrent lookup result - ip: {}",
+                    req_id,
+                    ip_address
+                );
+                share_lookup(result)?
+            }
+            None => {
+                let result = self.lookup_uncached(ip_address, req_id.as_str()).await;
+                *shared = Some(share_lookup(&result));
+                result?
+            }
+        };
+        drop(shared);
+        drop(flight);
+
+        let Some(location) = location else {
+            return Ok(self.default_location());
+        };
+        self.counters.record_lookup(self.clock.now().duration_since(started));
 
         debug!(
             "GEO:get_location [SUCCESS] [req_id:{}] Location retrieved and cached - ip: {}, country: {}, city: {:?}",
@@ -202,12 +1109,33 @@ impl GeolocationService {
         Ok(location)
     }
 
+    #[cfg(feature = "geoip_db")]
+    async fn lookup_database(&self, ip_address: &str, req_id: &str) -> Option<LocationInfo> {
+        let database = self.database.as_ref()?;
+        GeoCacheCounters::increment(&self.counters.database_lookups);
+        let location = database.lookup(ip_address, &self.config.preferred_languages).await?;
+
+        debug!(
+            "GEO:get_location [DB_HIT] [req_id:{}] Found location in local database - ip: {}, country: {}",
+            req_id,
+            ip_address,
+            location.country_code
+        );
+
+        Some(location)
+    }
+
+    #[cfg(not(feature = "geoip_db"))]
+    async fn lookup_database(&self, _ip_address: &str, _req_id: &str) -> Option<LocationInfo> {
+        None
+    }
+
     /// Get location from cache if valid
-    async fn get_from_cache(&self, ip_address: &str) -> Option<LocationInfo> {
-        let cache = self.cache.read().await;
+    pub(crate) async fn get_from_cache(&self, ip_address: &str) -> Option<LocationInfo> {
+        let mut cache = self.cache.lock().await;
 
         if let Some(entry) = cache.get(ip_address) {
-            let age = entry.timestamp.elapsed();
+            let age = self.clock.now().duration_since(entry.timestamp);
             let ttl = Duration::from_secs(self.config.cache_ttl_seconds);
 
             if age < ttl {
@@ -219,55 +1147,87 @@ impl GeolocationService {
     }
 
     /// Cache location result
-    async fn cache_location(&self, ip_address: &str, location: &LocationInfo) {
-        let mut cache = self.cache.write().await;
+    pub(crate) async fn cache_location(&self, ip_address: &str, location: &LocationInfo) {
+        // A full cache evicts its least recently used entry; expired entries are never read, so
+        // they drift to the back and go first
+        let evicted = self.cache.lock().await.insert(ip_address.to_string(), CacheEntry {
+            location: location.clone(),
+            timestamp: self.clock.now(),
+        });
+        if evicted.is_some() {
+            GeoCacheCounters::increment(&self.counters.evictions);
+        }
+    }
 
-        // Clean old entries if cache is too large
-        if cache.len() >= self.config.max_cache_entries {
-            let now = Instant::now();
-            let ttl = Duration::from_secs(self.config.cache_ttl_seconds);
+    /// `Some(None)` for a recently unknown address, `Some(Some(error))` for a recently failed lookup
+    async fn get_from_negative_cache(&self, ip_address: &str) -> Option<Option<String>> {
+        let ttl = Duration::from_secs(self.config.negative_cache_ttl_seconds);
+        let mut negative_cache = self.negative_cache.lock().await;
+        let entry = negative_cache.get(ip_address)?;
 
-            cache.retain(|_, entry| now.duration_since(entry.timestamp) < ttl);
+        (self.clock.now().duration_since(entry.timestamp) < ttl).then(|| entry.error.clone())
+    }
 
-            // If still too large, remove oldest entries
-            if cache.len() >= self.config.max_cache_entries {
-                let mut entries_with_timestamps: Vec<(String, Instant)> = cache
-                    .iter()
-                    .map(|(ip, entry)| (ip.clone(), entry.timestamp))
-                    .collect();
+    async fn cache_negative(&self, ip_address: &str, error: Option<String>) {
+        if self.config.negative_cache_ttl_seconds == 0 {
+            return;
+        }
 
-                entries_with_timestamps.sort_by_key(|(_, timestamp)| *timestamp);
+        self.negative_cache.lock().await.insert(ip_address.to_string(), NegativeCacheEntry {
+            error,
+            timestamp: self.clock.now(),
+        });
+    }
 
-                let to_remove = cache.len() - self.config.max_cache_entries + 1;
-                for (ip, _) in entries_with_timestamps.into_iter().take(to_remove) {
-                    cache.remove(&ip);
+    /// Local database, then external providers; caches the outcome, negative ones included
+    async fn lookup_uncached(&self, ip_address: &str, req_id: &str) -> Result<Option<LocationInfo>, ApiError> {
+        GeoCacheCounters::increment(&self.counters.misses);
+        let location = match self.lookup_database(ip_address, req_id).await {
+            Some(location) => location,
+            None => {
+                debug!(
+                    "GEO:get_location [API_CALL] [req_id:{}] Cache miss, calling external API - ip: {}",
+                    req_id,
+                    ip_address
+                );
+
+                match self.fetch_from_api(ip_address, req_id).await {
+                    Ok(Some(location)) => location,
+                    Ok(None) => {
+                        self.cache_negative(ip_address, None).await;
+                        return Ok(None);
+                    }
+                    // Our own fallback rate limit says nothing about the address, and remembering it
+                    // would turn a few seconds of throttling into a full negative TTL of failures
+                    Err(e @ ApiError::TooManyRequests { .. }) => {
+                        return Err(e);
+                    }
+                    Err(e) => {
+                        self.cache_negative(ip_address, Some(e.to_string())).await;
+                        return Err(e);
+                    }
                 }
             }
-        }
+        };
 
-        cache.insert(ip_address.to_string(), CacheEntry {
-            location: location.clone(),
-            timestamp: Instant::now(),
-        });
+        self.cache_location(ip_address, &location).await;
+        Ok(Some(location))
     }
 
-    /// Fetch location from external API (MaxMind or fallback)
+    /// Fetch location from external API (MaxMind or fallback), `None` when the providers don't know the address
     async fn fetch_from_api(
         &self,
         ip_address: &str,
         req_id: &str
-    ) -> Result<LocationInfo, ApiError> {
+    ) -> Result<Option<LocationInfo>, ApiError> {
         // First try MaxMind if we have a valid API key
-        if
-            !self.config.api_key.is_empty() &&
-            self.config.api_key != "demo_key" &&
-            self.config.api_key != "your_maxmind_api_key"
-        {
+        if self.maxmind_configured() {
             match self.fetch_from_maxmind(ip_address, req_id).await {
-                Ok(location) => {
-                    return Ok(location);
+                Ok(lookup) => {
+                    return Ok(lookup);
                 }
                 Err(e) => {
+                    GeoCacheCounters::increment(&self.counters.provider_errors);
                     debug!(
                         "GEO:fetch_from_api [MAXMIND_FALLBACK] [req_id:{}] MaxMind failed, trying fallback - ip: {}, error: {}",
                         req_id,
@@ -278,8 +1238,29 @@ impl GeolocationService {
             }
         }
 
+        for provider in &self.providers {
+            GeoCacheCounters::increment(&self.counters.provider_calls);
+            match provider.lookup(ip_address).await {
+                Ok(lookup) => {
+                    return Ok(lookup);
+                }
+                Err(e) => {
+                    GeoCacheCounters::increment(&self.counters.provider_errors);
+                    debug!(
+                        "GEO:fetch_from_api [PROVIDER_FALLBACK] [req_id:{}] Provider failed, trying next - provider: {}, ip: {}, error: {}",
+                        req_id,
+                        provider.name(),
+                        ip_address,
+                        e
+                    );
+                }
+            }
+        }
+
         // Fallback to free service
-        self.fetch_from_fallback_service(ip_address, req_id).await
+        self.fetch_from_fallback_service(ip_address, req_id).await.inspect_err(|_| {
+            GeoCacheCounters::increment(&self.counters.provider_errors);
+        })
     }
 
     /// Fetch location from MaxMind API
@@ -287,9 +1268,10 @@ impl GeolocationService {
         &self,
         ip_address: &str,
         req_id: &str
-    ) -> Result<LocationInfo, ApiError> {
+    ) -> Result<Option<LocationInfo>, ApiError> {
         // Construct API URL
         let url = format!("{}/{}", self.config.service_url, ip_address);
+        GeoCacheCounters::increment(&self.counters.maxmind_calls);
 
         debug!(
             "GEO:fetch_from_api [API_REQUEST] [req_id:{}] Calling MaxMind API - url: {}",
@@ -298,12 +1280,15 @@ impl GeolocationService {
         );
 
         // Build request with authentication and timeout
-        let response = self.client
-            .get(&url)
-            .basic_auth(&self.config.api_key, Some(""))
-            .timeout(Duration::from_secs(self.config.timeout_seconds))
-            .send().await
+        let response = self
+            .send_with_retry("GEO:fetch_from_api", req_id, || {
+                self.client
+                    .get(&url)
+                    .basic_auth(self.config.api_key.expose_secret(), Some(""))
+                    .timeout(Duration::from_secs(self.config.timeout_seconds))
+            }).await
             .map_err(|e| {
+                let e = redact_uris(&e.to_string());
                 error!(
                     "GEO:fetch_from_api [API_ERROR] [req_id:{}] Request failed - ip: {}, error: {}",
                     req_id,
@@ -336,7 +1321,7 @@ impl GeolocationService {
                     });
                 }
                 404 => {
-                    return Ok(self.default_location());
+                    return Ok(None);
                 } // IP not found, use default
                 429 => {
                     return Err(ApiError::InternalServerError {
@@ -375,7 +1360,7 @@ impl GeolocationService {
             location.city
         );
 
-        Ok(location)
+        Ok(Some(location))
     }
 
     /// Fetch location from fallback free service (ip-api.com)
@@ -383,8 +1368,10 @@ impl GeolocationService {
         &self,
         ip_address: &str,
         req_id: &str
-    ) -> Result<LocationInfo, ApiError> {
-        let url = format!("http://ip-api.com/json/{ip_address}");
+    ) -> Result<Option<LocationInfo>, ApiError> {
+        let url = format!("{}/{}", self.config.fallback_service_url, ip_address);
+        self.acquire_fallback_slot(ip_address, req_id).await?;
+        GeoCacheCounters::increment(&self.counters.fallback_calls);
 
         debug!(
             "GEO:fetch_from_fallback_service [API_REQUEST] [req_id:{}] Calling fallback API - url: {}",
@@ -392,11 +1379,24 @@ impl GeolocationService {
             url
         );
 
-        let response = self.client
-            .get(&url)
-            .timeout(Duration::from_secs(self.config.timeout_seconds))
-            .send().await
+        let mut query = vec![("fields", IP_API_FIELDS)];
+        if let Some(lang) = ip_api_language(&self.config.preferred_languages) {
+            query.push(("lang", lang));
+        }
+        if !self.config.fallback_api_key.is_empty() {
+            query.push(("key", self.config.fallback_api_key.expose_secret()));
+        }
+
+        let response = self
+            .send_with_retry("GEO:fetch_from_fallback_service", req_id, || {
+                self.client
+                    .get(&url)
+                    .query(&query)
+                    .timeout(Duration::from_secs(self.config.timeout_seconds))
+            }).await
             .map_err(|e| {
+                // The pro endpoint takes its key as a query parameter
+                let e = redact_uris(&e.to_string());
                 error!(
                     "GEO:fetch_from_fallback_service [API_ERROR] [req_id:{}] Request failed - ip: {}, error: {}",
                     req_id,
@@ -416,7 +1416,7 @@ impl GeolocationService {
                 ip_address,
                 status
             );
-            return Ok(self.default_location());
+            return Ok(None);
         }
 
         // Parse ip-api.com response format
@@ -439,17 +1439,21 @@ impl GeolocationService {
                 ip_address,
                 fallback_response.message
             );
-            return Ok(self.default_location());
+            return Ok(None);
         }
 
         let location = LocationInfo {
             country_code: fallback_response.country_code,
             country_name: fallback_response.country,
+            continent_code: non_empty(fallback_response.continent_code),
             city: Some(fallback_response.city),
             region: Some(fallback_response.region_name),
             latitude: Some(fallback_response.lat),
             longitude: Some(fallback_response.lon),
             timezone: Some(fallback_response.timezone),
+            asn: parse_asn(&fallback_response.as_name),
+            isp: non_empty(fallback_response.isp),
+            organization: non_empty(fallback_response.org),
         };
 
         debug!(
@@ -460,37 +1464,138 @@ impl GeolocationService {
             location.city
         );
 
-        Ok(location)
+        Ok(Some(location))
+    }
+
+    /// Wait for or reject on the free fallback's rate limit
+    async fn acquire_fallback_slot(&self, ip_address: &str, req_id: &str) -> Result<(), ApiError> {
+        let Some(limiter) = &self.fallback_limiter else {
+            return Ok(());
+        };
+
+        let max_wait = match self.config.fallback_rate_limit {
+            RateLimitBehavior::Queue { max_wait } => max_wait,
+            RateLimitBehavior::FailFast => Duration::ZERO,
+        };
+        let reservation = limiter.lock().await.reserve(self.clock.now(), max_wait);
+
+        match reservation {
+            Some(wait) if wait.is_zero() => Ok(()),
+            Some(wait) => {
+                debug!(
+                    "GEO:fetch_from_fallback_service [RATE_LIMIT] [req_id:{}] Queued for fallback capacity - ip: {}, wait_ms: {}",
+                    req_id,
+                    ip_address,
+                    wait.as_millis()
+                );
+                self.clock.sleep(wait).await;
+                Ok(())
+            }
+            None => {
+                warn!(
+                    "GEO:fetch_from_fallback_service [RATE_LIMIT] [req_id:{}] Fallback rate limit reached - ip: {}, limit_per_minute: {}",
+                    req_id,
+                    ip_address,
+                    self.config.fallback_requests_per_minute
+                );
+                Err(ApiError::TooManyRequests {
+                    message: "Fallback geolocation service rate limit reached".to_string(),
+                    retry_after_seconds: (60 / self.config.fallback_requests_per_minute.max(1)).max(1) as u64,
+                })
+            }
+        }
+    }
+
+    /// Send a provider request, retrying connection errors and 5xx responses with exponential backoff
+    /// Timeouts are not retried since each attempt already waited the full provider timeout.
+    async fn send_with_retry(
+        &self,
+        operation: &str,
+        req_id: &str,
+        request: impl Fn() -> reqwest::RequestBuilder
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let mut attempt = 0;
+
+        loop {
+            let result = request().send().await;
+            let retryable = match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(e) => e.is_connect() && !e.is_timeout(),
+            };
+
+            if !retryable || attempt >= self.config.retry_attempts {
+                return result;
+            }
+
+            let delay = self.retry_delay(attempt);
+            attempt += 1;
+            warn!(
+                "{} [RETRY] [req_id:{}] Transient provider failure, retrying - attempt: {}/{}, delay_ms: {}, status: {:?}",
+                operation,
+                req_id,
+                attempt,
+                self.config.retry_attempts,
+                delay.as_millis(),
+                result.as_ref().map(|response| response.status()).ok()
+            );
+            self.clock.sleep(delay).await;
+        }
+    }
+
+    fn retry_delay(&self, attempt: u32) -> Duration {
+        let delay_ms = self.config.retry_base_delay_ms.saturating_mul(1 << attempt.min(16));
+
+        if self.config.retry_jitter && delay_ms > 1 {
+            Duration::from_millis(delay_ms / 2 + rand::rng().random_range(0..=delay_ms / 2))
+        } else {
+            Duration::from_millis(delay_ms)
+        }
     }
 
     /// Convert MaxMind response to our LocationInfo format
     fn convert_maxmind_response(&self, response: MaxMindResponse) -> LocationInfo {
+        let languages = &self.config.preferred_languages;
+        let name = |names: &HashMap<String, String>| {
+            localized_name(languages, |language| names.get(language).cloned())
+        };
+
+        let continent_code = response.continent.and_then(|continent| continent.code);
         let country_code = response.country.iso_code;
-        let country_name = response.country.names
-            .get("en")
-            .cloned()
-            .unwrap_or_else(|| country_code.clone());
+        let country_name = name(&response.country.names).unwrap_or_else(|| country_code.clone());
 
-        let city = response.city.and_then(|c| c.names.get("en").cloned());
+        let city = response.city.and_then(|c| name(&c.names));
 
         let region = response.subdivisions
             .as_ref()
             .and_then(|subdivisions| subdivisions.first())
-            .and_then(|subdivision| subdivision.names.get("en"))
-            .cloned();
+            .and_then(|subdivision| name(&subdivision.names));
 
         let (latitude, longitude, timezone) = response.location
             .map(|loc| (loc.latitude, loc.longitude, loc.time_zone))
             .unwrap_or((None, None, None));
 
+        let (asn, isp, organization) = response.traits
+            .map(|traits| {
+                (
+                    traits.autonomous_system_number,
+                    traits.isp,
+                    traits.organization.or(traits.autonomous_system_organization),
+                )
+            })
+            .unwrap_or((None, None, None));
+
         LocationInfo {
             country_code,
             country_name,
+            continent_code,
             city,
             region,
             latitude,
             longitude,
             timezone,
+            asn,
+            isp,
+            organization,
         }
     }
 
@@ -499,58 +1604,553 @@ impl GeolocationService {
         LocationInfo {
             country_code: "US".to_string(),
             country_name: "United States".to_string(),
+            continent_code: None,
             city: None,
             region: None,
             latitude: None,
             longitude: None,
             timezone: None,
+            asn: None,
+            isp: None,
+            organization: None,
         }
     }
 
-    /// Health check for geolocation service
-    pub async fn health_check(&self) -> Result<(), ApiError> {
-        let req_id = generate_correlation_id();
+    /// Health of the geolocation setup, one component per check
+    ///
+    /// `Shallow` only checks the configuration and that the caches respond, so it is cheap enough
+    /// for load balancer probes. `Deep` also sends a HEAD request to every configured provider:
+    /// any HTTP answer counts as reachable, so no lookup quota is used and the cache is untouched.
+    /// An unreachable provider is `Degraded` while another one still answers, `Down` otherwise.
+    pub async fn health_check(&self, mode: HealthCheckMode) -> HealthReport {
+        let req_id = RequestId::new();
+        debug!("GEO:health_check [START] [req_id:{}] Checking geolocation health - mode: {:?}", req_id, mode);
+
+        let mut components = vec![
+            match self.config.validate() {
+                Ok(()) => HealthStatus::up("geolocation.config", 0),
+                Err(e) => HealthStatus::down("geolocation.config", 0, &e.to_string()),
+            },
+            self.cache_health().await
+        ];
+
+        if mode == HealthCheckMode::Deep {
+            let mut providers = Vec::new();
+            if self.maxmind_configured() {
+                providers.push(self.ping_provider("maxmind", &self.config.service_url).await);
+            }
+            for provider in &self.providers {
+                let started = self.clock.now();
+                let result = provider.ping().await;
+                providers.push(self.provider_health(provider.name(), started, result));
+            }
+            providers.push(self.ping_provider("ip_api", &self.config.fallback_service_url).await);
 
-        debug!("GEO:health_check [START] [req_id:{}] Testing service connectivity", req_id);
+            // Lookups fall through to the next provider, so one outage only degrades the service
+            let any_reachable = providers.iter().any(|provider| provider.status == HealthState::Up);
+            if any_reachable {
+                for provider in providers.iter_mut().filter(|provider| provider.status == HealthState::Down) {
+                    provider.status = HealthState::Degraded;
+                }
+            }
+            components.extend(providers);
+        }
 
-        // Test with a known IP (Google DNS)
-        match self.get_location("8.8.8.8").await {
-            Ok(location) => {
-                info!(
-                    "GEO:health_check [SUCCESS] [req_id:{}] Service healthy - test_country: {}",
+        let report = HealthReport::new(&BuildInfo::current().version, components);
+        match report.status {
+            HealthState::Up => {
+                info!("GEO:health_check [SUCCESS] [req_id:{}] Service healthy - mode: {:?}", req_id, mode);
+            }
+            status => {
+                let failing: Vec<String> = report.components
+                    .iter()
+                    .filter(|component| component.status != HealthState::Up)
+                    .map(|component| format!("{} ({})", component.component, component.last_error.as_deref().unwrap_or("")))
+                    .collect();
+                warn!(
+                    "GEO:health_check [UNHEALTHY] [req_id:{}] Service {:?} - mode: {:?}, components: {}",
                     req_id,
-                    location.country_code
+                    status,
+                    mode,
+                    failing.join(", ")
                 );
-                Ok(())
+            }
+        }
+
+        report
+    }
+
+    fn maxmind_configured(&self) -> bool {
+        !self.config.api_key.is_empty() &&
+            self.config.api_key.expose_secret() != "demo_key" &&
+            self.config.api_key.expose_secret() != "your_maxmind_api_key"
+    }
+
+    /// The caches respond within a second; a held lock means lookups are stuck
+    async fn cache_health(&self) -> HealthStatus {
+        let started = self.clock.now();
+        let locked = tokio::time::timeout(Duration::from_secs(1), async {
+            let positive = self.cache.lock().await.len();
+            let negative = self.negative_cache.lock().await.len();
+            positive + negative
+        }).await;
+        let latency_ms = self.clock.now().duration_since(started).as_millis() as u64;
+
+        match locked {
+            Ok(_) => HealthStatus::up("geolocation.cache", latency_ms),
+            Err(_) => HealthStatus::degraded("geolocation.cache", latency_ms, "Cache lock is held for over a second"),
+        }
+    }
+
+    async fn ping_provider(&self, name: &str, url: &str) -> HealthStatus {
+        let started = self.clock.now();
+        let result = ping_url(&self.client, url, Duration::from_secs(self.config.timeout_seconds)).await;
+        self.provider_health(name, started, result)
+    }
+
+    fn provider_health(&self, name: &str, started: Instant, result: Result<(), ApiError>) -> HealthStatus {
+        let component = format!("geolocation.{}", name);
+        let latency_ms = self.clock.now().duration_since(started).as_millis() as u64;
+
+        match result {
+            Ok(()) => HealthStatus::up(&component, latency_ms),
+            Err(e) => HealthStatus::down(&component, latency_ms, &e.to_string()),
+        }
+    }
+
+    /// Remove expired entries from every cache; returns how many were removed
+    pub async fn evict_expired(&self) -> usize {
+        let now = self.clock.now();
+        let ttl = Duration::from_secs(self.config.cache_ttl_seconds);
+        let negative_ttl = Duration::from_secs(self.config.negative_cache_ttl_seconds);
+        let fresh = |timestamp: &Instant, ttl: Duration| now.duration_since(*timestamp) < ttl;
+
+        // One lock at a time so lookups are never blocked on more than one sweep
+        let mut removed = 0;
+        {
+            let mut cache = self.cache.lock().await;
+            let before = cache.len();
+            cache.retain(|_, entry| fresh(&entry.timestamp, ttl));
+            removed += before - cache.len();
+        }
+        {
+            let mut negative_cache = self.negative_cache.lock().await;
+            let before = negative_cache.len();
+            negative_cache.retain(|_, entry| fresh(&entry.timestamp, negative_ttl));
+            removed += before - negative_cache.len();
+        }
+        {
+            let mut risk_cache = self.risk_cache.lock().await;
+            let before = risk_cache.len();
+            risk_cache.retain(|_, (_, timestamp)| fresh(timestamp, ttl));
+            removed += before - risk_cache.len();
+        }
+        {
+            let mut reverse_cache = self.reverse_cache.lock().await;
+            let before = reverse_cache.len();
+            reverse_cache.retain(|_, (_, timestamp)| fresh(timestamp, ttl));
+            removed += before - reverse_cache.len();
+        }
+
+        removed
+    }
+
+    /// Spawn the task sweeping expired cache entries every `cache_eviction_interval_seconds`
+    ///
+    /// Lookups never clean up themselves, so writes stay O(1). The task ends on `shutdown` or when
+    /// the service is dropped; spawning again replaces a running task.
+    pub fn spawn_eviction_task(self: &Arc<Self>) {
+        let service = Arc::downgrade(self);
+        let stop = Arc::new(Notify::new());
+        let stopped = Arc::clone(&stop);
+        let interval = Duration::from_secs(self.config.cache_eviction_interval_seconds.max(1));
+
+        let handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // The first tick completes immediately and the caches start empty
+            ticker.tick().await;
+
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = stopped.notified() => return,
+                }
+
+                let Some(service) = service.upgrade() else {
+                    return;
+                };
+                let removed = service.evict_expired().await;
+                if removed > 0 {
+                    debug!("GEO:evict_expired [SWEEP] Expired cache entries removed - count: {}", removed);
+                }
+            }
+        });
+
+        let previous = self.eviction_task
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .replace((handle, stop));
+        if let Some((previous, _)) = previous {
+            previous.abort();
+        }
+    }
+
+    /// Stop the eviction task and wait for a sweep in progress to finish
+    pub async fn shutdown(&self) {
+        let task = self.eviction_task.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+
+        if let Some((handle, stop)) = task {
+            stop.notify_one();
+            let _ = handle.await;
+            info!("GEO:shutdown [STOPPED] Cache eviction task stopped");
+        }
+    }
+
+    /// Copy the unexpired cache entries, most recently used first, e.g. to upload to S3 on shutdown
+    pub async fn export_cache(&self) -> CacheSnapshot {
+        let cache = self.cache.lock().await;
+        let now = self.clock.now();
+        let now_utc = self.clock.now_utc();
+        let ttl = Duration::from_secs(self.config.cache_ttl_seconds);
+
+        let entries = cache
+            .iter()
+            .filter(|(_, entry)| now.duration_since(entry.timestamp) < ttl)
+            .map(|(ip_address, entry)| {
+                let age = chrono::Duration::from_std(now.duration_since(entry.timestamp)).unwrap_or_default();
+
+                CacheSnapshotEntry {
+                    ip_address: ip_address.clone(),
+                    location: entry.location.clone(),
+                    cached_at: now_utc - age,
+                }
+            })
+            .collect();
+
+        CacheSnapshot { entries }
+    }
+
+    /// Load a snapshot, skipping entries that expired meanwhile; returns the number loaded
+    pub async fn import_cache(&self, snapshot: CacheSnapshot) -> usize {
+        let now = self.clock.now();
+        let now_utc = self.clock.now_utc();
+        let ttl = Duration::from_secs(self.config.cache_ttl_seconds);
+        let mut cache = self.cache.lock().await;
+        let room = cache.capacity() - cache.len();
+
+        let entries: Vec<(String, CacheEntry)> = snapshot.entries
+            .into_iter()
+            .filter_map(|entry| {
+                let age = (now_utc - entry.cached_at).to_std().unwrap_or_default();
+                let timestamp = now.checked_sub(age).filter(|_| age < ttl)?;
+                Some((entry.ip_address, CacheEntry { location: entry.location, timestamp }))
+            })
+            .take(room)
+            .collect();
+        let loaded = entries.len();
+
+        // Least recent first, so the snapshot's most recent entry ends up most recently used
+        for (ip_address, entry) in entries.into_iter().rev() {
+            cache.insert(ip_address, entry);
+        }
+
+        loaded
+    }
+
+    /// Write the cache to a JSON file, typically on shutdown
+    pub async fn save_cache_snapshot(&self, path: impl AsRef<Path>) -> Result<usize, ApiError> {
+        let snapshot = self.export_cache().await;
+        let bytes = serde_json::to_vec(&snapshot).map_err(|e| ApiError::InternalServerError {
+            message: format!("Failed to serialize geolocation cache: {e}"),
+        })?;
+
+        tokio::fs::write(path.as_ref(), bytes).await.map_err(|e| ApiError::InternalServerError {
+            message: format!("Failed to write geolocation cache snapshot '{}': {e}", path.as_ref().display()),
+        })?;
+
+        info!(
+            "GEO:save_cache_snapshot [SUCCESS] Cache snapshot written - path: {}, entries: {}",
+            path.as_ref().display(),
+            snapshot.entries.len()
+        );
+        Ok(snapshot.entries.len())
+    }
+
+    /// Preload the cache from a snapshot file; a missing file is not an error on first deploy
+    pub async fn load_cache_snapshot(&self, path: impl AsRef<Path>) -> Result<usize, ApiError> {
+        let bytes = match tokio::fs::read(path.as_ref()).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(0);
             }
             Err(e) => {
-                error!(
-                    "GEO:health_check [FAILED] [req_id:{}] Service unhealthy - error: {}",
-                    req_id,
-                    e
-                );
-                Err(e)
+                return Err(ApiError::InternalServerError {
+                    message: format!("Failed to read geolocation cache snapshot '{}': {e}", path.as_ref().display()),
+                });
             }
+        };
+        let snapshot: CacheSnapshot = serde_json::from_slice(&bytes).map_err(|e| ApiError::InternalServerError {
+            message: format!("Invalid geolocation cache snapshot '{}': {e}", path.as_ref().display()),
+        })?;
+
+        let loaded = self.import_cache(snapshot).await;
+        info!(
+            "GEO:load_cache_snapshot [SUCCESS] Cache preloaded - path: {}, entries: {}",
+            path.as_ref().display(),
+            loaded
+        );
+        Ok(loaded)
+    }
+
+    /// Look up addresses that are not cached yet, at most `concurrency` at a time
+    /// Failed lookups are logged and skipped; returns the number of addresses now cached.
+    pub async fn warm_cache(self: &Arc<Self>, ip_addresses: Vec<String>, concurrency: usize) -> usize {
+        let semaphore = Arc::new(tokio::sync::Semaphore::new(concurrency.max(1)));
+        let mut lookups = tokio::task::JoinSet::new();
+
+        for ip_address in ip_addresses {
+            let service = Arc::clone(self);
+            let semaphore = Arc::clone(&semaphore);
+
+            lookups.spawn(async move {
+                let _permit = semaphore.acquire_owned().await.ok()?;
+                match service.get_location(&ip_address).await {
+                    Ok(_) => Some(()),
+                    Err(e) => {
+                        warn!("GEO:warm_cache [LOOKUP_ERROR] Skipping address - ip: {}, error: {}", ip_address, e);
+                        None
+                    }
+                }
+            });
         }
+
+        let mut warmed = 0;
+        while let Some(result) = lookups.join_next().await {
+            if let Ok(Some(())) = result {
+                warmed += 1;
+            }
+        }
+
+        warmed
     }
 
-    /// Get cache statistics for monitoring
-    pub async fn get_cache_stats(&self) -> (usize, usize) {
-        let cache = self.cache.read().await;
+    /// Get cache and provider metrics for monitoring
+    pub async fn get_cache_stats(&self) -> GeoCacheMetrics {
+        let cache = self.cache.lock().await;
         let total_entries = cache.len();
 
-        let now = Instant::now();
+        let now = self.clock.now();
         let ttl = Duration::from_secs(self.config.cache_ttl_seconds);
         let valid_entries = cache
-            .values()
-            .filter(|entry| now.duration_since(entry.timestamp) < ttl)
+            .iter()
+            .filter(|(_, entry)| now.duration_since(entry.timestamp) < ttl)
             .count();
 
-        (total_entries, valid_entries)
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let lookups = load(&self.counters.lookups);
+        let average_lookup_latency_ms = if lookups == 0 {
+            0.0
+        } else {
+            (load(&self.counters.lookup_micros) as f64) / (lookups as f64) / 1000.0
+        };
+
+        GeoCacheMetrics {
+            total_entries,
+            valid_entries,
+            hits: load(&self.counters.hits),
+            misses: load(&self.counters.misses),
+            negative_hits: load(&self.counters.negative_hits),
+            coalesced: load(&self.counters.coalesced),
+            evictions: load(&self.counters.evictions),
+            database_lookups: load(&self.counters.database_lookups),
+            maxmind_calls: load(&self.counters.maxmind_calls),
+            provider_calls: load(&self.counters.provider_calls),
+            fallback_calls: load(&self.counters.fallback_calls),
+            provider_errors: load(&self.counters.provider_errors),
+            average_lookup_latency_ms,
+        }
+    }
+}
+
+/// Name in the first available preferred language, trying the base language of regional tags
+/// ("pt-BR", then "pt") and falling back to English
+pub(crate) fn localized_name(
+    preferred_languages: &[String],
+    name_for: impl Fn(&str) -> Option<String>
+) -> Option<String> {
+    preferred_languages
+        .iter()
+        .flat_map(|language| [language.as_str(), language.split('-').next().unwrap_or_default()])
+        .chain(["en"])
+        .find_map(name_for)
+}
+
+/// First preferred language ip-api.com can localize names into
+fn ip_api_language(preferred_languages: &[String]) -> Option<&'static str> {
+    const SUPPORTED: &[&str] = &["en", "de", "es", "pt-BR", "fr", "ja", "zh-CN", "ru"];
+
+    preferred_languages.iter().find_map(|language| {
+        SUPPORTED.iter()
+            .find(|supported| {
+                supported.eq_ignore_ascii_case(language) ||
+                    supported.split('-').next() == language.split('-').next()
+            })
+            .copied()
+    })
+}
+
+/// HEAD request to a provider; any HTTP response means it is reachable
+pub(crate) async fn ping_url(client: &Client, url: &str, timeout: Duration) -> Result<(), ApiError> {
+    client
+        .head(url)
+        .timeout(timeout)
+        .send().await
+        .map(|_| ())
+        .map_err(|e| ApiError::InternalServerError {
+            message: format!("Provider unreachable: {}", redact_uris(&e.to_string())),
+        })
+}
+
+/// ASN from ip-api's `as` field, e.g. 3320 from "AS3320 Deutsche Telekom AG"
+pub(crate) fn parse_asn(as_name: &str) -> Option<u32> {
+    as_name.strip_prefix("AS")?.split_whitespace().next()?.parse().ok()
+}
+
+pub(crate) fn non_empty(value: String) -> Option<String> {
+    if value.trim().is_empty() { None } else { Some(value) }
+}
+
+/// Parse and normalize an IP address for lookups and cache keys
+///
+/// Accepts bracketed IPv6 (`[2001:db8::1]`), returns IPv6 in canonical compressed form and
+/// IPv4-mapped IPv6 (`::ffff:192.0.2.1`) as plain IPv4.
+pub fn normalize_ip_address(ip_address: &str) -> Result<String, ApiError> {
+    parse_ip_address(ip_address).map(|ip| ip.to_string())
+}
+
+fn parse_ip_address(ip_address: &str) -> Result<IpAddr, ApiError> {
+    let trimmed = ip_address.trim();
+    let unbracketed = trimmed
+        .strip_prefix('[')
+        .and_then(|ip| ip.strip_suffix(']'))
+        .unwrap_or(trimmed);
+
+    let ip: IpAddr = unbracketed.parse().map_err(|_| ApiError::BadRequest {
+        message: format!("Invalid IP address: '{}'", ip_address),
+    })?;
+
+    let normalized = match ip {
+        IpAddr::V6(ipv6) =>
+            match ipv6.to_ipv4_mapped() {
+                Some(ipv4) => IpAddr::V4(ipv4),
+                None => IpAddr::V6(ipv6),
+            }
+        ipv4 => ipv4,
+    };
+
+    Ok(normalized)
+}
+
+/// IPv4 address embedded in a NAT64 well-known prefix address (64:ff9b::/96)
+fn nat64_embedded_ipv4(ipv6: &Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = ipv6.segments();
+    if segments[..6] != [0x64, 0xff9b, 0, 0, 0, 0] {
+        return None;
+    }
+    let [.., a, b, c, d] = ipv6.octets();
+    Some(Ipv4Addr::new(a, b, c, d))
+}
+
+/// Whether an address is private (RFC 1918 / unique local), loopback, link-local, CGNAT or
+/// "this network"; IPv4-mapped and NAT64 addresses are judged by the IPv4 address they carry
+pub fn is_internal_ip(ip: &IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ipv4) => {
+            let [first, second, ..] = ipv4.octets();
+            let is_cgnat = first == 100 && (second & 0b1100_0000) == 64; // 100.64.0.0/10
+            let is_this_network = first == 0; // 0.0.0.0/8
+
+            ipv4.is_private() || ipv4.is_loopback() || ipv4.is_link_local() || is_this_network || is_cgnat
+        }
+        IpAddr::V6(ipv6) => {
+            if let Some(ipv4) = nat64_embedded_ipv4(&ipv6) {
+                return is_internal_ip(&IpAddr::V4(ipv4));
+            }
+            let first_segment = ipv6.segments()[0];
+            let is_unique_local = (first_segment & 0xfe00) == 0xfc00; // fc00::/7
+            let is_link_local = (first_segment & 0xffc0) == 0xfe80; // fe80::/10
+
+            ipv6.is_loopback() || ipv6.is_unspecified() || is_unique_local || is_link_local
+        }
+    }
+}
+
+/// Whether an address is globally reachable unicast, for outbound requests to user-supplied hosts
+///
+/// Besides internal addresses this excludes the IETF protocol, documentation and benchmarking
+/// ranges, multicast, reserved and broadcast addresses. Geolocation only skips internal addresses,
+/// so documentation ranges stay usable as test addresses there.
+pub fn is_public_ip(ip: &IpAddr) -> bool {
+    if is_internal_ip(ip) {
+        return false;
+    }
+    match ip.to_canonical() {
+        IpAddr::V4(ipv4) => {
+            let [first, second, third, _] = ipv4.octets();
+            let is_special = match (first, second, third) {
+                (192, 0, 0 | 2) | (198, 51, 100) | (203, 0, 113) => true, // IETF, TEST-NET-1/2/3
+                (198, 18 | 19, _) => true, // 198.18.0.0/15 benchmarking
+                _ => first >= 224, // 224.0.0.0/4 multicast, 240.0.0.0/4 reserved, broadcast
+            };
+            !is_special
+        }
+        IpAddr::V6(ipv6) => {
+            if let Some(ipv4) = nat64_embedded_ipv4(&ipv6) {
+                return is_public_ip(&IpAddr::V4(ipv4));
+            }
+            let segments = ipv6.segments();
+            let is_documentation = segments[0] == 0x2001 && segments[1] == 0x0db8; // 2001:db8::/32
+            // ::/96, including the deprecated IPv4-compatible addresses
+            let is_ipv4_compatible = segments[..6].iter().all(|segment| *segment == 0);
+
+            !ipv6.is_multicast() && !is_documentation && !is_ipv4_compatible
+        }
     }
 }
 
+/// Client IP from an RFC 7239 `Forwarded` header value
+///
+/// Takes the `for` parameter of the first element, as with X-Forwarded-For, accepting quoted
+/// IPv6 (`for="[2001:db8::1]:4711"`) and ports. Obfuscated (`_hidden`) and `unknown` nodes give `None`.
+pub fn parse_forwarded_for(forwarded: &str) -> Option<String> {
+    let mut in_quotes = false;
+    let first_element = forwarded
+        .split(|c: char| {
+            if c == '"' {
+                in_quotes = !in_quotes;
+            }
+            c == ',' && !in_quotes
+        })
+        .next()?;
+
+    let node = first_element
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+        .map(|(_, value)| value.trim().trim_matches('"'))?;
+
+    let address = match node.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next()?,
+        // A single colon separates an IPv4 address from its port
+        None if node.matches(':').count() == 1 => node.split(':').next()?,
+        None => node,
+    };
+
+    address.parse::<IpAddr>().ok().map(|ip| ip.to_string())
+}
+
 /// Extract real client IP from request headers (handles API Gateway forwarding)
+#[cfg(not(feature = "no_web"))]
 pub fn extract_client_ip_from_headers(headers: &rocket::http::HeaderMap) -> Option<String> {
     // Try X-Forwarded-For first (API Gateway standard)
     if let Some(forwarded_for) = headers.get_one("X-Forwarded-For") {
@@ -564,6 +2164,11 @@ pub fn extract_client_ip_from_headers(headers: &rocket::http::HeaderMap) -> Opti
         }
     }
 
+    // Try Forwarded (RFC 7239 standard)
+    if let Some(client_ip) = headers.get_one("Forwarded").and_then(parse_forwarded_for) {
+        return Some(client_ip);
+    }
+
     // Try X-Real-IP (Nginx proxy standard)
     if let Some(real_ip) = headers.get_one("X-Real-IP") {
         let trimmed_ip = real_ip.trim();
@@ -591,11 +2196,202 @@ pub fn extract_client_ip_from_headers(headers: &rocket::http::HeaderMap) -> Opti
     None
 }
 
+/// Request guard resolving the client's location from forwarded IP headers
+///
+/// Requires an `Arc<GeolocationService>` in managed state. `location` is `None` when no client IP
+/// was forwarded or the lookup failed, so handlers decide how to treat unknown locations.
+#[cfg(not(feature = "no_web"))]
+#[derive(Debug, Clone)]
+pub struct ClientLocation {
+    pub ip_address: Option<String>,
+    pub location: Option<LocationInfo>,
+}
+
+#[cfg(not(feature = "no_web"))]
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientLocation {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(service) = request.rocket().state::<Arc<GeolocationService>>() else {
+            error!("GEO:client_location [CONFIG] GeolocationService is not managed by Rocket");
+            return Outcome::Error((
+                rocket::http::Status::InternalServerError,
+                ApiError::InternalServerError {
+                    message: "Geolocation is not configured".to_string(),
+                },
+            ));
+        };
+
+        let client_location = request.local_cache_async(async {
+            let ip_address = extract_client_ip_from_headers(request.headers());
+            let location = match &ip_address {
+                Some(ip_address) =>
+                    match service.get_location(ip_address).await {
+                        Ok(location) => Some(location),
+                        Err(e) => {
+                            warn!(
+                                "GEO:client_location [LOOKUP_ERROR] Client location unavailable - ip: {}, error: {}",
+                                ip_address,
+                                e
+                            );
+                            None
+                        }
+                    }
+                None => None,
+            };
+
+            ClientLocation { ip_address, location }
+        }).await;
+
+        Outcome::Success(client_location.clone())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common_lib::clock::MockClock;
+    use crate::common_lib::health::HealthState;
+    use crate::common_lib::test_http_stubs::{ fixtures, ProviderStubServer };
+
+    fn stubbed_service(config: GeolocationConfig) -> GeolocationService {
+        GeolocationService::new(Arc::new(Client::new()), config).unwrap()
+    }
+
+    #[test]
+    fn test_normalize_ip_address() {
+        assert_eq!(normalize_ip_address(" 203.0.113.7 ").unwrap(), "203.0.113.7");
+        assert_eq!(normalize_ip_address("2001:0DB8:0000::0001").unwrap(), "2001:db8::1");
+        assert_eq!(normalize_ip_address("[2001:db8::1]").unwrap(), "2001:db8::1");
+        assert_eq!(normalize_ip_address("::ffff:192.0.2.1").unwrap(), "192.0.2.1");
+
+        for malformed in ["not-an-ip", "256.1.1.1", "203.0.113", "2001:db8::1::2"] {
+            assert!(matches!(normalize_ip_address(malformed), Err(ApiError::BadRequest { .. })));
+        }
+    }
+
+    #[test]
+    fn test_parse_forwarded_for() {
+        assert_eq!(parse_forwarded_for("for=192.0.2.60;proto=http;by=203.0.113.43").as_deref(), Some("192.0.2.60"));
+        assert_eq!(parse_forwarded_for("For=\"[2001:db8:cafe::17]:4711\"").as_deref(), Some("2001:db8:cafe::17"));
+        assert_eq!(parse_forwarded_for("for=\"192.0.2.43:47011\", for=198.51.100.17").as_deref(), Some("192.0.2.43"));
+        assert_eq!(parse_forwarded_for("proto=https;for=\"[::1]\";host=example.com").as_deref(), Some("::1"));
+
+        for unusable in ["for=unknown", "for=_hidden, for=198.51.100.17", "proto=https", ""] {
+            assert_eq!(parse_forwarded_for(unusable), None, "{unusable}");
+        }
+    }
+
+    #[test]
+    fn test_localized_names() {
+        let names: HashMap<&str, &str> = HashMap::from([("en", "Munich"), ("de", "München"), ("pt-BR", "Munique")]);
+        let name = |languages: &[&str]| {
+            let languages: Vec<String> = languages.iter().map(|language| language.to_string()).collect();
+            localized_name(&languages, |language| names.get(language).map(|name| name.to_string()))
+        };
+
+        assert_eq!(name(&["pt-BR"]).as_deref(), Some("Munique"));
+        assert_eq!(name(&["de-AT"]).as_deref(), Some("München"));
+        assert_eq!(name(&["ja", "de"]).as_deref(), Some("München"));
+        assert_eq!(name(&["fr"]).as_deref(), Some("Munich"));
+
+        let languages = vec!["pt-PT".to_string()];
+        assert_eq!(ip_api_language(&languages), Some("pt-BR"));
+        assert_eq!(ip_api_language(&["it".to_string()]), None);
+    }
+
+    #[test]
+    fn test_ip_risk_dataset() {
+        let dataset = IpRiskDataset::from_csv(
+            "# cloud and tor ranges\n203.0.113.0/24,datacenter\n203.0.113.7,tor\n2001:db8::/32,vpn\n"
+        ).unwrap();
+
+        assert_eq!(dataset.len(), 3);
+        assert_eq!(dataset.lookup(&"203.0.113.7".parse().unwrap()), Some(IpRiskCategory::Tor));
+        assert_eq!(dataset.lookup(&"203.0.113.8".parse().unwrap()), Some(IpRiskCategory::Datacenter));
+        assert_eq!(dataset.lookup(&"2001:db8::1".parse().unwrap()), Some(IpRiskCategory::Vpn));
+        assert_eq!(dataset.lookup(&"198.51.100.1".parse().unwrap()), None);
+        assert!(IpRiskDataset::from_csv("203.0.113.0/33,tor").is_err());
+        assert!(IpRiskDataset::from_csv("203.0.113.0/24,satellite").is_err());
+    }
+
+    #[test]
+    fn test_is_internal_ip() {
+        for internal in [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "100.127.255.255",
+            "0.1.2.3",
+            "::1",
+            "::",
+            "fd12:3456::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(is_internal_ip(&internal.parse().unwrap()), "{} should be internal", internal);
+        }
+        for public in ["8.8.8.8", "100.128.0.1", "172.32.0.1", "2001:4860:4860::8888", "::ffff:8.8.8.8"] {
+            assert!(!is_internal_ip(&public.parse().unwrap()), "{} should be public", public);
+        }
+    }
+
+    #[test]
+    fn test_is_public_ip() {
+        for special in [
+            "10.0.0.1",
+            "::ffff:10.0.0.1",
+            "192.0.0.8",
+            "192.0.2.1",
+            "198.18.0.1",
+            "198.19.255.255",
+            "198.51.100.7",
+            "203.0.113.1",
+            "224.0.0.1",
+            "240.0.0.1",
+            "255.255.255.255",
+            "::ffff:203.0.113.1",
+            "ff02::1",
+            "2001:db8::1",
+            "64:ff9b::c000:201",
+            "::a00:1",
+        ] {
+            assert!(!is_public_ip(&special.parse().unwrap()), "{} should not be public", special);
+        }
+        for public in ["8.8.8.8", "93.184.216.34", "198.20.0.1", "::ffff:1.1.1.1", "64:ff9b::808:808", "2606:4700::1111"] {
+            assert!(is_public_ip(&public.parse().unwrap()), "{} should be public", public);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_internal_ip_skips_providers() {
+        let stubs = ProviderStubServer::start().await;
+        let service = stubbed_service(stubs.geolocation_config());
+
+        let location = service.get_location("10.0.0.12").await.unwrap();
+        assert_eq!(location.country_code, "ZZ");
+        let location = service.get_location("::ffff:192.168.0.5").await.unwrap();
+        assert_eq!(location.country_name, "Internal Network");
+        assert_eq!(stubs.maxmind_request_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_get_location_uses_normalized_cache_key() {
+        let service = stubbed_service(GeolocationConfig::default());
+        service.cache_location("192.0.2.1", &service.default_location()).await;
+
+        assert!(service.get_location("::ffff:192.0.2.1").await.is_ok());
+        assert!(matches!(service.get_location("bogus").await, Err(ApiError::BadRequest { .. })));
+    }
 
     #[test]
+    #[cfg(not(feature = "no_web"))]
     fn test_extract_client_ip_from_headers() {
         let mut headers = rocket::http::HeaderMap::new();
 
@@ -622,11 +2418,15 @@ mod tests {
         let location = LocationInfo {
             country_code: "US".to_string(),
             country_name: "United States".to_string(),
+            continent_code: Some("NA".to_string()),
             city: Some("New York".to_string()),
             region: Some("New York".to_string()),
             latitude: Some(40.7128),
             longitude: Some(-74.006),
             timezone: Some("America/New_York".to_string()),
+            asn: None,
+            isp: None,
+            organization: None,
         };
 
         let json = serde_json::to_string(&location).unwrap();
@@ -635,4 +2435,473 @@ mod tests {
         assert_eq!(location.country_code, deserialized.country_code);
         assert_eq!(location.city, deserialized.city);
     }
+
+    #[test]
+    fn test_config_builder_validation() {
+        let config = GeolocationConfig::builder()
+            .api_key("key")
+            .timeout(Duration::from_secs(2))
+            .cache_ttl(Duration::from_secs(600))
+            .build()
+            .unwrap();
+        assert_eq!(config.timeout_seconds, 2);
+        assert_eq!(config.cache_ttl_seconds, 600);
+
+        let zero_timeout = GeolocationConfig::builder().timeout(Duration::from_millis(500)).build();
+        assert!(matches!(zero_timeout, Err(ApiError::BadRequest { .. })));
+        assert!(GeolocationConfig::builder().max_cache_entries(0).build().is_err());
+        assert!(GeolocationConfig::builder().cache_ttl(Duration::ZERO).build().is_err());
+        assert!(GeolocationConfig::builder().service_url("api.maxmind.com").build().is_err());
+        assert!(GeolocationConfig::builder().retry(6, Duration::from_millis(100)).build().is_err());
+
+        // Struct literals skip the builder, so the service checks again
+        let literal = GeolocationConfig { max_cache_entries: 0, ..GeolocationConfig::default() };
+        assert!(GeolocationService::new(Arc::new(Client::new()), literal).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cache_entries_expire_after_ttl() {
+        let clock = Arc::new(MockClock::default());
+        let service = GeolocationService::with_clock(
+            Arc::new(Client::new()),
+            GeolocationConfig::builder().cache_ttl(Duration::from_secs(60)).build().unwrap(),
+            clock.clone()
+        ).unwrap();
+        let location = service.default_location();
+
+        service.cache_location("203.0.113.1", &location).await;
+        assert!(service.get_from_cache("203.0.113.1").await.is_some());
+
+        clock.advance(Duration::from_secs(59));
+        assert!(service.get_from_cache("203.0.113.1").await.is_some());
+
+        clock.advance(Duration::from_secs(1));
+        assert!(service.get_from_cache("203.0.113.1").await.is_none());
+        let metrics = service.get_cache_stats().await;
+        assert_eq!((metrics.total_entries, metrics.valid_entries), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_eviction_task_removes_expired_entries() {
+        let clock = Arc::new(MockClock::default());
+        let config = GeolocationConfig::builder()
+            .cache_ttl(Duration::from_secs(60))
+            .cache_eviction_interval(Duration::from_secs(1))
+            .build()
+            .unwrap();
+        let service = Arc::new(GeolocationService::with_clock(Arc::new(Client::new()), config, clock.clone()).unwrap());
+        service.cache_location("203.0.113.1", &service.default_location()).await;
+        service.cache_location("203.0.113.2", &service.default_location()).await;
+        clock.advance(Duration::from_secs(61));
+        service.cache_location("203.0.113.3", &service.default_location()).await;
+
+        assert_eq!(service.evict_expired().await, 2);
+        assert_eq!(service.get_cache_stats().await.total_entries, 1);
+
+        clock.advance(Duration::from_secs(61));
+        service.spawn_eviction_task();
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(service.get_cache_stats().await.total_entries, 0, "swept by the background task");
+
+        service.shutdown().await;
+        assert!(service.eviction_task.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_full_cache_evicts_least_recently_used() {
+        let config = GeolocationConfig::builder().max_cache_entries(2).build().unwrap();
+        let service = GeolocationService::new(Arc::new(Client::new()), config).unwrap();
+        let location = service.default_location();
+
+        service.cache_location("203.0.113.1", &location).await;
+        service.cache_location("203.0.113.2", &location).await;
+        assert!(service.get_from_cache("203.0.113.1").await.is_some());
+        service.cache_location("203.0.113.3", &location).await;
+
+        assert!(service.get_from_cache("203.0.113.1").await.is_some());
+        assert!(service.get_from_cache("203.0.113.2").await.is_none());
+        let metrics = service.get_cache_stats().await;
+        assert_eq!((metrics.total_entries, metrics.valid_entries, metrics.evictions), (2, 2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_cache_snapshot_round_trip() {
+        let clock = Arc::new(MockClock::default());
+        let config = GeolocationConfig::builder().cache_ttl(Duration::from_secs(60)).build().unwrap();
+        let service = GeolocationService::with_clock(Arc::new(Client::new()), config.clone(), clock.clone()).unwrap();
+        service.cache_location("203.0.113.1", &service.default_location()).await;
+        clock.advance(Duration::from_secs(30));
+        service.cache_location("203.0.113.2", &service.default_location()).await;
+
+        let path = std::env::temp_dir().join(format!("geo-cache-{}.json", uuid::Uuid::new_v4()));
+        assert_eq!(service.save_cache_snapshot(&path).await.unwrap(), 2);
+
+        // The restarted instance comes up 40s later: the first entry has expired
+        clock.advance(Duration::from_secs(40));
+        let restarted = GeolocationService::with_clock(Arc::new(Client::new()), config, clock.clone()).unwrap();
+        assert_eq!(restarted.load_cache_snapshot(&path).await.unwrap(), 1);
+        assert!(restarted.get_from_cache("203.0.113.2").await.is_some());
+
+        clock.advance(Duration::from_secs(20));
+        assert!(restarted.get_from_cache("203.0.113.2").await.is_none());
+        assert_eq!(restarted.load_cache_snapshot(path.with_extension("missing")).await.unwrap(), 0);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_warm_cache_skips_cached_addresses() {
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_maxmind(200, fixtures::maxmind_city("8.8.8.8")).await;
+        let service = Arc::new(stubbed_service(stubs.geolocation_config()));
+        service.cache_location("8.8.4.4", &service.default_location()).await;
+
+        let ips = vec!["8.8.8.8".to_string(), "8.8.4.4".to_string(), "not-an-ip".to_string()];
+        assert_eq!(service.warm_cache(ips, 4).await, 2);
+        assert_eq!(stubs.maxmind_request_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_ip_api_requests_only_needed_fields() {
+        assert_eq!(
+            GeolocationConfig::builder().fallback_api_key("pro-key").build().unwrap().fallback_service_url,
+            GeolocationConfig::IP_API_PRO_URL
+        );
+
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_ip_api(200, fixtures::ip_api_success("8.8.4.4")).await;
+        let config = GeolocationConfig::builder()
+            .fallback_service_url(&format!("{}/json", stubs.uri()))
+            .fallback_api_key("pro-key")
+            .build()
+            .unwrap();
+
+        let location = stubbed_service(config).get_location("8.8.4.4").await.unwrap();
+        assert_eq!(location.country_code, "DE");
+        assert_eq!(location.continent_code.as_deref(), Some("EU"));
+
+        let requests = stubs.server().received_requests().await.unwrap();
+        let query: HashMap<_, _> = requests[0].url.query_pairs().into_owned().collect();
+        assert_eq!(query.get("fields").map(String::as_str), Some(IP_API_FIELDS));
+        assert_eq!(query.get("key").map(String::as_str), Some("pro-key"));
+    }
+
+    #[tokio::test]
+    async fn test_get_ip_risk() {
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_ipinfo_privacy(200, fixtures::ipinfo_privacy(true, false, true, "NordVPN")).await;
+        let dataset = IpRiskDataset::from_csv("185.220.101.0/24,tor").unwrap();
+        let service = stubbed_service(stubs.geolocation_config()).with_risk_dataset(Arc::new(dataset));
+
+        let vpn = service.get_ip_risk("8.8.8.8").await.unwrap();
+        assert_eq!(vpn.category, IpRiskCategory::Vpn);
+        assert_eq!(vpn.service.as_deref(), Some("NordVPN"));
+        assert!(vpn.is_anonymized());
+        service.get_ip_risk("8.8.8.8").await.unwrap();
+        assert_eq!(stubs.request_count("/ipinfo").await, 1);
+
+        let tor = service.get_ip_risk("185.220.101.4").await.unwrap();
+        assert_eq!(tor.category, IpRiskCategory::Tor);
+        assert_eq!(service.get_ip_risk("10.0.0.1").await.unwrap().category, IpRiskCategory::Unknown);
+        assert_eq!(stubs.request_count("/ipinfo").await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_maxmind_success_skips_fallback() {
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_maxmind(200, fixtures::maxmind_city("8.8.8.8")).await;
+        let service = stubbed_service(stubs.geolocation_config());
+
+        let location = service.get_location("8.8.8.8").await.unwrap();
+
+        assert_eq!(location.country_code, "US");
+        assert_eq!(location.city.as_deref(), Some("Mountain View"));
+        assert_eq!(location.region.as_deref(), Some("California"));
+        assert_eq!(location.continent_code.as_deref(), Some("NA"));
+        assert_eq!(location.asn, Some(15169));
+
+        let config = GeolocationConfig::builder()
+            .api_key("test_maxmind_api_key")
+            .service_url(&format!("{}/geoip/v2.1/city", stubs.uri()))
+            .preferred_languages(&["de"])
+            .build()
+            .unwrap();
+        let localized = stubbed_service(config).get_location("8.8.8.8").await.unwrap();
+        assert_eq!(localized.country_name, "USA");
+        assert_eq!(localized.region.as_deref(), Some("California"));
+        assert_eq!(location.organization.as_deref(), Some("GOOGLE"));
+        assert_eq!(stubs.ip_api_request_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_maxmind_errors_fall_back_to_ip_api() {
+        for case in ["rate_limited", "malformed", "timeout"] {
+            let stubs = ProviderStubServer::start().await;
+            match case {
+                "rate_limited" => {
+                    stubs.stub_maxmind(429, fixtures::maxmind_error("RATE_LIMITED", "slow down")).await;
+                }
+                "malformed" => stubs.stub_maxmind_malformed().await,
+                _ => stubs.stub_maxmind_delay(Duration::from_secs(3)).await,
+            }
+            stubs.stub_ip_api(200, fixtures::ip_api_success("8.8.4.4")).await;
+            let service = stubbed_service(stubs.geolocation_config());
+
+            let location = service.get_location("8.8.4.4").await.unwrap();
+
+            assert_eq!(location.country_code, "DE", "case: {case}");
+            assert_eq!(location.asn, Some(3320), "case: {case}");
+            assert_eq!(location.isp.as_deref(), Some("Deutsche Telekom AG"), "case: {case}");
+            assert_eq!(stubs.maxmind_request_count().await, 1, "case: {case}");
+            assert_eq!(stubs.ip_api_request_count().await, 1, "case: {case}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_are_retried_with_backoff() {
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_maxmind(503, fixtures::maxmind_error("SERVER_ERROR", "unavailable")).await;
+        stubs.stub_ip_api(200, fixtures::ip_api_success("8.8.4.4")).await;
+        let config = GeolocationConfig {
+            retry_jitter: false,
+            ..stubs.geolocation_config()
+        };
+        let clock = Arc::new(MockClock::default());
+        let service = GeolocationService::with_clock(Arc::new(Client::new()), config, clock.clone()).unwrap();
+
+        let location = service.get_location("8.8.4.4").await.unwrap();
+
+        assert_eq!(location.country_code, "DE");
+        assert_eq!(stubs.maxmind_request_count().await, 3);
+        assert_eq!(stubs.ip_api_request_count().await, 1);
+        assert!(clock.elapsed() >= Duration::from_millis(300));
+
+        service.get_location("8.8.4.4").await.unwrap();
+        let metrics = service.get_cache_stats().await;
+        assert_eq!((metrics.hits, metrics.misses), (1, 1));
+        assert_eq!((metrics.maxmind_calls, metrics.fallback_calls, metrics.provider_errors), (1, 1, 1));
+        assert!(metrics.average_lookup_latency_ms >= 150.0);
+    }
+
+    #[cfg(feature = "geoip_db")]
+    #[tokio::test]
+    async fn test_missing_database_falls_back_to_providers() {
+        use crate::common_lib::geoip_database::{ GeoIpDatabase, GeoIpDatabaseConfig };
+
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_maxmind(200, fixtures::maxmind_city("8.8.8.8")).await;
+        let database = GeoIpDatabase::open(GeoIpDatabaseConfig {
+            path: "/nonexistent/GeoLite2-City.mmdb".to_string(),
+            ..GeoIpDatabaseConfig::default()
+        }).await;
+        let service = stubbed_service(stubs.geolocation_config()).with_database(Arc::new(database));
+
+        let location = service.get_location("8.8.8.8").await.unwrap();
+
+        assert_eq!(location.country_code, "US");
+        assert_eq!(stubs.maxmind_request_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_health_check_modes() {
+        let stubs = ProviderStubServer::start().await;
+        let service = stubbed_service(stubs.geolocation_config());
+
+        let shallow = service.health_check(HealthCheckMode::Shallow).await;
+        let components: Vec<&str> = shallow.components
+            .iter()
+            .map(|component| component.component.as_str())
+            .collect();
+        assert_eq!(components, vec!["geolocation.config", "geolocation.cache"]);
+        assert_eq!(shallow.status, HealthState::Up);
+
+        // Unmatched HEAD requests get a 404 from the stub server, which still proves reachability
+        let deep = service.health_check(HealthCheckMode::Deep).await;
+        assert_eq!(deep.status, HealthState::Up);
+        assert_eq!(deep.components.len(), 4);
+        assert_eq!(stubs.maxmind_request_count().await + stubs.ip_api_request_count().await, 2);
+        assert_eq!(service.get_cache_stats().await.total_entries, 0, "no lookups");
+
+        let unreachable = stubbed_service(
+            GeolocationConfig::builder()
+                .api_key("test_key")
+                .service_url("http://127.0.0.1:9/geoip")
+                .fallback_service_url(&stubs.uri())
+                .build()
+                .unwrap()
+        );
+        let deep = unreachable.health_check(HealthCheckMode::Deep).await;
+        assert_eq!(deep.status, HealthState::Degraded);
+        assert_eq!(deep.components[2].component, "geolocation.maxmind");
+        assert!(deep.components[2].last_error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_fallback_failures() {
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_ip_api_malformed().await;
+        let service = stubbed_service(GeolocationConfig {
+            api_key: SecretString::default(),
+            ..stubs.geolocation_config()
+        });
+
+        let result = service.get_location("198.51.100.1").await;
+        assert!(matches!(result, Err(ApiError::InternalServerError { .. })));
+        assert_eq!(stubs.maxmind_request_count().await, 0);
+
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_ip_api(200, fixtures::ip_api_failure("198.51.100.1", "reserved range")).await;
+        let service = stubbed_service(GeolocationConfig {
+            api_key: SecretString::default(),
+            ..stubs.geolocation_config()
+        });
+
+        let location = service.get_location("198.51.100.1").await.unwrap();
+        assert_eq!(location.country_code, "US");
+        assert!(location.city.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_misses_share_one_lookup() {
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_maxmind_delay(Duration::from_millis(200)).await;
+        let service = Arc::new(stubbed_service(stubs.geolocation_config()));
+
+        let mut lookups = tokio::task::JoinSet::new();
+        for _ in 0..5 {
+            let service = Arc::clone(&service);
+            lookups.spawn(async move { service.get_location("8.8.8.8").await });
+        }
+        while let Some(result) = lookups.join_next().await {
+            assert_eq!(result.unwrap().unwrap().country_code, "US");
+        }
+
+        assert_eq!(stubs.maxmind_request_count().await, 1);
+        let stats = service.get_cache_stats().await;
+        assert_eq!((stats.misses, stats.coalesced), (1, 4));
+        assert!(service.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_in_flight_entry_removed_when_guards_drop_together() {
+        let in_flight = std::sync::Mutex::new(HashMap::new());
+        for _ in 0..10_000 {
+            let barrier = std::sync::Barrier::new(2);
+            let lookup = Arc::clone(in_flight.lock().unwrap().entry("8.8.8.8".to_string()).or_default());
+            let guards = [Arc::clone(&lookup), lookup].map(|lookup| InFlightGuard {
+                in_flight: &in_flight,
+                ip_address: "8.8.8.8",
+                lookup,
+            });
+
+            std::thread::scope(|scope| {
+                for guard in guards {
+                    let barrier = &barrier;
+                    scope.spawn(move || {
+                        barrier.wait();
+                        drop(guard);
+                    });
+                }
+            });
+            assert!(in_flight.lock().unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_negative_results_are_cached() {
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_maxmind(404, fixtures::maxmind_error("IP_ADDRESS_NOT_FOUND", "not in database")).await;
+        let clock = Arc::new(MockClock::default());
+        let service = GeolocationService::with_clock(
+            Arc::new(Client::new()),
+            stubs.geolocation_config(),
+            clock.clone()
+        ).unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(service.get_location("198.51.100.7").await.unwrap().country_code, "US");
+        }
+        assert_eq!(stubs.maxmind_request_count().await, 1);
+        assert_eq!(service.get_cache_stats().await.negative_hits, 2);
+
+        clock.advance(Duration::from_secs(61));
+        service.get_location("198.51.100.7").await.unwrap();
+        assert_eq!(stubs.maxmind_request_count().await, 2, "negative entry expired");
+
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_ip_api_malformed().await;
+        let service = stubbed_service(GeolocationConfig {
+            api_key: SecretString::default(),
+            ..stubs.geolocation_config()
+        });
+        assert!(service.get_location("198.51.100.8").await.is_err());
+        assert!(matches!(service.get_location("198.51.100.8").await, Err(ApiError::InternalServerError { .. })));
+        assert_eq!(stubs.ip_api_request_count().await, 1, "failed lookup not retried within the TTL");
+    }
+
+    #[tokio::test]
+    async fn test_reverse_geocode() {
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_reverse_geocode(200, fixtures::nominatim_reverse("pt")).await;
+        let service = stubbed_service(stubs.geolocation_config());
+
+        assert_eq!(service.reverse_geocode(38.7223, -9.1393).await.unwrap().as_deref(), Some("PT"));
+        assert_eq!(service.reverse_geocode(38.7241, -9.1412).await.unwrap().as_deref(), Some("PT"));
+        assert_eq!(stubs.reverse_geocode_request_count().await, 1, "same cell served from cache");
+        assert!(matches!(service.reverse_geocode(91.0, 0.0).await, Err(ApiError::BadRequest { .. })));
+
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_reverse_geocode(200, serde_json::json!({ "error": "Unable to geocode" })).await;
+        let service = stubbed_service(stubs.geolocation_config());
+        assert_eq!(service.reverse_geocode(0.0, -30.0).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_rate_limit() {
+        let stubs = ProviderStubServer::start().await;
+        stubs.stub_ip_api(200, fixtures::ip_api_success("8.8.4.4")).await;
+        let limited = |behavior| GeolocationConfig {
+            api_key: SecretString::default(),
+            fallback_requests_per_minute: 1,
+            fallback_rate_limit: behavior,
+            ..stubs.geolocation_config()
+        };
+
+        let clock = Arc::new(MockClock::default());
+        let service = GeolocationService::with_clock(
+            Arc::new(Client::new()),
+            limited(RateLimitBehavior::FailFast),
+            clock.clone()
+        ).unwrap();
+        service.get_location("8.8.4.4").await.unwrap();
+        assert!(matches!(service.get_location("8.8.8.8").await, Err(ApiError::TooManyRequests { .. })));
+        clock.advance(Duration::from_secs(60));
+        assert!(service.get_location("8.8.8.8").await.is_ok());
+
+        // Throttled lookups aren't negative-cached, so capacity is used as soon as it refills
+        let clock = Arc::new(MockClock::default());
+        let service = GeolocationService::with_clock(
+            Arc::new(Client::new()),
+            GeolocationConfig { fallback_requests_per_minute: 60, ..limited(RateLimitBehavior::FailFast) },
+            clock.clone()
+        ).unwrap();
+        for host in 0..60 {
+            service.get_location(&format!("8.8.4.{}", host)).await.unwrap();
+        }
+        assert!(service.get_location("8.8.8.8").await.is_err());
+        clock.advance(Duration::from_secs(1));
+        assert!(service.get_location("8.8.8.8").await.is_ok());
+
+        let clock = Arc::new(MockClock::default());
+        let service = GeolocationService::with_clock(
+            Arc::new(Client::new()),
+            limited(RateLimitBehavior::Queue { max_wait: Duration::from_secs(90) }),
+            clock.clone()
+        ).unwrap();
+        service.get_location("8.8.4.4").await.unwrap();
+        service.get_location("8.8.8.8").await.unwrap();
+        assert!(clock.elapsed() >= Duration::from_secs(60));
+        service.get_location("1.1.1.1").await.unwrap();
+        assert!(clock.elapsed() >= Duration::from_secs(120));
+        assert_eq!(stubs.ip_api_request_count().await, 66);
+    }
 }
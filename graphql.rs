@@ -0,0 +1,104 @@
+//! async-graphql interop, enabled with the `graphql` feature
+//! Converts `ApiError` into GraphQL errors whose `extensions` carry the error code, HTTP status,
+//! correlation ID and any field-level validation errors.
+
+use async_graphql::{ Error, ErrorExtensions, Value };
+use serde::{ Deserialize, Serialize };
+
+use crate::common_lib::error::ApiError;
+
+/// Validation error for a single input field
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl ErrorExtensions for ApiError {
+    fn extend(&self) -> Error {
+        Error::new(self.to_string()).extend_with(|_, extensions| {
+            extensions.set("code", self.error_type());
+            extensions.set("status", self.status_code() as i32);
+
+            match self {
+                ApiError::QuotaExceeded {
+                    resource,
+                    monthly_count,
+                    lifetime_count,
+                    monthly_limit,
+                    lifetime_limit,
+                } => {
+                    extensions.set("resource", resource.as_str());
+                    extensions.set("monthlyCount", *monthly_count);
+                    extensions.set("lifetimeCount", *lifetime_count);
+                    extensions.set("monthlyLimit", *monthly_limit);
+                    extensions.set("lifetimeLimit", *lifetime_limit);
+                }
+                ApiError::RegistrationRequired { reason, suggested_action, .. } => {
+                    extensions.set("reason", reason.as_str());
+                    extensions.set("suggestedAction", suggested_action.as_str());
+                }
+                ApiError::TooManyRequests { retry_after_seconds, .. } => {
+                    extensions.set("retryAfterSeconds", *retry_after_seconds);
+                }
+                ApiError::UpgradeRequired { platform, minimum_version, update_url, .. } => {
+                    extensions.set("platform", platform.as_str());
+                    extensions.set("minimumVersion", minimum_version.as_str());
+                    if let Some(update_url) = update_url {
+                        extensions.set("updateUrl", update_url.as_str());
+                    }
+                }
+                _ => {}
+            }
+        })
+    }
+}
+
+/// Build a GraphQL error for the gateway, tagged with the request's correlation ID
+pub fn to_graphql_error(error: &ApiError, correlation_id: &str, field_errors: &[FieldError]) -> Error {
+    error.extend().extend_with(|_, extensions| {
+        extensions.set("correlationId", correlation_id);
+
+        if !field_errors.is_empty() {
+            let value = serde_json
+                ::to_value(field_errors)
+                .ok()
+                .and_then(|json| Value::from_json(json).ok())
+                .unwrap_or(Value::Null);
+            extensions.set("fieldErrors", value);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_graphql_error_extensions() {
+        let error = ApiError::BadRequest { message: "invalid input".to_string() };
+        let graphql_error = to_graphql_error(&error, "req-123", &[
+            FieldError::new("countryCode", "Invalid country code format"),
+        ]);
+
+        assert_eq!(graphql_error.message, "Bad Request Error: invalid input");
+
+        let extensions = graphql_error.extensions.unwrap();
+        assert_eq!(extensions.get("code"), Some(&Value::from("BadRequest")));
+        assert_eq!(extensions.get("status"), Some(&Value::from(400)));
+        assert_eq!(extensions.get("correlationId"), Some(&Value::from("req-123")));
+
+        let field_errors = extensions.get("fieldErrors").unwrap().clone().into_json().unwrap();
+        assert_eq!(field_errors[0]["field"], "countryCode");
+    }
+}
@@ -0,0 +1,173 @@
+//! tonic interop, enabled with the `grpc` feature
+//! Maps `ApiError` to and from gRPC `Status` and propagates correlation IDs through request metadata.
+
+use tonic::metadata::{ MetadataMap, MetadataValue };
+use tonic::service::Interceptor;
+use tonic::{ Code, Request, Status };
+use tracing::{ debug, warn };
+
+use crate::common_lib::error::ApiError;
+use crate::common_lib::logging::RequestId;
+
+/// Metadata key carrying the correlation ID (gRPC metadata keys are lowercase)
+pub const CORRELATION_ID_METADATA_KEY: &str = "x-correlation-id";
+/// Metadata key carrying `ApiError::error_type` on error responses
+pub const ERROR_TYPE_METADATA_KEY: &str = "x-error-type";
+
+impl From<ApiError> for Status {
+    fn from(error: ApiError) -> Self {
+        let code = match &error {
+            ApiError::NotFound { .. } => Code::NotFound,
+            ApiError::InternalServerError { .. } => Code::Internal,
+            ApiError::BadRequest { .. } => Code::InvalidArgument,
+            ApiError::Unauthorized { .. } => Code::Unauthenticated,
+            ApiError::Forbidden { .. } => Code::PermissionDenied,
+            ApiError::UnavailableForLegalReasons { .. } => Code::PermissionDenied,
+            ApiError::PaymentRequired { .. } => Code::FailedPrecondition,
+            ApiError::QuotaExceeded { .. } => Code::ResourceExhausted,
+            ApiError::RegistrationRequired { .. } => Code::FailedPrecondition,
+            ApiError::UpgradeRequired { .. } => Code::FailedPrecondition,
+            ApiError::TooManyRequests { .. } => Code::ResourceExhausted,
+        };
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert(ERROR_TYPE_METADATA_KEY, MetadataValue::from_static(error.error_type()));
+
+        // The full error is carried in the status details so Rust clients can rebuild it exactly
+        let details = serde_json::to_vec(&error).unwrap_or_default();
+
+        Status::with_details_and_metadata(code, error.to_string(), details.into(), metadata)
+    }
+}
+
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        if let Ok(error) = serde_json::from_slice::<ApiError>(status.details()) {
+            return error;
+        }
+
+        let message = status.message().to_string();
+        match status.code() {
+            Code::NotFound => ApiError::NotFound { message },
+            Code::InvalidArgument | Code::OutOfRange | Code::FailedPrecondition => {
+                ApiError::BadRequest { message }
+            }
+            Code::Unauthenticated => ApiError::Unauthorized { message },
+            Code::PermissionDenied => ApiError::Forbidden { message },
+            _ => ApiError::InternalServerError { message },
+        }
+    }
+}
+
+/// Correlation ID from request metadata, if present and valid
+pub fn correlation_id_from_metadata(metadata: &MetadataMap) -> Option<String> {
+    metadata
+        .get(CORRELATION_ID_METADATA_KEY)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Server interceptor ensuring every request carries a correlation ID
+/// A missing ID is generated, and the resolved ID is stored in the request extensions as a `RequestId`
+/// when it is in a parseable format.
+#[allow(clippy::result_large_err)] // signature required by tonic
+pub fn correlation_id_interceptor(mut request: Request<()>) -> Result<Request<()>, Status> {
+    let req_id = match correlation_id_from_metadata(request.metadata()) {
+        Some(correlation_id) => correlation_id.parse::<RequestId>().ok(),
+        None => {
+            let req_id = RequestId::new();
+            debug!(
+                "GRPC:interceptor [CORRELATION_ID_GENERATED] [req_id:{}] No correlation id in metadata",
+                req_id
+            );
+            set_correlation_id(request.metadata_mut(), req_id.as_str());
+            Some(req_id)
+        }
+    };
+
+    if let Some(req_id) = req_id {
+        request.extensions_mut().insert(req_id);
+    }
+
+    Ok(request)
+}
+
+/// Client interceptor attaching a fixed correlation ID to outgoing requests
+#[derive(Debug, Clone)]
+pub struct CorrelationIdInterceptor {
+    correlation_id: String,
+}
+
+impl CorrelationIdInterceptor {
+    pub fn new(correlation_id: impl Into<String>) -> Self {
+        Self {
+            correlation_id: correlation_id.into(),
+        }
+    }
+}
+
+impl Interceptor for CorrelationIdInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        set_correlation_id(request.metadata_mut(), &self.correlation_id);
+        Ok(request)
+    }
+}
+
+fn set_correlation_id(metadata: &mut MetadataMap, correlation_id: &str) {
+    match correlation_id.parse() {
+        Ok(value) => {
+            metadata.insert(CORRELATION_ID_METADATA_KEY, value);
+        }
+        Err(_) => {
+            warn!(
+                "GRPC:interceptor [INVALID_CORRELATION_ID] Correlation id is not valid metadata - value: {}",
+                correlation_id
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_error_status_round_trip() {
+        let error = ApiError::QuotaExceeded {
+            resource: "sparks".to_string(),
+            monthly_count: 10,
+            lifetime_count: 120,
+            monthly_limit: 10,
+            lifetime_limit: 500,
+        };
+
+        let status = Status::from(error);
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(status.metadata().get(ERROR_TYPE_METADATA_KEY).unwrap(), "QuotaExceeded");
+
+        // `ApiError::from` is the inherent catch-all constructor, so go through `Into` explicitly
+        let error: ApiError = status.into();
+        match error {
+            ApiError::QuotaExceeded { lifetime_limit, .. } => assert_eq!(lifetime_limit, 500),
+            other => panic!("unexpected error: {other:?}"),
+        }
+
+        let foreign: ApiError = Status::permission_denied("nope").into();
+        assert!(matches!(foreign, ApiError::Forbidden { message } if message == "nope"));
+    }
+
+    #[test]
+    fn test_correlation_id_interceptors() {
+        let req_id = RequestId::new();
+        let request = CorrelationIdInterceptor::new(req_id.to_string()).call(Request::new(())).unwrap();
+        let request = correlation_id_interceptor(request).unwrap();
+
+        assert_eq!(correlation_id_from_metadata(request.metadata()), Some(req_id.to_string()));
+        assert_eq!(request.extensions().get::<RequestId>(), Some(&req_id));
+
+        let generated = correlation_id_interceptor(Request::new(())).unwrap();
+        let generated_id = generated.extensions().get::<RequestId>().unwrap();
+        assert_eq!(correlation_id_from_metadata(generated.metadata()).as_deref(), Some(generated_id.as_str()));
+    }
+}
@@ -0,0 +1,141 @@
+use chrono::{ DateTime, Utc };
+#[cfg(not(feature = "no_web"))]
+use rocket::{
+    http::{ ContentType, Status },
+    request::Request,
+    response::{ self, Responder, Response },
+};
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
+use serde::{ Deserialize, Serialize };
+
+/// Health of a single component or of the whole service
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum HealthState {
+    Up,
+    Degraded,
+    Down,
+}
+
+/// Result of checking one dependency (database, provider, cache, ...)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct HealthStatus {
+    pub component: String,
+    pub status: HealthState,
+    pub latency_ms: u64,
+    pub last_error: Option<String>,
+    #[cfg_attr(not(feature = "no_web"), schemars(with = "String"))]
+    pub checked_at: DateTime<Utc>,
+}
+
+impl HealthStatus {
+    pub fn up(component: &str, latency_ms: u64) -> Self {
+        Self::new(component, HealthState::Up, latency_ms, None)
+    }
+
+    pub fn degraded(component: &str, latency_ms: u64, reason: &str) -> Self {
+        Self::new(component, HealthState::Degraded, latency_ms, Some(reason.to_string()))
+    }
+
+    pub fn down(component: &str, latency_ms: u64, error: &str) -> Self {
+        Self::new(component, HealthState::Down, latency_ms, Some(error.to_string()))
+    }
+
+    fn new(component: &str, status: HealthState, latency_ms: u64, last_error: Option<String>) -> Self {
+        Self {
+            component: component.to_string(),
+            status,
+            latency_ms,
+            last_error,
+            checked_at: Utc::now(),
+        }
+    }
+}
+
+/// Aggregated health for the /health route
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    /// Worst status across all components
+    pub status: HealthState,
+    pub version: String,
+    pub region: Option<String>,
+    pub components: Vec<HealthStatus>,
+}
+
+impl HealthReport {
+    /// Build a report for the running service; the region is read from `AWS_REGION`
+    pub fn new(version: &str, components: Vec<HealthStatus>) -> Self {
+        let status = components
+            .iter()
+            .map(|component| component.status)
+            .max()
+            .unwrap_or(HealthState::Up);
+
+        Self {
+            status,
+            version: version.to_string(),
+            region: std::env::var("AWS_REGION").ok(),
+            components,
+        }
+    }
+
+    /// Degraded services still serve traffic, so only `Down` fails the load balancer check
+    pub fn status_code(&self) -> u16 {
+        match self.status {
+            HealthState::Up | HealthState::Degraded => 200,
+            HealthState::Down => 503,
+        }
+    }
+}
+
+#[cfg(not(feature = "no_web"))]
+impl<'r> Responder<'r, 'static> for HealthReport {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let status_code = Status::from_code(self.status_code()).unwrap_or(Status::InternalServerError);
+        let body = serde_json::to_string(&self).unwrap_or_default();
+
+        Response::build()
+            .sized_body(body.len(), std::io::Cursor::new(body))
+            .header(ContentType::JSON)
+            .status(status_code)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_uses_worst_component_status() {
+        let healthy = HealthReport::new("1.2.3", vec![HealthStatus::up("mongo", 3)]);
+        assert_eq!(healthy.status, HealthState::Up);
+        assert_eq!(healthy.status_code(), 200);
+
+        let degraded = HealthReport::new("1.2.3", vec![
+            HealthStatus::up("mongo", 3),
+            HealthStatus::degraded("geolocation", 2400, "slow response"),
+        ]);
+        assert_eq!(degraded.status, HealthState::Degraded);
+        assert_eq!(degraded.status_code(), 200);
+
+        let down = HealthReport::new("1.2.3", vec![
+            HealthStatus::degraded("geolocation", 2400, "slow response"),
+            HealthStatus::down("mongo", 5000, "connection timed out"),
+        ]);
+        assert_eq!(down.status, HealthState::Down);
+        assert_eq!(down.status_code(), 503);
+
+        let json = serde_json::to_value(&down).unwrap();
+        assert_eq!(json["components"][1]["status"], "down");
+        assert_eq!(json["components"][1]["lastError"], "connection timed out");
+    }
+}
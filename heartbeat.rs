@@ -0,0 +1,205 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{ DateTime, Utc };
+#[cfg(not(feature = "no_http"))]
+use reqwest::Client;
+use serde::{ Deserialize, Serialize };
+use tokio::sync::RwLock;
+use tracing::{ debug, warn };
+use uuid::Uuid;
+
+use crate::common_lib::build_info::BuildInfo;
+use crate::common_lib::error::ApiError;
+use crate::common_lib::health::{ HealthState, HealthStatus };
+
+pub type SendFuture<'a> = Pin<Box<dyn Future<Output = Result<(), ApiError>> + Send + 'a>>;
+
+/// Periodic service identity and health report for the fleet inventory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Heartbeat {
+    /// Unique per process, so restarts and multiple replicas are distinguishable
+    pub instance_id: String,
+    pub build: BuildInfo,
+    pub started_at: DateTime<Utc>,
+    pub uptime_seconds: i64,
+    pub sent_at: DateTime<Utc>,
+    pub status: HealthState,
+    pub components: Vec<HealthStatus>,
+}
+
+/// Destination for heartbeats (HTTP collector, SQS queue, ...)
+pub trait HeartbeatSink: Send + Sync {
+    fn send<'a>(&'a self, heartbeat: &'a Heartbeat) -> SendFuture<'a>;
+}
+
+/// Configuration for the heartbeat reporter
+#[derive(Debug, Clone)]
+pub struct HeartbeatConfig {
+    pub interval_seconds: u64,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval_seconds: 60,
+        }
+    }
+}
+
+/// Sends heartbeats on an interval with the latest known component health
+pub struct HeartbeatReporter {
+    config: HeartbeatConfig,
+    sink: Arc<dyn HeartbeatSink>,
+    instance_id: String,
+    started_at: DateTime<Utc>,
+    components: RwLock<Vec<HealthStatus>>,
+}
+
+impl HeartbeatReporter {
+    pub fn new(config: HeartbeatConfig, sink: Arc<dyn HeartbeatSink>) -> Self {
+        Self {
+            config,
+            sink,
+            instance_id: Uuid::new_v4().to_string(),
+            started_at: Utc::now(),
+            components: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Record the latest health of a component, replacing any previous status for it
+    pub async fn update_health(&self, status: HealthStatus) {
+        let mut components = self.components.write().await;
+        components.retain(|existing| existing.component != status.component);
+        components.push(status);
+    }
+
+    /// Build the current heartbeat
+    pub async fn heartbeat(&self) -> Heartbeat {
+        let components = self.components.read().await.clone();
+        let now = Utc::now();
+
+        Heartbeat {
+            instance_id: self.instance_id.clone(),
+            build: BuildInfo::current(),
+            started_at: self.started_at,
+            uptime_seconds: (now - self.started_at).num_seconds(),
+            sent_at: now,
+            status: components
+                .iter()
+                .map(|component| component.status)
+                .max()
+                .unwrap_or(HealthState::Up),
+            components,
+        }
+    }
+
+    /// Send one heartbeat now
+    pub async fn send_once(&self) -> Result<(), ApiError> {
+        let heartbeat = self.heartbeat().await;
+        self.sink.send(&heartbeat).await?;
+
+        debug!(
+            "HEARTBEAT:send [SUCCESS] Heartbeat sent - instance_id: {}, status: {:?}",
+            heartbeat.instance_id,
+            heartbeat.status
+        );
+
+        Ok(())
+    }
+
+    /// Spawn the background task; send failures are logged and retried on the next tick
+    pub fn spawn(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let reporter = Arc::clone(self);
+        let interval = Duration::from_secs(reporter.config.interval_seconds.max(1));
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+
+            loop {
+                ticker.tick().await;
+                if let Err(e) = reporter.send_once().await {
+                    warn!("HEARTBEAT:send [SEND_ERROR] Failed to send heartbeat - error: {}", e);
+                }
+            }
+        })
+    }
+}
+
+/// Posts heartbeats as JSON to a collector endpoint
+#[cfg(not(feature = "no_http"))]
+pub struct HttpHeartbeatSink {
+    client: Arc<Client>,
+    endpoint_url: String,
+}
+
+#[cfg(not(feature = "no_http"))]
+impl HttpHeartbeatSink {
+    pub fn new(client: Arc<Client>, endpoint_url: &str) -> Self {
+        Self {
+            client,
+            endpoint_url: endpoint_url.to_string(),
+        }
+    }
+}
+
+#[cfg(not(feature = "no_http"))]
+impl HeartbeatSink for HttpHeartbeatSink {
+    fn send<'a>(&'a self, heartbeat: &'a Heartbeat) -> SendFuture<'a> {
+        Box::pin(async move {
+            let response = self.client
+                .post(&self.endpoint_url)
+                .timeout(Duration::from_secs(5))
+                .json(heartbeat)
+                .send().await
+                .map_err(|e| ApiError::InternalServerError {
+                    message: format!("Heartbeat request failed: {}", e),
+                })?;
+
+            if !response.status().is_success() {
+                return Err(ApiError::InternalServerError {
+                    message: format!("Heartbeat collector returned {}", response.status()),
+                });
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        sent: Mutex<Vec<Heartbeat>>,
+    }
+
+    impl HeartbeatSink for RecordingSink {
+        fn send<'a>(&'a self, heartbeat: &'a Heartbeat) -> SendFuture<'a> {
+            self.sent.lock().unwrap().push(heartbeat.clone());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_reports_latest_health() {
+        let sink = Arc::new(RecordingSink::default());
+        let reporter = HeartbeatReporter::new(HeartbeatConfig::default(), sink.clone());
+
+        reporter.update_health(HealthStatus::down("mongo", 5000, "timeout")).await;
+        reporter.update_health(HealthStatus::up("mongo", 4)).await;
+        reporter.update_health(HealthStatus::degraded("geolocation", 3000, "slow")).await;
+        reporter.send_once().await.unwrap();
+
+        let sent = sink.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].components.len(), 2);
+        assert_eq!(sent[0].status, HealthState::Degraded);
+        assert_eq!(sent[0].build.version, env!("CARGO_PKG_VERSION"));
+    }
+}
@@ -0,0 +1,352 @@
+//! iCalendar (RFC 5545) generation and basic parsing for booking confirmations
+//!
+//! Times are written in UTC, which every calendar client converts to the viewer's zone, so no
+//! VTIMEZONE definitions are needed; events booked in local time are converted with
+//! `IcsEvent::starting_at_local`:
+//!
+//! ```ignore
+//! let event = IcsEvent::starting_at_local(&booking.id, "Table for 4", local_start, Duration::hours(2), "Europe/Lisbon")?
+//!     .location("Rua Augusta 100, Lisboa");
+//! let attachment = to_calendar(&[event], Utc::now());  // served as ICS_CONTENT_TYPE
+//! ```
+//!
+//! The parser reads the VEVENTs of simple calendars (UTC, TZID, floating and all-day times); it
+//! does not expand recurrence rules.
+
+use chrono::{ DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc };
+use chrono_tz::Tz;
+use serde::{ Deserialize, Serialize };
+
+use crate::common_lib::error::ApiError;
+
+pub const ICS_CONTENT_TYPE: &str = "text/calendar; charset=utf-8; method=PUBLISH";
+const PRODUCT_ID: &str = "-//Bondinary//common-lib//EN";
+/// Content lines longer than this many octets are folded
+const MAX_LINE_OCTETS: usize = 75;
+const UTC_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const LOCAL_FORMAT: &str = "%Y%m%dT%H%M%S";
+const DATE_FORMAT: &str = "%Y%m%d";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum IcsStatus {
+    #[default]
+    Confirmed,
+    Tentative,
+    Cancelled,
+}
+
+impl IcsStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            IcsStatus::Confirmed => "CONFIRMED",
+            IcsStatus::Tentative => "TENTATIVE",
+            IcsStatus::Cancelled => "CANCELLED",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IcsEvent {
+    /// Stable per booking, so updates and cancellations replace the same calendar entry
+    pub uid: String,
+    pub summary: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Whole days from `start`'s date to `end`'s date, written without times
+    pub all_day: bool,
+    pub status: IcsStatus,
+    /// Incremented on every update of the same `uid`
+    pub sequence: u32,
+}
+
+impl IcsEvent {
+    pub fn new(uid: &str, summary: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        Self {
+            uid: uid.to_string(),
+            summary: summary.to_string(),
+            description: None,
+            location: None,
+            start,
+            end,
+            all_day: false,
+            status: IcsStatus::Confirmed,
+            sequence: 0,
+        }
+    }
+
+    /// Event starting at a local time in an IANA timezone, e.g. a venue's
+    pub fn starting_at_local(
+        uid: &str,
+        summary: &str,
+        local_start: NaiveDateTime,
+        duration: Duration,
+        timezone: &str
+    ) -> Result<Self, ApiError> {
+        let tz: Tz = timezone.parse().map_err(|_| ApiError::BadRequest {
+            message: format!("Invalid timezone: '{}'", timezone),
+        })?;
+        let start = local_to_utc(&tz, local_start).ok_or_else(|| ApiError::BadRequest {
+            message: format!("{} does not exist in {}", local_start, timezone),
+        })?;
+
+        Ok(Self::new(uid, summary, start, start + duration))
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn location(mut self, location: &str) -> Self {
+        self.location = Some(location.to_string());
+        self
+    }
+
+    pub fn all_day(mut self, all_day: bool) -> Self {
+        self.all_day = all_day;
+        self
+    }
+
+    pub fn status(mut self, status: IcsStatus, sequence: u32) -> Self {
+        self.status = status;
+        self.sequence = sequence;
+        self
+    }
+
+    fn write(&self, lines: &mut Vec<String>, stamp: DateTime<Utc>) {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", escape_text(&self.uid)));
+        lines.push(format!("DTSTAMP:{}", stamp.format(UTC_FORMAT)));
+        if self.all_day {
+            lines.push(format!("DTSTART;VALUE=DATE:{}", self.start.format(DATE_FORMAT)));
+            lines.push(format!("DTEND;VALUE=DATE:{}", self.end.format(DATE_FORMAT)));
+        } else {
+            lines.push(format!("DTSTART:{}", self.start.format(UTC_FORMAT)));
+            lines.push(format!("DTEND:{}", self.end.format(UTC_FORMAT)));
+        }
+        lines.push(format!("SUMMARY:{}", escape_text(&self.summary)));
+        if let Some(description) = &self.description {
+            lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        if let Some(location) = &self.location {
+            lines.push(format!("LOCATION:{}", escape_text(location)));
+        }
+        lines.push(format!("STATUS:{}", self.status.as_str()));
+        lines.push(format!("SEQUENCE:{}", self.sequence));
+        lines.push("END:VEVENT".to_string());
+    }
+}
+
+fn local_to_utc(tz: &Tz, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&local)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+fn escape_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn unescape_text(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(escaped) => unescaped.push(escaped),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Split a line into pieces of at most 75 octets, continuing with a leading space
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 8);
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            // The leading space counts towards the next line
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded
+}
+
+/// A VCALENDAR with the events, CRLF line endings and folded lines; `stamp` is the DTSTAMP
+pub fn to_calendar(events: &[IcsEvent], stamp: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", PRODUCT_ID),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string()
+    ];
+    for event in events {
+        event.write(&mut lines, stamp);
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|line| fold(line) + "\r\n")
+        .collect()
+}
+
+/// Date-time property value; floating times are read as UTC
+fn parse_time(params: &str, value: &str) -> Result<(DateTime<Utc>, bool), ApiError> {
+    let invalid = || ApiError::BadRequest { message: format!("Invalid calendar date-time: '{}'", value) };
+
+    if params.split(';').any(|param| param.eq_ignore_ascii_case("VALUE=DATE")) || value.len() == DATE_FORMAT.len() + 4 {
+        let date = NaiveDate::parse_from_str(value, DATE_FORMAT).map_err(|_| invalid())?;
+        return Ok((Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).ok_or_else(invalid)?), true));
+    }
+    if let Some(utc) = value.strip_suffix('Z') {
+        let time = NaiveDateTime::parse_from_str(utc, LOCAL_FORMAT).map_err(|_| invalid())?;
+        return Ok((Utc.from_utc_datetime(&time), false));
+    }
+
+    let time = NaiveDateTime::parse_from_str(value, LOCAL_FORMAT).map_err(|_| invalid())?;
+    let tzid = params
+        .split(';')
+        .find_map(|param| param.strip_prefix("TZID="))
+        .map(|tzid| tzid.trim_matches('"'));
+    match tzid {
+        Some(tzid) => {
+            let tz: Tz = tzid.parse().map_err(|_| ApiError::BadRequest {
+                message: format!("Unknown calendar timezone: '{}'", tzid),
+            })?;
+            Ok((local_to_utc(&tz, time).ok_or_else(invalid)?, false))
+        }
+        None => Ok((Utc.from_utc_datetime(&time), false)),
+    }
+}
+
+/// Events of a calendar; events without DTEND last an hour, or a day when all-day
+pub fn parse_calendar(text: &str) -> Result<Vec<IcsEvent>, ApiError> {
+    // Unfold: a line starting with a space or tab continues the previous one
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.split('\n').map(|line| line.trim_end_matches('\r')) {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(previous)) => previous.push_str(continuation),
+            _ if line.is_empty() => {}
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut events = Vec::new();
+    let mut current: Option<(IcsEvent, bool)> = None;
+    let missing = |property: &str| ApiError::BadRequest { message: format!("Calendar event without {}", property) };
+
+    for line in &lines {
+        let Some((name_and_params, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, params) = name_and_params.split_once(';').unwrap_or((name_and_params, ""));
+
+        match (name.to_ascii_uppercase().as_str(), current.as_mut()) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => {
+                let epoch = DateTime::<Utc>::UNIX_EPOCH;
+                current = Some((IcsEvent::new("", "", epoch, epoch), false));
+            }
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => {
+                let Some((mut event, has_end)) = current.take() else {
+                    continue;
+                };
+                if event.uid.is_empty() {
+                    return Err(missing("UID"));
+                }
+                if event.start == DateTime::<Utc>::UNIX_EPOCH {
+                    return Err(missing("DTSTART"));
+                }
+                if !has_end {
+                    event.end = event.start + if event.all_day { Duration::days(1) } else { Duration::hours(1) };
+                }
+                events.push(event);
+            }
+            ("UID", Some((event, _))) => {
+                event.uid = unescape_text(value);
+            }
+            ("SUMMARY", Some((event, _))) => {
+                event.summary = unescape_text(value);
+            }
+            ("DESCRIPTION", Some((event, _))) => {
+                event.description = Some(unescape_text(value));
+            }
+            ("LOCATION", Some((event, _))) => {
+                event.location = Some(unescape_text(value));
+            }
+            ("DTSTART", Some((event, _))) => {
+                (event.start, event.all_day) = parse_time(params, value)?;
+            }
+            ("DTEND", Some((event, has_end))) => {
+                event.end = parse_time(params, value)?.0;
+                *has_end = true;
+            }
+            ("STATUS", Some((event, _))) => {
+                event.status = match value.to_ascii_uppercase().as_str() {
+                    "TENTATIVE" => IcsStatus::Tentative,
+                    "CANCELLED" => IcsStatus::Cancelled,
+                    _ => IcsStatus::Confirmed,
+                };
+            }
+            ("SEQUENCE", Some((event, _))) => {
+                event.sequence = value.trim().parse().unwrap_or(0);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_and_parse_round_trip() {
+        let local_start = NaiveDate::from_ymd_opt(2025, 7, 10).unwrap().and_hms_opt(20, 0, 0).unwrap();
+        let event = IcsEvent::starting_at_local("booking-42", "Dinner; table for 4", local_start, Duration::hours(2), "Europe/Lisbon")
+            .unwrap()
+            .description(&format!("Notes: {}\nSee you soon, Ana", "window seat ".repeat(10)))
+            .location("Rua Augusta 100, Lisboa");
+        assert_eq!(event.start, Utc.with_ymd_and_hms(2025, 7, 10, 19, 0, 0).unwrap());
+
+        let calendar = to_calendar(std::slice::from_ref(&event), Utc.with_ymd_and_hms(2025, 7, 1, 0, 0, 0).unwrap());
+        assert!(calendar.contains("DTSTART:20250710T190000Z\r\n"));
+        assert!(calendar.contains("SUMMARY:Dinner\\; table for 4\r\n"));
+        assert!(calendar.lines().all(|line| line.trim_end_matches('\r').len() <= MAX_LINE_OCTETS));
+
+        assert_eq!(parse_calendar(&calendar).unwrap(), vec![event]);
+    }
+
+    #[test]
+    fn test_parse_tzid_and_all_day_events() {
+        let calendar = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:a\r\nDTSTART;TZID=America/New_York:20250115T090000\r\n\
+            DTEND;TZID=America/New_York:20250115T100000\r\nSUMMARY:Call\r\nEND:VEVENT\r\n\
+            BEGIN:VEVENT\r\nUID:b\r\nDTSTART;VALUE=DATE:20250201\r\nSUMMARY:Holiday\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+
+        let events = parse_calendar(calendar).unwrap();
+        assert_eq!(events[0].start, Utc.with_ymd_and_hms(2025, 1, 15, 14, 0, 0).unwrap());
+        assert!(events[1].all_day);
+        assert_eq!(events[1].end - events[1].start, Duration::days(1));
+
+        assert!(parse_calendar("BEGIN:VEVENT\r\nSUMMARY:No uid\r\nDTSTART:20250101T000000Z\r\nEND:VEVENT").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! Passport and national ID numbers for KYC onboarding
+//!
+//! The number is held as a `SecretString`, so Debug and Display print `[REDACTED]` and it can't end
+//! up in logs by accident; validation errors never include it either. Serialize writes the real
+//! number so documents can be stored and read back, which makes `IdentityDocument` a storage type:
+//! API responses and screens show `masked()`, and only the KYC provider call reads
+//! `number.expose_secret()`:
+//!
+//! ```ignore
+//! let document = IdentityDocument::new(DocumentKind::NationalId, "ES", &request.document_number)?;
+//! info!("KYC:submit [DOCUMENT] [req_id:{}] Document accepted - document: {}", req_id, document.masked());
+//! ```
+
+use serde::{ Deserialize, Serialize };
+
+use crate::common_lib::address::matches_format;
+use crate::common_lib::error::ApiError;
+use crate::common_lib::secret::{ serialize_exposed, SecretString };
+
+/// Characters left visible at the end of a masked number
+const VISIBLE_SUFFIX: usize = 4;
+const MASK_CHAR: char = '•';
+/// Spanish DNI and NIE control letters, indexed by the number modulo 23
+const DNI_LETTERS: &[u8; 23] = b"TRWAGMYFPDXBNJZSQVHLCKE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DocumentKind {
+    Passport,
+    NationalId,
+}
+
+/// Number formats: `9` is a digit, `A` a letter, `X` either, anything else literal
+/// Countries without rules fall back to the ICAO passport range of 6 to 9 characters.
+fn formats_for(kind: DocumentKind, country_code: &str) -> &'static [&'static str] {
+    match (kind, country_code) {
+        (DocumentKind::Passport, "US") => &["999999999", "A99999999"],
+        (DocumentKind::Passport, "GB") => &["999999999"],
+        (DocumentKind::Passport, "PT") => &["A999999"],
+        (DocumentKind::Passport, "ES") => &["AAA999999"],
+        (DocumentKind::Passport, "FR") => &["99AA99999"],
+        (DocumentKind::Passport, "DE") => &["XXXXXXXXX"],
+        (DocumentKind::Passport, "BR") => &["AA999999"],
+        (DocumentKind::Passport, "IN") => &["A9999999"],
+        (DocumentKind::Passport, _) => &["XXXXXX", "XXXXXXX", "XXXXXXXX", "XXXXXXXXX"],
+        // Cartão de Cidadão: civil number, check digit, version and second check digit
+        (DocumentKind::NationalId, "PT") => &["999999999AA9", "999999999AX9"],
+        // DNI, and NIE for foreign residents
+        (DocumentKind::NationalId, "ES") => &["99999999A", "A9999999A"],
+        (DocumentKind::NationalId, "IT") => &["AA99999AA"],
+        // CPF
+        (DocumentKind::NationalId, "BR") => &["99999999999"],
+        // Emirates ID
+        (DocumentKind::NationalId, "AE") => &["784999999999999"],
+        (DocumentKind::NationalId, "SA") => &["1999999999", "2999999999"],
+        (DocumentKind::NationalId, _) => &[],
+    }
+}
+
+fn digits(number: &str) -> Vec<u32> {
+    number.chars().filter_map(|c| c.to_digit(10)).collect()
+}
+
+fn luhn_valid(number: &str) -> bool {
+    let sum: u32 = digits(number)
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, digit)| if i % 2 == 1 { (digit * 2) / 10 + (digit * 2) % 10 } else { *digit })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+fn check_digits_valid(kind: DocumentKind, country_code: &str, number: &str) -> bool {
+    match (kind, country_code) {
+        (DocumentKind::NationalId, "ES") => {
+            // NIE prefixes X, Y and Z stand for 0, 1 and 2
+            let numeric: String = number[..8]
+                .replacen('X', "0", 1)
+                .replacen('Y', "1", 1)
+                .replacen('Z', "2", 1);
+            match numeric.parse::<usize>() {
+                Ok(value) => number.as_bytes()[8] == DNI_LETTERS[value % 23],
+                Err(_) => false,
+            }
+        }
+        (DocumentKind::NationalId, "BR") => {
+            let d = digits(number);
+            // Repeated digits pass the checksum but are never issued
+            if d.iter().all(|digit| *digit == d[0]) {
+                return false;
+            }
+            let check = |len: usize| {
+                let sum: u32 = d[..len]
+                    .iter()
+                    .zip((2..=(len as u32) + 1).rev())
+                    .map(|(digit, weight)| digit * weight)
+                    .sum();
+                ((sum * 10) % 11) % 10
+            };
+            check(9) == d[9] && check(10) == d[10]
+        }
+        (DocumentKind::NationalId, "AE" | "SA") => luhn_valid(number),
+        _ => true,
+    }
+}
+
+/// A passport or national ID number, validated for its country
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityDocument {
+    pub kind: DocumentKind,
+    /// ISO 3166-1 alpha-2 of the issuing country
+    pub country_code: String,
+    /// Uppercase, without spaces or dashes
+    #[serde(serialize_with = "serialize_exposed")]
+    pub number: SecretString,
+}
+
+impl IdentityDocument {
+    /// Normalize and validate a number as typed; errors name the document, never the number
+    pub fn new(kind: DocumentKind, country_code: &str, number: &str) -> Result<Self, ApiError> {
+        let country_code = country_code.trim().to_uppercase();
+        let number: String = number
+            .chars()
+            .filter(|c| !c.is_whitespace() && !matches!(c, '-' | '.'))
+            .collect::<String>()
+            .to_uppercase();
+
+        let formats = formats_for(kind, &country_code);
+        if formats.is_empty() {
+            return Err(ApiError::BadRequest {
+                message: format!("National ID documents are not supported for country '{}'", country_code),
+            });
+        }
+        let valid = formats.iter().any(|format| matches_format(&number, format)) &&
+            check_digits_valid(kind, &country_code, &number);
+        if !valid {
+            let document = match kind {
+                DocumentKind::Passport => "passport",
+                DocumentKind::NationalId => "national ID",
+            };
+            return Err(ApiError::BadRequest {
+                message: format!("Invalid {} number for {}", document, country_code),
+            });
+        }
+
+        Ok(Self { kind, country_code, number: SecretString::from(number) })
+    }
+
+    /// The number with all but its last characters hidden, for display and logs
+    pub fn masked(&self) -> String {
+        mask_document_number(self.number.expose_secret())
+    }
+}
+
+/// Hide all but the last 4 characters ("X1234567A" -> "•••••567A"); numbers of 8 characters or
+/// fewer keep only 2 visible so most of a short number is never shown
+pub fn mask_document_number(number: &str) -> String {
+    let length = number.chars().count();
+    let visible = if length > 8 { VISIBLE_SUFFIX } else { 2.min(length / 2) };
+
+    number
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i + visible < length { MASK_CHAR } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_validation_and_masking() {
+        let dni = IdentityDocument::new(DocumentKind::NationalId, "es", "12345678-z").unwrap();
+        assert_eq!(dni.masked(), "•••••678Z");
+        assert_eq!(format!("{:?}", dni.number), "Secret([REDACTED])");
+        let stored = serde_json::to_string(&dni).unwrap();
+        assert_eq!(serde_json::from_str::<IdentityDocument>(&stored).unwrap(), dni, "round-trips through storage");
+
+        assert!(IdentityDocument::new(DocumentKind::NationalId, "ES", "X1234567L").is_ok());
+        assert!(IdentityDocument::new(DocumentKind::NationalId, "ES", "12345678A").is_err());
+        assert!(IdentityDocument::new(DocumentKind::NationalId, "BR", "529.982.247-25").is_ok());
+        assert!(IdentityDocument::new(DocumentKind::NationalId, "BR", "111.111.111-11").is_err());
+        assert!(IdentityDocument::new(DocumentKind::NationalId, "AE", "784-1990-1234567-6").is_ok());
+        assert!(IdentityDocument::new(DocumentKind::Passport, "PT", "C123456").is_ok());
+        assert!(IdentityDocument::new(DocumentKind::Passport, "KE", "AK12345").is_ok(), "ICAO fallback");
+        assert!(IdentityDocument::new(DocumentKind::NationalId, "KE", "12345678").is_err(), "unsupported");
+
+        let error = IdentityDocument::new(DocumentKind::Passport, "US", "12345").unwrap_err();
+        assert!(!error.to_string().contains("12345"), "numbers are never echoed");
+        assert_eq!(mask_document_number("C123456"), "•••••56");
+    }
+}
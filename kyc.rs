@@ -0,0 +1,247 @@
+//! Identity verification (KYC) through a pluggable provider such as Onfido or Sumsub
+//!
+//! The onboarding flow talks to a `KycProvider`: it creates an applicant, hands the app presigned
+//! upload URLs so document images go straight to the provider, starts the check, and then learns
+//! the outcome from the provider's webhook. Provider statuses are normalized into `KycStatus` and
+//! `RejectionReason`, so the flow doesn't depend on one vendor's vocabulary:
+//!
+//! ```ignore
+//! let applicant_id = provider.create_applicant(&Applicant::new(&user.id, &user.country_code)).await?;
+//! let upload = provider.document_upload_url(&applicant_id, KycDocumentType::Passport, DocumentSide::Front).await?;
+//! // ... the app uploads to `upload.url`, then:
+//! provider.start_verification(&applicant_id).await?;
+//!
+//! // webhook route
+//! let event = provider.parse_webhook(signature.as_deref(), &body)?;
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+use chrono::{ DateTime, NaiveDate, Utc };
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
+use serde::{ Deserialize, Serialize };
+
+use crate::common_lib::error::ApiError;
+
+pub type KycFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, ApiError>> + Send + 'a>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum KycDocumentType {
+    Passport,
+    NationalId,
+    DrivingLicence,
+    ResidencePermit,
+    /// Photo or video of the applicant for the face match
+    Selfie,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DocumentSide {
+    Front,
+    Back,
+}
+
+/// Person to verify; only `external_user_id` and `country_code` are required by every provider
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Applicant {
+    /// Our user id, sent to the provider so webhooks can be matched without a lookup table
+    pub external_user_id: String,
+    /// ISO 3166-1 alpha-2 of residence
+    pub country_code: String,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub email: Option<String>,
+    pub date_of_birth: Option<NaiveDate>,
+}
+
+impl Applicant {
+    pub fn new(external_user_id: &str, country_code: &str) -> Self {
+        Self {
+            external_user_id: external_user_id.to_string(),
+            country_code: country_code.to_uppercase(),
+            first_name: None,
+            last_name: None,
+            email: None,
+            date_of_birth: None,
+        }
+    }
+}
+
+/// Where the app uploads one document image, directly to the provider
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentUpload {
+    pub url: String,
+    /// "PUT" or "POST"
+    pub method: String,
+    /// Headers the upload must send, e.g. the content type the URL was signed for
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[cfg_attr(not(feature = "no_web"), schemars(with = "String"))]
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Verification state, normalized across providers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum KycStatus {
+    /// Created, documents not submitted yet
+    #[default]
+    Pending,
+    InReview,
+    Approved,
+    /// The applicant may upload new documents and try again
+    ResubmissionRequested,
+    /// Final; the applicant can't retry
+    Rejected,
+}
+
+impl KycStatus {
+    /// Whether the provider will send no further updates without a new submission
+    pub fn is_final(&self) -> bool {
+        matches!(self, KycStatus::Approved | KycStatus::Rejected)
+    }
+}
+
+/// Why a verification did not pass, normalized across providers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RejectionReason {
+    DocumentExpired,
+    /// Blurry, cropped or glare-covered images
+    DocumentUnreadable,
+    DocumentNotSupported,
+    SuspectedFraud,
+    FaceMismatch,
+    Underage,
+    /// Sanctions, PEP or watchlist hit
+    WatchlistMatch,
+    /// Provider reason without a normalized equivalent
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationResult {
+    pub applicant_id: String,
+    pub status: KycStatus,
+    #[serde(default)]
+    pub rejection_reasons: Vec<RejectionReason>,
+    #[cfg_attr(not(feature = "no_web"), schemars(with = "String"))]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A verified webhook delivery
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KycWebhookEvent {
+    /// Provider's delivery id, for deduplicating retried deliveries
+    pub event_id: String,
+    pub external_user_id: Option<String>,
+    pub result: VerificationResult,
+}
+
+/// A KYC vendor
+pub trait KycProvider: Send + Sync {
+    /// Short name for logs and stored records, e.g. "onfido"
+    fn name(&self) -> &str;
+
+    /// Register an applicant; returns the provider's applicant id
+    fn create_applicant<'a>(&'a self, applicant: &'a Applicant) -> KycFuture<'a, String>;
+
+    /// Presigned URL for one document image
+    fn document_upload_url<'a>(
+        &'a self,
+        applicant_id: &'a str,
+        document_type: KycDocumentType,
+        side: DocumentSide
+    ) -> KycFuture<'a, DocumentUpload>;
+
+    /// Submit the uploaded documents for checking
+    fn start_verification<'a>(&'a self, applicant_id: &'a str) -> KycFuture<'a, ()>;
+
+    /// Current result, for polling when a webhook was missed
+    fn verification_result<'a>(&'a self, applicant_id: &'a str) -> KycFuture<'a, VerificationResult>;
+
+    /// Verify a webhook's signature against its raw body and parse it
+    /// Fails with `Unauthorized` when the signature is missing or wrong.
+    fn parse_webhook(&self, signature: Option<&str>, body: &[u8]) -> Result<KycWebhookEvent, ApiError>;
+}
+
+/// Onfido check `status` ("in_progress", "awaiting_applicant", "complete", ...) and `result`
+/// ("clear", "consider") as a status; "consider" results await review in the Onfido dashboard
+pub fn onfido_status(status: &str, result: Option<&str>) -> KycStatus {
+    match (status, result) {
+        ("complete", Some("clear")) => KycStatus::Approved,
+        // "consider" flags the check for a human decision, it isn't a rejection
+        ("complete", Some("consider")) => KycStatus::InReview,
+        ("complete", _) => KycStatus::Rejected,
+        ("awaiting_applicant" | "withdrawn", _) => KycStatus::ResubmissionRequested,
+        ("in_progress" | "paused" | "reopened", _) => KycStatus::InReview,
+        _ => KycStatus::Pending,
+    }
+}
+
+/// Sumsub `reviewStatus` ("init", "pending", "completed", ...), `reviewAnswer` ("GREEN", "RED")
+/// and `reviewRejectType` ("RETRY", "FINAL") as a status
+pub fn sumsub_status(review_status: &str, review_answer: Option<&str>, reject_type: Option<&str>) -> KycStatus {
+    match (review_status, review_answer, reject_type) {
+        ("completed", Some("GREEN"), _) => KycStatus::Approved,
+        ("completed", Some("RED"), Some("RETRY")) => KycStatus::ResubmissionRequested,
+        ("completed", _, _) => KycStatus::Rejected,
+        ("pending" | "queued" | "prechecked" | "onHold", _, _) => KycStatus::InReview,
+        _ => KycStatus::Pending,
+    }
+}
+
+/// Sumsub rejection label, e.g. "DOCUMENT_DAMAGED" or "SELFIE_MISMATCH", as a reason
+pub fn sumsub_rejection_reason(label: &str) -> RejectionReason {
+    match label {
+        "EXPIRATION_DATE" | "ID_INVALID" => RejectionReason::DocumentExpired,
+        "BAD_PROOF_OF_IDENTITY" | "DOCUMENT_DAMAGED" | "LOW_QUALITY" | "UNSATISFACTORY_PHOTOS" | "INCOMPLETE_DOCUMENT" =>
+            RejectionReason::DocumentUnreadable,
+        "UNSUPPORTED_DOCUMENT" | "WRONG_USER_REGION" => RejectionReason::DocumentNotSupported,
+        "FORGERY" | "FRAUDULENT_PATTERNS" | "DIGITAL_DOCUMENT" | "GRAPHIC_EDITOR" => RejectionReason::SuspectedFraud,
+        "SELFIE_MISMATCH" | "BAD_FACE_MATCHING" => RejectionReason::FaceMismatch,
+        "AGE_REQUIREMENT_MISMATCH" => RejectionReason::Underage,
+        "SANCTIONS" | "PEP" | "ADVERSE_MEDIA" | "CRIMINAL" => RejectionReason::WatchlistMatch,
+        other => RejectionReason::Other(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_statuses_are_normalized() {
+        assert_eq!(onfido_status("complete", Some("clear")), KycStatus::Approved);
+        assert_eq!(onfido_status("complete", Some("consider")), KycStatus::InReview);
+        assert_eq!(onfido_status("complete", None), KycStatus::Rejected);
+        assert_eq!(onfido_status("awaiting_applicant", None), KycStatus::ResubmissionRequested);
+        assert_eq!(onfido_status("in_progress", None), KycStatus::InReview);
+
+        assert_eq!(sumsub_status("completed", Some("GREEN"), None), KycStatus::Approved);
+        assert_eq!(sumsub_status("completed", Some("RED"), Some("RETRY")), KycStatus::ResubmissionRequested);
+        assert_eq!(sumsub_status("completed", Some("RED"), Some("FINAL")), KycStatus::Rejected);
+        assert_eq!(sumsub_status("init", None, None), KycStatus::Pending);
+        assert!(KycStatus::Rejected.is_final() && !KycStatus::ResubmissionRequested.is_final());
+
+        assert_eq!(sumsub_rejection_reason("SELFIE_MISMATCH"), RejectionReason::FaceMismatch);
+        assert_eq!(sumsub_rejection_reason("NEW_LABEL"), RejectionReason::Other("NEW_LABEL".to_string()));
+        let json = serde_json::to_value(vec![RejectionReason::Underage, RejectionReason::Other("X".to_string())]).unwrap();
+        assert_eq!(json, serde_json::json!(["UNDERAGE", { "OTHER": "X" }]));
+    }
+}
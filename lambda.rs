@@ -0,0 +1,180 @@
+//! AWS Lambda adapters, enabled with the `lambda` feature
+//! Clients are created lazily on first use and reused across warm invocations of the same container.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::{ Arc, OnceLock };
+use std::time::{ Duration, Instant };
+use lambda_runtime::Context;
+use reqwest::Client;
+use tokio::sync::{ OnceCell, RwLock };
+use tracing::{ debug, info, warn };
+use tracing_subscriber::EnvFilter;
+
+use crate::common_lib::constants::{ MAXMIND_API_KEY, MAXMIND_API_URL };
+use crate::common_lib::error::ApiError;
+use crate::common_lib::geolocation::{ GeolocationConfig, GeolocationService };
+use crate::common_lib::logging::RequestId;
+use crate::common_lib::secret::SecretString;
+use crate::common_lib::utils::{ get_env_var, get_secret_value };
+
+static COLD_START: AtomicBool = AtomicBool::new(true);
+static GEOLOCATION: OnceCell<Arc<GeolocationService>> = OnceCell::const_new();
+static SECRETS: OnceLock<RwLock<HashMap<String, CachedSecret>>> = OnceLock::new();
+
+/// How long `cached_secret` reuses a value before reading it again, so rotations reach warm containers
+pub const SECRET_TTL: Duration = Duration::from_secs(300);
+
+struct CachedSecret {
+    value: SecretString,
+    loaded_at: Instant,
+}
+
+/// Returns true only for the first invocation handled by this container
+pub fn is_cold_start() -> bool {
+    COLD_START.swap(false, Ordering::Relaxed)
+}
+
+/// Initialise JSON logging for CloudWatch
+/// Timestamps are omitted because CloudWatch records its own; the level is read from `RUST_LOG`.
+pub fn init_logging() {
+    let result = tracing_subscriber
+        ::fmt()
+        .json()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with_current_span(false)
+        .with_ansi(false)
+        .without_time()
+        .try_init();
+
+    if result.is_err() {
+        debug!("LAMBDA:init_logging [ALREADY_INIT] Global subscriber already set");
+    }
+}
+
+/// Request ID for an invocation: the Lambda request ID as-is, so logs can be correlated with
+/// CloudWatch and X-Ray; a fresh ID is generated if the context ID is not a UUID
+pub fn request_id_from_context(context: &Context) -> RequestId {
+    match context.request_id.parse::<RequestId>() {
+        Ok(req_id) => req_id,
+        Err(_) => {
+            warn!(
+                "LAMBDA:request_id [INVALID_CONTEXT_ID] Generating request id - aws_request_id: {}",
+                context.request_id
+            );
+            RequestId::new()
+        }
+    }
+}
+
+/// Shared geolocation service, created on first use from `MAXMIND_API_KEY` and `MAXMIND_API_URL`
+pub async fn geolocation_service() -> Result<Arc<GeolocationService>, ApiError> {
+    GEOLOCATION.get_or_try_init(|| async {
+        let api_key = get_env_var(MAXMIND_API_KEY, None).map_err(|e| ApiError::InternalServerError {
+            message: e.to_string(),
+        })?;
+        let service_url = get_env_var(
+            MAXMIND_API_URL,
+            Some(&GeolocationConfig::default().service_url)
+        ).map_err(|e| ApiError::InternalServerError { message: e.to_string() })?;
+        let config = GeolocationConfig::builder().api_key(&api_key).service_url(&service_url).build()?;
+
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .build()
+            .map_err(|e| ApiError::InternalServerError {
+                message: format!("Failed to build HTTP client: {e}"),
+            })?;
+
+        info!("LAMBDA:geolocation_service [COLD_INIT] Created geolocation service");
+
+        Ok(Arc::new(GeolocationService::new(Arc::new(client), config)?))
+    }).await.cloned()
+}
+
+/// Secret value, cached across warm invocations for up to `SECRET_TTL`
+pub async fn cached_secret(secret_name: &str) -> Result<SecretString, ApiError> {
+    let secrets = SECRETS.get_or_init(|| RwLock::new(HashMap::new()));
+    cached_or_load(secrets, secret_name, SECRET_TTL, load_secret).await
+}
+
+/// Secret value read again from Secrets Manager, for callers whose cached value was just rejected
+/// (e.g. a 401 from the provider after a rotation)
+pub async fn refresh_secret(secret_name: &str) -> Result<SecretString, ApiError> {
+    let secrets = SECRETS.get_or_init(|| RwLock::new(HashMap::new()));
+    cached_or_load(secrets, secret_name, Duration::ZERO, load_secret).await
+}
+
+async fn load_secret(secret_name: String) -> Result<SecretString, ApiError> {
+    get_secret_value(&secret_name).await
+        .map(SecretString::from)
+        .map_err(|e| ApiError::InternalServerError {
+            message: format!("Failed to load secret '{}': {}", secret_name, e),
+        })
+}
+
+async fn cached_or_load<F, Fut>(
+    secrets: &RwLock<HashMap<String, CachedSecret>>,
+    secret_name: &str,
+    max_age: Duration,
+    load: F
+) -> Result<SecretString, ApiError>
+    where F: FnOnce(String) -> Fut, Fut: Future<Output = Result<SecretString, ApiError>>
+{
+    if let Some(secret) = secrets.read().await.get(secret_name) {
+        if secret.loaded_at.elapsed() < max_age {
+            return Ok(secret.value.clone());
+        }
+    }
+
+    let value = load(secret_name.to_string()).await?;
+
+    info!("LAMBDA:cached_secret [LOADED] Loaded secret - name: {}", secret_name);
+    secrets.write().await.insert(secret_name.to_string(), CachedSecret {
+        value: value.clone(),
+        loaded_at: Instant::now(),
+    });
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_request_id_from_context() {
+        let aws_request_id = "8476a536-e9f4-11e8-9739-2dfe598c3fcd";
+        let mut context = Context::default();
+        context.request_id = aws_request_id.to_string();
+
+        let req_id = request_id_from_context(&context);
+        assert_eq!(req_id.as_u128(), Uuid::parse_str(aws_request_id).unwrap().as_u128());
+        assert_eq!(req_id.as_str(), aws_request_id);
+
+        let fallback = request_id_from_context(&Context::default());
+        assert_ne!(fallback.as_u128(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cached_secret_reloads_once_stale() {
+        let secrets = RwLock::new(HashMap::new());
+        let load = |version: &'static str| move |_: String| async move { Ok(SecretString::from(version)) };
+
+        let first = cached_or_load(&secrets, "app/config", SECRET_TTL, load("v1")).await.unwrap();
+        let cached = cached_or_load(&secrets, "app/config", SECRET_TTL, load("v2")).await.unwrap();
+        assert_eq!((first.expose_secret().as_str(), cached.expose_secret().as_str()), ("v1", "v1"));
+
+        let refreshed = cached_or_load(&secrets, "app/config", Duration::ZERO, load("v2")).await.unwrap();
+        assert_eq!(refreshed.expose_secret(), "v2");
+        let cached = cached_or_load(&secrets, "app/config", SECRET_TTL, load("v3")).await.unwrap();
+        assert_eq!(cached.expose_secret(), "v2");
+
+        let failed = cached_or_load(&secrets, "app/config", Duration::ZERO, |_| async {
+            Err(ApiError::InternalServerError { message: "unavailable".to_string() })
+        }).await;
+        assert!(failed.is_err());
+    }
+}
@@ -1,34 +1,232 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Instant;
+#[cfg(not(feature = "no_web"))]
+use rocket::fairing::{ Fairing, Info, Kind };
+#[cfg(not(feature = "no_web"))]
+use rocket::http::Header;
+#[cfg(not(feature = "no_web"))]
+use rocket::request::{ FromRequest, Outcome, Request };
+#[cfg(not(feature = "no_web"))]
+use rocket::Response;
 use uuid::Uuid;
 
+use crate::common_lib::clock::{ system_clock, Clock };
+#[cfg(not(feature = "no_web"))]
+use crate::common_lib::constants::X_CORRELATION_ID;
+
 /// Generate a correlation ID for request tracing
 pub fn generate_correlation_id() -> String {
     Uuid::new_v4().to_string()
 }
 
+const BASE62_ALPHABET: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const REQUEST_ID_LEN: usize = 22;
+/// Longest incoming ID kept verbatim: a UUID in URN form
+const MAX_REQUEST_ID_LEN: usize = 45;
+
+/// Compact request identifier: 128 random bits rendered as a fixed-width 22-char base62 string
+/// Incoming UUID correlation IDs are kept as received so logs match the caller's. The text is
+/// stored inline, so formatting and `as_str` never allocate.
+/// Create it once per request (e.g. via the Rocket request guard) and pass it down by value.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId {
+    value: u128,
+    encoded: [u8; MAX_REQUEST_ID_LEN],
+    len: u8,
+}
+
+impl RequestId {
+    pub fn new() -> Self {
+        Self::from_u128(rand::random())
+    }
+
+    pub fn from_u128(value: u128) -> Self {
+        // Peel off 10 base62 digits at a time so the inner loop runs on u64 instead of u128
+        const CHUNK_DIGITS: usize = 10;
+        const CHUNK: u128 = (62u64.pow(CHUNK_DIGITS as u32)) as u128;
+
+        let mut encoded = [b'0'; MAX_REQUEST_ID_LEN];
+        let mut remaining = value;
+        let mut pos = REQUEST_ID_LEN;
+
+        while pos > 0 {
+            let mut chunk = (remaining % CHUNK) as u64;
+            remaining /= CHUNK;
+
+            for _ in 0..pos.min(CHUNK_DIGITS) {
+                pos -= 1;
+                encoded[pos] = BASE62_ALPHABET[(chunk % 62) as usize];
+                chunk /= 62;
+            }
+        }
+
+        Self { value, encoded, len: REQUEST_ID_LEN as u8 }
+    }
+
+    /// Wrap an existing UUID correlation ID, keeping its hyphenated form
+    pub fn from_uuid(uuid: Uuid) -> Self {
+        Self::verbatim(uuid.as_u128(), uuid.hyphenated().encode_lower(&mut Uuid::encode_buffer()))
+    }
+
+    fn verbatim(value: u128, text: &str) -> Self {
+        let mut encoded = [0; MAX_REQUEST_ID_LEN];
+        encoded[..text.len()].copy_from_slice(text.as_bytes());
+        Self { value, encoded, len: text.len() as u8 }
+    }
+
+    pub fn as_u128(&self) -> u128 {
+        self.value
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only base62 digits or the bytes of a parsed UUID are ever written to `encoded`
+        std::str::from_utf8(&self.encoded[..usize::from(self.len)]).unwrap_or_default()
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RequestId({})", self.as_str())
+    }
+}
+
+impl FromStr for RequestId {
+    type Err = String;
+
+    /// Accepts the compact base62 form or a UUID, which is kept exactly as written
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() == REQUEST_ID_LEN {
+            let mut value: u128 = 0;
+            for byte in s.bytes() {
+                let digit = BASE62_ALPHABET.iter()
+                    .position(|c| *c == byte)
+                    .ok_or_else(|| format!("Invalid request ID: '{}'", s))?;
+                value = value
+                    .checked_mul(62)
+                    .and_then(|v| v.checked_add(digit as u128))
+                    .ok_or_else(|| format!("Request ID out of range: '{}'", s))?;
+            }
+            return Ok(Self::from_u128(value));
+        }
+
+        match Uuid::parse_str(s) {
+            Ok(uuid) if s.len() <= MAX_REQUEST_ID_LEN => Ok(Self::verbatim(uuid.as_u128(), s)),
+            _ => Err(format!("Invalid request ID: '{}'", s)),
+        }
+    }
+}
+
+/// Request guard resolving one `RequestId` per request, reusing an incoming
+/// `X-Correlation-ID` header when it holds a valid ID
+#[cfg(not(feature = "no_web"))]
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let req_id = *request.local_cache(|| {
+            request
+                .headers()
+                .get_one(X_CORRELATION_ID)
+                .and_then(|header| header.parse().ok())
+                .unwrap_or_default()
+        });
+
+        Outcome::Success(req_id)
+    }
+}
+
+/// Fairing echoing the request's `RequestId` in the `X-Correlation-ID` response header, so callers
+/// can quote the same ID the handler logged
+#[cfg(not(feature = "no_web"))]
+pub struct CorrelationIdFairing;
+
+#[cfg(not(feature = "no_web"))]
+#[rocket::async_trait]
+impl Fairing for CorrelationIdFairing {
+    fn info(&self) -> Info {
+        Info { name: "Correlation ID", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let req_id = request.guard::<RequestId>().await.succeeded().unwrap_or_default();
+        response.set_header(Header::new(X_CORRELATION_ID, req_id.to_string()));
+    }
+}
+
 /// Extract correlation ID from request headers or generate new one
 pub fn extract_or_generate_correlation_id(headers: Option<&str>) -> String {
     headers.and_then(|h| h.parse().ok()).unwrap_or_else(|| generate_correlation_id())
 }
 
+/// Request ID held by a timer: either a legacy string ID or a compact `RequestId`
+enum TimerRequestId {
+    Owned(String),
+    Compact(RequestId),
+}
+
+impl fmt::Display for TimerRequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimerRequestId::Owned(req_id) => f.write_str(req_id),
+            TimerRequestId::Compact(req_id) => f.write_str(req_id.as_str()),
+        }
+    }
+}
+
 /// Timer for measuring operation duration
 pub struct OperationTimer {
     start: Instant,
-    operation: String,
-    req_id: String,
+    operation: Cow<'static, str>,
+    req_id: TimerRequestId,
+    clock: Arc<dyn Clock>,
 }
 
 impl OperationTimer {
     pub fn new(operation: &str, req_id: &str) -> Self {
+        Self::with_clock(operation, req_id, system_clock())
+    }
+
+    /// Create a timer measuring against the given clock (e.g. a `MockClock` in tests)
+    pub fn with_clock(operation: &str, req_id: &str, clock: Arc<dyn Clock>) -> Self {
+        Self::build(Cow::Owned(operation.to_string()), TimerRequestId::Owned(req_id.to_string()), clock)
+    }
+
+    /// Create a timer for a compact request ID; nothing is allocated for the operation or the ID
+    pub fn for_request(operation: &'static str, req_id: RequestId) -> Self {
+        Self::for_request_with_clock(operation, req_id, system_clock())
+    }
+
+    pub fn for_request_with_clock(operation: &'static str, req_id: RequestId, clock: Arc<dyn Clock>) -> Self {
+        Self::build(Cow::Borrowed(operation), TimerRequestId::Compact(req_id), clock)
+    }
+
+    fn build(operation: Cow<'static, str>, req_id: TimerRequestId, clock: Arc<dyn Clock>) -> Self {
         Self {
-            start: Instant::now(),
-            operation: operation.to_string(),
-            req_id: req_id.to_string(),
+            start: clock.now(),
+            operation,
+            req_id,
+            clock,
         }
     }
 
     pub fn elapsed_ms(&self) -> u64 {
-        self.start.elapsed().as_millis() as u64
+        self.clock.now().duration_since(self.start).as_millis() as u64
     }
 
     pub fn log_completion(&self, level: LogLevel, category: &str, message: &str) {
@@ -259,3 +457,36 @@ macro_rules! log_security {
             $operation, $event, $req_id, $user_id, $user_role, format!($($arg)*))
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_id_encoding() {
+        assert_eq!(RequestId::from_u128(0).as_str(), "0000000000000000000000");
+        assert_eq!(RequestId::from_u128(61).as_str(), "000000000000000000000z");
+        assert_eq!(RequestId::from_u128(u128::MAX).as_str(), "7n42DGM5Tflk9n8mt7Fhc7");
+
+        let req_id = RequestId::new();
+        assert_eq!(req_id.to_string().len(), 22);
+        assert_eq!(req_id.as_str().parse::<RequestId>().unwrap(), req_id);
+    }
+
+    #[test]
+    fn test_request_id_parsing() {
+        let uuid = Uuid::new_v4();
+        let from_uuid: RequestId = uuid.to_string().parse().unwrap();
+        assert_eq!(from_uuid.as_u128(), uuid.as_u128());
+        assert_eq!(from_uuid.as_str(), uuid.to_string());
+        assert_eq!(RequestId::from_uuid(uuid), from_uuid);
+
+        // Incoming IDs are logged exactly as the caller sent them
+        let upper = uuid.simple().to_string().to_uppercase();
+        assert_eq!(upper.parse::<RequestId>().unwrap().to_string(), upper);
+
+        assert!("not-a-request-id".parse::<RequestId>().is_err());
+        assert!("000000000000000000000!".parse::<RequestId>().is_err());
+        assert!("zzzzzzzzzzzzzzzzzzzzzz".parse::<RequestId>().is_err());
+    }
+}
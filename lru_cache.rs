@@ -0,0 +1,247 @@
+//! Bounded least-recently-used map with O(1) lookups, inserts and evictions
+//!
+//! Entries live in a slab of doubly-linked nodes indexed by a `HashMap`, so promoting or evicting
+//! an entry only relinks two neighbours. Not synchronized; wrap it in a `Mutex` when shared.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    /// Towards the most recently used entry
+    prev: Option<usize>,
+    /// Towards the least recently used entry
+    next: Option<usize>,
+}
+
+pub struct LruCache<K, V> {
+    capacity: usize,
+    index: HashMap<K, usize>,
+    nodes: Vec<Option<Node<K, V>>>,
+    free: Vec<usize>,
+    /// Most recently used
+    head: Option<usize>,
+    /// Least recently used, evicted first
+    tail: Option<usize>,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    /// `capacity` must be at least 1
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "LruCache capacity must be at least 1");
+
+        Self {
+            capacity,
+            index: HashMap::new(),
+            nodes: Vec::new(),
+            free: Vec::new(),
+            head: None,
+            tail: None,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Look up an entry and mark it most recently used
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        let slot = *self.index.get(key)?;
+        self.detach(slot);
+        self.attach_front(slot);
+        self.node(slot).map(|node| &node.value)
+    }
+
+    /// Look up an entry without changing its recency
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        let slot = *self.index.get(key)?;
+        self.node(slot).map(|node| &node.value)
+    }
+
+    /// Insert or replace an entry as most recently used; returns the entry evicted to make room
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if let Some(&slot) = self.index.get(&key) {
+            if let Some(node) = self.nodes[slot].as_mut() {
+                node.value = value;
+            }
+            self.detach(slot);
+            self.attach_front(slot);
+            return None;
+        }
+
+        let evicted = if self.len() >= self.capacity {
+            self.tail.and_then(|slot| self.remove_slot(slot))
+        } else {
+            None
+        };
+
+        let node = Node { key: key.clone(), value, prev: None, next: None };
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = Some(node);
+                slot
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        self.index.insert(key, slot);
+        self.attach_front(slot);
+
+        evicted
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V> where K: Borrow<Q>, Q: Hash + Eq + ?Sized {
+        let slot = *self.index.get(key)?;
+        self.remove_slot(slot).map(|(_, value)| value)
+    }
+
+    /// Keep only the entries for which `keep` returns true
+    pub fn retain(&mut self, mut keep: impl FnMut(&K, &V) -> bool) {
+        let mut cursor = self.head;
+        while let Some(slot) = cursor {
+            let Some(node) = self.node(slot) else {
+                break;
+            };
+            cursor = node.next;
+            let kept = keep(&node.key, &node.value);
+            if !kept {
+                self.remove_slot(slot);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.index.clear();
+        self.nodes.clear();
+        self.free.clear();
+        self.head = None;
+        self.tail = None;
+    }
+
+    /// Entries from most to least recently used
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let mut cursor = self.head;
+        std::iter::from_fn(move || {
+            let node = self.node(cursor?)?;
+            cursor = node.next;
+            Some((&node.key, &node.value))
+        })
+    }
+
+    fn node(&self, slot: usize) -> Option<&Node<K, V>> {
+        self.nodes.get(slot).and_then(Option::as_ref)
+    }
+
+    fn remove_slot(&mut self, slot: usize) -> Option<(K, V)> {
+        self.detach(slot);
+        let node = self.nodes[slot].take()?;
+        self.index.remove(&node.key);
+        self.free.push(slot);
+        Some((node.key, node.value))
+    }
+
+    fn detach(&mut self, slot: usize) {
+        let Some((prev, next)) = self.node(slot).map(|node| (node.prev, node.next)) else {
+            return;
+        };
+
+        match prev {
+            Some(prev) => {
+                if let Some(node) = self.nodes[prev].as_mut() {
+                    node.next = next;
+                }
+            }
+            None => {
+                self.head = next;
+            }
+        }
+        match next {
+            Some(next) => {
+                if let Some(node) = self.nodes[next].as_mut() {
+                    node.prev = prev;
+                }
+            }
+            None => {
+                self.tail = prev;
+            }
+        }
+    }
+
+    fn attach_front(&mut self, slot: usize) {
+        let old_head = self.head;
+        if let Some(node) = self.nodes[slot].as_mut() {
+            node.prev = None;
+            node.next = old_head;
+        }
+        if let Some(old_head) = old_head {
+            if let Some(node) = self.nodes[old_head].as_mut() {
+                node.prev = Some(slot);
+            }
+        }
+        self.head = Some(slot);
+        if self.tail.is_none() {
+            self.tail = Some(slot);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(cache: &LruCache<&'static str, u32>) -> Vec<&'static str> {
+        cache.iter().map(|(key, _)| *key).collect()
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = LruCache::new(3);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+
+        assert_eq!(cache.get(&"a"), Some(&1));
+        assert_eq!(cache.insert("d", 4), Some(("b", 2)));
+        assert_eq!(keys(&cache), vec!["d", "a", "c"]);
+
+        assert_eq!(cache.peek(&"c"), Some(&3));
+        assert_eq!(cache.insert("c", 30), None);
+        assert_eq!(cache.insert("e", 5), Some(("a", 1)));
+        assert_eq!(keys(&cache), vec!["e", "c", "d"]);
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn test_remove_and_retain_reuse_slots() {
+        let mut cache = LruCache::new(4);
+        for (key, value) in [("a", 1), ("b", 2), ("c", 3), ("d", 4)] {
+            cache.insert(key, value);
+        }
+
+        assert_eq!(cache.remove(&"d"), Some(4));
+        cache.retain(|_, value| value % 2 == 1);
+        assert_eq!(keys(&cache), vec!["c", "a"]);
+
+        cache.insert("e", 5);
+        cache.insert("f", 6);
+        assert_eq!(keys(&cache), vec!["f", "e", "c", "a"]);
+        assert_eq!(cache.nodes.len(), 4);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.insert("g", 7), None);
+        assert_eq!(keys(&cache), vec!["g"]);
+    }
+}
@@ -0,0 +1,353 @@
+//! Post-upload media processing, compiled out by the `no_mongo` or `no_web` feature
+//!
+//! Completed uploads are queued as `MediaJob`s and a worker runs the registered `MediaProcessor`
+//! hooks (dimension probing, duration, thumbnails, EXIF) to fill in a `MediaMetadata` record. The
+//! EXIF map is filtered against `SAFE_EXIF_TAGS` after every hook has run, so GPS coordinates and
+//! device serial numbers never reach the stored metadata whichever extractor produced them.
+//!
+//! A job whose hook fails is requeued until `max_attempts`, after which the metadata is stored
+//! with `MediaProcessingStatus::Failed` so clients can fall back to a generic preview.
+
+use std::collections::{ BTreeMap, VecDeque };
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex };
+use std::time::Duration;
+use serde::{ Deserialize, Serialize };
+use tokio::sync::RwLock;
+use tracing::{ debug, error, warn };
+
+#[cfg(not(feature = "no_aws"))]
+use crate::common_lib::chunked_upload::UploadSession;
+use crate::common_lib::error::ApiError;
+use crate::common_lib::shared_models::{ MediaKind, MediaMetadata, MediaProcessingStatus };
+
+pub type MediaFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, ApiError>> + Send + 'a>>;
+
+/// EXIF tags that describe the image itself; everything else is dropped
+pub const SAFE_EXIF_TAGS: &[&str] = &[
+    "DateTimeOriginal",
+    "ExposureTime",
+    "FNumber",
+    "FocalLength",
+    "ISOSpeedRatings",
+    "Orientation",
+    "PixelXDimension",
+    "PixelYDimension",
+];
+
+/// Keep only allowlisted EXIF tags
+pub fn sanitize_exif(tags: BTreeMap<String, String>) -> BTreeMap<String, String> {
+    tags.into_iter()
+        .filter(|(tag, _)| SAFE_EXIF_TAGS.contains(&tag.as_str()))
+        .collect()
+}
+
+/// A stored upload waiting to be processed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaJob {
+    pub upload_id: String,
+    pub owner_id: String,
+    pub bucket: String,
+    pub key: String,
+    pub content_type: String,
+    pub size_bytes: u64,
+    /// Number of failed attempts so far
+    #[serde(default)]
+    pub attempt: u32,
+}
+
+impl MediaJob {
+    pub fn kind(&self) -> MediaKind {
+        MediaKind::from_content_type(&self.content_type)
+    }
+
+    /// Metadata with only the fields known from the upload itself
+    fn initial_metadata(&self) -> MediaMetadata {
+        MediaMetadata {
+            upload_id: self.upload_id.clone(),
+            key: self.key.clone(),
+            content_type: self.content_type.clone(),
+            kind: self.kind(),
+            size_bytes: self.size_bytes,
+            width: None,
+            height: None,
+            duration_ms: None,
+            thumbnail_key: None,
+            exif: BTreeMap::new(),
+            status: MediaProcessingStatus::Ready,
+        }
+    }
+}
+
+#[cfg(not(feature = "no_aws"))]
+impl From<&UploadSession> for MediaJob {
+    fn from(session: &UploadSession) -> Self {
+        Self {
+            upload_id: session.id.clone(),
+            owner_id: session.owner_id.clone(),
+            bucket: session.bucket.clone(),
+            key: session.key.clone(),
+            content_type: session.content_type.clone(),
+            size_bytes: session.total_size,
+            attempt: 0,
+        }
+    }
+}
+
+/// Queue of pending jobs (SQS, Redis list, ...); delivery is at least once
+pub trait MediaJobQueue: Send + Sync {
+    fn enqueue<'a>(&'a self, job: &'a MediaJob) -> MediaFuture<'a, ()>;
+    /// Next job, or `None` when the queue is empty
+    fn dequeue(&self) -> MediaFuture<'_, Option<MediaJob>>;
+}
+
+/// In-process queue for tests and single-instance tools
+#[derive(Default)]
+pub struct InMemoryMediaJobQueue {
+    jobs: Mutex<VecDeque<MediaJob>>,
+}
+
+impl MediaJobQueue for InMemoryMediaJobQueue {
+    fn enqueue<'a>(&'a self, job: &'a MediaJob) -> MediaFuture<'a, ()> {
+        self.jobs.lock().unwrap().push_back(job.clone());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn dequeue(&self) -> MediaFuture<'_, Option<MediaJob>> {
+        let job = self.jobs.lock().unwrap().pop_front();
+        Box::pin(async move { Ok(job) })
+    }
+}
+
+/// Where processed metadata is written
+pub trait MediaMetadataStore: Send + Sync {
+    fn save<'a>(&'a self, metadata: &'a MediaMetadata) -> MediaFuture<'a, ()>;
+}
+
+/// In-process metadata store for tests and single-instance tools
+#[derive(Default)]
+pub struct InMemoryMediaMetadataStore {
+    metadata: RwLock<BTreeMap<String, MediaMetadata>>,
+}
+
+impl InMemoryMediaMetadataStore {
+    pub async fn get(&self, upload_id: &str) -> Option<MediaMetadata> {
+        self.metadata.read().await.get(upload_id).cloned()
+    }
+}
+
+impl MediaMetadataStore for InMemoryMediaMetadataStore {
+    fn save<'a>(&'a self, metadata: &'a MediaMetadata) -> MediaFuture<'a, ()> {
+        Box::pin(async move {
+            self.metadata.write().await.insert(metadata.upload_id.clone(), metadata.clone());
+            Ok(())
+        })
+    }
+}
+
+/// A processing hook, e.g. an image probe or an ffprobe-based video extractor
+pub trait MediaProcessor: Send + Sync {
+    /// Name used in logs
+    fn name(&self) -> &str;
+
+    fn handles(&self, kind: MediaKind) -> bool;
+
+    /// Fill in the fields this hook knows about
+    fn process<'a>(&'a self, job: &'a MediaJob, metadata: &'a mut MediaMetadata) -> MediaFuture<'a, ()>;
+}
+
+/// Runs processing hooks for queued uploads
+pub struct MediaPipeline {
+    queue: Arc<dyn MediaJobQueue>,
+    store: Arc<dyn MediaMetadataStore>,
+    processors: Vec<Arc<dyn MediaProcessor>>,
+    max_attempts: u32,
+}
+
+impl MediaPipeline {
+    pub fn new(queue: Arc<dyn MediaJobQueue>, store: Arc<dyn MediaMetadataStore>) -> Self {
+        Self {
+            queue,
+            store,
+            processors: Vec::new(),
+            max_attempts: 3,
+        }
+    }
+
+    /// Register a hook; hooks run in registration order
+    pub fn with_processor(mut self, processor: Arc<dyn MediaProcessor>) -> Self {
+        self.processors.push(processor);
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Queue an upload for processing, typically right after it completes
+    pub async fn enqueue(&self, job: &MediaJob) -> Result<(), ApiError> {
+        self.queue.enqueue(job).await
+    }
+
+    /// Process one job; returns `false` when the queue was empty
+    pub async fn process_next(&self) -> Result<bool, ApiError> {
+        let Some(job) = self.queue.dequeue().await? else {
+            return Ok(false);
+        };
+
+        let mut metadata = job.initial_metadata();
+        let kind = job.kind();
+
+        for processor in self.processors.iter().filter(|processor| processor.handles(kind)) {
+            if let Err(e) = processor.process(&job, &mut metadata).await {
+                return self.retry_or_fail(job, processor.name(), e).await.map(|_| true);
+            }
+        }
+
+        metadata.exif = sanitize_exif(std::mem::take(&mut metadata.exif));
+        self.store.save(&metadata).await?;
+
+        debug!(
+            "MEDIA:process [SUCCESS] Media metadata stored - upload_id: {}, kind: {:?}",
+            job.upload_id,
+            kind
+        );
+        Ok(true)
+    }
+
+    async fn retry_or_fail(&self, mut job: MediaJob, processor: &str, e: ApiError) -> Result<(), ApiError> {
+        job.attempt += 1;
+
+        if job.attempt < self.max_attempts {
+            warn!(
+                "MEDIA:process [HOOK_ERROR] Processing failed, requeueing - upload_id: {}, processor: {}, attempt: {}, error: {}",
+                job.upload_id,
+                processor,
+                job.attempt,
+                e
+            );
+            return self.queue.enqueue(&job).await;
+        }
+
+        error!(
+            "MEDIA:process [FAILED] Giving up on media processing - upload_id: {}, processor: {}, error: {}",
+            job.upload_id,
+            processor,
+            e
+        );
+        let mut metadata = job.initial_metadata();
+        metadata.status = MediaProcessingStatus::Failed;
+        self.store.save(&metadata).await
+    }
+
+    /// Spawn a worker that drains the queue and polls it when empty
+    pub fn spawn(self: &Arc<Self>, poll_interval: Duration) -> tokio::task::JoinHandle<()> {
+        let pipeline = Arc::clone(self);
+
+        tokio::spawn(async move {
+            loop {
+                match pipeline.process_next().await {
+                    Ok(true) => {}
+                    Ok(false) => tokio::time::sleep(poll_interval).await,
+                    Err(e) => {
+                        warn!("MEDIA:worker [QUEUE_ERROR] Media worker error - error: {}", e);
+                        tokio::time::sleep(poll_interval).await;
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{ AtomicU32, Ordering };
+
+    /// Reports fixed dimensions and EXIF, including tags that must be stripped
+    struct FakeImageProbe;
+
+    impl MediaProcessor for FakeImageProbe {
+        fn name(&self) -> &str {
+            "image_probe"
+        }
+
+        fn handles(&self, kind: MediaKind) -> bool {
+            kind == MediaKind::Image
+        }
+
+        fn process<'a>(&'a self, _: &'a MediaJob, metadata: &'a mut MediaMetadata) -> MediaFuture<'a, ()> {
+            metadata.width = Some(4032);
+            metadata.height = Some(3024);
+            metadata.exif = BTreeMap::from([
+                ("Orientation".to_string(), "6".to_string()),
+                ("GPSLatitude".to_string(), "38.72".to_string()),
+                ("BodySerialNumber".to_string(), "X123".to_string()),
+            ]);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[derive(Default)]
+    struct FailingProbe {
+        calls: AtomicU32,
+    }
+
+    impl MediaProcessor for FailingProbe {
+        fn name(&self) -> &str {
+            "video_probe"
+        }
+
+        fn handles(&self, kind: MediaKind) -> bool {
+            kind == MediaKind::Video
+        }
+
+        fn process<'a>(&'a self, _: &'a MediaJob, _: &'a mut MediaMetadata) -> MediaFuture<'a, ()> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async {
+                Err(ApiError::InternalServerError {
+                    message: "ffprobe exited with status 1".to_string(),
+                })
+            })
+        }
+    }
+
+    fn job(upload_id: &str, content_type: &str) -> MediaJob {
+        MediaJob {
+            upload_id: upload_id.to_string(),
+            owner_id: "user-1".to_string(),
+            bucket: "attachments".to_string(),
+            key: format!("uploads/user-1/{}/file", upload_id),
+            content_type: content_type.to_string(),
+            size_bytes: 2048,
+            attempt: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_runs_hooks_and_strips_exif() {
+        let store = Arc::new(InMemoryMediaMetadataStore::default());
+        let probe = Arc::new(FailingProbe::default());
+        let pipeline = MediaPipeline::new(Arc::new(InMemoryMediaJobQueue::default()), store.clone())
+            .with_processor(Arc::new(FakeImageProbe))
+            .with_processor(probe.clone())
+            .with_max_attempts(2);
+
+        pipeline.enqueue(&job("photo", "image/jpeg")).await.unwrap();
+        pipeline.enqueue(&job("clip", "video/mp4")).await.unwrap();
+        while pipeline.process_next().await.unwrap() {}
+
+        let photo = store.get("photo").await.unwrap();
+        assert_eq!(photo.status, MediaProcessingStatus::Ready);
+        assert_eq!((photo.width, photo.height), (Some(4032), Some(3024)));
+        assert_eq!(photo.exif, BTreeMap::from([("Orientation".to_string(), "6".to_string())]));
+
+        let clip = store.get("clip").await.unwrap();
+        assert_eq!(clip.status, MediaProcessingStatus::Failed);
+        assert_eq!(clip.kind, MediaKind::Video);
+        assert_eq!(probe.calls.load(Ordering::SeqCst), 2);
+    }
+}
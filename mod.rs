@@ -1,7 +1,136 @@
+// Heavy dependencies can be left out with opt-out cargo features declared by the host crate.
+// Everything below is compiled unless a `no_*` feature is set, so existing services build
+// unchanged without declaring any features; lightweight consumers such as workers declare the
+// features, set the ones they need and drop the matching dependencies:
+//
+// [features]
+// no_web = []     # rocket, rocket_okapi: responders, guards, fairings, OpenAPI models
+// no_aws = []     # rusoto_core, rusoto_s3, aws-config, aws-sdk-secretsmanager, sha2: S3, Secrets Manager, uploads
+// no_mongo = []   # mongodb: shared models, delta sync, collations
+// no_geo = []     # IP geolocation, geo consent, country restriction
+// no_http = ["no_geo"]  # reqwest: outbound HTTP clients; geolocation needs it
+// no_phone = []   # phonenumber: phone parsing and formatting
+//
+// Optional extras are opt-in:
+//
+// redis = ["dep:redis"]    # Redis-backed stores
+// geoip_db = ["dep:maxminddb"]  # local GeoLite2 database backend, needs geo
+// axum = ["dep:axum"]       # axum IntoResponse adapter for ApiError
+// grpc = ["dep:tonic"]     # tonic Status mapping and correlation id interceptors
+// graphql = ["dep:async-graphql"]  # async-graphql error extensions for ApiError
+// lambda = ["dep:lambda_runtime", "dep:tracing-subscriber"]  # AWS Lambda adapters, needs aws and geo
+// binary_formats = ["dep:rmp-serde", "dep:ciborium"]  # MessagePack/CBOR content negotiation, needs web
+// request_signing = ["dep:hmac", "dep:sha2"]  # signed nonce replay protection guard, needs web
+// breach_check = ["dep:sha1"]  # Have I Been Pwned client for password_policy, needs http
+// text = ["dep:unicode-normalization"]  # normalization helpers for search and matching
+// contact_matching = ["dep:sha2"]  # hashed "find friends" contact matching, needs phone
+// test_support = ["dep:proptest", "dep:wiremock"]  # fixtures, proptest strategies, Rocket test client and provider stubs, for dev-dependencies
+// benchmarks = ["dep:criterion"]  # criterion benchmarks of hot paths, needs mongo, web and geo
+// test_containers = ["redis", "dep:rusoto_sqs", "dep:testcontainers", "dep:testcontainers-modules"]  # Docker-backed integration harness, needs mongo and aws
+//
+// With every `no_*` feature set only error types, logging, constants and the pure utilities compile.
 pub mod error;
+#[cfg(not(any(feature = "no_mongo", feature = "no_web")))]
 pub mod shared_models;
+#[cfg(not(feature = "no_mongo"))]
+pub mod delta_sync;
+#[cfg(not(feature = "no_mongo"))]
+pub mod conflict_resolution;
+#[cfg(not(feature = "no_mongo"))]
+pub mod collation;
 pub mod utils;
 pub mod constants;
 pub mod country_utils;
+pub mod address;
+pub mod tax_id;
+pub mod identity_document;
+pub mod kyc;
+pub mod data_retention;
+pub mod pii_catalog;
+pub mod geo_point;
+pub mod geofence;
 pub mod logging;
+#[cfg(not(feature = "no_geo"))]
 pub mod geolocation;
+#[cfg(all(feature = "geoip_db", not(feature = "no_geo")))]
+pub mod geoip_database;
+#[cfg(not(feature = "no_geo"))]
+pub mod geo_providers;
+#[cfg(not(feature = "no_geo"))]
+pub mod geo_consent;
+#[cfg(not(feature = "no_geo"))]
+pub mod geo_velocity;
+#[cfg(not(any(feature = "no_web", feature = "no_geo")))]
+pub mod country_restriction;
+#[cfg(not(any(feature = "no_web", feature = "no_geo")))]
+pub mod request_context;
+#[cfg(all(feature = "contact_matching", not(feature = "no_phone")))]
+pub mod contact_matching;
+pub mod bank_utils;
+pub mod notifications;
+pub mod sms_delivery;
+pub mod email_validation;
+pub mod availability;
+pub mod ics;
+pub mod templates;
+pub mod notification_digest;
+pub mod clock;
+pub mod lru_cache;
+pub mod dns_cache;
+pub mod secret;
+pub mod health;
+pub mod build_info;
+pub mod heartbeat;
+pub mod warmup;
+#[cfg(not(feature = "no_http"))]
+pub mod traffic_shadow;
+pub mod windowed_metrics;
+pub mod attempts;
+pub mod password_policy;
+#[cfg(feature = "text")]
+pub mod text_normalization;
+pub mod analytics;
+#[cfg(not(feature = "no_aws"))]
+pub mod chunked_upload;
+#[cfg(not(feature = "no_aws"))]
+pub mod upload_scan;
+#[cfg(not(any(feature = "no_mongo", feature = "no_web")))]
+pub mod media_processing;
+#[cfg(not(any(feature = "no_mongo", feature = "no_web")))]
+pub mod openapi;
+#[cfg(not(feature = "no_web"))]
+pub mod api_model;
+#[cfg(not(feature = "no_web"))]
+pub mod ndjson;
+#[cfg(not(feature = "no_web"))]
+pub mod remote_config;
+#[cfg(not(feature = "no_web"))]
+pub mod app_version;
+#[cfg(not(feature = "no_web"))]
+pub mod deprecation;
+#[cfg(not(feature = "no_web"))]
+pub mod debug_routes;
+#[cfg(not(feature = "no_web"))]
+pub mod panic_handler;
+#[cfg(all(feature = "binary_formats", not(feature = "no_web")))]
+pub mod content_negotiation;
+#[cfg(all(feature = "request_signing", not(feature = "no_web")))]
+pub mod replay_protection;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(all(feature = "lambda", not(any(feature = "no_aws", feature = "no_geo"))))]
+pub mod lambda;
+#[cfg(all(any(test, feature = "test_support"), not(any(feature = "no_mongo", feature = "no_web", feature = "no_geo"))))]
+pub mod test_support;
+#[cfg(all(any(test, feature = "test_support"), not(any(feature = "no_mongo", feature = "no_web", feature = "no_geo"))))]
+pub mod contract_fixtures;
+#[cfg(all(feature = "test_containers", feature = "redis", not(any(feature = "no_mongo", feature = "no_aws"))))]
+pub mod test_containers;
+#[cfg(all(any(test, feature = "test_support"), not(feature = "no_web")))]
+pub mod test_client;
+#[cfg(all(any(test, feature = "test_support"), not(feature = "no_geo")))]
+pub mod test_http_stubs;
+#[cfg(all(feature = "benchmarks", not(any(feature = "no_mongo", feature = "no_web", feature = "no_geo"))))]
+pub mod benchmarks;
@@ -0,0 +1,102 @@
+//! Streaming newline-delimited JSON responses, compiled out by the `no_web` feature
+//!
+//! `NdJson` serializes items as the client reads them, so exports and sync endpoints can return a
+//! MongoDB cursor directly without buffering the whole result set:
+//!
+//! ```ignore
+//! #[get("/admin/export")]
+//! async fn export(db: &State<Database>) -> Result<NdJson<Cursor<Document>>, ApiError> {
+//!     let cursor = db.collection::<Document>("users").find(None, None).await.map_err(ApiError::from)?;
+//!     Ok(NdJson(cursor))
+//! }
+//! ```
+//!
+//! Items are pulled from the stream only when the connection can accept more data. If the stream
+//! yields an error after the response has started, a final `{"error": ...}` line is written and the
+//! stream ends, so clients must treat a trailing error object as a failed export.
+
+use std::fmt::Display;
+use std::future::ready;
+use std::io::Cursor;
+use rocket::futures::stream::{ Stream, StreamExt };
+use rocket::http::ContentType;
+use rocket::request::Request;
+use rocket::response::{ self, stream::ReaderStream, Responder, Response };
+use serde::Serialize;
+use tracing::error;
+
+use crate::common_lib::error::ApiError;
+
+/// Stream of `Result` items written as one JSON document per line
+pub struct NdJson<S>(pub S);
+
+impl NdJson<()> {
+    pub fn content_type() -> ContentType {
+        ContentType::new("application", "x-ndjson")
+    }
+}
+
+impl<'r, S, T, E> Responder<'r, 'static> for NdJson<S>
+    where S: Stream<Item = Result<T, E>> + Send + 'static, T: Serialize, E: Display
+{
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let lines = self.0.scan(false, |failed, item| {
+            if *failed {
+                return ready(None);
+            }
+
+            let line = match item {
+                Ok(value) => serde_json::to_vec(&value).map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+
+            let mut bytes = line.unwrap_or_else(|message| {
+                error!("NDJSON:stream [STREAM_ERROR] Ending stream after error - error: {}", message);
+                *failed = true;
+                ApiError::InternalServerError { message }.error_body().to_string().into_bytes()
+            });
+            bytes.push(b'\n');
+
+            ready(Some(bytes))
+        });
+
+        Response::build()
+            .header(NdJson::content_type())
+            .streamed_body(ReaderStream::from(lines.map(Cursor::new)))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::futures::stream;
+    use rocket::{ get, routes };
+    use crate::common_lib::test_client::{ test_client, test_rocket };
+
+    #[get("/export")]
+    fn export() -> NdJson<impl Stream<Item = Result<serde_json::Value, String>>> {
+        NdJson(
+            stream::iter(vec![
+                Ok(serde_json::json!({ "id": 1 })),
+                Ok(serde_json::json!({ "id": 2 })),
+                Err("cursor closed".to_string()),
+                Ok(serde_json::json!({ "id": 3 })),
+            ])
+        )
+    }
+
+    #[rocket::async_test]
+    async fn test_ndjson_stream_ends_after_error() {
+        let client = test_client(test_rocket(routes![export])).await;
+        let response = client.get("/export").dispatch().await;
+
+        assert_eq!(response.content_type(), Some(NdJson::content_type()));
+        let body = response.into_string().await.unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], r#"{"id":2}"#);
+        assert_eq!(lines[2], r#"{"error":"Internal Server Error: cursor closed"}"#);
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{ DateTime, Utc };
+use tokio::sync::{ mpsc, Mutex };
+use tracing::{ debug, info, warn };
+
+use crate::common_lib::error::ApiError;
+use crate::common_lib::notifications::NotificationPriority;
+
+/// Configuration for the notification digest batcher
+#[derive(Debug, Clone)]
+pub struct DigestConfig {
+    /// Flush a user's digest as soon as it holds this many items
+    pub max_batch_size: usize,
+    /// Flush a user's digest once its oldest item has waited this long
+    pub max_delay_seconds: u64,
+    /// How often the scheduler checks for due digests
+    pub flush_interval_seconds: u64,
+    /// Events at or above this priority are never batched
+    pub bypass_priority: NotificationPriority,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 20,
+            max_delay_seconds: 900, // 15 minutes
+            flush_interval_seconds: 60,
+            bypass_priority: NotificationPriority::High,
+        }
+    }
+}
+
+impl DigestConfig {
+    /// Start from the defaults and override individual settings
+    pub fn builder() -> DigestConfigBuilder {
+        DigestConfigBuilder::default()
+    }
+
+    /// Check configuration invariants
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.max_batch_size == 0 {
+            return Err(ApiError::BadRequest {
+                message: "Digest max batch size must be greater than zero".to_string(),
+            });
+        }
+        if self.flush_interval_seconds == 0 {
+            return Err(ApiError::BadRequest {
+                message: "Digest flush interval must be greater than zero".to_string(),
+            });
+        }
+        if self.max_delay_seconds < self.flush_interval_seconds {
+            return Err(ApiError::BadRequest {
+                message: format!(
+                    "Digest max delay ({}s) must not be shorter than the flush interval ({}s)",
+                    self.max_delay_seconds,
+                    self.flush_interval_seconds
+                ),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Validated builder for `DigestConfig`
+#[derive(Debug, Clone, Default)]
+pub struct DigestConfigBuilder {
+    config: DigestConfig,
+}
+
+impl DigestConfigBuilder {
+    pub fn max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.config.max_batch_size = max_batch_size;
+        self
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.config.max_delay_seconds = max_delay.as_secs();
+        self
+    }
+
+    pub fn flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.config.flush_interval_seconds = flush_interval.as_secs();
+        self
+    }
+
+    pub fn bypass_priority(mut self, bypass_priority: NotificationPriority) -> Self {
+        self.config.bypass_priority = bypass_priority;
+        self
+    }
+
+    pub fn build(self) -> Result<DigestConfig, ApiError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// A batch of notifications for one user, ready to be sent as a single digest
+#[derive(Debug, Clone)]
+pub struct Digest<T> {
+    pub user_id: String,
+    pub items: Vec<T>,
+    pub first_queued_at: DateTime<Utc>,
+    pub last_queued_at: DateTime<Utc>,
+}
+
+/// Result of enqueueing a notification
+#[derive(Debug)]
+pub enum EnqueueOutcome<T> {
+    /// Priority is high enough to skip batching; send the item immediately
+    SendNow(T),
+    /// Item was added to the user's pending digest
+    Batched,
+    /// Item filled the user's batch; the digest should be sent now
+    Flushed(Digest<T>),
+}
+
+#[derive(Debug)]
+struct PendingBatch<T> {
+    items: Vec<T>,
+    first_queued_at: DateTime<Utc>,
+    last_queued_at: DateTime<Utc>,
+}
+
+/// Accumulates low-priority notifications per user and flushes them as digests
+/// on schedule or when a size threshold is reached
+pub struct DigestBatcher<T> {
+    config: DigestConfig,
+    pending: Mutex<HashMap<String, PendingBatch<T>>>,
+}
+
+impl<T: Send + 'static> DigestBatcher<T> {
+    /// Fails with `BadRequest` when the configuration breaks an invariant, see `DigestConfig::validate`
+    pub fn new(config: DigestConfig) -> Result<Self, ApiError> {
+        config.validate()?;
+
+        Ok(Self {
+            config,
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Enqueue a notification for a user
+    pub async fn enqueue(
+        &self,
+        user_id: &str,
+        priority: NotificationPriority,
+        item: T,
+        now: DateTime<Utc>
+    ) -> EnqueueOutcome<T> {
+        if priority >= self.config.bypass_priority {
+            return EnqueueOutcome::SendNow(item);
+        }
+
+        let mut pending = self.pending.lock().await;
+        let batch = pending.entry(user_id.to_string()).or_insert_with(|| PendingBatch {
+            items: Vec::new(),
+            first_queued_at: now,
+            last_queued_at: now,
+        });
+
+        batch.items.push(item);
+        batch.last_queued_at = now;
+
+        if batch.items.len() >= self.config.max_batch_size {
+            if let Some(batch) = pending.remove(user_id) {
+                debug!(
+                    "NOTIFY:digest [SIZE_FLUSH] Batch size threshold reached - user_id: {}, items: {}",
+                    user_id,
+                    batch.items.len()
+                );
+                return EnqueueOutcome::Flushed(Self::into_digest(user_id.to_string(), batch));
+            }
+        }
+
+        EnqueueOutcome::Batched
+    }
+
+    /// Remove and return all digests whose oldest item has waited at least `max_delay_seconds`
+    pub async fn flush_due(&self, now: DateTime<Utc>) -> Vec<Digest<T>> {
+        let max_delay = chrono::Duration::seconds(self.config.max_delay_seconds as i64);
+        let mut pending = self.pending.lock().await;
+
+        let due_users: Vec<String> = pending
+            .iter()
+            .filter(|(_, batch)| now - batch.first_queued_at >= max_delay)
+            .map(|(user_id, _)| user_id.clone())
+            .collect();
+
+        due_users
+            .into_iter()
+            .filter_map(|user_id| {
+                pending.remove(&user_id).map(|batch| Self::into_digest(user_id, batch))
+            })
+            .collect()
+    }
+
+    /// Remove and return every pending digest (e.g. on shutdown)
+    pub async fn flush_all(&self) -> Vec<Digest<T>> {
+        let mut pending = self.pending.lock().await;
+
+        pending
+            .drain()
+            .map(|(user_id, batch)| Self::into_digest(user_id, batch))
+            .collect()
+    }
+
+    /// Number of users with a pending digest
+    pub async fn pending_users(&self) -> usize {
+        self.pending.lock().await.len()
+    }
+
+    /// Spawn the scheduler task that periodically flushes due digests into `sender`
+    /// The task stops once the receiving side is dropped
+    pub fn spawn_scheduler(self: &Arc<Self>, sender: mpsc::Sender<Digest<T>>) -> tokio::task::JoinHandle<()> {
+        let batcher = Arc::clone(self);
+        let interval = Duration::from_secs(batcher.config.flush_interval_seconds.max(1));
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+
+            loop {
+                ticker.tick().await;
+
+                let digests = batcher.flush_due(Utc::now()).await;
+                if !digests.is_empty() {
+                    info!("NOTIFY:digest [SCHEDULED_FLUSH] Flushing due digests - count: {}", digests.len());
+                }
+
+                for digest in digests {
+                    if sender.send(digest).await.is_err() {
+                        warn!("NOTIFY:digest [SCHEDULER_STOPPED] Digest receiver dropped, stopping scheduler");
+                        return;
+                    }
+                }
+            }
+        })
+    }
+
+    fn into_digest(user_id: String, batch: PendingBatch<T>) -> Digest<T> {
+        Digest {
+            user_id,
+            items: batch.items,
+            first_queued_at: batch.first_queued_at,
+            last_queued_at: batch.last_queued_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn batcher(max_batch_size: usize) -> DigestBatcher<u32> {
+        DigestBatcher::new(
+            DigestConfig::builder()
+                .max_batch_size(max_batch_size)
+                .max_delay(Duration::from_secs(600))
+                .build()
+                .unwrap()
+        ).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_bypasses_batching() {
+        let batcher = batcher(10);
+        let now = Utc::now();
+
+        let outcome = batcher.enqueue("user-1", NotificationPriority::High, 1, now).await;
+        assert!(matches!(outcome, EnqueueOutcome::SendNow(1)));
+        assert_eq!(batcher.pending_users().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_size_threshold_flushes_digest() {
+        let batcher = batcher(3);
+        let now = Utc::now();
+
+        for item in 0..2 {
+            let outcome = batcher.enqueue("user-1", NotificationPriority::Low, item, now).await;
+            assert!(matches!(outcome, EnqueueOutcome::Batched));
+        }
+
+        match batcher.enqueue("user-1", NotificationPriority::Low, 2, now).await {
+            EnqueueOutcome::Flushed(digest) => {
+                assert_eq!(digest.user_id, "user-1");
+                assert_eq!(digest.items, vec![0, 1, 2]);
+            }
+            other => panic!("expected flushed digest, got {:?}", other),
+        }
+
+        assert_eq!(batcher.pending_users().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_flush_due_only_returns_expired_batches() {
+        let batcher = batcher(100);
+        let start = Utc.with_ymd_and_hms(2025, 1, 10, 12, 0, 0).unwrap();
+
+        batcher.enqueue("user-1", NotificationPriority::Normal, 1, start).await;
+        batcher.enqueue("user-2", NotificationPriority::Normal, 2, start + chrono::Duration::minutes(8)).await;
+
+        let due = batcher.flush_due(start + chrono::Duration::minutes(10)).await;
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].user_id, "user-1");
+
+        let remaining = batcher.flush_all().await;
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].user_id, "user-2");
+    }
+}
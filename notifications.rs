@@ -0,0 +1,352 @@
+use chrono::{ DateTime, NaiveTime, Utc };
+use chrono_tz::Tz;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
+use serde::{ Deserialize, Serialize };
+use tracing::{ debug, warn };
+
+/// Delivery channel a notification can be sent through
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum NotificationChannel {
+    Push,
+    Sms,
+    Email,
+}
+
+impl NotificationChannel {
+    pub const ALL: [NotificationChannel; 3] = [
+        NotificationChannel::Push,
+        NotificationChannel::Sms,
+        NotificationChannel::Email,
+    ];
+
+    /// Interruptive channels are silenced during quiet hours
+    pub fn is_interruptive(&self) -> bool {
+        matches!(self, NotificationChannel::Push | NotificationChannel::Sms)
+    }
+}
+
+/// Category of a notification event, used to look up per-category preferences
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum NotificationCategory {
+    Messages,
+    Engagements,
+    Checkins,
+    Security,
+    Marketing,
+    System,
+}
+
+/// Priority of a notification event
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum NotificationPriority {
+    Low,
+    Normal,
+    High,
+    Critical,
+}
+
+/// Quiet hours window in the user's local timezone
+/// `start` and `end` are "HH:MM" strings; a window may span midnight (e.g. 22:00 -> 07:00)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
+    /// IANA timezone name, e.g. "Europe/London"
+    pub timezone: String,
+}
+
+impl QuietHours {
+    /// Check whether the given instant falls inside the quiet hours window
+    /// Invalid configuration is treated as "not quiet" so notifications are never lost
+    pub fn is_active_at(&self, now: DateTime<Utc>) -> bool {
+        let (start, end, tz) = match self.parse() {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!("NOTIFY:quiet_hours [VALIDATION] Ignoring invalid quiet hours - error: {}", e);
+                return false;
+            }
+        };
+
+        let local_time = now.with_timezone(&tz).time();
+
+        if start == end {
+            false
+        } else if start < end {
+            local_time >= start && local_time < end
+        } else {
+            // Window spans midnight
+            local_time >= start || local_time < end
+        }
+    }
+
+    /// Validate the quiet hours configuration
+    pub fn validate(&self) -> Result<(), String> {
+        self.parse().map(|_| ())
+    }
+
+    fn parse(&self) -> Result<(NaiveTime, NaiveTime, Tz), String> {
+        let start = NaiveTime::parse_from_str(&self.start, "%H:%M").map_err(|_|
+            format!("Invalid quiet hours start: '{}'", self.start)
+        )?;
+        let end = NaiveTime::parse_from_str(&self.end, "%H:%M").map_err(|_|
+            format!("Invalid quiet hours end: '{}'", self.end)
+        )?;
+        let tz: Tz = self.timezone
+            .parse()
+            .map_err(|_| format!("Invalid quiet hours timezone: '{}'", self.timezone))?;
+
+        Ok((start, end, tz))
+    }
+}
+
+/// Per-category channel selection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryPreference {
+    pub category: NotificationCategory,
+    pub enabled: bool,
+    pub channels: Vec<NotificationChannel>,
+}
+
+/// A user's notification preferences shared across all services
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationPreference {
+    pub user_id: String,
+    /// Channels the user has enabled globally
+    pub enabled_channels: Vec<NotificationChannel>,
+    /// Category overrides; categories without an entry use `enabled_channels`
+    #[serde(default)]
+    pub categories: Vec<CategoryPreference>,
+    pub quiet_hours: Option<QuietHours>,
+}
+
+impl NotificationPreference {
+    /// Default preferences for a user who has not configured anything yet
+    pub fn default_for_user(user_id: &str) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            enabled_channels: NotificationChannel::ALL.to_vec(),
+            categories: Vec::new(),
+            quiet_hours: None,
+        }
+    }
+
+    fn category_preference(&self, category: NotificationCategory) -> Option<&CategoryPreference> {
+        self.categories.iter().find(|pref| pref.category == category)
+    }
+}
+
+/// Event to be fanned out to a user's channels
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationEvent {
+    pub category: NotificationCategory,
+    pub priority: NotificationPriority,
+    /// Channels the producing service is able to deliver this event on
+    pub available_channels: Vec<NotificationChannel>,
+}
+
+/// Outcome of routing an event against a user's preferences
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoutingDecision {
+    /// Channels that should fire now
+    pub channels: Vec<NotificationChannel>,
+    /// Channels the user wants but that were held back by quiet hours
+    pub suppressed_by_quiet_hours: Vec<NotificationChannel>,
+}
+
+impl RoutingDecision {
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+}
+
+/// Decides which channels should fire for an event given user preferences
+pub struct NotificationRouter;
+
+impl NotificationRouter {
+    /// Route an event for a user at the given instant
+    /// Security and critical events bypass quiet hours and category opt-outs
+    pub fn route(
+        event: &NotificationEvent,
+        prefs: &NotificationPreference,
+        now: DateTime<Utc>
+    ) -> RoutingDecision {
+        let is_mandatory =
+            event.category == NotificationCategory::Security ||
+            event.priority == NotificationPriority::Critical;
+
+        let wanted_channels: Vec<NotificationChannel> = match
+            prefs.category_preference(event.category)
+        {
+            Some(category_pref) if !category_pref.enabled && !is_mandatory => Vec::new(),
+            Some(category_pref) if category_pref.enabled => category_pref.channels.clone(),
+            _ => prefs.enabled_channels.clone(),
+        };
+
+        let quiet_now = prefs.quiet_hours
+            .as_ref()
+            .map(|quiet_hours| quiet_hours.is_active_at(now))
+            .unwrap_or(false);
+
+        let mut decision = RoutingDecision::default();
+
+        for channel in NotificationChannel::ALL {
+            if !wanted_channels.contains(&channel) || !event.available_channels.contains(&channel) {
+                continue;
+            }
+
+            if quiet_now && channel.is_interruptive() && !is_mandatory {
+                decision.suppressed_by_quiet_hours.push(channel);
+            } else {
+                decision.channels.push(channel);
+            }
+        }
+
+        debug!(
+            "NOTIFY:route [DECISION] Routed event - user_id: {}, category: {:?}, priority: {:?}, channels: {:?}, suppressed: {:?}",
+            prefs.user_id,
+            event.category,
+            event.priority,
+            decision.channels,
+            decision.suppressed_by_quiet_hours
+        );
+
+        decision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn prefs_with_quiet_hours(start: &str, end: &str, timezone: &str) -> NotificationPreference {
+        NotificationPreference {
+            quiet_hours: Some(QuietHours {
+                start: start.to_string(),
+                end: end.to_string(),
+                timezone: timezone.to_string(),
+            }),
+            ..NotificationPreference::default_for_user("user-1")
+        }
+    }
+
+    fn event(category: NotificationCategory, priority: NotificationPriority) -> NotificationEvent {
+        NotificationEvent {
+            category,
+            priority,
+            available_channels: NotificationChannel::ALL.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_quiet_hours_spanning_midnight_with_timezone() {
+        let quiet_hours = QuietHours {
+            start: "22:00".to_string(),
+            end: "07:00".to_string(),
+            timezone: "Asia/Tokyo".to_string(),
+        };
+
+        // 14:00 UTC is 23:00 in Tokyo
+        assert!(quiet_hours.is_active_at(Utc.with_ymd_and_hms(2025, 1, 10, 14, 0, 0).unwrap()));
+        // 03:00 UTC is 12:00 in Tokyo
+        assert!(!quiet_hours.is_active_at(Utc.with_ymd_and_hms(2025, 1, 10, 3, 0, 0).unwrap()));
+        // 21:59 UTC is 06:59 in Tokyo
+        assert!(quiet_hours.is_active_at(Utc.with_ymd_and_hms(2025, 1, 10, 21, 59, 0).unwrap()));
+    }
+
+    #[test]
+    fn test_invalid_quiet_hours_are_ignored() {
+        let quiet_hours = QuietHours {
+            start: "25:00".to_string(),
+            end: "07:00".to_string(),
+            timezone: "Mars/Olympus".to_string(),
+        };
+
+        assert!(quiet_hours.validate().is_err());
+        assert!(!quiet_hours.is_active_at(Utc::now()));
+    }
+
+    #[test]
+    fn test_route_suppresses_interruptive_channels_during_quiet_hours() {
+        let prefs = prefs_with_quiet_hours("00:00", "23:59", "UTC");
+        let now = Utc.with_ymd_and_hms(2025, 1, 10, 12, 0, 0).unwrap();
+
+        let decision = NotificationRouter::route(
+            &event(NotificationCategory::Messages, NotificationPriority::Normal),
+            &prefs,
+            now
+        );
+
+        assert_eq!(decision.channels, vec![NotificationChannel::Email]);
+        assert_eq!(decision.suppressed_by_quiet_hours, vec![
+            NotificationChannel::Push,
+            NotificationChannel::Sms,
+        ]);
+    }
+
+    #[test]
+    fn test_route_security_bypasses_quiet_hours_and_opt_out() {
+        let mut prefs = prefs_with_quiet_hours("00:00", "23:59", "UTC");
+        prefs.categories.push(CategoryPreference {
+            category: NotificationCategory::Security,
+            enabled: false,
+            channels: Vec::new(),
+        });
+        let now = Utc.with_ymd_and_hms(2025, 1, 10, 12, 0, 0).unwrap();
+
+        let decision = NotificationRouter::route(
+            &event(NotificationCategory::Security, NotificationPriority::Normal),
+            &prefs,
+            now
+        );
+
+        assert_eq!(decision.channels, NotificationChannel::ALL.to_vec());
+        assert!(decision.suppressed_by_quiet_hours.is_empty());
+    }
+
+    #[test]
+    fn test_route_respects_category_preferences() {
+        let mut prefs = NotificationPreference::default_for_user("user-1");
+        prefs.categories.push(CategoryPreference {
+            category: NotificationCategory::Marketing,
+            enabled: false,
+            channels: vec![NotificationChannel::Email],
+        });
+        prefs.categories.push(CategoryPreference {
+            category: NotificationCategory::Engagements,
+            enabled: true,
+            channels: vec![NotificationChannel::Push],
+        });
+
+        let marketing = NotificationRouter::route(
+            &event(NotificationCategory::Marketing, NotificationPriority::Low),
+            &prefs,
+            Utc::now()
+        );
+        assert!(marketing.is_empty());
+
+        let engagements = NotificationRouter::route(
+            &event(NotificationCategory::Engagements, NotificationPriority::Normal),
+            &prefs,
+            Utc::now()
+        );
+        assert_eq!(engagements.channels, vec![NotificationChannel::Push]);
+    }
+}
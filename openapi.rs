@@ -0,0 +1,125 @@
+//! Shared OpenAPI components and security schemes
+//!
+//! Apply to the spec returned by `openapi_get_routes_spec!` before serving it, so every service
+//! documents the same error body, pagination envelope, ObjectId format, Money and auth schemes:
+//!
+//! ```ignore
+//! let (routes, mut spec) = openapi_get_routes_spec![settings: my_route];
+//! common_lib::openapi::apply_shared_components(&mut spec);
+//! ```
+
+use rocket_okapi::okapi::openapi3::{
+    Components,
+    OpenApi,
+    RefOr,
+    SchemaObject,
+    SecurityRequirement,
+    SecurityScheme,
+    SecuritySchemeData,
+};
+use rocket_okapi::okapi::schemars::r#gen::{ SchemaGenerator, SchemaSettings };
+use rocket_okapi::okapi::schemars::JsonSchema;
+use rocket_okapi::okapi::schemars::{ self };
+use serde::Serialize;
+
+use crate::common_lib::constants::X_INTERNAL_API_KEY;
+use crate::common_lib::shared_models::{ Money, PaginatedResponse };
+
+pub const ERROR_BODY_SCHEMA: &str = "ErrorBody";
+pub const PAGINATED_RESPONSE_SCHEMA: &str = "PaginatedResponse";
+pub const OBJECT_ID_SCHEMA: &str = "ObjectId";
+pub const MONEY_SCHEMA: &str = "Money";
+pub const BEARER_AUTH_SCHEME: &str = "bearerAuth";
+pub const API_KEY_AUTH_SCHEME: &str = "apiKeyAuth";
+
+/// JSON body returned by `ApiError` responders
+#[derive(Serialize, JsonSchema)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+/// Register shared schemas and security schemes into the spec
+/// Entries are keyed by name, so applying more than once leaves the spec unchanged.
+pub fn apply_shared_components(spec: &mut OpenApi) {
+    let components = spec.components.get_or_insert_with(Components::default);
+
+    let mut generator = SchemaGenerator::new(SchemaSettings::openapi3());
+    let schemas = [
+        (ERROR_BODY_SCHEMA, generator.root_schema_for::<ErrorBody>().schema),
+        (
+            PAGINATED_RESPONSE_SCHEMA,
+            generator.root_schema_for::<PaginatedResponse<serde_json::Value>>().schema,
+        ),
+        (MONEY_SCHEMA, generator.root_schema_for::<Money>().schema),
+        (OBJECT_ID_SCHEMA, object_id_schema()),
+    ];
+
+    for (name, schema) in schemas {
+        components.schemas.insert(name.to_string(), schema);
+    }
+
+    components.security_schemes.insert(
+        BEARER_AUTH_SCHEME.to_string(),
+        RefOr::Object(SecurityScheme {
+            description: Some("Firebase ID token (JWT) in the Authorization header".to_string()),
+            data: SecuritySchemeData::Http {
+                scheme: "bearer".to_string(),
+                bearer_format: Some("JWT".to_string()),
+            },
+            extensions: Default::default(),
+        })
+    );
+    components.security_schemes.insert(
+        API_KEY_AUTH_SCHEME.to_string(),
+        RefOr::Object(SecurityScheme {
+            description: Some("Internal service-to-service API key".to_string()),
+            data: SecuritySchemeData::ApiKey {
+                name: X_INTERNAL_API_KEY.to_string(),
+                location: "header".to_string(),
+            },
+            extensions: Default::default(),
+        })
+    );
+}
+
+/// Security requirement for a registered scheme, e.g. for per-operation `security`
+pub fn security_requirement(scheme: &str) -> SecurityRequirement {
+    let mut requirement = SecurityRequirement::new();
+    requirement.insert(scheme.to_string(), Vec::new());
+    requirement
+}
+
+/// MyObjectId serializes as a 24 character hex string
+fn object_id_schema() -> SchemaObject {
+    SchemaObject {
+        instance_type: Some(schemars::schema::InstanceType::String.into()),
+        format: Some("objectid".to_string()),
+        string: Some(
+            Box::new(schemars::schema::StringValidation {
+                max_length: Some(24),
+                min_length: Some(24),
+                pattern: Some("^[0-9a-fA-F]{24}$".to_string()),
+            })
+        ),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_shared_components_is_idempotent() {
+        let mut spec = OpenApi::default();
+        apply_shared_components(&mut spec);
+        apply_shared_components(&mut spec);
+
+        let components = spec.components.unwrap();
+        assert_eq!(components.schemas.len(), 4);
+        assert_eq!(components.security_schemes.len(), 2);
+
+        let money = serde_json::to_value(&components.schemas[MONEY_SCHEMA]).unwrap();
+        assert!(money["properties"]["amountMinor"].is_object());
+    }
+}
@@ -0,0 +1,190 @@
+//! Handler panics as logged, counted JSON 500s, compiled out by the `no_web` feature
+//!
+//! Rocket already catches handler panics and answers 500, but through its default HTML catcher
+//! and without the request's correlation ID. Attaching `PanicHandler` installs a panic hook that
+//! logs and counts every panic, and registers a 500 catcher answering with the standard
+//! `ApiError` body and logging the panic message with the correlation ID:
+//!
+//! ```ignore
+//! rocket::build().attach(PanicHandler)
+//! ```
+//!
+//! The hook hands the panic message to the catcher through a thread-local. Rocket runs the catcher
+//! right after catching the panic on the same worker thread, so this holds in practice; if it ever
+//! doesn't, the hook's own log line still has the message and the catcher logs a plain 500. The
+//! fairing records when each request started and the catcher ignores messages from panics before
+//! then, so a panic elsewhere on the thread (a background task, an earlier request) is never
+//! attached to a later 500.
+
+use std::cell::RefCell;
+use std::panic::PanicHookInfo;
+use std::sync::Once;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::time::Instant;
+use rocket::fairing::{ self, Fairing, Info, Kind };
+use rocket::request::Request;
+use rocket::{ catch, catchers, Build, Catcher, Data, Rocket };
+use tracing::error;
+
+use crate::common_lib::error::ApiError;
+use crate::common_lib::logging::RequestId;
+
+static PANICS: AtomicU64 = AtomicU64::new(0);
+static INSTALL_HOOK: Once = Once::new();
+
+thread_local! {
+    /// Message of the last panic on this thread and when it happened, taken by the 500 catcher
+    static LAST_PANIC: RefCell<Option<(Instant, String)>> = const { RefCell::new(None) };
+}
+
+/// When the request started, cached by `PanicHandler` so older panics aren't attributed to it
+struct RequestStarted(Option<Instant>);
+
+/// Panics since the hook was installed, for health and metrics endpoints
+pub fn panic_count() -> u64 {
+    PANICS.load(Ordering::Relaxed)
+}
+
+fn panic_message(info: &PanicHookInfo<'_>) -> String {
+    let payload = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "non-string panic payload".to_string());
+
+    match info.location() {
+        Some(location) => format!("{} at {}:{}", payload, location.file(), location.line()),
+        None => payload,
+    }
+}
+
+/// Log and count every panic, keeping the previous hook (e.g. the default stderr output)
+/// Installing more than once has no further effect.
+pub fn install_panic_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+
+        std::panic::set_hook(
+            Box::new(move |info| {
+                let message = panic_message(info);
+                PANICS.fetch_add(1, Ordering::Relaxed);
+                error!(
+                    "PANIC:hook [PANIC] Thread panicked - thread: {}, message: {}",
+                    std::thread::current().name().unwrap_or("unnamed"),
+                    message
+                );
+                LAST_PANIC.with(|last| {
+                    *last.borrow_mut() = Some((Instant::now(), message));
+                });
+
+                previous(info);
+            })
+        );
+    });
+}
+
+#[catch(500)]
+async fn internal_server_error(request: &Request<'_>) -> ApiError {
+    let req_id = request.guard::<RequestId>().await.succeeded().unwrap_or_default();
+    let started = request.local_cache(|| RequestStarted(None)).0;
+    let panic = LAST_PANIC.with(|last| last.borrow_mut().take())
+        .filter(|(panicked_at, _)| started.is_some_and(|started| *panicked_at >= started))
+        .map(|(_, message)| message);
+
+    match panic {
+        Some(message) => {
+            error!(
+                "PANIC:handler [HANDLER_PANIC] [req_id:{}] Handler panicked - method: {}, uri: {}, message: {}",
+                req_id,
+                request.method(),
+                request.uri(),
+                message
+            );
+        }
+        None => {
+            error!(
+                "PANIC:handler [INTERNAL_ERROR] [req_id:{}] Request failed without an error body - method: {}, uri: {}",
+                req_id,
+                request.method(),
+                request.uri()
+            );
+        }
+    }
+
+    ApiError::InternalServerError {
+        message: format!("Unexpected error, reference: {}", req_id),
+    }
+}
+
+/// JSON 500 catcher, registered by `PanicHandler`; registered directly with
+/// `rocket.register("/", catchers())` it logs plain 500s, without the panic message
+pub fn catchers() -> Vec<Catcher> {
+    catchers![internal_server_error]
+}
+
+/// Fairing installing the panic hook and the JSON 500 catcher, and recording when requests start
+pub struct PanicHandler;
+
+#[rocket::async_trait]
+impl Fairing for PanicHandler {
+    fn info(&self) -> Info {
+        Info { name: "Panic handler", kind: Kind::Ignite | Kind::Request }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        install_panic_hook();
+        Ok(rocket.register("/", catchers()))
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        request.local_cache(|| RequestStarted(Some(Instant::now())));
+        LAST_PANIC.with(|last| last.borrow_mut().take());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::get;
+    use rocket::http::{ Header, Status };
+    use crate::common_lib::constants::X_CORRELATION_ID;
+    use crate::common_lib::test_client::{ test_client, test_rocket };
+
+    #[get("/boom")]
+    fn boom() -> &'static str {
+        panic!("ledger out of balance")
+    }
+
+    #[get("/fail")]
+    fn fail() -> Status {
+        Status::InternalServerError
+    }
+
+    #[rocket::async_test]
+    async fn test_handler_panic_returns_api_error_body() {
+        let client = test_client(test_rocket(rocket::routes![boom])).await;
+        let panics_before = panic_count();
+        let req_id = RequestId::new();
+
+        let response = client
+            .get("/boom")
+            .header(Header::new(X_CORRELATION_ID, req_id.to_string()))
+            .dispatch().await;
+
+        assert_eq!(response.status(), Status::InternalServerError);
+        let body: serde_json::Value = response.into_json().await.unwrap();
+        assert_eq!(body["error"], format!("Internal Server Error: Unexpected error, reference: {}", req_id));
+        assert!(panic_count() > panics_before);
+    }
+
+    #[rocket::async_test]
+    async fn test_stale_panic_is_not_attributed() {
+        let client = test_client(test_rocket(rocket::routes![fail])).await;
+        let _ = std::panic::catch_unwind(|| panic!("background job failed"));
+        assert!(LAST_PANIC.with(|last| last.borrow().is_some()));
+
+        assert_eq!(client.get("/fail").dispatch().await.status(), Status::InternalServerError);
+        assert!(LAST_PANIC.with(|last| last.borrow().is_none()), "cleared when the request started");
+    }
+}
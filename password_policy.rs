@@ -0,0 +1,378 @@
+//! Password and PIN strength policies with localized failure reasons
+//!
+//! `PasswordPolicy::validate` and `PinPolicy::validate` return every violation at once so clients
+//! can show them together; `PolicyViolation::message` renders each one in the user's language.
+//! The breach-list check asks a `BreachChecker`; `PwnedPasswordsClient` (the `breach_check`
+//! feature) implements it with the Have I Been Pwned k-anonymity range API, which only ever
+//! receives the first 5 hex characters of the password's SHA-1.
+
+use std::future::Future;
+use std::pin::Pin;
+#[cfg(feature = "breach_check")]
+use std::sync::Arc;
+#[cfg(feature = "breach_check")]
+use std::time::Duration;
+#[cfg(feature = "breach_check")]
+use reqwest::Client;
+use serde::{ Deserialize, Serialize };
+#[cfg(feature = "breach_check")]
+use sha1::{ Digest, Sha1 };
+use tracing::warn;
+
+use crate::common_lib::error::ApiError;
+
+pub type BreachFuture<'a> = Pin<Box<dyn Future<Output = Result<u64, ApiError>> + Send + 'a>>;
+
+/// A reason a password or PIN was rejected
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "code", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PolicyViolation {
+    TooShort {
+        min_length: usize,
+    },
+    TooLong {
+        max_length: usize,
+    },
+    TooFewCharacterClasses {
+        min_classes: usize,
+    },
+    ContainsIdentifier,
+    NotNumeric,
+    RepeatedDigits,
+    SequentialDigits,
+    /// Seen in known data breaches
+    Breached,
+}
+
+impl PolicyViolation {
+    /// Stable code for clients, matching the serialized `code` tag
+    pub fn code(&self) -> &'static str {
+        match self {
+            PolicyViolation::TooShort { .. } => "TOO_SHORT",
+            PolicyViolation::TooLong { .. } => "TOO_LONG",
+            PolicyViolation::TooFewCharacterClasses { .. } => "TOO_FEW_CHARACTER_CLASSES",
+            PolicyViolation::ContainsIdentifier => "CONTAINS_IDENTIFIER",
+            PolicyViolation::NotNumeric => "NOT_NUMERIC",
+            PolicyViolation::RepeatedDigits => "REPEATED_DIGITS",
+            PolicyViolation::SequentialDigits => "SEQUENTIAL_DIGITS",
+            PolicyViolation::Breached => "BREACHED",
+        }
+    }
+
+    /// Message in the given language ("pt-BR", "es", ...), English when it isn't translated
+    pub fn message(&self, language: &str) -> String {
+        let language = language.split(['-', '_']).next().unwrap_or_default().to_lowercase();
+
+        match (self, language.as_str()) {
+            (PolicyViolation::TooShort { min_length }, "pt") => format!("Deve ter pelo menos {} caracteres", min_length),
+            (PolicyViolation::TooShort { min_length }, "es") => format!("Debe tener al menos {} caracteres", min_length),
+            (PolicyViolation::TooShort { min_length }, _) => format!("Must be at least {} characters long", min_length),
+            (PolicyViolation::TooLong { max_length }, "pt") => format!("Deve ter no máximo {} caracteres", max_length),
+            (PolicyViolation::TooLong { max_length }, "es") => format!("Debe tener como máximo {} caracteres", max_length),
+            (PolicyViolation::TooLong { max_length }, _) => format!("Must be at most {} characters long", max_length),
+            (PolicyViolation::TooFewCharacterClasses { min_classes }, "pt") =>
+                format!("Use pelo menos {} tipos de caracteres: minúsculas, maiúsculas, números ou símbolos", min_classes),
+            (PolicyViolation::TooFewCharacterClasses { min_classes }, "es") =>
+                format!("Usa al menos {} tipos de caracteres: minúsculas, mayúsculas, números o símbolos", min_classes),
+            (PolicyViolation::TooFewCharacterClasses { min_classes }, _) =>
+                format!("Use at least {} of lowercase letters, uppercase letters, digits and symbols", min_classes),
+            (PolicyViolation::ContainsIdentifier, "pt") => "Não pode conter o seu email ou número de telefone".to_string(),
+            (PolicyViolation::ContainsIdentifier, "es") => "No puede contener tu email o número de teléfono".to_string(),
+            (PolicyViolation::ContainsIdentifier, _) => "Must not contain your email or phone number".to_string(),
+            (PolicyViolation::NotNumeric, "pt") => "O PIN só pode conter números".to_string(),
+            (PolicyViolation::NotNumeric, "es") => "El PIN solo puede contener números".to_string(),
+            (PolicyViolation::NotNumeric, _) => "PIN must contain only digits".to_string(),
+            (PolicyViolation::RepeatedDigits, "pt") => "O PIN não pode repetir os mesmos dígitos".to_string(),
+            (PolicyViolation::RepeatedDigits, "es") => "El PIN no puede repetir los mismos dígitos".to_string(),
+            (PolicyViolation::RepeatedDigits, _) => "PIN must not repeat the same digits".to_string(),
+            (PolicyViolation::SequentialDigits, "pt") => "O PIN não pode ser uma sequência de dígitos".to_string(),
+            (PolicyViolation::SequentialDigits, "es") => "El PIN no puede ser una secuencia de dígitos".to_string(),
+            (PolicyViolation::SequentialDigits, _) => "PIN must not be a sequence of digits".to_string(),
+            (PolicyViolation::Breached, "pt") =>
+                "Esta palavra-passe apareceu numa fuga de dados, escolha outra".to_string(),
+            (PolicyViolation::Breached, "es") =>
+                "Esta contraseña ha aparecido en una filtración de datos, elige otra".to_string(),
+            (PolicyViolation::Breached, _) => "This password appeared in a data breach, please choose another".to_string(),
+        }
+    }
+}
+
+/// `BadRequest` listing every violation in the given language
+pub fn violations_error(violations: &[PolicyViolation], language: &str) -> ApiError {
+    let messages: Vec<String> = violations
+        .iter()
+        .map(|violation| violation.message(language))
+        .collect();
+
+    ApiError::BadRequest { message: messages.join("; ") }
+}
+
+/// Counts how often a password appears in breach corpora
+pub trait BreachChecker: Send + Sync {
+    fn breach_count<'a>(&'a self, password: &'a str) -> BreachFuture<'a>;
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Required number of lowercase/uppercase/digit/symbol classes, 0 to 4
+    pub min_character_classes: usize,
+    /// Passwords seen at least this many times in breaches are rejected
+    pub breach_threshold: u64,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        // NIST SP 800-63B: favour length and breach checks over composition rules
+        Self {
+            min_length: 10,
+            max_length: 128,
+            min_character_classes: 0,
+            breach_threshold: 1,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Local checks; `identifiers` are the user's email, phone number, ... which must not appear in it
+    pub fn validate(&self, password: &str, identifiers: &[&str]) -> Result<(), Vec<PolicyViolation>> {
+        let mut violations = Vec::new();
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            violations.push(PolicyViolation::TooShort { min_length: self.min_length });
+        }
+        if length > self.max_length {
+            violations.push(PolicyViolation::TooLong { max_length: self.max_length });
+        }
+
+        let classes = [
+            password.chars().any(|c| c.is_lowercase()),
+            password.chars().any(|c| c.is_uppercase()),
+            password.chars().any(|c| c.is_ascii_digit()),
+            password.chars().any(|c| !c.is_alphanumeric()),
+        ];
+        if classes.iter().filter(|present| **present).count() < self.min_character_classes {
+            violations.push(PolicyViolation::TooFewCharacterClasses { min_classes: self.min_character_classes });
+        }
+
+        let lowercase = password.to_lowercase();
+        let contains_identifier = identifiers
+            .iter()
+            .map(|identifier| identifier.trim().to_lowercase())
+            // The local part of an email is what people reuse
+            .map(|identifier| identifier.split('@').next().unwrap_or_default().to_string())
+            .any(|identifier| identifier.chars().count() >= 4 && lowercase.contains(&identifier));
+        if contains_identifier {
+            violations.push(PolicyViolation::ContainsIdentifier);
+        }
+
+        if violations.is_empty() { Ok(()) } else { Err(violations) }
+    }
+
+    /// Local checks followed by the breach check
+    /// An unavailable breach service doesn't block the user; the failure is logged instead.
+    pub async fn validate_with_breach_check(
+        &self,
+        password: &str,
+        identifiers: &[&str],
+        checker: &dyn BreachChecker
+    ) -> Result<(), Vec<PolicyViolation>> {
+        self.validate(password, identifiers)?;
+
+        match checker.breach_count(password).await {
+            Ok(count) if count >= self.breach_threshold => Err(vec![PolicyViolation::Breached]),
+            Ok(_) => Ok(()),
+            Err(e) => {
+                warn!("PASSWORD_POLICY:breach_check [PROVIDER_ERROR] Breach check skipped - error: {}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PinPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Reject PINs made of one repeated digit or a repeated group, e.g. "1111" or "1212"
+    pub reject_repeated: bool,
+    /// Reject ascending or descending runs, e.g. "1234", "9876" or "7890"
+    pub reject_sequential: bool,
+}
+
+impl Default for PinPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 4,
+            max_length: 8,
+            reject_repeated: true,
+            reject_sequential: true,
+        }
+    }
+}
+
+impl PinPolicy {
+    pub fn validate(&self, pin: &str) -> Result<(), Vec<PolicyViolation>> {
+        if pin.is_empty() || !pin.chars().all(|c| c.is_ascii_digit()) {
+            return Err(vec![PolicyViolation::NotNumeric]);
+        }
+
+        let mut violations = Vec::new();
+        if pin.len() < self.min_length {
+            violations.push(PolicyViolation::TooShort { min_length: self.min_length });
+        }
+        if pin.len() > self.max_length {
+            violations.push(PolicyViolation::TooLong { max_length: self.max_length });
+        }
+
+        let digits: Vec<u8> = pin.bytes().map(|b| b - b'0').collect();
+        if self.reject_repeated && is_repeated(&digits) {
+            violations.push(PolicyViolation::RepeatedDigits);
+        }
+        if self.reject_sequential && is_sequential(&digits) {
+            violations.push(PolicyViolation::SequentialDigits);
+        }
+
+        if violations.is_empty() { Ok(()) } else { Err(violations) }
+    }
+}
+
+/// Whether the digits are a shorter group repeated, e.g. 1111, 1212, 123123
+fn is_repeated(digits: &[u8]) -> bool {
+    (1..=digits.len() / 2)
+        .filter(|group| digits.len().is_multiple_of(*group))
+        .any(|group| digits.chunks(group).all(|chunk| chunk == &digits[..group]))
+}
+
+/// Whether every digit is one more (or one less) than the previous, wrapping 9 to 0
+fn is_sequential(digits: &[u8]) -> bool {
+    let steps: Vec<u8> = digits
+        .windows(2)
+        .map(|pair| (pair[1] + 10 - pair[0]) % 10)
+        .collect();
+
+    !steps.is_empty() && (steps.iter().all(|step| *step == 1) || steps.iter().all(|step| *step == 9))
+}
+
+/// Have I Been Pwned range API client, enabled with the `breach_check` feature
+#[cfg(feature = "breach_check")]
+pub struct PwnedPasswordsClient {
+    client: Arc<Client>,
+    base_url: String,
+}
+
+#[cfg(feature = "breach_check")]
+impl PwnedPasswordsClient {
+    pub const DEFAULT_URL: &'static str = "https://api.pwnedpasswords.com";
+
+    pub fn new(client: Arc<Client>) -> Self {
+        Self::with_base_url(client, Self::DEFAULT_URL)
+    }
+
+    pub fn with_base_url(client: Arc<Client>, base_url: &str) -> Self {
+        Self { client, base_url: base_url.trim_end_matches('/').to_string() }
+    }
+}
+
+#[cfg(feature = "breach_check")]
+impl BreachChecker for PwnedPasswordsClient {
+    fn breach_count<'a>(&'a self, password: &'a str) -> BreachFuture<'a> {
+        Box::pin(async move {
+            let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+            let (prefix, suffix) = hash.split_at(5);
+
+            // Padding hides the real number of matches from anyone watching response sizes
+            let body = self.client
+                .get(format!("{}/range/{}", self.base_url, prefix))
+                .header("Add-Padding", "true")
+                .timeout(Duration::from_secs(5))
+                .send().await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| ApiError::InternalServerError {
+                    message: format!("Breach check request failed: {e}"),
+                })?
+                .text().await
+                .map_err(|e| ApiError::InternalServerError {
+                    message: format!("Failed to read breach check response: {e}"),
+                })?;
+
+            let count = body
+                .lines()
+                .filter_map(|line| line.trim().split_once(':'))
+                .find(|(candidate, _)| candidate.eq_ignore_ascii_case(suffix))
+                .and_then(|(_, count)| count.parse().ok())
+                .unwrap_or(0);
+
+            Ok(count)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_policy() {
+        let policy = PasswordPolicy { min_character_classes: 3, ..PasswordPolicy::default() };
+
+        assert!(policy.validate("Correct-horse-9", &["ana@example.com"]).is_ok());
+        assert_eq!(
+            policy.validate("short", &[]).unwrap_err(),
+            vec![PolicyViolation::TooShort { min_length: 10 }, PolicyViolation::TooFewCharacterClasses { min_classes: 3 }]
+        );
+        assert_eq!(policy.validate("Ana.Silva#2024", &["ana.silva@example.com"]).unwrap_err(), vec![
+            PolicyViolation::ContainsIdentifier,
+        ]);
+    }
+
+    #[test]
+    fn test_pin_policy() {
+        let policy = PinPolicy::default();
+
+        assert!(policy.validate("2851").is_ok());
+        for (pin, violation) in [
+            ("1111", PolicyViolation::RepeatedDigits),
+            ("1212", PolicyViolation::RepeatedDigits),
+            ("1234", PolicyViolation::SequentialDigits),
+            ("7890", PolicyViolation::SequentialDigits),
+            ("6543", PolicyViolation::SequentialDigits),
+            ("12a4", PolicyViolation::NotNumeric),
+        ] {
+            assert_eq!(policy.validate(pin).unwrap_err(), vec![violation], "pin: {pin}");
+        }
+
+        let error = violations_error(&policy.validate("123").unwrap_err(), "pt-BR");
+        assert_eq!(
+            error.to_string(),
+            "Bad Request Error: Deve ter pelo menos 4 caracteres; O PIN não pode ser uma sequência de dígitos"
+        );
+    }
+
+    #[cfg(feature = "breach_check")]
+    #[tokio::test]
+    async fn test_pwned_passwords_range_lookup() {
+        use wiremock::matchers::{ header, method, path };
+        use wiremock::{ Mock, MockServer, ResponseTemplate };
+
+        // SHA-1 of "password" is 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/range/5BAA6"))
+            .and(header("Add-Padding", "true"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                "003D68EB55068C33ACE09247EE4C639306B:0\r\n1E4C9B93F3F0682250B6CF8331B7EE68FD8:9659365\r\n"
+            ))
+            .mount(&server).await;
+        let checker = PwnedPasswordsClient::with_base_url(Arc::new(Client::new()), &server.uri());
+
+        assert_eq!(checker.breach_count("password").await.unwrap(), 9659365);
+        let result = PasswordPolicy::default().validate_with_breach_check("password123", &[], &checker).await;
+        assert!(result.is_ok(), "suffix not in the range response");
+        let result = PasswordPolicy { min_length: 8, ..PasswordPolicy::default() }
+            .validate_with_breach_check("password", &[], &checker).await;
+        assert_eq!(result.unwrap_err(), vec![PolicyViolation::Breached]);
+    }
+}
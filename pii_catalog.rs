@@ -0,0 +1,389 @@
+//! Catalog of personal data fields, their purpose and legal basis
+//!
+//! Models declare their PII fields by implementing `PiiModel`; a `PiiCatalog` of registered
+//! models produces the machine-readable data map for the privacy team and drives redaction (for
+//! logs, support tooling) and data subject exports from the same declarations, so a new field
+//! can't be covered by one and forgotten by the other. A declared path that no longer matches the
+//! model is an error rather than a field left unredacted; fields serialized with
+//! `skip_serializing_if` are declared `optional()` so their absence is accepted:
+//!
+//! ```ignore
+//! impl PiiModel for User {
+//!     const MODEL: &'static str = "users";
+//!     fn pii_fields() -> Vec<PiiField> {
+//!         vec![
+//!             PiiField::new("email", PiiCategory::Contact, "Login and account notifications", LegalBasis::Contract),
+//!             PiiField::new("address.line1", PiiCategory::Location, "Invoicing", LegalBasis::LegalObligation),
+//!             PiiField::new("nickname", PiiCategory::Identifier, "Profile", LegalBasis::Consent).optional(),
+//!         ]
+//!     }
+//! }
+//!
+//! let catalog = PiiCatalog::new().register::<User>().register::<Booking>();
+//! let data_map = catalog.data_map();            // served to the privacy team as JSON
+//! let safe = catalog.redact(&user)?;            // for logs and support views
+//! let export = catalog.export(&user)?;          // for subject access requests
+//! ```
+
+use serde::Serialize;
+use serde_json::{ Map, Value };
+
+use crate::common_lib::error::ApiError;
+use crate::common_lib::identity_document::mask_document_number;
+use crate::common_lib::secret::REDACTED;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PiiCategory {
+    /// Names, user handles, device identifiers
+    Identifier,
+    /// Email addresses and phone numbers
+    Contact,
+    /// Addresses and coordinates
+    Location,
+    Financial,
+    /// Passport and national ID numbers
+    GovernmentId,
+    /// Special category data under GDPR article 9, e.g. face templates
+    Biometric,
+}
+
+/// GDPR article 6 lawful basis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LegalBasis {
+    Consent,
+    Contract,
+    LegalObligation,
+    VitalInterests,
+    PublicTask,
+    LegitimateInterests,
+}
+
+/// How `PiiCatalog::redact` hides a field
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Redaction {
+    /// Replace the value with `[REDACTED]`
+    #[default]
+    Replace,
+    /// Keep the last characters of string values visible, for support staff matching records
+    Mask,
+    /// Drop the field
+    Remove,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PiiField {
+    /// Path in the serialized model, dot-separated for nested objects, e.g. "address.line1"
+    /// Paths can't reach into arrays; declare the array field itself instead.
+    pub path: &'static str,
+    pub category: PiiCategory,
+    pub purpose: &'static str,
+    pub legal_basis: LegalBasis,
+    pub redaction: Redaction,
+    /// The field, or one of its parents, may be left out of the serialized model
+    pub optional: bool,
+}
+
+impl PiiField {
+    pub fn new(path: &'static str, category: PiiCategory, purpose: &'static str, legal_basis: LegalBasis) -> Self {
+        Self { path, category, purpose, legal_basis, redaction: Redaction::default(), optional: false }
+    }
+
+    pub fn redaction(mut self, redaction: Redaction) -> Self {
+        self.redaction = redaction;
+        self
+    }
+
+    /// Accept the field being absent, for fields serialized with `skip_serializing_if`
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+}
+
+/// A model holding personal data
+pub trait PiiModel: Serialize {
+    /// Collection or table name, as the privacy team knows it
+    const MODEL: &'static str;
+
+    fn pii_fields() -> Vec<PiiField>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataMapEntry {
+    pub model: &'static str,
+    pub fields: Vec<PiiField>,
+}
+
+/// Registered models and their PII fields
+#[derive(Debug, Clone, Default)]
+pub struct PiiCatalog {
+    entries: Vec<DataMapEntry>,
+}
+
+/// An object and the key of one of its fields
+type FieldSlot<'a> = (&'a mut Map<String, Value>, String);
+
+/// The object holding `field` and the field's key; `None` when a parent is null, as for an unset
+/// optional object, or when an `optional` field is absent. Otherwise a missing field or a non-object
+/// parent means the declared path doesn't match the model, which is an error so a renamed field
+/// can't silently escape redaction.
+fn field_mut<'a>(
+    model: &str,
+    value: &'a mut Value,
+    field: &PiiField
+) -> Result<Option<FieldSlot<'a>>, ApiError> {
+    let path = field.path;
+    let invalid = |reason: String| ApiError::InternalServerError {
+        message: format!("PII path '{}' of model '{}' {}", path, model, reason),
+    };
+    let segments: Vec<&str> = path.split('.').collect();
+    if let Some(segment) = segments.iter().find(|segment| segment.is_empty() || segment.contains(['[', ']', '*'])) {
+        return Err(invalid(format!("has unsupported segment '{}'", segment)));
+    }
+    let Some((last, parents)) = segments.split_last() else {
+        return Err(invalid("is empty".to_string()));
+    };
+
+    let mut object = value.as_object_mut().ok_or_else(|| invalid("needs a model serialized as an object".to_string()))?;
+    for parent in parents {
+        match object.get_mut(*parent) {
+            Some(Value::Object(child)) => {
+                object = child;
+            }
+            Some(Value::Null) => {
+                return Ok(None);
+            }
+            Some(Value::Array(_)) => {
+                return Err(invalid(format!("crosses the array '{}'", parent)));
+            }
+            Some(_) => {
+                return Err(invalid(format!("reads into '{}', which is not an object", parent)));
+            }
+            None if field.optional => {
+                return Ok(None);
+            }
+            None => {
+                return Err(invalid(format!("has no field '{}'", parent)));
+            }
+        }
+    }
+    match object.get(*last) {
+        Some(Value::Null) => Ok(None),
+        Some(_) => Ok(Some((object, last.to_string()))),
+        None if field.optional => Ok(None),
+        None => Err(invalid(format!("has no field '{}'", last))),
+    }
+}
+
+fn serialize<T: Serialize>(model: &T) -> Result<Value, ApiError> {
+    serde_json::to_value(model).map_err(|e| ApiError::InternalServerError {
+        message: format!("Failed to serialize model for PII processing: {}", e),
+    })
+}
+
+impl PiiCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a model; registering it again replaces its fields
+    pub fn register<T: PiiModel>(mut self) -> Self {
+        self.entries.retain(|entry| entry.model != T::MODEL);
+        self.entries.push(DataMapEntry { model: T::MODEL, fields: T::pii_fields() });
+        self
+    }
+
+    fn fields_of<T: PiiModel>(&self) -> Result<&[PiiField], ApiError> {
+        self.entries
+            .iter()
+            .find(|entry| entry.model == T::MODEL)
+            .map(|entry| entry.fields.as_slice())
+            .ok_or_else(|| ApiError::InternalServerError {
+                message: format!("Model '{}' is not registered in the PII catalog", T::MODEL),
+            })
+    }
+
+    /// Every registered model and its PII fields, sorted by model
+    pub fn data_map(&self) -> Vec<DataMapEntry> {
+        let mut entries = self.entries.clone();
+        entries.sort_by_key(|entry| entry.model);
+        entries
+    }
+
+    /// The model serialized with its PII fields hidden as declared
+    pub fn redact<T: PiiModel>(&self, model: &T) -> Result<Value, ApiError> {
+        let fields = self.fields_of::<T>()?;
+        let mut value = serialize(model)?;
+
+        for field in fields {
+            let Some((object, key)) = field_mut(T::MODEL, &mut value, field)? else {
+                continue;
+            };
+            match (field.redaction, &object[&key]) {
+                (Redaction::Remove, _) => {
+                    object.remove(&key);
+                }
+                (Redaction::Mask, Value::String(text)) => {
+                    let masked = mask_document_number(text);
+                    object.insert(key, Value::String(masked));
+                }
+                (Redaction::Replace | Redaction::Mask, _) => {
+                    object.insert(key, Value::String(REDACTED.to_string()));
+                }
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// Only the model's PII fields, keyed by path, for data subject access requests
+    pub fn export<T: PiiModel>(&self, model: &T) -> Result<Map<String, Value>, ApiError> {
+        let fields = self.fields_of::<T>()?;
+        let mut value = serialize(model)?;
+
+        let mut export = Map::new();
+        for field in fields {
+            if let Some((object, key)) = field_mut(T::MODEL, &mut value, field)? {
+                export.insert(field.path.to_string(), object[&key].clone());
+            }
+        }
+
+        Ok(export)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Serialize)]
+    struct User {
+        id: String,
+        email: String,
+        phone: Option<String>,
+        address: Value,
+    }
+
+    impl PiiModel for User {
+        const MODEL: &'static str = "users";
+
+        fn pii_fields() -> Vec<PiiField> {
+            vec![
+                PiiField::new("email", PiiCategory::Contact, "Login", LegalBasis::Contract).redaction(Redaction::Mask),
+                PiiField::new("phone", PiiCategory::Contact, "OTP", LegalBasis::Contract),
+                PiiField::new("address.line1", PiiCategory::Location, "Invoicing", LegalBasis::LegalObligation)
+                    .redaction(Redaction::Remove),
+            ]
+        }
+    }
+
+    #[test]
+    fn test_catalog_drives_data_map_redaction_and_export() {
+        let catalog = PiiCatalog::new().register::<User>();
+        let user = User {
+            id: "u1".to_string(),
+            email: "ana@example.com".to_string(),
+            phone: None,
+            address: json!({ "line1": "Rua Augusta 100", "city": "Lisboa" }),
+        };
+
+        let data_map = serde_json::to_value(catalog.data_map()).unwrap();
+        assert_eq!(data_map[0]["model"], "users");
+        assert_eq!(data_map[0]["fields"][2]["legalBasis"], "LEGAL_OBLIGATION");
+
+        let redacted = catalog.redact(&user).unwrap();
+        assert_eq!(redacted, json!({
+            "id": "u1", "email": "•••••••••••.com", "phone": null, "address": { "city": "Lisboa" }
+        }));
+
+        let export = catalog.export(&user).unwrap();
+        assert_eq!(Value::Object(export), json!({ "email": "ana@example.com", "address.line1": "Rua Augusta 100" }));
+        assert!(PiiCatalog::new().redact(&user).is_err(), "unregistered model");
+
+        // An unset optional parent has nothing to hide
+        let unset = User { address: Value::Null, ..user };
+        assert_eq!(catalog.redact(&unset).unwrap()["address"], Value::Null);
+        assert!(!catalog.export(&unset).unwrap().contains_key("address.line1"));
+    }
+
+    struct Unsupported;
+
+    impl Serialize for Unsupported {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            json!({ "contacts": [] }).serialize(serializer)
+        }
+    }
+
+    impl PiiModel for Unsupported {
+        const MODEL: &'static str = "unsupported";
+
+        fn pii_fields() -> Vec<PiiField> {
+            vec![PiiField::new("contacts[].email", PiiCategory::Contact, "Invites", LegalBasis::Consent)]
+        }
+    }
+
+    #[test]
+    fn test_paths_that_dont_match_the_model_are_errors() {
+        let unsupported = PiiCatalog::new().register::<Unsupported>().redact(&Unsupported);
+        assert!(matches!(
+            unsupported,
+            Err(ApiError::InternalServerError { message }) if message.contains("unsupported segment")
+        ));
+
+        let catalog = PiiCatalog::new().register::<User>();
+        let user = |address: Value| User {
+            id: "u1".to_string(),
+            email: "ana@example.com".to_string(),
+            phone: None,
+            address,
+        };
+
+        for address in [json!("Rua Augusta 100"), json!([{ "line1": "Rua Augusta 100" }]), json!({ "street": "Rua" })] {
+            assert!(catalog.redact(&user(address.clone())).is_err(), "address: {}", address);
+            assert!(catalog.export(&user(address.clone())).is_err(), "address: {}", address);
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Account {
+        email_address: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        nickname: Option<String>,
+    }
+
+    impl PiiModel for Account {
+        const MODEL: &'static str = "accounts";
+
+        fn pii_fields() -> Vec<PiiField> {
+            vec![
+                PiiField::new("email", PiiCategory::Contact, "Login", LegalBasis::Contract),
+                PiiField::new("nickname", PiiCategory::Identifier, "Profile", LegalBasis::Consent).optional(),
+            ]
+        }
+    }
+
+    #[test]
+    fn test_renamed_leaf_is_an_error_and_optional_fields_may_be_absent() {
+        let catalog = PiiCatalog::new().register::<Account>();
+        let account = Account { email_address: "ana@example.com".to_string(), nickname: None };
+
+        for result in [catalog.redact(&account).map(|_| ()), catalog.export(&account).map(|_| ())] {
+            assert!(matches!(
+                result,
+                Err(ApiError::InternalServerError { message }) if message.contains("has no field 'email'")
+            ));
+        }
+
+        let nickname = PiiField::new("nickname", PiiCategory::Identifier, "Profile", LegalBasis::Consent);
+        let mut value = json!({ "emailAddress": "ana@example.com" });
+        assert!(field_mut("accounts", &mut value, &nickname.clone().optional()).unwrap().is_none());
+        assert!(field_mut("accounts", &mut value, &nickname).is_err(), "absent field not declared optional");
+    }
+}
@@ -0,0 +1,302 @@
+//! Server-driven configuration for mobile clients, compiled out by the `no_web` feature
+//!
+//! A `RemoteConfig` holds default values, targeted overrides and experiments. `resolve` merges them
+//! for one client, in this order (later wins, objects are merged key by key, `null` removes a key):
+//!
+//! 1. `defaults`
+//! 2. each matching `ConfigOverride`, in declaration order
+//! 3. the values of the client's variant in each experiment
+//!
+//! Variants are assigned by hashing the experiment key with the user ID, so a user keeps their
+//! variant across devices and requests. `ClientConfigResponse` serves the result with an ETag and
+//! answers `If-None-Match` revalidations with 304.
+
+use std::collections::BTreeMap;
+use rocket::http::{ Header, Status };
+use rocket::request::Request;
+use rocket::response::{ self, Responder, Response };
+use rocket::serde::json::Json;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use rocket_okapi::okapi::schemars::{ self };
+use serde::{ Deserialize, Serialize };
+use serde_json::{ Map, Value };
+
+#[cfg(not(feature = "no_geo"))]
+use crate::common_lib::request_context::RequestContext;
+
+/// Values applied to clients matching all of the given criteria; empty criteria match everyone
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigOverride {
+    #[serde(default)]
+    pub countries: Vec<String>,
+    /// Language tags, matched on the primary language ("pt" matches "pt-BR")
+    #[serde(default)]
+    pub locales: Vec<String>,
+    #[serde(default)]
+    pub platforms: Vec<String>,
+    pub values: Map<String, Value>,
+}
+
+impl ConfigOverride {
+    fn matches(&self, client: &ClientAttributes) -> bool {
+        let matches_any = |allowed: &[String], value: Option<&str>| {
+            allowed.is_empty() ||
+                value.is_some_and(|value| allowed.iter().any(|allowed| allowed.eq_ignore_ascii_case(value)))
+        };
+        let language = client.locale.as_deref().and_then(|locale| locale.split('-').next());
+
+        matches_any(&self.countries, client.country_code.as_deref()) &&
+            (matches_any(&self.locales, client.locale.as_deref()) || matches_any(&self.locales, language)) &&
+            matches_any(&self.platforms, client.platform.as_deref())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExperimentVariant {
+    pub name: String,
+    /// Relative share of users; a variant with weight 0 is never assigned
+    pub weight: u32,
+    #[serde(default)]
+    pub values: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Experiment {
+    pub key: String,
+    pub variants: Vec<ExperimentVariant>,
+}
+
+impl Experiment {
+    /// Deterministic variant for a user; `None` when every weight is zero
+    pub fn assign(&self, user_id: &str) -> Option<&ExperimentVariant> {
+        let total: u64 = self.variants
+            .iter()
+            .map(|variant| variant.weight as u64)
+            .sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut bucket = fnv1a(format!("{}:{}", self.key, user_id).as_bytes()) % total;
+        self.variants.iter().find(|variant| {
+            if bucket < (variant.weight as u64) {
+                return true;
+            }
+            bucket -= variant.weight as u64;
+            false
+        })
+    }
+}
+
+/// Full configuration of a service's client settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteConfig {
+    pub defaults: Map<String, Value>,
+    #[serde(default)]
+    pub overrides: Vec<ConfigOverride>,
+    #[serde(default)]
+    pub experiments: Vec<Experiment>,
+}
+
+/// What the config is resolved for
+#[derive(Debug, Clone, Default)]
+pub struct ClientAttributes {
+    /// Stable ID used for experiment bucketing; clients without one get no experiment
+    pub user_id: Option<String>,
+    pub country_code: Option<String>,
+    pub locale: Option<String>,
+    /// e.g. "ios", "android", "web"
+    pub platform: Option<String>,
+}
+
+#[cfg(not(feature = "no_geo"))]
+impl ClientAttributes {
+    pub fn from_context(context: &RequestContext, user_id: Option<&str>, platform: Option<&str>) -> Self {
+        Self {
+            user_id: user_id.map(str::to_string),
+            country_code: context.country_code.clone(),
+            locale: Some(context.locale.clone()),
+            platform: platform.map(str::to_string),
+        }
+    }
+}
+
+/// Resolved configuration for one client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientConfig {
+    pub values: Map<String, Value>,
+    /// Experiment key to assigned variant name, for analytics
+    pub experiments: BTreeMap<String, String>,
+}
+
+impl ClientConfig {
+    /// Strong ETag over the payload; keys are sorted so equal configs hash equally
+    pub fn etag(&self) -> String {
+        format!("\"{:016x}\"", fnv1a(&serde_json::to_vec(self).unwrap_or_default()))
+    }
+}
+
+impl RemoteConfig {
+    pub fn resolve(&self, client: &ClientAttributes) -> ClientConfig {
+        let mut values = self.defaults.clone();
+
+        for config_override in self.overrides.iter().filter(|config_override| config_override.matches(client)) {
+            merge(&mut values, &config_override.values);
+        }
+
+        let mut experiments = BTreeMap::new();
+        if let Some(user_id) = &client.user_id {
+            for experiment in &self.experiments {
+                if let Some(variant) = experiment.assign(user_id) {
+                    merge(&mut values, &variant.values);
+                    experiments.insert(experiment.key.clone(), variant.name.clone());
+                }
+            }
+        }
+
+        ClientConfig { values, experiments }
+    }
+}
+
+/// JSON merge patch (RFC 7386) of `patch` into `target`
+fn merge(target: &mut Map<String, Value>, patch: &Map<String, Value>) {
+    for (key, value) in patch {
+        match (target.get_mut(key), value) {
+            (_, Value::Null) => {
+                target.remove(key);
+            }
+            (Some(Value::Object(existing)), Value::Object(patch)) => merge(existing, patch),
+            _ => {
+                target.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// 64-bit FNV-1a; stable across releases, unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ (*byte as u64)).wrapping_mul(0x100000001b3))
+}
+
+/// Serves a `ClientConfig` with an ETag, or 304 when the client's `If-None-Match` matches
+pub struct ClientConfigResponse {
+    pub config: ClientConfig,
+    /// `Cache-Control` max-age in seconds
+    pub max_age_seconds: u32,
+}
+
+impl ClientConfigResponse {
+    pub fn new(config: ClientConfig) -> Self {
+        Self { config, max_age_seconds: 300 }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ClientConfigResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let etag = self.config.etag();
+        let cache_control = format!("private, max-age={}", self.max_age_seconds);
+        let not_modified = request
+            .headers()
+            .get("If-None-Match")
+            .flat_map(|value| value.split(','))
+            .any(|candidate| {
+                let candidate = candidate.trim();
+                candidate == "*" || candidate.trim_start_matches("W/") == etag
+            });
+
+        if not_modified {
+            return Response::build()
+                .status(Status::NotModified)
+                .header(Header::new("ETag", etag))
+                .header(Header::new("Cache-Control", cache_control))
+                .ok();
+        }
+
+        Response::build_from(Json(self.config).respond_to(request)?)
+            .header(Header::new("ETag", etag))
+            .header(Header::new("Cache-Control", cache_control))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::get;
+    use serde_json::json;
+    use crate::common_lib::test_client::{ test_client, test_rocket };
+
+    fn object(value: Value) -> Map<String, Value> {
+        value.as_object().cloned().unwrap()
+    }
+
+    fn remote_config() -> RemoteConfig {
+        RemoteConfig {
+            defaults: object(json!({ "chat": { "maxAttachments": 5, "gifs": true }, "banner": "welcome" })),
+            overrides: vec![ConfigOverride {
+                countries: vec!["DE".to_string()],
+                values: object(json!({ "chat": { "gifs": false }, "banner": null })),
+                ..ConfigOverride::default()
+            }],
+            experiments: vec![Experiment {
+                key: "attachments".to_string(),
+                variants: vec![
+                    ExperimentVariant { name: "control".to_string(), weight: 1, values: Map::new() },
+                    ExperimentVariant {
+                        name: "more".to_string(),
+                        weight: 1,
+                        values: object(json!({ "chat": { "maxAttachments": 10 } })),
+                    }
+                ],
+            }],
+        }
+    }
+
+    #[get("/config")]
+    fn config() -> ClientConfigResponse {
+        ClientConfigResponse::new(remote_config().resolve(&ClientAttributes::default()))
+    }
+
+    #[test]
+    fn test_resolve_merges_overrides_and_variants() {
+        let config = remote_config();
+        let german = ClientAttributes {
+            user_id: Some("user-1".to_string()),
+            country_code: Some("DE".to_string()),
+            ..ClientAttributes::default()
+        };
+
+        let resolved = config.resolve(&german);
+        assert_eq!(resolved.values["chat"]["gifs"], json!(false));
+        assert!(!resolved.values.contains_key("banner"));
+
+        let variant = &resolved.experiments["attachments"];
+        let max_attachments = if variant == "more" { 10 } else { 5 };
+        assert_eq!(resolved.values["chat"]["maxAttachments"], json!(max_attachments));
+        assert_eq!(config.resolve(&german), resolved);
+
+        let variants: std::collections::HashSet<String> = (0..50)
+            .map(|i| config.resolve(&ClientAttributes { user_id: Some(format!("user-{i}")), ..ClientAttributes::default() }))
+            .map(|resolved| resolved.experiments["attachments"].clone())
+            .collect();
+        assert_eq!(variants.len(), 2);
+    }
+
+    #[rocket::async_test]
+    async fn test_etag_revalidation() {
+        let client = test_client(test_rocket(rocket::routes![config])).await;
+
+        let response = client.get("/config").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let etag = response.headers().get_one("ETag").unwrap().to_string();
+
+        let revalidated = client.get("/config").header(Header::new("If-None-Match", etag)).dispatch().await;
+        assert_eq!(revalidated.status(), Status::NotModified);
+        assert!(revalidated.into_string().await.is_none());
+    }
+}
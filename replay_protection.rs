@@ -0,0 +1,341 @@
+//! Replay protection for sensitive endpoints, enabled with the `request_signing` feature
+//!
+//! Clients send `X-Request-Timestamp` (unix seconds), `X-Request-Nonce` (random, single use) and
+//! `X-Request-Signature`, the hex HMAC-SHA256 of `"{METHOD}\n{path and query}\n{timestamp}\n{nonce}"`
+//! with the shared signing secret. The secret is a `SecretKeySet`: signatures made with the previous
+//! key are still accepted during a rotation window, and `update_keys` swaps in a reloaded set. Requests outside the allowed clock skew, with a bad signature or
+//! with a nonce seen within the skew window are rejected with 401. The body is not signed, so
+//! this protects against replays, not against tampering on a channel without TLS.
+//!
+//! ```ignore
+//! rocket::build().manage(ReplayProtection::new(signing_secret, Arc::new(RedisNonceStore::connect(url).await?)))
+//!
+//! #[post("/otp/verify")]
+//! async fn verify_otp(_signed: ReplayProtected, ...) { ... }
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{ Arc, RwLock };
+use std::time::{ Duration, Instant };
+use hmac::{ Hmac, Mac };
+#[cfg(feature = "redis")]
+use redis::AsyncCommands;
+use rocket::http::Status;
+use rocket::request::{ FromRequest, Outcome, Request };
+use sha2::Sha256;
+use tokio::sync::Mutex;
+use tracing::error;
+
+use crate::common_lib::clock::{ system_clock, Clock };
+use crate::common_lib::constants::{ X_REQUEST_NONCE, X_REQUEST_SIGNATURE, X_REQUEST_TIMESTAMP };
+use crate::common_lib::error::ApiError;
+use crate::common_lib::logging::RequestId;
+use crate::common_lib::secret::SecretKeySet;
+use crate::log_security;
+
+pub type NonceFuture<'a> = Pin<Box<dyn Future<Output = Result<bool, ApiError>> + Send + 'a>>;
+
+/// Remembers used nonces for a short TTL
+pub trait NonceStore: Send + Sync {
+    /// Record the nonce; `false` if it was already recorded within its TTL
+    fn insert_if_new<'a>(&'a self, nonce: &'a str, ttl: Duration) -> NonceFuture<'a>;
+}
+
+/// In-process nonce store for tests and single-instance services
+pub struct InMemoryNonceStore {
+    nonces: Mutex<HashMap<String, Instant>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for InMemoryNonceStore {
+    fn default() -> Self {
+        Self::with_clock(system_clock())
+    }
+}
+
+impl InMemoryNonceStore {
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self { nonces: Mutex::new(HashMap::new()), clock }
+    }
+}
+
+impl NonceStore for InMemoryNonceStore {
+    fn insert_if_new<'a>(&'a self, nonce: &'a str, ttl: Duration) -> NonceFuture<'a> {
+        Box::pin(async move {
+            let now = self.clock.now();
+            let mut nonces = self.nonces.lock().await;
+            nonces.retain(|_, expires_at| *expires_at > now);
+
+            if nonces.contains_key(nonce) {
+                return Ok(false);
+            }
+            nonces.insert(nonce.to_string(), now + ttl);
+            Ok(true)
+        })
+    }
+}
+
+/// Nonce store shared across instances through Redis, enabled with the `redis` feature
+#[cfg(feature = "redis")]
+pub struct RedisNonceStore {
+    connection: redis::aio::MultiplexedConnection,
+}
+
+#[cfg(feature = "redis")]
+impl RedisNonceStore {
+    const KEY_PREFIX: &'static str = "request_nonce:";
+
+    pub async fn connect(redis_url: &str) -> Result<Self, ApiError> {
+        let client = redis::Client::open(redis_url).map_err(Self::redis_error)?;
+        let connection = client.get_multiplexed_async_connection().await.map_err(Self::redis_error)?;
+
+        Ok(Self { connection })
+    }
+
+    fn redis_error(e: redis::RedisError) -> ApiError {
+        ApiError::InternalServerError {
+            message: format!("Nonce store error: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+impl NonceStore for RedisNonceStore {
+    fn insert_if_new<'a>(&'a self, nonce: &'a str, ttl: Duration) -> NonceFuture<'a> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let options = redis::SetOptions
+                ::default()
+                .conditional_set(redis::ExistenceCheck::NX)
+                .with_expiration(redis::SetExpiry::EX(ttl.as_secs().max(1)));
+            let stored: Option<String> = connection
+                .set_options(format!("{}{}", Self::KEY_PREFIX, nonce), "1", options).await
+                .map_err(Self::redis_error)?;
+
+            Ok(stored.is_some())
+        })
+    }
+}
+
+/// Signing keys, accepted clock skew and nonce store, managed by Rocket
+pub struct ReplayProtection {
+    keys: RwLock<SecretKeySet>,
+    /// How far the request timestamp may be from the server clock, in either direction
+    pub max_clock_skew: Duration,
+    store: Arc<dyn NonceStore>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ReplayProtection {
+    const MAX_NONCE_LENGTH: usize = 128;
+
+    pub fn new(keys: impl Into<SecretKeySet>, store: Arc<dyn NonceStore>) -> Self {
+        Self::with_clock(keys, store, system_clock())
+    }
+
+    pub fn with_clock(keys: impl Into<SecretKeySet>, store: Arc<dyn NonceStore>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            keys: RwLock::new(keys.into()),
+            max_clock_skew: Duration::from_secs(300),
+            store,
+            clock,
+        }
+    }
+
+    pub fn with_max_clock_skew(mut self, max_clock_skew: Duration) -> Self {
+        self.max_clock_skew = max_clock_skew;
+        self
+    }
+
+    /// Replace the signing keys, e.g. after reloading a rotated secret
+    pub fn update_keys(&self, keys: SecretKeySet) {
+        *self.keys.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = keys;
+    }
+
+    fn keys(&self) -> SecretKeySet {
+        self.keys.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    fn mac(key: &str, method: &str, path: &str, timestamp: &str, nonce: &str) -> Hmac<Sha256> {
+        // HMAC accepts keys of any length
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC key");
+        mac.update(format!("{}\n{}\n{}\n{}", method, path, timestamp, nonce).as_bytes());
+        mac
+    }
+
+    /// Hex signature for a request with the current key, as clients compute it
+    pub fn sign(&self, method: &str, path: &str, timestamp: &str, nonce: &str) -> String {
+        let keys = self.keys();
+        hex::encode(Self::mac(keys.current.expose_secret(), method, path, timestamp, nonce).finalize().into_bytes())
+    }
+
+    /// Check a request's signature headers and consume its nonce
+    pub async fn verify(
+        &self,
+        method: &str,
+        path: &str,
+        timestamp: Option<&str>,
+        nonce: Option<&str>,
+        signature: Option<&str>
+    ) -> Result<(), ApiError> {
+        let unauthorized = |message: &str| ApiError::Unauthorized { message: message.to_string() };
+
+        let (Some(timestamp), Some(nonce), Some(signature)) = (timestamp, nonce, signature) else {
+            return Err(unauthorized("Missing request signature headers"));
+        };
+        if nonce.is_empty() || nonce.len() > Self::MAX_NONCE_LENGTH {
+            return Err(unauthorized("Invalid request nonce"));
+        }
+
+        let sent_at = timestamp.parse::<i64>().map_err(|_| unauthorized("Invalid request timestamp"))?;
+        let skew = (self.clock.now_utc().timestamp() - sent_at).unsigned_abs();
+        if skew > self.max_clock_skew.as_secs() {
+            return Err(unauthorized("Request timestamp outside the allowed window"));
+        }
+
+        let signature = hex::decode(signature).map_err(|_| unauthorized("Invalid request signature"))?;
+        let keys = self.keys();
+        let signed_with_active_key = keys
+            .active_keys(self.clock.now_utc())
+            .any(|key| Self::mac(key.expose_secret(), method, path, timestamp, nonce).verify_slice(&signature).is_ok());
+        if !signed_with_active_key {
+            return Err(unauthorized("Invalid request signature"));
+        }
+
+        // Nonces only need to outlive the window in which their timestamp is accepted
+        if !self.store.insert_if_new(nonce, self.max_clock_skew * 2).await? {
+            return Err(unauthorized("Request has already been processed"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Request guard admitting only freshly signed, never-seen requests
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayProtected;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ReplayProtected {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(protection) = request.rocket().state::<ReplayProtection>() else {
+            error!("SECURITY:replay_protection [CONFIG] ReplayProtection is not managed by Rocket");
+            return Outcome::Error((
+                Status::InternalServerError,
+                ApiError::InternalServerError {
+                    message: "Replay protection is not configured".to_string(),
+                },
+            ));
+        };
+
+        let headers = request.headers();
+        let path = request.uri().to_string();
+        let result = protection.verify(
+            request.method().as_str(),
+            &path,
+            headers.get_one(X_REQUEST_TIMESTAMP),
+            headers.get_one(X_REQUEST_NONCE),
+            headers.get_one(X_REQUEST_SIGNATURE)
+        ).await;
+
+        match result {
+            Ok(()) => Outcome::Success(ReplayProtected),
+            Err(error) => {
+                let req_id = request.guard::<RequestId>().await.succeeded().unwrap_or_default();
+                log_security!(warn, "replay_protection", "REJECTED", req_id, "Signed request rejected - path: {}, reason: {}", path, error);
+                Outcome::Error((error.http_status(), error))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::Header;
+    use rocket::post;
+    use crate::common_lib::clock::MockClock;
+    use crate::common_lib::secret::SecretString;
+    use crate::common_lib::test_client::{ test_client, test_rocket };
+
+    fn protection(clock: Arc<MockClock>) -> ReplayProtection {
+        ReplayProtection::with_clock(
+            SecretString::from("signing-secret"),
+            Arc::new(InMemoryNonceStore::with_clock(clock.clone())),
+            clock
+        )
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let clock = Arc::new(MockClock::default());
+        let protection = protection(clock.clone());
+        let now = clock.now_utc().timestamp().to_string();
+        let signature = protection.sign("POST", "/otp/verify", &now, "nonce-1");
+
+        let verify = |nonce, signature| protection.verify("POST", "/otp/verify", Some(&now), Some(nonce), Some(signature));
+
+        assert!(verify("nonce-1", &signature).await.is_ok());
+        assert!(verify("nonce-1", &signature).await.is_err(), "replayed nonce");
+        assert!(verify("nonce-2", &signature).await.is_err(), "signature bound to another nonce");
+
+        let stale = (clock.now_utc().timestamp() - 301).to_string();
+        let stale_signature = protection.sign("POST", "/otp/verify", &stale, "nonce-3");
+        let result = protection.verify("POST", "/otp/verify", Some(&stale), Some("nonce-3"), Some(&stale_signature)).await;
+        assert!(matches!(result, Err(ApiError::Unauthorized { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_previous_key_accepted_during_rotation() {
+        let clock = Arc::new(MockClock::default());
+        let protection = protection(clock.clone());
+        let now = clock.now_utc().timestamp().to_string();
+        let old_signature = protection.sign("POST", "/otp/verify", &now, "nonce-1");
+
+        let rotated = SecretKeySet::new(SecretString::from("signing-secret"))
+            .rotate(SecretString::from("new-signing-secret"), chrono::Duration::minutes(10), clock.now_utc());
+        protection.update_keys(rotated);
+        assert_ne!(protection.sign("POST", "/otp/verify", &now, "nonce-1"), old_signature);
+
+        let result = protection.verify("POST", "/otp/verify", Some(&now), Some("nonce-1"), Some(&old_signature)).await;
+        assert!(result.is_ok(), "previous key within grace period");
+
+        clock.advance(Duration::from_secs(11 * 60));
+        let now = clock.now_utc().timestamp().to_string();
+        let old_signature = ReplayProtection::new(SecretString::from("signing-secret"), Arc::new(InMemoryNonceStore::default()))
+            .sign("POST", "/otp/verify", &now, "nonce-2");
+        let result = protection.verify("POST", "/otp/verify", Some(&now), Some("nonce-2"), Some(&old_signature)).await;
+        assert!(matches!(result, Err(ApiError::Unauthorized { .. })), "previous key expired");
+    }
+
+    #[post("/payouts")]
+    fn payouts(_signed: ReplayProtected) -> &'static str {
+        "ok"
+    }
+
+    #[rocket::async_test]
+    async fn test_guard_rejects_replays() {
+        let clock = Arc::new(MockClock::default());
+        let protection = protection(clock.clone());
+        let timestamp = clock.now_utc().timestamp().to_string();
+        let signature = protection.sign("POST", "/payouts?currency=EUR", &timestamp, "n-42");
+        let client = test_client(test_rocket(rocket::routes![payouts]).manage(protection)).await;
+
+        let send = || {
+            client
+                .post("/payouts?currency=EUR")
+                .header(Header::new(X_REQUEST_TIMESTAMP, timestamp.clone()))
+                .header(Header::new(X_REQUEST_NONCE, "n-42"))
+                .header(Header::new(X_REQUEST_SIGNATURE, signature.clone()))
+                .dispatch()
+        };
+
+        assert_eq!(send().await.status(), Status::Ok);
+        assert_eq!(send().await.status(), Status::Unauthorized);
+        assert_eq!(client.post("/payouts?currency=EUR").dispatch().await.status(), Status::Unauthorized);
+    }
+}
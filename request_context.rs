@@ -0,0 +1,225 @@
+//! Per-request localization context, compiled out by the `no_web` or `no_geo` feature
+//!
+//! Each field is resolved independently, first match wins:
+//!
+//! | Field    | 1. Stored profile | 2. Headers        | 3. IP geolocation     | 4. Default |
+//! |----------|-------------------|-------------------|-----------------------|------------|
+//! | locale   | `locale`          | `Accept-Language` |                       | `en`       |
+//! | currency | `currency`        | `X-Currency`      | country's currency    | `USD`      |
+//! | timezone | `timezone`        | `X-Timezone`      | location timezone     | `UTC`      |
+//! | country  | `country_code`    |                   | location country      | none       |
+//!
+//! The profile is read from a managed `Arc<dyn LocalizationProfileStore>` when the request carries
+//! the gateway's `X-Firebase-UID`; geolocation is used when an `Arc<GeolocationService>` is managed.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use rocket::request::{ FromRequest, Outcome, Request };
+use serde::{ Deserialize, Serialize };
+use tracing::warn;
+
+use crate::common_lib::constants::{ X_CURRENCY, X_FIREBASE_UID, X_TIMEZONE };
+use crate::common_lib::country_utils::CountryService;
+use crate::common_lib::error::ApiError;
+use crate::common_lib::geolocation::{ ClientLocation, GeolocationService };
+
+const DEFAULT_LOCALE: &str = "en";
+const DEFAULT_CURRENCY: &str = "USD";
+const DEFAULT_TIMEZONE: &str = "UTC";
+
+pub type ProfileFuture<'a> = Pin<
+    Box<dyn Future<Output = Result<Option<LocalizationProfile>, ApiError>> + Send + 'a>
+>;
+
+/// Localization preferences stored on a user's profile; unset fields fall through to headers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalizationProfile {
+    pub locale: Option<String>,
+    pub currency: Option<String>,
+    pub timezone: Option<String>,
+    pub country_code: Option<String>,
+}
+
+/// Lookup of stored localization preferences by Firebase UID
+pub trait LocalizationProfileStore: Send + Sync {
+    fn profile<'a>(&'a self, firebase_uid: &'a str) -> ProfileFuture<'a>;
+}
+
+/// Resolved localization for the current request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestContext {
+    pub locale: String,
+    pub currency: String,
+    pub timezone: String,
+    pub country_code: Option<String>,
+}
+
+/// First language tag of an `Accept-Language` header, e.g. "pt-BR" for "pt-BR,pt;q=0.9"
+pub fn preferred_language(accept_language: &str) -> Option<String> {
+    let tag = accept_language.split(',').next()?.split(';').next()?.trim();
+
+    match tag {
+        "" | "*" => None,
+        tag => Some(tag.replace('_', "-")),
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestContext {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let context = request.local_cache_async(async {
+            let profile = load_profile(request).await.unwrap_or_default();
+
+            let location = if request.rocket().state::<Arc<GeolocationService>>().is_some() {
+                request
+                    .guard::<ClientLocation>().await
+                    .succeeded()
+                    .and_then(|client_location| client_location.location)
+            } else {
+                None
+            };
+
+            let header = |name: &str| {
+                request
+                    .headers()
+                    .get_one(name)
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(str::to_string)
+            };
+
+            let country_code = profile.country_code.or_else(|| {
+                location.as_ref().map(|location| location.country_code.clone())
+            });
+
+            RequestContext {
+                locale: profile.locale
+                    .or_else(|| header("Accept-Language").and_then(|value| preferred_language(&value)))
+                    .unwrap_or_else(|| DEFAULT_LOCALE.to_string()),
+                currency: profile.currency
+                    .or_else(|| header(X_CURRENCY))
+                    .or_else(|| {
+                        country_code
+                            .as_deref()
+                            .and_then(CountryService::currency_for_country)
+                            .map(str::to_string)
+                    })
+                    .unwrap_or_else(|| DEFAULT_CURRENCY.to_string()),
+                timezone: profile.timezone
+                    .or_else(|| header(X_TIMEZONE))
+                    .or_else(|| location.and_then(|location| location.timezone))
+                    .unwrap_or_else(|| DEFAULT_TIMEZONE.to_string()),
+                country_code,
+            }
+        }).await;
+
+        Outcome::Success(context.clone())
+    }
+}
+
+/// Stored profile of the authenticated caller; store errors degrade to headers and geolocation
+async fn load_profile(request: &Request<'_>) -> Option<LocalizationProfile> {
+    let store = request.rocket().state::<Arc<dyn LocalizationProfileStore>>()?;
+    let firebase_uid = request.headers().get_one(X_FIREBASE_UID)?;
+
+    match store.profile(firebase_uid).await {
+        Ok(profile) => profile,
+        Err(e) => {
+            warn!(
+                "REQUEST_CONTEXT:profile [STORE_ERROR] Profile lookup failed, using request data - firebase_uid: {}, error: {}",
+                firebase_uid,
+                e
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+    use rocket::get;
+    use rocket::http::Header;
+    use rocket::serde::json::Json;
+    use crate::common_lib::geolocation::{ GeolocationConfig, LocationInfo };
+    use crate::common_lib::test_client::{ test_client, test_rocket, TestIdentity, TestRequestExt };
+
+    struct FixedProfileStore;
+
+    impl LocalizationProfileStore for FixedProfileStore {
+        fn profile<'a>(&'a self, firebase_uid: &'a str) -> ProfileFuture<'a> {
+            let profile = (firebase_uid == "test-firebase-uid").then(|| LocalizationProfile {
+                locale: Some("pt-PT".to_string()),
+                ..LocalizationProfile::default()
+            });
+            Box::pin(async move { Ok(profile) })
+        }
+    }
+
+    #[get("/context")]
+    fn context(context: RequestContext) -> Json<RequestContext> {
+        Json(context)
+    }
+
+    #[test]
+    fn test_preferred_language() {
+        assert_eq!(preferred_language("pt-BR,pt;q=0.9,en;q=0.8").as_deref(), Some("pt-BR"));
+        assert_eq!(preferred_language("en_GB").as_deref(), Some("en-GB"));
+        assert_eq!(preferred_language("*"), None);
+    }
+
+    #[rocket::async_test]
+    async fn test_request_context_precedence() {
+        let service = Arc::new(GeolocationService::new(Arc::new(Client::new()), GeolocationConfig::default()).unwrap());
+        service.cache_location("203.0.113.9", &LocationInfo {
+            country_code: "BR".to_string(),
+            country_name: "Brazil".to_string(),
+            continent_code: Some("SA".to_string()),
+            city: None,
+            region: None,
+            latitude: None,
+            longitude: None,
+            timezone: Some("America/Sao_Paulo".to_string()),
+            asn: None,
+            isp: None,
+            organization: None,
+        }).await;
+        let store: Arc<dyn LocalizationProfileStore> = Arc::new(FixedProfileStore);
+        let client = test_client(
+            test_rocket(rocket::routes![context]).manage(service).manage(store)
+        ).await;
+
+        let anonymous: RequestContext = client
+            .get("/context")
+            .header(Header::new("X-Forwarded-For", "203.0.113.9"))
+            .header(Header::new("Accept-Language", "es-AR,es;q=0.9"))
+            .dispatch().await
+            .into_json().await
+            .unwrap();
+        assert_eq!(anonymous, RequestContext {
+            locale: "es-AR".to_string(),
+            currency: "BRL".to_string(),
+            timezone: "America/Sao_Paulo".to_string(),
+            country_code: Some("BR".to_string()),
+        });
+
+        let authenticated: RequestContext = client
+            .get("/context")
+            .with_identity(&TestIdentity::default())
+            .header(Header::new("Accept-Language", "es-AR"))
+            .header(Header::new(X_CURRENCY, "EUR"))
+            .dispatch().await
+            .into_json().await
+            .unwrap();
+        assert_eq!(authenticated.locale, "pt-PT");
+        assert_eq!(authenticated.currency, "EUR");
+        assert_eq!(authenticated.timezone, DEFAULT_TIMEZONE);
+        assert_eq!(authenticated.country_code, None);
+    }
+}
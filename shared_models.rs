@@ -10,8 +10,11 @@ use serde::de::{self, Visitor};
 use serde::ser::Error;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::cmp::{Eq, Ord, PartialEq, PartialOrd};
+use std::collections::BTreeMap;
 use std::fmt;
 
+use crate::common_lib::secret::{ serialize_exposed, SecretString };
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct MyObjectId(pub ObjectId);
 
@@ -191,7 +194,9 @@ pub struct TwilioApiKeyResponse {
     pub friendly_name: String,
     pub date_created: String,
     pub date_updated: String,
-    pub secret: String,
+    /// Only returned by Twilio when the key is created, so it is passed on to the caller as-is
+    #[serde(serialize_with = "serialize_exposed")]
+    pub secret: SecretString,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
@@ -207,3 +212,94 @@ pub struct IdKeyPair {
     pub id: String,
     pub key: String,
 }
+
+/// Monetary amount in minor units (e.g. pence, cents) with an ISO 4217 currency code
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Money {
+    pub amount_minor: i64,
+    pub currency: String,
+}
+
+impl Money {
+    pub fn new(amount_minor: i64, currency: &str) -> Self {
+        Money {
+            amount_minor,
+            currency: currency.to_uppercase(),
+        }
+    }
+}
+
+/// Standard envelope for paginated list responses
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PaginatedResponse<T> {
+    pub items: Vec<T>,
+    pub page: u32,
+    pub page_size: u32,
+    pub total: u64,
+    pub has_more: bool,
+}
+
+impl<T> PaginatedResponse<T> {
+    /// Build a page; `page` is 1-based
+    pub fn new(items: Vec<T>, page: u32, page_size: u32, total: u64) -> Self {
+        let has_more = (page as u64) * (page_size as u64) < total;
+
+        PaginatedResponse {
+            items,
+            page,
+            page_size,
+            total,
+            has_more,
+        }
+    }
+}
+
+/// Broad media category, used to pick processing hooks and client renderers
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MediaKind {
+    Image,
+    Video,
+    Audio,
+    Other,
+}
+
+impl MediaKind {
+    pub fn from_content_type(content_type: &str) -> Self {
+        match content_type.split('/').next().unwrap_or_default().trim().to_ascii_lowercase().as_str() {
+            "image" => MediaKind::Image,
+            "video" => MediaKind::Video,
+            "audio" => MediaKind::Audio,
+            _ => MediaKind::Other,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MediaProcessingStatus {
+    Ready,
+    /// Processing gave up; only the fields known from the upload are set
+    Failed,
+}
+
+/// Metadata extracted from an uploaded media file, shared by every service that displays media
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaMetadata {
+    pub upload_id: String,
+    pub key: String,
+    pub content_type: String,
+    pub kind: MediaKind,
+    pub size_bytes: u64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub duration_ms: Option<u64>,
+    pub thumbnail_key: Option<String>,
+    /// Allowlisted EXIF tags only; location and device identifiers are never stored
+    #[serde(default)]
+    pub exif: BTreeMap<String, String>,
+    pub status: MediaProcessingStatus,
+}
@@ -0,0 +1,401 @@
+//! SMS delivery tracking from Twilio status callbacks
+//!
+//! Each sent message is recorded as `Queued`; Twilio's status callbacks then move it through
+//! `Sent` to `Delivered` or `Failed`. Callbacks are retried and can arrive out of order, so a
+//! transition only ever moves forward and repeating one changes nothing. OTP flows ask
+//! `should_resend` instead of guessing from elapsed time alone:
+//!
+//! ```ignore
+//! let tracker = SmsDeliveryTracker::new(Arc::new(RedisSmsDeliveryStore::connect(redis_url).await?));
+//! tracker.record_sent(&message.sid, &req_id).await?;
+//!
+//! // status callback route, after verifying X-Twilio-Signature
+//! tracker.handle_callback(&TwilioStatusCallback::from_form(&body)?, &req_id).await?;
+//!
+//! if tracker.should_resend(&otp.message_sid, Duration::from_secs(30)).await? { ... }
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{ DateTime, Utc };
+#[cfg(feature = "redis")]
+use redis::AsyncCommands;
+use serde::{ Deserialize, Serialize };
+use tokio::sync::RwLock;
+use tracing::{ debug, info };
+
+use crate::common_lib::clock::{ system_clock, Clock };
+use crate::common_lib::error::ApiError;
+
+pub type DeliveryFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, ApiError>> + Send + 'a>>;
+
+/// Delivery state of one message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SmsDeliveryStatus {
+    Queued,
+    /// Handed to the carrier
+    Sent,
+    Delivered,
+    Failed,
+}
+
+impl SmsDeliveryStatus {
+    /// Twilio `MessageStatus`; `None` for statuses that say nothing about delivery
+    pub fn from_twilio(status: &str) -> Option<Self> {
+        match status {
+            "accepted" | "scheduled" | "queued" => Some(SmsDeliveryStatus::Queued),
+            "sending" | "sent" => Some(SmsDeliveryStatus::Sent),
+            "delivered" | "read" => Some(SmsDeliveryStatus::Delivered),
+            "undelivered" | "failed" | "canceled" => Some(SmsDeliveryStatus::Failed),
+            _ => None,
+        }
+    }
+
+    pub fn is_final(&self) -> bool {
+        matches!(self, SmsDeliveryStatus::Delivered | SmsDeliveryStatus::Failed)
+    }
+
+    /// Whether moving from `self` to `next` is progress; final states never change
+    pub fn can_transition_to(&self, next: SmsDeliveryStatus) -> bool {
+        let rank = |status: &SmsDeliveryStatus| {
+            match status {
+                SmsDeliveryStatus::Queued => 0,
+                SmsDeliveryStatus::Sent => 1,
+                SmsDeliveryStatus::Delivered | SmsDeliveryStatus::Failed => 2,
+            }
+        };
+        !self.is_final() && rank(&next) > rank(self)
+    }
+}
+
+/// Twilio message status callback, posted as a form
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TwilioStatusCallback {
+    pub message_sid: String,
+    pub message_status: String,
+    pub account_sid: Option<String>,
+    pub to: Option<String>,
+    /// Set for undelivered and failed messages, e.g. 30003 (unreachable handset)
+    pub error_code: Option<u32>,
+}
+
+fn decode_form_component(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = value.get(i + 1..i + 3)?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).ok()
+}
+
+impl TwilioStatusCallback {
+    /// Parse the `application/x-www-form-urlencoded` callback body
+    pub fn from_form(body: &str) -> Result<Self, ApiError> {
+        let mut fields = HashMap::new();
+        for pair in body.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            if let (Some(key), Some(value)) = (decode_form_component(key), decode_form_component(value)) {
+                fields.insert(key, value);
+            }
+        }
+
+        let required = |name: &str| {
+            fields.get(name).cloned().ok_or_else(|| ApiError::BadRequest {
+                message: format!("Status callback without {}", name),
+            })
+        };
+        Ok(Self {
+            message_sid: required("MessageSid")?,
+            message_status: required("MessageStatus")?,
+            account_sid: fields.get("AccountSid").cloned(),
+            to: fields.get("To").cloned(),
+            error_code: fields.get("ErrorCode").and_then(|code| code.parse().ok()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SmsDeliveryRecord {
+    pub message_sid: String,
+    pub status: SmsDeliveryStatus,
+    pub error_code: Option<u32>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Delivery records; implementations must be shared by every instance of a service
+pub trait SmsDeliveryStore: Send + Sync {
+    fn get<'a>(&'a self, message_sid: &'a str) -> DeliveryFuture<'a, Option<SmsDeliveryRecord>>;
+
+    /// Save `record` if the stored status is still `expected` (`None`: no record yet)
+    /// Returns `false` when another writer got there first.
+    fn compare_and_set<'a>(
+        &'a self,
+        record: &'a SmsDeliveryRecord,
+        expected: Option<SmsDeliveryStatus>
+    ) -> DeliveryFuture<'a, bool>;
+}
+
+/// In-process delivery store for tests and single-instance tools
+#[derive(Default)]
+pub struct InMemorySmsDeliveryStore {
+    records: RwLock<HashMap<String, SmsDeliveryRecord>>,
+}
+
+impl SmsDeliveryStore for InMemorySmsDeliveryStore {
+    fn get<'a>(&'a self, message_sid: &'a str) -> DeliveryFuture<'a, Option<SmsDeliveryRecord>> {
+        Box::pin(async move { Ok(self.records.read().await.get(message_sid).cloned()) })
+    }
+
+    fn compare_and_set<'a>(
+        &'a self,
+        record: &'a SmsDeliveryRecord,
+        expected: Option<SmsDeliveryStatus>
+    ) -> DeliveryFuture<'a, bool> {
+        Box::pin(async move {
+            let mut records = self.records.write().await;
+            if records.get(&record.message_sid).map(|stored| stored.status) != expected {
+                return Ok(false);
+            }
+            records.insert(record.message_sid.clone(), record.clone());
+            Ok(true)
+        })
+    }
+}
+
+/// Delivery store shared across instances through Redis, enabled with the `redis` feature
+///
+/// Each message is a hash holding the status, compared by the CAS script, and the record as JSON.
+/// Records expire `TTL` after their last update.
+#[cfg(feature = "redis")]
+pub struct RedisSmsDeliveryStore {
+    connection: redis::aio::MultiplexedConnection,
+}
+
+#[cfg(feature = "redis")]
+impl RedisSmsDeliveryStore {
+    const KEY_PREFIX: &'static str = "sms_delivery:";
+    /// Well past the last status callback; OTP flows only look back minutes
+    const TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+    /// A missing status reads as JSON `null`, the encoding of an expected `None`
+    const COMPARE_AND_SET_SCRIPT: &'static str = r"
+        local status = redis.call('HGET', KEYS[1], 'status') or 'null'
+        if status ~= ARGV[1] then
+            return 0
+        end
+        redis.call('HSET', KEYS[1], 'status', ARGV[2], 'record', ARGV[3])
+        redis.call('PEXPIRE', KEYS[1], ARGV[4])
+        return 1
+    ";
+
+    pub async fn connect(redis_url: &str) -> Result<Self, ApiError> {
+        let client = redis::Client::open(redis_url).map_err(Self::redis_error)?;
+        let connection = client.get_multiplexed_async_connection().await.map_err(Self::redis_error)?;
+
+        Ok(Self { connection })
+    }
+
+    fn key(message_sid: &str) -> String {
+        format!("{}{}", Self::KEY_PREFIX, message_sid)
+    }
+
+    fn redis_error(e: redis::RedisError) -> ApiError {
+        ApiError::InternalServerError {
+            message: format!("SMS delivery store error: {}", e),
+        }
+    }
+
+    fn json_error(e: serde_json::Error) -> ApiError {
+        ApiError::InternalServerError {
+            message: format!("Invalid SMS delivery record: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+impl SmsDeliveryStore for RedisSmsDeliveryStore {
+    fn get<'a>(&'a self, message_sid: &'a str) -> DeliveryFuture<'a, Option<SmsDeliveryRecord>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let record: Option<String> = connection
+                .hget(Self::key(message_sid), "record").await
+                .map_err(Self::redis_error)?;
+
+            record.map(|record| serde_json::from_str(&record).map_err(Self::json_error)).transpose()
+        })
+    }
+
+    fn compare_and_set<'a>(
+        &'a self,
+        record: &'a SmsDeliveryRecord,
+        expected: Option<SmsDeliveryStatus>
+    ) -> DeliveryFuture<'a, bool> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let expected = serde_json::to_string(&expected).map_err(Self::json_error)?;
+            let status = serde_json::to_string(&record.status).map_err(Self::json_error)?;
+            let json = serde_json::to_string(record).map_err(Self::json_error)?;
+
+            let saved: u32 = redis
+                ::cmd("EVAL")
+                .arg(Self::COMPARE_AND_SET_SCRIPT)
+                .arg(1)
+                .arg(Self::key(&record.message_sid))
+                .arg(expected)
+                .arg(status)
+                .arg(json)
+                .arg(Self::TTL.as_millis() as u64)
+                .query_async(&mut connection).await
+                .map_err(Self::redis_error)?;
+
+            Ok(saved == 1)
+        })
+    }
+}
+
+/// Applies delivery callbacks to stored records
+pub struct SmsDeliveryTracker {
+    store: Arc<dyn SmsDeliveryStore>,
+    clock: Arc<dyn Clock>,
+}
+
+impl SmsDeliveryTracker {
+    /// Conflicting writers retry this many times before giving up
+    const MAX_CONFLICTS: usize = 3;
+
+    pub fn new(store: Arc<dyn SmsDeliveryStore>) -> Self {
+        Self::with_clock(store, system_clock())
+    }
+
+    pub fn with_clock(store: Arc<dyn SmsDeliveryStore>, clock: Arc<dyn Clock>) -> Self {
+        Self { store, clock }
+    }
+
+    /// Record a message Twilio accepted; a callback that already arrived is kept
+    pub async fn record_sent(&self, message_sid: &str, req_id: &str) -> Result<(), ApiError> {
+        let now = self.clock.now_utc();
+        let record = SmsDeliveryRecord {
+            message_sid: message_sid.to_string(),
+            status: SmsDeliveryStatus::Queued,
+            error_code: None,
+            created_at: now,
+            updated_at: now,
+        };
+        if self.store.compare_and_set(&record, None).await? {
+            debug!("SMS_DELIVERY:record_sent [QUEUED] [req_id:{}] Tracking message - sid: {}", req_id, message_sid);
+        }
+        Ok(())
+    }
+
+    /// Apply a status callback; out-of-order and repeated callbacks leave the record unchanged
+    pub async fn handle_callback(
+        &self,
+        callback: &TwilioStatusCallback,
+        req_id: &str
+    ) -> Result<Option<SmsDeliveryRecord>, ApiError> {
+        let Some(next) = SmsDeliveryStatus::from_twilio(&callback.message_status) else {
+            return Ok(None);
+        };
+
+        for _ in 0..Self::MAX_CONFLICTS {
+            let now = self.clock.now_utc();
+            let current = self.store.get(&callback.message_sid).await?;
+            let expected = current.as_ref().map(|record| record.status);
+
+            // Callbacks for messages sent before tracking started are recorded from the callback
+            let mut record = current.unwrap_or_else(|| SmsDeliveryRecord {
+                message_sid: callback.message_sid.clone(),
+                status: SmsDeliveryStatus::Queued,
+                error_code: None,
+                created_at: now,
+                updated_at: now,
+            });
+            if expected.is_some() && !record.status.can_transition_to(next) {
+                return Ok(Some(record));
+            }
+
+            record.status = next;
+            record.error_code = callback.error_code.or(record.error_code);
+            record.updated_at = now;
+            if self.store.compare_and_set(&record, expected).await? {
+                info!(
+                    "SMS_DELIVERY:callback [{:?}] [req_id:{}] Delivery status updated - sid: {}, error_code: {:?}",
+                    next,
+                    req_id,
+                    record.message_sid,
+                    record.error_code
+                );
+                return Ok(Some(record));
+            }
+        }
+
+        Err(ApiError::InternalServerError {
+            message: format!("Concurrent delivery updates for message {}", callback.message_sid),
+        })
+    }
+
+    /// Whether an OTP should be resent: delivery failed, or it isn't delivered `after` sending
+    pub async fn should_resend(&self, message_sid: &str, after: Duration) -> Result<bool, ApiError> {
+        let Some(record) = self.store.get(message_sid).await? else {
+            return Ok(true);
+        };
+        let age = (self.clock.now_utc() - record.created_at).to_std().unwrap_or_default();
+
+        Ok(match record.status {
+            SmsDeliveryStatus::Delivered => false,
+            SmsDeliveryStatus::Failed => true,
+            SmsDeliveryStatus::Queued | SmsDeliveryStatus::Sent => age >= after,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_lib::clock::MockClock;
+
+    #[tokio::test]
+    async fn test_callbacks_only_move_forward() {
+        let clock = Arc::new(MockClock::default());
+        let tracker = SmsDeliveryTracker::with_clock(Arc::new(InMemorySmsDeliveryStore::default()), clock.clone());
+        let callback = |status: &str| {
+            TwilioStatusCallback::from_form(
+                &format!("MessageSid=SM123&MessageStatus={}&To=%2B351912345678&ErrorCode=", status)
+            ).unwrap()
+        };
+        assert_eq!(callback("sent").to.as_deref(), Some("+351912345678"));
+
+        tracker.record_sent("SM123", "req").await.unwrap();
+        assert!(!tracker.should_resend("SM123", Duration::from_secs(30)).await.unwrap());
+        clock.advance(Duration::from_secs(31));
+        assert!(tracker.should_resend("SM123", Duration::from_secs(30)).await.unwrap());
+
+        let delivered = tracker.handle_callback(&callback("delivered"), "req").await.unwrap().unwrap();
+        assert_eq!(delivered.status, SmsDeliveryStatus::Delivered);
+        // A late "sent" and a retried "delivered" change nothing
+        assert_eq!(tracker.handle_callback(&callback("sent"), "req").await.unwrap(), Some(delivered.clone()));
+        assert_eq!(tracker.handle_callback(&callback("delivered"), "req").await.unwrap(), Some(delivered));
+        assert!(!tracker.should_resend("SM123", Duration::from_secs(30)).await.unwrap());
+
+        let failed = TwilioStatusCallback::from_form("MessageSid=SM456&MessageStatus=undelivered&ErrorCode=30003").unwrap();
+        let record = tracker.handle_callback(&failed, "req").await.unwrap().unwrap();
+        assert_eq!((record.status, record.error_code), (SmsDeliveryStatus::Failed, Some(30003)));
+        assert!(TwilioStatusCallback::from_form("MessageStatus=sent").is_err());
+    }
+}
@@ -0,0 +1,358 @@
+//! VAT and tax registration number validation for invoicing
+//!
+//! `TaxId::parse` checks the number against its country's format (EU VAT IDs, UK VAT numbers and
+//! GCC TRNs) and, where the country publishes one, its check digit. Whether an EU VAT ID is
+//! actually registered is only known to VIES; `ViesClient` (left out by the `no_http` feature)
+//! asks it and caches the answers:
+//!
+//! ```ignore
+//! let tax_id = TaxId::parse(&request.vat_number, &billing_address.country_code)?;
+//! if tax_id.scheme == TaxIdScheme::EuVat && !vies.check(&tax_id).await?.valid {
+//!     return Err(ApiError::BadRequest { message: "VAT number is not registered".to_string() });
+//! }
+//! ```
+
+#[cfg(not(feature = "no_http"))]
+use std::sync::Arc;
+#[cfg(not(feature = "no_http"))]
+use std::time::{ Duration, Instant };
+#[cfg(not(feature = "no_http"))]
+use reqwest::Client;
+use serde::{ Deserialize, Serialize };
+#[cfg(not(feature = "no_http"))]
+use tokio::sync::Mutex;
+
+use crate::common_lib::address::matches_format;
+#[cfg(not(feature = "no_http"))]
+use crate::common_lib::clock::{ system_clock, Clock };
+use crate::common_lib::error::ApiError;
+#[cfg(not(feature = "no_http"))]
+use crate::common_lib::lru_cache::LruCache;
+#[cfg(not(feature = "no_http"))]
+use crate::common_lib::secret::redact_uris;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TaxIdScheme {
+    /// EU member states and Northern Ireland (XI), checkable in VIES
+    EuVat,
+    UkVat,
+    /// GCC tax registration number
+    GccTrn,
+}
+
+/// A tax number in canonical form: uppercase, without separators or country prefix
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxId {
+    /// ISO 3166-1 alpha-2, or "XI" for Northern Ireland
+    pub country_code: String,
+    pub scheme: TaxIdScheme,
+    pub number: String,
+}
+
+/// Scheme, the prefix written before the number, and the number formats
+/// (`9` is a digit, `A` a letter, `X` either, anything else literal)
+fn rules_for(country_code: &str) -> Option<(TaxIdScheme, Option<&'static str>, &'static [&'static str])> {
+    use TaxIdScheme::*;
+
+    let rules: (TaxIdScheme, Option<&'static str>, &'static [&'static str]) = match country_code {
+        "AT" => (EuVat, Some("AT"), &["U99999999"]),
+        "BE" => (EuVat, Some("BE"), &["9999999999"]),
+        "BG" => (EuVat, Some("BG"), &["999999999", "9999999999"]),
+        "CY" => (EuVat, Some("CY"), &["99999999A"]),
+        "CZ" => (EuVat, Some("CZ"), &["99999999", "999999999", "9999999999"]),
+        "DE" => (EuVat, Some("DE"), &["999999999"]),
+        "DK" => (EuVat, Some("DK"), &["99999999"]),
+        "EE" => (EuVat, Some("EE"), &["999999999"]),
+        "GR" => (EuVat, Some("EL"), &["999999999"]),
+        "ES" => (EuVat, Some("ES"), &["X9999999X"]),
+        "FI" => (EuVat, Some("FI"), &["99999999"]),
+        "FR" => (EuVat, Some("FR"), &["XX999999999"]),
+        "HR" => (EuVat, Some("HR"), &["99999999999"]),
+        "HU" => (EuVat, Some("HU"), &["99999999"]),
+        "IE" => (EuVat, Some("IE"), &["9999999A", "9999999AA", "9X99999A"]),
+        "IT" => (EuVat, Some("IT"), &["99999999999"]),
+        "LT" => (EuVat, Some("LT"), &["999999999", "999999999999"]),
+        "LU" => (EuVat, Some("LU"), &["99999999"]),
+        "LV" => (EuVat, Some("LV"), &["99999999999"]),
+        "MT" => (EuVat, Some("MT"), &["99999999"]),
+        "NL" => (EuVat, Some("NL"), &["999999999B99"]),
+        "PL" => (EuVat, Some("PL"), &["9999999999"]),
+        "PT" => (EuVat, Some("PT"), &["999999999"]),
+        "RO" => (EuVat, Some("RO"), &["99", "999", "9999", "99999", "999999", "9999999", "99999999", "999999999", "9999999999"]),
+        "SE" => (EuVat, Some("SE"), &["999999999901"]),
+        "SI" => (EuVat, Some("SI"), &["99999999"]),
+        "SK" => (EuVat, Some("SK"), &["9999999999"]),
+        "XI" => (EuVat, Some("XI"), &["999999999", "999999999999", "GD999", "HA999"]),
+        "GB" => (UkVat, Some("GB"), &["999999999", "999999999999", "GD999", "HA999"]),
+        "AE" => (GccTrn, None, &["100999999999999"]),
+        "SA" => (GccTrn, None, &["399999999999993"]),
+        "BH" => (GccTrn, None, &["999999999999999"]),
+        "OM" => (GccTrn, Some("OM"), &["9999999999"]),
+        _ => return None,
+    };
+    Some(rules)
+}
+
+fn digits(number: &str) -> Vec<u32> {
+    number.chars().filter_map(|c| c.to_digit(10)).collect()
+}
+
+/// Check digit, for the countries where the algorithm is public and stable
+fn check_digit_valid(country_code: &str, number: &str) -> bool {
+    let d = digits(number);
+    match country_code {
+        // ISO 7064 MOD 11,10
+        "DE" => {
+            let mut product = 10;
+            for digit in &d[..8] {
+                let sum = match (digit + product) % 10 {
+                    0 => 10,
+                    sum => sum,
+                };
+                product = (2 * sum) % 11;
+            }
+            (11 - product) % 10 == d[8]
+        }
+        // Luhn over the first ten digits
+        "IT" => {
+            let sum: u32 = d[..10]
+                .iter()
+                .enumerate()
+                .map(|(i, digit)| if i % 2 == 1 { (digit * 2) / 10 + (digit * 2) % 10 } else { *digit })
+                .sum();
+            (10 - sum % 10) % 10 == d[10]
+        }
+        "PT" => {
+            let sum: u32 = d[..8].iter().zip((2..=9).rev()).map(|(digit, weight)| digit * weight).sum();
+            let check = 11 - sum % 11;
+            (if check >= 10 { 0 } else { check }) == d[8]
+        }
+        "PL" => {
+            let weights = [6, 5, 7, 2, 3, 4, 5, 6, 7];
+            let sum: u32 = d[..9].iter().zip(weights).map(|(digit, weight)| digit * weight).sum();
+            sum % 11 == d[9]
+        }
+        _ => true,
+    }
+}
+
+impl TaxId {
+    /// Parse a number as typed, with or without its country prefix and separators
+    /// (spaces, dots, dashes), for the given country of the billing address
+    pub fn parse(input: &str, country_code: &str) -> Result<Self, ApiError> {
+        let country_code = country_code.trim().to_uppercase();
+        let (scheme, prefix, formats) = rules_for(&country_code).ok_or_else(|| ApiError::BadRequest {
+            message: format!("Tax numbers are not supported for country '{}'", country_code),
+        })?;
+
+        let compact: String = input
+            .chars()
+            .filter(|c| !c.is_whitespace() && !matches!(c, '.' | '-' | '/'))
+            .collect::<String>()
+            .to_uppercase();
+        let number = prefix
+            .and_then(|prefix| compact.strip_prefix(prefix))
+            .unwrap_or(&compact)
+            .to_string();
+
+        let valid = formats.iter().any(|format| matches_format(&number, format)) &&
+            check_digit_valid(&country_code, &number);
+        if !valid {
+            return Err(ApiError::BadRequest {
+                message: format!("Invalid tax number for {}: '{}'", country_code, input.trim()),
+            });
+        }
+
+        Ok(Self { country_code, scheme, number })
+    }
+
+    /// The number as printed on invoices, with its prefix, e.g. "EL094259216"
+    pub fn formatted(&self) -> String {
+        match self.vat_prefix() {
+            Some(prefix) => format!("{}{}", prefix, self.number),
+            None => self.number.clone(),
+        }
+    }
+
+    /// Prefix written before the number, if the country uses one; "EL" rather than "GR" for Greece
+    pub fn vat_prefix(&self) -> Option<&'static str> {
+        rules_for(&self.country_code).and_then(|(_, prefix, _)| prefix)
+    }
+}
+
+/// A VIES registration check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ViesResult {
+    pub valid: bool,
+    /// Registered name and address, when the member state discloses them
+    pub name: Option<String>,
+    pub address: Option<String>,
+}
+
+#[cfg(not(feature = "no_http"))]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ViesResponse {
+    is_valid: bool,
+    /// "VALID" or "INVALID" for an answer, otherwise why there is none, e.g. "MS_UNAVAILABLE"
+    user_error: Option<String>,
+    name: Option<String>,
+    address: Option<String>,
+}
+
+/// EU VIES REST API client with cached answers, compiled out by the `no_http` feature
+#[cfg(not(feature = "no_http"))]
+pub struct ViesClient {
+    client: Arc<Client>,
+    base_url: String,
+    cache_ttl: Duration,
+    cache: Mutex<LruCache<String, (ViesResult, Instant)>>,
+    clock: Arc<dyn Clock>,
+}
+
+#[cfg(not(feature = "no_http"))]
+impl ViesClient {
+    pub const DEFAULT_URL: &'static str = "https://ec.europa.eu/taxation_customs/vies/rest-api";
+
+    pub fn new(client: Arc<Client>) -> Self {
+        Self {
+            client,
+            base_url: Self::DEFAULT_URL.to_string(),
+            cache_ttl: Duration::from_secs(24 * 60 * 60),
+            cache: Mutex::new(LruCache::new(10_000)),
+            clock: system_clock(),
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Whether an EU VAT ID is registered; VIES outages are errors and are not cached
+    pub async fn check(&self, tax_id: &TaxId) -> Result<ViesResult, ApiError> {
+        let prefix = match (tax_id.scheme, tax_id.vat_prefix()) {
+            (TaxIdScheme::EuVat, Some(prefix)) => prefix,
+            _ => {
+                return Err(ApiError::BadRequest {
+                    message: format!("VIES only checks EU VAT numbers, got {}", tax_id.formatted()),
+                });
+            }
+        };
+
+        let key = tax_id.formatted();
+        if let Some((result, cached_at)) = self.cache.lock().await.get(&key) {
+            if self.clock.now().duration_since(*cached_at) < self.cache_ttl {
+                return Ok(result.clone());
+            }
+        }
+
+        let response: ViesResponse = self.client
+            .get(format!("{}/ms/{}/vat/{}", self.base_url, prefix, tax_id.number))
+            .timeout(Duration::from_secs(10))
+            .send().await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ApiError::InternalServerError {
+                message: format!("VIES request failed: {}", redact_uris(&e.to_string())),
+            })?
+            .json().await
+            .map_err(|e| ApiError::InternalServerError {
+                message: format!("Failed to parse VIES response: {}", redact_uris(&e.to_string())),
+            })?;
+
+        match response.user_error.as_deref() {
+            None | Some("VALID") | Some("INVALID") => {}
+            Some(reason) => {
+                return Err(ApiError::InternalServerError {
+                    message: format!("VIES could not check {}: {}", key, reason),
+                });
+            }
+        }
+
+        // "---" means the member state doesn't disclose it
+        let disclosed = |value: Option<String>| {
+            value
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty() && value != "---")
+        };
+        let result = ViesResult {
+            valid: response.is_valid,
+            name: disclosed(response.name),
+            address: disclosed(response.address),
+        };
+        self.cache.lock().await.insert(key, (result.clone(), self.clock.now()));
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tax_id_formats_and_check_digits() {
+        let pt = TaxId::parse("PT 501 964 843", "pt").unwrap();
+        assert_eq!((pt.number.as_str(), pt.formatted()), ("501964843", "PT501964843".to_string()));
+        assert!(TaxId::parse("501964844", "PT").is_err(), "check digit");
+
+        assert_eq!(TaxId::parse("EL094259216", "GR").unwrap().vat_prefix(), Some("EL"));
+        assert!(TaxId::parse("DE136695976", "DE").is_ok());
+        assert!(TaxId::parse("IT00743110157", "IT").is_ok());
+        assert!(TaxId::parse("5260250274", "PL").is_ok());
+        assert!(TaxId::parse("ATU12345678", "AT").is_ok());
+        assert!(TaxId::parse("NL123456789B01", "NL").is_ok());
+        assert!(TaxId::parse("FR-12-345678901", "FR").is_ok());
+        assert!(TaxId::parse("garbage!!", "FR").is_err());
+
+        let trn = TaxId::parse("100-1234-5678-9003", "AE").unwrap();
+        assert_eq!((trn.scheme, trn.formatted()), (TaxIdScheme::GccTrn, "100123456789003".to_string()));
+        assert!(TaxId::parse("300123456789003", "SA").is_ok());
+        assert!(TaxId::parse("300123456789001", "SA").is_err());
+        assert!(TaxId::parse("123", "US").is_err(), "unsupported country");
+    }
+
+    #[cfg(not(feature = "no_http"))]
+    #[tokio::test]
+    async fn test_vies_check_is_cached() {
+        use wiremock::matchers::{ method, path };
+        use wiremock::{ Mock, MockServer, ResponseTemplate };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/ms/PT/vat/501964843"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "isValid": true, "userError": "VALID", "name": "EMPRESA EXEMPLO LDA", "address": "---"
+            })))
+            .expect(1)
+            .mount(&server).await;
+        Mock::given(method("GET"))
+            .and(path("/ms/DE/vat/136695976"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "isValid": false, "userError": "MS_UNAVAILABLE"
+            })))
+            .mount(&server).await;
+        let vies = ViesClient::new(Arc::new(Client::new())).with_base_url(&server.uri());
+
+        let tax_id = TaxId::parse("PT501964843", "PT").unwrap();
+        let result = vies.check(&tax_id).await.unwrap();
+        assert_eq!((result.valid, result.name.as_deref(), result.address.as_deref()), (true, Some("EMPRESA EXEMPLO LDA"), None));
+        assert_eq!(vies.check(&tax_id).await.unwrap(), result);
+
+        assert!(vies.check(&TaxId::parse("DE136695976", "DE").unwrap()).await.is_err());
+        assert!(vies.check(&TaxId::parse("100123456789003", "AE").unwrap()).await.is_err());
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use minijinja::{ AutoEscape, Environment };
+use serde::Serialize;
+use tokio::sync::RwLock;
+use tracing::{ debug, error };
+#[cfg(not(feature = "no_aws"))]
+use tracing::info;
+
+use crate::common_lib::error::ApiError;
+use crate::common_lib::logging::{ OperationTimer, LogLevel, RequestId };
+use crate::common_lib::notifications::NotificationChannel;
+#[cfg(not(feature = "no_aws"))]
+use crate::common_lib::utils::download_file_from_s3;
+
+/// Where template sources are loaded from
+///
+/// Templates are keyed as `{name}/{channel}/{locale}`, e.g. `otp/sms/en` or `welcome/email/pt-BR`.
+#[derive(Debug, Clone)]
+pub enum TemplateSource {
+    /// Templates compiled into the binary (e.g. via `include_str!`)
+    Embedded(Vec<(&'static str, &'static str)>),
+    /// A JSON bundle object in S3 mapping template keys to template sources
+    #[cfg(not(feature = "no_aws"))]
+    S3 {
+        bucket: String,
+        key: String,
+    },
+}
+
+/// Configuration for the template engine
+#[derive(Debug, Clone)]
+pub struct TemplateConfig {
+    pub source: TemplateSource,
+    pub default_locale: String,
+    pub reload_interval_seconds: u64,
+}
+
+impl Default for TemplateConfig {
+    fn default() -> Self {
+        Self {
+            source: TemplateSource::Embedded(Vec::new()),
+            default_locale: "en".to_string(),
+            reload_interval_seconds: 300, // 5 minutes
+        }
+    }
+}
+
+/// Template rendering engine with per-locale fallback and per-channel escaping
+pub struct TemplateEngine {
+    config: TemplateConfig,
+    env: RwLock<Arc<Environment<'static>>>,
+}
+
+impl TemplateEngine {
+    /// Create the engine and load templates from the configured source
+    pub async fn new(config: TemplateConfig) -> Result<Self, ApiError> {
+        let templates = Self::load_templates(&config.source).await?;
+        let env = Self::build_environment(templates)?;
+
+        Ok(Self {
+            config,
+            env: RwLock::new(Arc::new(env)),
+        })
+    }
+
+    /// Reload templates from the source, keeping the current set if loading fails
+    pub async fn reload(&self) -> Result<(), ApiError> {
+        let req_id = RequestId::new();
+        let timer = OperationTimer::for_request("TEMPLATES:reload", req_id);
+
+        let templates = Self::load_templates(&self.config.source).await?;
+        let template_count = templates.len();
+        let env = Self::build_environment(templates)?;
+
+        *self.env.write().await = Arc::new(env);
+
+        timer.log_completion(
+            LogLevel::Info,
+            "SUCCESS",
+            &format!("Templates reloaded - count: {}", template_count)
+        );
+
+        Ok(())
+    }
+
+    /// Spawn a background task that periodically reloads templates (hot reload)
+    pub fn spawn_hot_reload(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let engine = Arc::clone(self);
+        let interval = Duration::from_secs(engine.config.reload_interval_seconds.max(1));
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // The first tick completes immediately and templates are already loaded
+            ticker.tick().await;
+
+            loop {
+                ticker.tick().await;
+                if let Err(e) = engine.reload().await {
+                    error!("TEMPLATES:hot_reload [RELOAD_ERROR] Keeping previous templates - error: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Render a template for a channel and locale, falling back through the locale chain
+    /// e.g. "pt-BR" tries "pt-BR", then "pt", then the default locale
+    pub async fn render<S: Serialize>(
+        &self,
+        name: &str,
+        channel: NotificationChannel,
+        locale: &str,
+        context: S
+    ) -> Result<String, ApiError> {
+        let env = Arc::clone(&*self.env.read().await);
+
+        for candidate in self.locale_chain(locale) {
+            let key = Self::template_key(name, channel, &candidate);
+
+            if let Ok(template) = env.get_template(&key) {
+                debug!(
+                    "TEMPLATES:render [RESOLVED] Rendering template - requested: {}/{}, resolved: {}",
+                    name,
+                    locale,
+                    key
+                );
+
+                return template.render(context).map_err(|e| {
+                    error!("TEMPLATES:render [RENDER_ERROR] Failed to render - key: {}, error: {}", key, e);
+                    ApiError::InternalServerError {
+                        message: format!("Failed to render template '{}': {}", key, e),
+                    }
+                });
+            }
+        }
+
+        Err(ApiError::NotFound {
+            message: format!(
+                "Template '{}' not found for channel {:?} and locale '{}'",
+                name,
+                channel,
+                locale
+            ),
+        })
+    }
+
+    /// Ordered list of locales to try for a requested locale
+    fn locale_chain(&self, locale: &str) -> Vec<String> {
+        let mut chain: Vec<String> = Vec::new();
+        let normalized = locale.trim().replace('_', "-");
+
+        if !normalized.is_empty() {
+            chain.push(normalized.clone());
+
+            if let Some((language, _)) = normalized.split_once('-') {
+                chain.push(language.to_string());
+            }
+        }
+
+        chain.push(self.config.default_locale.clone());
+        chain.dedup();
+        chain
+    }
+
+    fn template_key(name: &str, channel: NotificationChannel, locale: &str) -> String {
+        format!("{}/{}/{}", name, Self::channel_segment(channel), locale)
+    }
+
+    fn channel_segment(channel: NotificationChannel) -> &'static str {
+        match channel {
+            NotificationChannel::Email => "email",
+            NotificationChannel::Sms => "sms",
+            NotificationChannel::Push => "push",
+        }
+    }
+
+    /// HTML escaping for email bodies; SMS and push are plain text and must not be escaped
+    fn auto_escape_for(key: &str) -> AutoEscape {
+        if key.contains("/email/") {
+            AutoEscape::Html
+        } else {
+            AutoEscape::None
+        }
+    }
+
+    fn build_environment(templates: HashMap<String, String>) -> Result<Environment<'static>, ApiError> {
+        let mut env = Environment::new();
+        env.set_auto_escape_callback(Self::auto_escape_for);
+
+        for (key, source) in templates {
+            env.add_template_owned(key.clone(), source).map_err(|e| {
+                error!("TEMPLATES:load [PARSE_ERROR] Invalid template - key: {}, error: {}", key, e);
+                ApiError::InternalServerError {
+                    message: format!("Invalid template '{}': {}", key, e),
+                }
+            })?;
+        }
+
+        Ok(env)
+    }
+
+    async fn load_templates(source: &TemplateSource) -> Result<HashMap<String, String>, ApiError> {
+        match source {
+            TemplateSource::Embedded(templates) => {
+                Ok(
+                    templates
+                        .iter()
+                        .map(|(key, source)| (key.to_string(), source.to_string()))
+                        .collect()
+                )
+            }
+            #[cfg(not(feature = "no_aws"))]
+            TemplateSource::S3 { bucket, key } => {
+                let bundle = download_file_from_s3(bucket, key).await.map_err(|e| {
+                    error!("TEMPLATES:load [S3_ERROR] Failed to download bundle - key: {}, error: {}", key, e);
+                    ApiError::InternalServerError {
+                        message: format!("Failed to download template bundle: {e}"),
+                    }
+                })?;
+
+                let templates: HashMap<String, String> = serde_json::from_str(&bundle).map_err(|e| {
+                    ApiError::InternalServerError {
+                        message: format!("Failed to parse template bundle: {e}"),
+                    }
+                })?;
+
+                info!("TEMPLATES:load [S3_LOADED] Loaded template bundle - key: {}, count: {}", key, templates.len());
+
+                Ok(templates)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn engine() -> TemplateEngine {
+        TemplateEngine::new(TemplateConfig {
+            source: TemplateSource::Embedded(
+                vec![
+                    ("otp/sms/en", "Your code is {{ code }}"),
+                    ("otp/sms/pt", "O seu código é {{ code }}"),
+                    ("welcome/email/en", "<p>Hello {{ name }}</p>")
+                ]
+            ),
+            ..TemplateConfig::default()
+        }).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_render_with_locale_fallback() {
+        let engine = engine().await;
+        let ctx = json!({ "code": "123456" });
+
+        let exact = engine.render("otp", NotificationChannel::Sms, "pt", &ctx).await.unwrap();
+        assert_eq!(exact, "O seu código é 123456");
+
+        let regional = engine.render("otp", NotificationChannel::Sms, "pt_BR", &ctx).await.unwrap();
+        assert_eq!(regional, "O seu código é 123456");
+
+        let default = engine.render("otp", NotificationChannel::Sms, "de-DE", &ctx).await.unwrap();
+        assert_eq!(default, "Your code is 123456");
+
+        assert!(engine.render("otp", NotificationChannel::Email, "en", &ctx).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_escaping_per_channel() {
+        let engine = engine().await;
+
+        let email = engine
+            .render("welcome", NotificationChannel::Email, "en", json!({ "name": "<b>Sam</b>" })).await
+            .unwrap();
+        assert_eq!(email, "<p>Hello &lt;b&gt;Sam&lt;&#x2f;b&gt;</p>");
+
+        let sms = engine
+            .render("otp", NotificationChannel::Sms, "en", json!({ "code": "<1&2>" })).await
+            .unwrap();
+        assert_eq!(sms, "Your code is <1&2>");
+    }
+}
@@ -0,0 +1,208 @@
+//! Rocket test client helpers, enabled with the `test_support` feature
+
+use rocket::http::{ Header, Status };
+use rocket::local::asynchronous::{ Client, LocalRequest, LocalResponse };
+use rocket::{ Build, Rocket, Route };
+use serde_json::Value;
+
+use crate::common_lib::app_version;
+use crate::common_lib::constants::{
+    X_CITY,
+    X_CORRELATION_ID,
+    X_COUNTRY_CODE,
+    X_FIREBASE_UID,
+    X_INTERNAL_API_KEY,
+    X_PHONE_NUMBER,
+};
+use crate::common_lib::logging::CorrelationIdFairing;
+use crate::common_lib::panic_handler::PanicHandler;
+
+/// Caller identity forwarded by the gateway on internal requests
+#[derive(Debug, Clone)]
+pub struct TestIdentity {
+    pub firebase_uid: String,
+    pub phone_number: String,
+    pub country_code: String,
+    pub city: Option<String>,
+}
+
+impl Default for TestIdentity {
+    fn default() -> Self {
+        Self {
+            firebase_uid: "test-firebase-uid".to_string(),
+            phone_number: "+15550100000".to_string(),
+            country_code: "US".to_string(),
+            city: None,
+        }
+    }
+}
+
+/// Common-lib fairings and catchers `test_rocket` attaches, so handlers are tested the way
+/// services run them; switch one off when a test attaches its own
+#[derive(Debug, Clone, Copy)]
+pub struct TestRocketOptions {
+    /// `PanicHandler` and its JSON 500 catcher
+    pub panic_handler: bool,
+    /// `CorrelationIdFairing`, echoing `X-Correlation-ID` on every response
+    pub correlation_id: bool,
+    /// The JSON 426 catcher from `app_version::catchers`
+    pub app_version_catchers: bool,
+}
+
+impl Default for TestRocketOptions {
+    fn default() -> Self {
+        Self {
+            panic_handler: true,
+            correlation_id: true,
+            app_version_catchers: true,
+        }
+    }
+}
+
+/// Build a Rocket instance for tests with the given routes mounted at `/` and the common
+/// fairings and catchers attached
+/// Logging is silenced and the port is irrelevant since requests never hit the network.
+/// Guards like `RequestId` and `RequestContext` need no setup beyond the state they read.
+/// Country restriction catchers are scoped to the restricted mount, so tests register those.
+pub fn test_rocket(routes: Vec<Route>) -> Rocket<Build> {
+    test_rocket_with(routes, TestRocketOptions::default())
+}
+
+/// `test_rocket` with some of the common fairings and catchers left out
+pub fn test_rocket_with(routes: Vec<Route>, options: TestRocketOptions) -> Rocket<Build> {
+    let figment = rocket::Config::figment().merge(("log_level", rocket::config::LogLevel::Off));
+    let mut rocket = rocket::custom(figment).mount("/", routes);
+
+    if options.panic_handler {
+        rocket = rocket.attach(PanicHandler);
+    }
+    if options.correlation_id {
+        rocket = rocket.attach(CorrelationIdFairing);
+    }
+    if options.app_version_catchers {
+        rocket = rocket.register("/", app_version::catchers());
+    }
+    rocket
+}
+
+/// Create a tracked async test client for a Rocket instance
+pub async fn test_client(rocket: Rocket<Build>) -> Client {
+    Client::tracked(rocket).await.expect("valid rocket instance")
+}
+
+/// Convenience methods for building authenticated test requests
+pub trait TestRequestExt<'c> {
+    /// Add the internal API key header
+    fn with_internal_api_key(self, api_key: &str) -> LocalRequest<'c>;
+
+    /// Add the gateway identity headers for the given identity
+    fn with_identity(self, identity: &TestIdentity) -> LocalRequest<'c>;
+
+    /// Add a correlation ID header
+    fn with_correlation_id(self, correlation_id: &str) -> LocalRequest<'c>;
+}
+
+impl<'c> TestRequestExt<'c> for LocalRequest<'c> {
+    fn with_internal_api_key(self, api_key: &str) -> LocalRequest<'c> {
+        self.header(Header::new(X_INTERNAL_API_KEY, api_key.to_string()))
+    }
+
+    fn with_identity(self, identity: &TestIdentity) -> LocalRequest<'c> {
+        let request = self
+            .header(Header::new(X_FIREBASE_UID, identity.firebase_uid.clone()))
+            .header(Header::new(X_PHONE_NUMBER, identity.phone_number.clone()))
+            .header(Header::new(X_COUNTRY_CODE, identity.country_code.clone()));
+
+        match &identity.city {
+            Some(city) => request.header(Header::new(X_CITY, city.clone())),
+            None => request,
+        }
+    }
+
+    fn with_correlation_id(self, correlation_id: &str) -> LocalRequest<'c> {
+        self.header(Header::new(X_CORRELATION_ID, correlation_id.to_string()))
+    }
+}
+
+/// Assert that a response carries the standard `ApiError` body and status
+/// Returns the error message so callers can make further assertions on it
+pub async fn assert_api_error(
+    response: LocalResponse<'_>,
+    expected_status: Status,
+    expected_prefix: &str
+) -> String {
+    assert_eq!(response.status(), expected_status, "unexpected status code");
+
+    let body = response.into_string().await.expect("response body");
+    let json: Value = serde_json::from_str(&body).unwrap_or_else(|e| {
+        panic!("error body is not JSON ({e}): {body}")
+    });
+    let message = json
+        .get("error")
+        .and_then(Value::as_str)
+        .unwrap_or_else(|| panic!("error body has no \"error\" field: {body}"))
+        .to_string();
+
+    assert!(
+        message.starts_with(expected_prefix),
+        "error message '{}' does not start with '{}'",
+        message,
+        expected_prefix
+    );
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_lib::error::ApiError;
+    use rocket::{ get, routes };
+
+    #[get("/missing")]
+    fn missing() -> Result<&'static str, ApiError> {
+        Err(ApiError::NotFound {
+            message: "nothing here".to_string(),
+        })
+    }
+
+    #[rocket::async_test]
+    async fn test_client_helpers() {
+        let client = test_client(test_rocket(routes![missing])).await;
+
+        let response = client.get("/missing").with_correlation_id("abc").dispatch().await;
+        let message = assert_api_error(response, Status::NotFound, "Not Found").await;
+        assert_eq!(message, "Not Found: nothing here");
+    }
+
+    #[get("/boom")]
+    fn boom() -> &'static str {
+        panic!("handler bug")
+    }
+
+    #[rocket::async_test]
+    async fn test_common_fairings_are_attached() {
+        let client = test_client(test_rocket(routes![boom])).await;
+        let req_id = "8476a536-e9f4-11e8-9739-2dfe598c3fcd";
+
+        let response = client.get("/boom").with_correlation_id(req_id).dispatch().await;
+        assert_eq!(response.headers().get_one(X_CORRELATION_ID), Some(req_id));
+        let message = assert_api_error(response, Status::InternalServerError, "Internal Server Error").await;
+        assert!(message.ends_with(&format!("reference: {}", req_id)));
+
+        let options = TestRocketOptions { correlation_id: false, ..TestRocketOptions::default() };
+        let client = test_client(test_rocket_with(routes![missing], options)).await;
+        let response = client.get("/missing").dispatch().await;
+        assert!(response.headers().get_one(X_CORRELATION_ID).is_none());
+    }
+
+    #[rocket::async_test]
+    async fn test_identity_headers_are_sent() {
+        let client = test_client(test_rocket(routes![missing])).await;
+        let request = client.get("/missing").with_identity(&TestIdentity::default());
+
+        assert_eq!(request.headers().get_one(X_FIREBASE_UID), Some("test-firebase-uid"));
+        assert_eq!(request.headers().get_one(X_COUNTRY_CODE), Some("US"));
+        assert!(request.headers().get_one(X_CITY).is_none());
+    }
+}
@@ -0,0 +1,253 @@
+//! Docker-backed integration test harness, enabled with the `test_containers` feature
+//! Starts MongoDB, Redis and LocalStack (S3/SQS/Secrets Manager) and points the standard
+//! environment variables at them, so repository and messaging tests run the same in CI and locally.
+//!
+//! ```ignore
+//! let env = TestEnvironment::start().await?;
+//! env.mongo.seed("users", &[user_fixture()]).await?;
+//! env.localstack.create_bucket("uploads").await?;
+//! let queue_url = env.localstack.create_queue("notifications").await?;
+//! env.localstack.create_secret("app/config", r#"{ "current": "key" }"#).await?;
+//! let mut redis = env.redis.connection().await?;
+//! ```
+
+use std::error::Error;
+use mongodb::{ Client as MongoClient, Database };
+use rusoto_core::Region;
+use rusoto_s3::{ CreateBucketRequest, S3Client, S3 };
+use rusoto_sqs::{ CreateQueueRequest, Sqs, SqsClient };
+use serde::Serialize;
+use testcontainers::{ runners::AsyncRunner, ContainerAsync, ImageExt };
+use testcontainers_modules::localstack::LocalStack;
+use testcontainers_modules::mongo::Mongo;
+use testcontainers_modules::redis::{ Redis, REDIS_PORT };
+use tracing::info;
+
+use crate::common_lib::constants::{ AWS_ENDPOINT_URL, AWS_REGION, DB_NAME, DB_URI, ENV, REDIS_URL };
+
+const MONGO_PORT: u16 = 27017;
+const LOCALSTACK_PORT: u16 = 4566;
+const LOCALSTACK_SERVICES: &str = "s3,sqs,secretsmanager";
+const TEST_DB_NAME: &str = "common_lib_test";
+const TEST_AWS_REGION: &str = "eu-west-2";
+
+pub type HarnessResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Running MongoDB container with a ready-made client
+pub struct MongoContainer {
+    _container: ContainerAsync<Mongo>,
+    pub uri: String,
+    pub client: MongoClient,
+}
+
+impl MongoContainer {
+    pub async fn start() -> HarnessResult<Self> {
+        let container = Mongo::default().start().await?;
+        let host = container.get_host().await?;
+        let port = container.get_host_port_ipv4(MONGO_PORT).await?;
+        let uri = format!("mongodb://{host}:{port}");
+        let client = MongoClient::with_uri_str(&uri).await?;
+
+        info!("TEST:containers [MONGO_READY] MongoDB container started - uri: {}", uri);
+
+        Ok(Self {
+            _container: container,
+            uri,
+            client,
+        })
+    }
+
+    pub fn database(&self) -> Database {
+        self.client.database(TEST_DB_NAME)
+    }
+
+    /// Insert fixture documents into a collection of the test database
+    pub async fn seed<T: Serialize + Send + Sync>(&self, collection: &str, documents: &[T]) -> HarnessResult<()> {
+        if documents.is_empty() {
+            return Ok(());
+        }
+        self.database().collection::<T>(collection).insert_many(documents, None).await?;
+
+        info!(
+            "TEST:containers [MONGO_SEEDED] Seeded collection - collection: {}, documents: {}",
+            collection,
+            documents.len()
+        );
+        Ok(())
+    }
+}
+
+/// Running Redis container
+pub struct RedisContainer {
+    _container: ContainerAsync<Redis>,
+    pub url: String,
+}
+
+impl RedisContainer {
+    pub async fn start() -> HarnessResult<Self> {
+        let container = Redis::default().start().await?;
+        let host = container.get_host().await?;
+        let port = container.get_host_port_ipv4(REDIS_PORT).await?;
+        let url = format!("redis://{host}:{port}");
+
+        info!("TEST:containers [REDIS_READY] Redis container started - url: {}", url);
+
+        Ok(Self {
+            _container: container,
+            url,
+        })
+    }
+
+    pub async fn connection(&self) -> HarnessResult<redis::aio::MultiplexedConnection> {
+        Ok(redis::Client::open(self.url.as_str())?.get_multiplexed_async_connection().await?)
+    }
+}
+
+/// Running LocalStack container exposing S3, SQS and Secrets Manager
+pub struct LocalStackContainer {
+    _container: ContainerAsync<LocalStack>,
+    pub endpoint_url: String,
+}
+
+impl LocalStackContainer {
+    pub async fn start() -> HarnessResult<Self> {
+        let container = LocalStack::default()
+            .with_env_var("SERVICES", LOCALSTACK_SERVICES)
+            .with_env_var("DEFAULT_REGION", TEST_AWS_REGION)
+            .start().await?;
+        let host = container.get_host().await?;
+        let port = container.get_host_port_ipv4(LOCALSTACK_PORT).await?;
+        let endpoint_url = format!("http://{host}:{port}");
+
+        info!(
+            "TEST:containers [LOCALSTACK_READY] LocalStack container started - endpoint: {}",
+            endpoint_url
+        );
+
+        Ok(Self {
+            _container: container,
+            endpoint_url,
+        })
+    }
+
+    pub fn region(&self) -> Region {
+        Region::Custom {
+            name: TEST_AWS_REGION.to_string(),
+            endpoint: self.endpoint_url.clone(),
+        }
+    }
+
+    /// Clients read the dummy credentials from the environment, see `TestEnvironment::apply_env`
+    pub fn s3_client(&self) -> S3Client {
+        S3Client::new(self.region())
+    }
+
+    pub fn sqs_client(&self) -> SqsClient {
+        SqsClient::new(self.region())
+    }
+
+    pub async fn create_bucket(&self, bucket: &str) -> HarnessResult<()> {
+        let request = CreateBucketRequest {
+            bucket: bucket.to_string(),
+            ..Default::default()
+        };
+        self.s3_client().create_bucket(request).await?;
+
+        info!("TEST:containers [S3_SEEDED] Created bucket - bucket: {}", bucket);
+        Ok(())
+    }
+
+    /// Create a queue and return its URL
+    pub async fn create_queue(&self, queue_name: &str) -> HarnessResult<String> {
+        let request = CreateQueueRequest {
+            queue_name: queue_name.to_string(),
+            ..Default::default()
+        };
+        let queue_url = self.sqs_client()
+            .create_queue(request).await?
+            .queue_url.ok_or("Queue created without a URL")?;
+
+        info!("TEST:containers [SQS_SEEDED] Created queue - url: {}", queue_url);
+        Ok(queue_url)
+    }
+
+    /// Store a secret for code that reads config through `utils::get_secret_value`
+    /// The SDK config is loaded from the environment, so call this after `TestEnvironment::apply_env`.
+    pub async fn create_secret(&self, secret_name: &str, value: &str) -> HarnessResult<()> {
+        let config = aws_config::load_from_env().await;
+        aws_sdk_secretsmanager::Client::new(&config)
+            .create_secret()
+            .name(secret_name)
+            .secret_string(value)
+            .send().await?;
+
+        info!("TEST:containers [SECRET_SEEDED] Created secret - name: {}", secret_name);
+        Ok(())
+    }
+}
+
+/// Full integration environment; containers are removed when this is dropped
+pub struct TestEnvironment {
+    pub mongo: MongoContainer,
+    pub redis: RedisContainer,
+    pub localstack: LocalStackContainer,
+}
+
+impl TestEnvironment {
+    /// Start all containers concurrently and export their settings as environment variables
+    pub async fn start() -> HarnessResult<Self> {
+        let (mongo, redis, localstack) = tokio::try_join!(
+            MongoContainer::start(),
+            RedisContainer::start(),
+            LocalStackContainer::start()
+        )?;
+
+        let environment = Self { mongo, redis, localstack };
+        environment.apply_env();
+
+        Ok(environment)
+    }
+
+    /// Point the standard config variables (and the AWS SDK) at the containers
+    /// Note: environment variables are process-wide; run container tests serially
+    pub fn apply_env(&self) {
+        std::env::set_var(ENV, "test");
+        std::env::set_var(DB_URI, &self.mongo.uri);
+        std::env::set_var(DB_NAME, TEST_DB_NAME);
+        std::env::set_var(REDIS_URL, &self.redis.url);
+        std::env::set_var(AWS_ENDPOINT_URL, &self.localstack.endpoint_url);
+        std::env::set_var(AWS_REGION, TEST_AWS_REGION);
+        std::env::set_var("AWS_ACCESS_KEY_ID", "test");
+        std::env::set_var("AWS_SECRET_ACCESS_KEY", "test");
+    }
+
+    pub fn database(&self) -> Database {
+        self.mongo.database()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusoto_s3::PutObjectRequest;
+    use crate::common_lib::utils::download_file_from_s3;
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_s3_round_trip_through_localstack() {
+        let env = TestEnvironment::start().await.unwrap();
+        env.localstack.create_bucket("templates").await.unwrap();
+        env.localstack
+            .s3_client()
+            .put_object(PutObjectRequest {
+                bucket: "templates".to_string(),
+                key: "welcome.html".to_string(),
+                body: Some(b"<p>Welcome</p>".to_vec().into()),
+                ..Default::default()
+            }).await
+            .unwrap();
+
+        let content = download_file_from_s3("templates", "welcome.html").await.unwrap();
+        assert_eq!(content, "<p>Welcome</p>");
+    }
+}
@@ -0,0 +1,295 @@
+//! HTTP provider stubbing helpers built on wiremock, enabled with the `test_support` feature
+//! Lets provider clients exercise error branches (429, 5xx, malformed JSON, timeouts) deterministically.
+
+use std::time::Duration;
+use serde_json::{ json, Value };
+use wiremock::matchers::{ method, path, path_regex };
+use wiremock::{ Mock, MockServer, ResponseTemplate };
+
+use crate::common_lib::geolocation::GeolocationConfig;
+
+const MAXMIND_PATH: &str = "/geoip/v2.1/city";
+const IP_API_PATH: &str = "/json";
+const IPINFO_PATH: &str = "/ipinfo";
+const NOMINATIM_PATH: &str = "/reverse";
+const TEST_MAXMIND_API_KEY: &str = "test_maxmind_api_key";
+
+/// Canned provider response bodies
+pub mod fixtures {
+    use super::*;
+
+    /// MaxMind GeoIP2 City response for a Mountain View address
+    pub fn maxmind_city(ip_address: &str) -> Value {
+        json!({
+            "continent": { "code": "NA", "names": { "en": "North America" } },
+            "city": { "names": { "en": "Mountain View", "de": "Mountain View" } },
+            "country": { "iso_code": "US", "names": { "en": "United States", "de": "USA" } },
+            "location": {
+                "latitude": 37.386,
+                "longitude": -122.0838,
+                "time_zone": "America/Los_Angeles"
+            },
+            "subdivisions": [{ "iso_code": "CA", "names": { "en": "California" } }],
+            "traits": {
+                "ip_address": ip_address,
+                "autonomous_system_number": 15169,
+                "autonomous_system_organization": "GOOGLE"
+            }
+        })
+    }
+
+    /// ipinfo.io privacy detection response
+    pub fn ipinfo_privacy(vpn: bool, tor: bool, hosting: bool, service: &str) -> Value {
+        json!({
+            "vpn": vpn,
+            "proxy": false,
+            "tor": tor,
+            "relay": false,
+            "hosting": hosting,
+            "service": service
+        })
+    }
+
+    /// MaxMind error body (e.g. for 401/404 responses)
+    pub fn maxmind_error(code: &str, error: &str) -> Value {
+        json!({ "code": code, "error": error })
+    }
+
+    /// Successful ip-api.com response for a Berlin address
+    pub fn ip_api_success(ip_address: &str) -> Value {
+        json!({
+            "status": "success",
+            "continentCode": "EU",
+            "country": "Germany",
+            "countryCode": "DE",
+            "region": "BE",
+            "regionName": "Land Berlin",
+            "city": "Berlin",
+            "zip": "10115",
+            "lat": 52.52,
+            "lon": 13.405,
+            "timezone": "Europe/Berlin",
+            "isp": "Deutsche Telekom AG",
+            "org": "Deutsche Telekom AG",
+            "as": "AS3320 Deutsche Telekom AG",
+            "query": ip_address
+        })
+    }
+
+    /// ip-api.com failure response (returned with HTTP 200)
+    pub fn ip_api_failure(ip_address: &str, message: &str) -> Value {
+        json!({
+            "status": "fail",
+            "country": "",
+            "countryCode": "",
+            "region": "",
+            "regionName": "",
+            "city": "",
+            "zip": "",
+            "lat": 0.0,
+            "lon": 0.0,
+            "timezone": "",
+            "isp": "",
+            "org": "",
+            "as": "",
+            "query": ip_address,
+            "message": message
+        })
+    }
+
+    /// ipinfo.io lookup response
+    pub fn ipinfo_lookup(ip_address: &str) -> Value {
+        json!({
+            "ip": ip_address,
+            "city": "Mountain View",
+            "region": "California",
+            "country": "US",
+            "loc": "37.4056,-122.0775",
+            "org": "AS15169 Google LLC",
+            "postal": "94043",
+            "timezone": "America/Los_Angeles"
+        })
+    }
+
+    /// IP2Location.io lookup response
+    pub fn ip2location_lookup(ip_address: &str) -> Value {
+        json!({
+            "ip": ip_address,
+            "country_code": "US",
+            "country_name": "United States of America",
+            "region_name": "California",
+            "city_name": "Mountain View",
+            "latitude": 37.38605,
+            "longitude": -122.08385,
+            "zip_code": "94035",
+            "time_zone": "-07:00",
+            "asn": "15169",
+            "as": "Google LLC",
+            "is_proxy": false
+        })
+    }
+
+    /// Nominatim reverse geocoding response at country zoom
+    pub fn nominatim_reverse(country_code: &str) -> Value {
+        json!({
+            "place_id": 12345,
+            "osm_type": "relation",
+            "category": "boundary",
+            "type": "administrative",
+            "place_rank": 4,
+            "display_name": "Test Country",
+            "address": { "country": "Test Country", "country_code": country_code }
+        })
+    }
+
+    /// Twilio API key creation response
+    pub fn twilio_api_key() -> Value {
+        json!({
+            "sid": "SKXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX",
+            "friendly_name": "test-key",
+            "date_created": "Mon, 13 Jan 2025 12:00:00 +0000",
+            "date_updated": "Mon, 13 Jan 2025 12:00:00 +0000",
+            "secret": "test_secret"
+        })
+    }
+
+    /// Twilio error response
+    pub fn twilio_error(status: u16, code: u32, message: &str) -> Value {
+        json!({
+            "code": code,
+            "message": message,
+            "more_info": format!("https://www.twilio.com/docs/errors/{code}"),
+            "status": status
+        })
+    }
+
+    /// Stripe error response
+    pub fn stripe_error(error_type: &str, code: &str, message: &str) -> Value {
+        json!({
+            "error": {
+                "type": error_type,
+                "code": code,
+                "message": message
+            }
+        })
+    }
+}
+
+/// Mock HTTP server standing in for external providers
+pub struct ProviderStubServer {
+    server: MockServer,
+}
+
+impl ProviderStubServer {
+    pub async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    /// Access the underlying server to mount custom mocks
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// Geolocation config pointing MaxMind, ip-api, the ipinfo risk API and Nominatim at this server
+    pub fn geolocation_config(&self) -> GeolocationConfig {
+        GeolocationConfig::builder()
+            .api_key(TEST_MAXMIND_API_KEY)
+            .service_url(&format!("{}{}", self.uri(), MAXMIND_PATH))
+            .fallback_service_url(&format!("{}{}", self.uri(), IP_API_PATH))
+            .ip_risk_url(&format!("{}{}", self.uri(), IPINFO_PATH))
+            .ip_risk_token("test_ipinfo_token")
+            .reverse_geocode_url(&format!("{}{}", self.uri(), NOMINATIM_PATH))
+            .timeout(Duration::from_secs(1))
+            .build()
+            .expect("stub geolocation config is valid")
+    }
+
+    /// Respond to MaxMind lookups with the given status and JSON body
+    pub async fn stub_maxmind(&self, status: u16, body: Value) {
+        self.mount(MAXMIND_PATH, ResponseTemplate::new(status).set_body_json(body)).await;
+    }
+
+    /// Respond to MaxMind lookups with a body that is not valid JSON
+    pub async fn stub_maxmind_malformed(&self) {
+        self.mount(MAXMIND_PATH, Self::malformed_response()).await;
+    }
+
+    /// Delay MaxMind responses, e.g. beyond the client timeout
+    pub async fn stub_maxmind_delay(&self, delay: Duration) {
+        let response = ResponseTemplate::new(200)
+            .set_body_json(fixtures::maxmind_city("0.0.0.0"))
+            .set_delay(delay);
+        self.mount(MAXMIND_PATH, response).await;
+    }
+
+    /// Respond to ip-api lookups with the given status and JSON body
+    pub async fn stub_ip_api(&self, status: u16, body: Value) {
+        self.mount(IP_API_PATH, ResponseTemplate::new(status).set_body_json(body)).await;
+    }
+
+    /// Respond to ip-api lookups with a body that is not valid JSON
+    pub async fn stub_ip_api_malformed(&self) {
+        self.mount(IP_API_PATH, Self::malformed_response()).await;
+    }
+
+    /// Respond to ipinfo.io privacy lookups with the given status and JSON body
+    pub async fn stub_ipinfo_privacy(&self, status: u16, body: Value) {
+        self.mount(IPINFO_PATH, ResponseTemplate::new(status).set_body_json(body)).await;
+    }
+
+    /// Respond to Nominatim reverse geocoding requests with the given status and JSON body
+    pub async fn stub_reverse_geocode(&self, status: u16, body: Value) {
+        self.stub_path("GET", NOMINATIM_PATH, status, body).await;
+    }
+
+    /// Respond to a fixed request path (e.g. a Twilio or Stripe endpoint)
+    pub async fn stub_path(&self, http_method: &str, request_path: &str, status: u16, body: Value) {
+        Mock::given(method(http_method))
+            .and(path(request_path))
+            .respond_with(ResponseTemplate::new(status).set_body_json(body))
+            .mount(&self.server).await;
+    }
+
+    /// Number of requests received under a path prefix
+    pub async fn request_count(&self, path_prefix: &str) -> usize {
+        self.server
+            .received_requests().await
+            .unwrap_or_default()
+            .iter()
+            .filter(|request| request.url.path().starts_with(path_prefix))
+            .count()
+    }
+
+    pub async fn maxmind_request_count(&self) -> usize {
+        self.request_count(MAXMIND_PATH).await
+    }
+
+    pub async fn ip_api_request_count(&self) -> usize {
+        self.request_count(IP_API_PATH).await
+    }
+
+    pub async fn reverse_geocode_request_count(&self) -> usize {
+        self.request_count(NOMINATIM_PATH).await
+    }
+
+    async fn mount(&self, path_prefix: &str, response: ResponseTemplate) {
+        Mock::given(method("GET"))
+            .and(path_regex(format!("^{}/.+", regex_escape(path_prefix))))
+            .respond_with(response)
+            .mount(&self.server).await;
+    }
+
+    fn malformed_response() -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_raw("{\"status\": \"success\", ", "application/json")
+    }
+}
+
+fn regex_escape(value: &str) -> String {
+    value.replace('.', "\\.")
+}
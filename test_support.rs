@@ -0,0 +1,275 @@
+//! Test fixtures for shared models, enabled with the `test_support` feature
+//! Downstream services should depend on common-lib with `features = ["test_support"]`
+//! in `[dev-dependencies]` instead of hand-rolling fixtures.
+//!
+//! There is no user fixture: user documents are owned by each service and common-lib has no user
+//! model. The parts of a user that cross services are covered instead, by `fake_identity` (the
+//! gateway headers), `fake_phone_number` and `fake_notification_preference`.
+
+use chrono::{ TimeZone, Utc };
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use proptest::prelude::*;
+use rand::Rng;
+
+use crate::common_lib::geolocation::LocationInfo;
+use crate::common_lib::notifications::{
+    NotificationCategory,
+    NotificationChannel,
+    NotificationEvent,
+    NotificationPreference,
+    NotificationPriority,
+};
+use crate::common_lib::shared_models::{
+    DevicesDeleteRequest,
+    EncryptedMessage,
+    IdNamePair,
+    Money,
+    MyDateTime,
+    MyObjectId,
+};
+use crate::common_lib::test_client::TestIdentity;
+use crate::common_lib::utils::generate_random_alphanumeric_string;
+
+/// Currencies `arb_money` draws from, with 2, 0 and 3 minor unit digits
+const FAKE_CURRENCIES: [&str; 5] = ["GBP", "EUR", "USD", "JPY", "KWD"];
+
+/// Random E.164 phone number in the US test range (+1 555 01xx)
+pub fn fake_phone_number() -> String {
+    let mut rng = rand::rng();
+    format!("+1555010{:04}", rng.random_range(0..10000))
+}
+
+/// Random device identifier
+pub fn fake_device_id() -> String {
+    generate_random_alphanumeric_string()[..16].to_string()
+}
+
+/// Gateway identity of a random user, for `TestRequestExt::with_identity`
+pub fn fake_identity() -> TestIdentity {
+    TestIdentity {
+        firebase_uid: generate_random_alphanumeric_string()[..28].to_string(),
+        phone_number: fake_phone_number(),
+        ..TestIdentity::default()
+    }
+}
+
+/// Random amount between 0.01 and 1000.00 in `currency`
+pub fn fake_money(currency: &str) -> Money {
+    Money::new(rand::rng().random_range(1..=100_000), currency)
+}
+
+pub fn fake_devices_delete_request(count: usize) -> DevicesDeleteRequest {
+    DevicesDeleteRequest {
+        device_ids: (0..count).map(|_| fake_device_id()).collect(),
+    }
+}
+
+pub fn fake_id_name_pair(name: &str) -> IdNamePair {
+    IdNamePair {
+        id: MyObjectId::new().to_string(),
+        name: name.to_string(),
+    }
+}
+
+pub fn fake_encrypted_message() -> EncryptedMessage {
+    EncryptedMessage {
+        address: fake_device_id(),
+        encrypted_message: generate_random_alphanumeric_string(),
+    }
+}
+
+/// Builder for `LocationInfo`, defaulting to a fully populated London location
+#[derive(Debug, Clone)]
+pub struct LocationInfoBuilder {
+    location: LocationInfo,
+}
+
+impl Default for LocationInfoBuilder {
+    fn default() -> Self {
+        Self {
+            location: LocationInfo {
+                country_code: "GB".to_string(),
+                country_name: "United Kingdom".to_string(),
+                continent_code: Some("EU".to_string()),
+                city: Some("London".to_string()),
+                region: Some("England".to_string()),
+                latitude: Some(51.5074),
+                longitude: Some(-0.1278),
+                timezone: Some("Europe/London".to_string()),
+                asn: None,
+                isp: None,
+                organization: None,
+            },
+        }
+    }
+}
+
+impl LocationInfoBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn country(mut self, country_code: &str, country_name: &str) -> Self {
+        self.location.country_code = country_code.to_string();
+        self.location.country_name = country_name.to_string();
+        self
+    }
+
+    pub fn continent(mut self, continent_code: Option<&str>) -> Self {
+        self.location.continent_code = continent_code.map(str::to_string);
+        self
+    }
+
+    pub fn city(mut self, city: Option<&str>) -> Self {
+        self.location.city = city.map(str::to_string);
+        self
+    }
+
+    pub fn region(mut self, region: Option<&str>) -> Self {
+        self.location.region = region.map(str::to_string);
+        self
+    }
+
+    pub fn coordinates(mut self, latitude: f64, longitude: f64) -> Self {
+        self.location.latitude = Some(latitude);
+        self.location.longitude = Some(longitude);
+        self
+    }
+
+    pub fn timezone(mut self, timezone: Option<&str>) -> Self {
+        self.location.timezone = timezone.map(str::to_string);
+        self
+    }
+
+    pub fn network(mut self, asn: Option<u32>, isp: Option<&str>, organization: Option<&str>) -> Self {
+        self.location.asn = asn;
+        self.location.isp = isp.map(str::to_string);
+        self.location.organization = organization.map(str::to_string);
+        self
+    }
+
+    /// Country-level only location, as returned when the provider has no city data
+    pub fn country_only(mut self) -> Self {
+        self.location.city = None;
+        self.location.region = None;
+        self.location.latitude = None;
+        self.location.longitude = None;
+        self.location.timezone = None;
+        self
+    }
+
+    pub fn build(self) -> LocationInfo {
+        self.location
+    }
+}
+
+/// Builder for `NotificationEvent`, defaulting to a normal-priority message on all channels
+#[derive(Debug, Clone)]
+pub struct NotificationEventBuilder {
+    event: NotificationEvent,
+}
+
+impl Default for NotificationEventBuilder {
+    fn default() -> Self {
+        Self {
+            event: NotificationEvent {
+                category: NotificationCategory::Messages,
+                priority: NotificationPriority::Normal,
+                available_channels: NotificationChannel::ALL.to_vec(),
+            },
+        }
+    }
+}
+
+impl NotificationEventBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn category(mut self, category: NotificationCategory) -> Self {
+        self.event.category = category;
+        self
+    }
+
+    pub fn priority(mut self, priority: NotificationPriority) -> Self {
+        self.event.priority = priority;
+        self
+    }
+
+    pub fn channels(mut self, channels: &[NotificationChannel]) -> Self {
+        self.event.available_channels = channels.to_vec();
+        self
+    }
+
+    pub fn build(self) -> NotificationEvent {
+        self.event
+    }
+}
+
+/// Notification preferences for a freshly generated user id
+pub fn fake_notification_preference() -> NotificationPreference {
+    NotificationPreference::default_for_user(&MyObjectId::new().to_string())
+}
+
+/// Proptest strategy producing arbitrary `MyObjectId`s
+pub fn arb_object_id() -> impl Strategy<Value = MyObjectId> {
+    any::<[u8; 12]>().prop_map(|bytes| MyObjectId(ObjectId::from_bytes(bytes)))
+}
+
+/// Proptest strategy producing `Money` in a few common currencies, including refunds (negative amounts)
+pub fn arb_money() -> impl Strategy<Value = Money> {
+    (-1_000_000_000i64..1_000_000_000, prop::sample::select(FAKE_CURRENCIES.as_slice()))
+        .prop_map(|(amount_minor, currency)| Money::new(amount_minor, currency))
+}
+
+/// Proptest strategy producing `MyDateTime`s between 1970 and 2100 with millisecond precision
+pub fn arb_datetime() -> impl Strategy<Value = MyDateTime> {
+    let max_millis = Utc.with_ymd_and_hms(2100, 1, 1, 0, 0, 0).unwrap().timestamp_millis();
+    (0..max_millis).prop_map(|millis| MyDateTime(DateTime::from_millis(millis)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_object_id_string_roundtrip(id in arb_object_id()) {
+            prop_assert_eq!(MyObjectId::parse_string(&id.to_string()).unwrap(), id);
+        }
+
+        #[test]
+        fn test_datetime_serde_roundtrip(dt in arb_datetime()) {
+            let json = serde_json::to_string(&dt).unwrap();
+            let parsed: MyDateTime = serde_json::from_str(&json).unwrap();
+            prop_assert_eq!(parsed.0, dt.0);
+        }
+
+        #[test]
+        fn test_money_serde_roundtrip(money in arb_money()) {
+            let json = serde_json::to_string(&money).unwrap();
+            prop_assert_eq!(serde_json::from_str::<Money>(&json).unwrap(), money);
+        }
+    }
+
+    #[test]
+    fn test_fake_identity_and_money() {
+        let (first, second) = (fake_identity(), fake_identity());
+        assert_ne!(first.firebase_uid, second.firebase_uid);
+        assert!(first.phone_number.starts_with("+1555010"));
+
+        let money = fake_money("gbp");
+        assert_eq!(money.currency, "GBP");
+        assert!((1..=100_000).contains(&money.amount_minor));
+    }
+
+    #[test]
+    fn test_location_builder() {
+        let location = LocationInfoBuilder::new().country("DE", "Germany").country_only().build();
+
+        assert_eq!(location.country_code, "DE");
+        assert!(location.city.is_none());
+        assert!(location.latitude.is_none());
+    }
+}
@@ -0,0 +1,96 @@
+//! Unicode text normalization for search and matching, enabled with the `text` feature
+//!
+//! Stored text keeps what the user typed; these helpers build comparison keys so "José", "JOSE"
+//! and "Jose\u{301}" match, and Arabic spelling variants ("أحمد", "احمد") are treated alike:
+//!
+//! ```ignore
+//! contact.search_key = search_key(&contact.display_name);
+//! let duplicates = collection.find(doc! { "search_key": search_key(&candidate.display_name) }, None).await?;
+//! ```
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Canonical composition, the form to store user input in
+pub fn nfc(text: &str) -> String {
+    text.nfc().collect()
+}
+
+/// Compatibility composition: also folds ligatures, full-width forms and superscripts ("ﬁ" -> "fi")
+pub fn nfkc(text: &str) -> String {
+    text.nfkc().collect()
+}
+
+/// Remove accents and other combining marks, e.g. "São Tomé" -> "Sao Tome"
+/// Letters that are not decomposable, such as "ø" or "ł", are kept.
+pub fn strip_diacritics(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .nfc()
+        .collect()
+}
+
+/// Fold Arabic spelling variants: hamza carriers and alef forms to alef, alef maksura to yeh,
+/// teh marbuta to heh; tatweel and harakat are removed
+pub fn normalize_arabic(text: &str) -> String {
+    text.chars()
+        .filter(|c| !matches!(c, '\u{0640}' | '\u{064B}'..='\u{065F}' | '\u{0670}'))
+        .map(|c| {
+            match c {
+                'أ' | 'إ' | 'آ' | 'ٱ' => 'ا',
+                'ى' | 'ئ' => 'ي',
+                'ؤ' => 'و',
+                'ة' => 'ه',
+                // Persian and Urdu forms of kaf and yeh
+                'ک' => 'ك',
+                'ی' => 'ي',
+                _ => c,
+            }
+        })
+        .collect()
+}
+
+/// Locale-independent case folding; unlike `to_lowercase` it maps "ß" to "ss", final sigma to
+/// sigma, and "İ" to a plain "i" so Turkish names match their ASCII spelling
+pub fn case_fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    let mut previous = None;
+
+    for c in text.chars().flat_map(char::to_lowercase) {
+        match c {
+            'ß' => folded.push_str("ss"),
+            'ς' => folded.push('σ'),
+            // "İ".to_lowercase() is "i" followed by a combining dot above
+            '\u{0307}' if previous == Some('i') => {}
+            _ => folded.push(c),
+        }
+        previous = Some(c);
+    }
+
+    folded
+}
+
+/// Key for search and duplicate detection: compatibility-normalized, case-folded, without
+/// diacritics, Arabic variants folded, and whitespace collapsed to single spaces
+pub fn search_key(text: &str) -> String {
+    let folded = normalize_arabic(&strip_diacritics(&case_fold(&nfkc(text))));
+    folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_keys_match_variants() {
+        assert_eq!(nfc("Jose\u{301}"), "José");
+        assert_eq!(nfkc("ﬁle ２"), "file 2");
+        assert_eq!(strip_diacritics("São Tomé, Łódź"), "Sao Tome, Łodz");
+        assert_eq!(case_fold("STRAßE İstanbul ΟΔΟΣ"), "strasse istanbul οδοσ");
+        assert_eq!(normalize_arabic("أحمـــد مَدْرَسَة"), "احمد مدرسه");
+
+        assert_eq!(search_key("  José\tda  SILVA "), search_key("jose da silva"));
+        assert_eq!(search_key("İSMAİL"), "ismail");
+        assert_eq!(search_key("إيمان"), search_key("ايمان"));
+    }
+}
@@ -0,0 +1,299 @@
+//! Blue/green traffic shadowing, compiled out by the `no_http` feature
+//!
+//! After answering a request, a service hands a sample of it to `TrafficShadow`, which replays it
+//! against the shadow environment in the background. Shadow requests carry the original correlation
+//! ID and `X-Shadow-Request: true` so the shadow can skip side effects (notifications, payments),
+//! and a `ShadowDiffHook` compares both responses:
+//!
+//! ```ignore
+//! let shadow = Arc::new(
+//!     TrafficShadow::new(client, ShadowConfig::new("https://api.eu-west-2.internal", 0.05))
+//!         .with_diff_hook(Arc::new(LoggingDiffHook))
+//! );
+//! shadow.mirror(ShadowRequest::new(Method::GET, "/venues?city=Lisbon", &req_id), primary_response);
+//! ```
+//!
+//! Shadowing never slows down the primary request: the replay is spawned, and requests beyond
+//! `max_in_flight` are dropped rather than queued.
+
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::time::Duration;
+use rand::Rng;
+use reqwest::{ Client, Method };
+use serde::Serialize;
+use tokio::sync::Semaphore;
+use tracing::{ debug, warn };
+
+use crate::common_lib::constants::{ X_CORRELATION_ID, X_SHADOW_REQUEST };
+use crate::common_lib::secret::redact_uris;
+
+/// Configuration for the shadow environment
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowConfig {
+    /// Base URL of the shadow environment, e.g. the new region's internal endpoint
+    pub base_url: String,
+    /// Fraction of offered requests to replay, from 0.0 to 1.0
+    pub sample_rate: f64,
+    pub timeout_seconds: u64,
+    /// Replays running at once; further requests are dropped
+    pub max_in_flight: usize,
+}
+
+impl ShadowConfig {
+    pub fn new(base_url: &str, sample_rate: f64) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            sample_rate: sample_rate.clamp(0.0, 1.0),
+            timeout_seconds: 5,
+            max_in_flight: 100,
+        }
+    }
+}
+
+/// A request to replay; headers should exclude hop-by-hop and authentication headers the shadow must not see
+#[derive(Debug, Clone)]
+pub struct ShadowRequest {
+    pub method: Method,
+    /// Path and query, e.g. "/venues?city=Lisbon"
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+    pub req_id: String,
+}
+
+impl ShadowRequest {
+    pub fn new(method: Method, path: &str, req_id: &str) -> Self {
+        Self {
+            method,
+            path: path.to_string(),
+            headers: Vec::new(),
+            body: None,
+            req_id: req_id.to_string(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = Some(body);
+        self
+    }
+}
+
+/// Status and body of a primary or shadow response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+/// Compares the primary response with the shadow's, e.g. to log or count mismatches
+pub trait ShadowDiffHook: Send + Sync {
+    /// `shadow` is the error message when the shadow request failed
+    fn compare(&self, request: &ShadowRequest, primary: &ShadowResponse, shadow: Result<&ShadowResponse, &str>);
+}
+
+/// Logs status mismatches, body mismatches and failed shadow requests
+pub struct LoggingDiffHook;
+
+impl ShadowDiffHook for LoggingDiffHook {
+    fn compare(&self, request: &ShadowRequest, primary: &ShadowResponse, shadow: Result<&ShadowResponse, &str>) {
+        match shadow {
+            Err(e) => {
+                warn!(
+                    "SHADOW:compare [SHADOW_ERROR] [req_id:{}] Shadow request failed - method: {}, path: {}, error: {}",
+                    request.req_id,
+                    request.method,
+                    request.path,
+                    e
+                );
+            }
+            Ok(shadow) if shadow.status != primary.status => {
+                warn!(
+                    "SHADOW:compare [STATUS_MISMATCH] [req_id:{}] Shadow status differs - method: {}, path: {}, primary: {}, shadow: {}",
+                    request.req_id,
+                    request.method,
+                    request.path,
+                    primary.status,
+                    shadow.status
+                );
+            }
+            Ok(shadow) if shadow.body != primary.body => {
+                warn!(
+                    "SHADOW:compare [BODY_MISMATCH] [req_id:{}] Shadow body differs - method: {}, path: {}, primary_bytes: {}, shadow_bytes: {}",
+                    request.req_id,
+                    request.method,
+                    request.path,
+                    primary.body.len(),
+                    shadow.body.len()
+                );
+            }
+            Ok(_) => {
+                debug!("SHADOW:compare [MATCH] [req_id:{}] Shadow response matches - path: {}", request.req_id, request.path);
+            }
+        }
+    }
+}
+
+/// Shadowing counters for monitoring
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowStats {
+    pub mirrored: u64,
+    /// Dropped because `max_in_flight` replays were already running
+    pub dropped: u64,
+    pub failed: u64,
+}
+
+#[derive(Default)]
+struct ShadowCounters {
+    mirrored: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// Replays a sample of requests against a shadow environment
+pub struct TrafficShadow {
+    client: Arc<Client>,
+    config: ShadowConfig,
+    diff_hook: Option<Arc<dyn ShadowDiffHook>>,
+    in_flight: Arc<Semaphore>,
+    counters: Arc<ShadowCounters>,
+}
+
+impl TrafficShadow {
+    pub fn new(client: Arc<Client>, config: ShadowConfig) -> Self {
+        Self {
+            client,
+            in_flight: Arc::new(Semaphore::new(config.max_in_flight)),
+            config,
+            diff_hook: None,
+            counters: Arc::new(ShadowCounters::default()),
+        }
+    }
+
+    pub fn with_diff_hook(mut self, diff_hook: Arc<dyn ShadowDiffHook>) -> Self {
+        self.diff_hook = Some(diff_hook);
+        self
+    }
+
+    fn sampled(&self) -> bool {
+        self.config.sample_rate >= 1.0 ||
+            (self.config.sample_rate > 0.0 && rand::rng().random::<f64>() < self.config.sample_rate)
+    }
+
+    /// Replay the request in the background if it is sampled
+    /// Returns the replay task, or `None` when the request was not sampled or was dropped.
+    pub fn mirror(&self, request: ShadowRequest, primary: ShadowResponse) -> Option<tokio::task::JoinHandle<()>> {
+        if !self.sampled() {
+            return None;
+        }
+        let Ok(permit) = Arc::clone(&self.in_flight).try_acquire_owned() else {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            debug!("SHADOW:mirror [DROPPED] [req_id:{}] Too many shadow requests in flight", request.req_id);
+            return None;
+        };
+
+        let client = Arc::clone(&self.client);
+        let url = format!("{}{}", self.config.base_url, request.path);
+        let timeout = Duration::from_secs(self.config.timeout_seconds);
+        let diff_hook = self.diff_hook.clone();
+        let counters = Arc::clone(&self.counters);
+        self.counters.mirrored.fetch_add(1, Ordering::Relaxed);
+
+        Some(
+            tokio::spawn(async move {
+                let _permit = permit;
+                let mut builder = client
+                    .request(request.method.clone(), &url)
+                    .timeout(timeout)
+                    .header(X_CORRELATION_ID, &request.req_id)
+                    .header(X_SHADOW_REQUEST, "true");
+                for (name, value) in &request.headers {
+                    builder = builder.header(name, value);
+                }
+                if let Some(body) = &request.body {
+                    builder = builder.body(body.clone());
+                }
+
+                let shadow = match builder.send().await {
+                    Ok(response) => {
+                        let status = response.status().as_u16();
+                        response
+                            .bytes().await
+                            .map(|body| ShadowResponse { status, body: body.to_vec() })
+                            .map_err(|e| redact_uris(&e.to_string()))
+                    }
+                    Err(e) => Err(redact_uris(&e.to_string())),
+                };
+                if shadow.is_err() {
+                    counters.failed.fetch_add(1, Ordering::Relaxed);
+                }
+
+                if let Some(diff_hook) = diff_hook {
+                    diff_hook.compare(&request, &primary, shadow.as_ref().map_err(String::as_str));
+                }
+            })
+        )
+    }
+
+    pub fn stats(&self) -> ShadowStats {
+        ShadowStats {
+            mirrored: self.counters.mirrored.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use wiremock::matchers::{ header, method, path };
+    use wiremock::{ Mock, MockServer, ResponseTemplate };
+
+    #[derive(Default)]
+    struct RecordingHook {
+        compared: Mutex<Vec<(u16, Option<u16>)>>,
+    }
+
+    impl ShadowDiffHook for RecordingHook {
+        fn compare(&self, _request: &ShadowRequest, primary: &ShadowResponse, shadow: Result<&ShadowResponse, &str>) {
+            let shadow_status = shadow.ok().map(|shadow| shadow.status);
+            self.compared.lock().unwrap().push((primary.status, shadow_status));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mirror_tags_request_and_compares_responses() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/venues"))
+            .and(header(X_SHADOW_REQUEST, "true"))
+            .and(header(X_CORRELATION_ID, "req-1"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server).await;
+        let hook = Arc::new(RecordingHook::default());
+        let shadow = TrafficShadow::new(Arc::new(Client::new()), ShadowConfig::new(&server.uri(), 1.0)).with_diff_hook(
+            hook.clone()
+        );
+
+        let request = ShadowRequest::new(Method::POST, "/venues", "req-1").body(b"{}".to_vec());
+        let primary = ShadowResponse { status: 201, body: b"{}".to_vec() };
+        shadow.mirror(request, primary).unwrap().await.unwrap();
+
+        assert_eq!(*hook.compared.lock().unwrap(), vec![(201, Some(500))]);
+        assert_eq!(shadow.stats(), ShadowStats { mirrored: 1, dropped: 0, failed: 0 });
+
+        let disabled = TrafficShadow::new(Arc::new(Client::new()), ShadowConfig::new(&server.uri(), 0.0));
+        assert!(disabled.mirror(ShadowRequest::new(Method::GET, "/", "req-2"), ShadowResponse {
+            status: 200,
+            body: Vec::new(),
+        }).is_none());
+    }
+}
@@ -0,0 +1,112 @@
+//! Malware scanning of completed uploads, compiled out by the `no_aws` feature
+//!
+//! `ChunkedUploadService::with_scanner` runs a `ScanProvider` (a clamd sidecar, a scanning Lambda,
+//! ...) on every assembled object. The upload is marked `Pending` before the scan starts and only
+//! becomes servable once it is `Clean`; infected uploads are marked `Quarantined`, a scan that could
+//! not run leaves the upload in `ScanFailed`, and calling `complete` again retries the scan.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
+use serde::{ Deserialize, Serialize };
+
+use crate::common_lib::error::ApiError;
+
+pub type ScanFuture<'a> = Pin<Box<dyn Future<Output = Result<ScanVerdict, ScanError>> + Send + 'a>>;
+
+/// Scan state of an upload
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ScanStatus {
+    /// No scanner is configured, or the upload is not complete yet
+    #[default]
+    NotScanned,
+    /// Recorded before the scanner runs; left behind if the instance dies mid-scan, in which
+    /// case calling `complete` again reruns the scan
+    Pending,
+    Clean,
+    /// Malware was found; the object must not be served
+    Quarantined,
+    /// The scanner could not give a verdict
+    ScanFailed,
+}
+
+/// Result of a scan that ran
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+    Clean,
+    Infected {
+        /// Signature name reported by the engine, e.g. "Eicar-Test-Signature"
+        signature: String,
+    },
+}
+
+/// Why a scan could not run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanError {
+    /// Larger than the engine's stream limit (clamd `StreamMaxLength`)
+    TooLarge {
+        size: u64,
+        limit: u64,
+    },
+    Timeout,
+    Unavailable {
+        message: String,
+    },
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanError::TooLarge { size, limit } => {
+                write!(f, "File of {} bytes exceeds the scan limit of {} bytes", size, limit)
+            }
+            ScanError::Timeout => write!(f, "Malware scan timed out"),
+            ScanError::Unavailable { message } => write!(f, "Malware scanner unavailable: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ScanError {}
+
+impl From<ScanError> for ApiError {
+    fn from(error: ScanError) -> Self {
+        match error {
+            ScanError::TooLarge { .. } => ApiError::BadRequest { message: error.to_string() },
+            ScanError::Timeout | ScanError::Unavailable { .. } =>
+                ApiError::InternalServerError {
+                    message: error.to_string(),
+                },
+        }
+    }
+}
+
+/// A malware scanning engine for stored objects
+pub trait ScanProvider: Send + Sync {
+    /// Name used in logs
+    fn name(&self) -> &str;
+
+    fn scan<'a>(&'a self, bucket: &'a str, key: &'a str, size: u64) -> ScanFuture<'a>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_error_mapping() {
+        let too_large: ApiError = (ScanError::TooLarge { size: 10, limit: 5 }).into();
+        assert!(matches!(too_large, ApiError::BadRequest { .. }));
+
+        let unavailable: ApiError = (ScanError::Unavailable { message: "connection refused".to_string() }).into();
+        assert_eq!(
+            unavailable.to_string(),
+            "Internal Server Error: Malware scanner unavailable: connection refused"
+        );
+    }
+}
@@ -1,12 +1,24 @@
 use hex::encode;
 use rand::Rng;
-use rocket::tokio::io::AsyncReadExt;
+#[cfg(not(feature = "no_aws"))]
+use tokio::io::AsyncReadExt;
+#[cfg(not(feature = "no_aws"))]
 use rusoto_core::Region;
+#[cfg(not(feature = "no_aws"))]
 use rusoto_s3::{ GetObjectRequest, S3Client, S3 };
-use tracing::{ debug, error, warn };
+#[cfg(not(feature = "no_aws"))]
+use tracing::{ debug, info };
+use tracing::{ error, warn };
 use std::error::Error;
+#[cfg(not(feature = "no_aws"))]
+use crate::common_lib::constants::{ AWS_ENDPOINT_URL, AWS_REGION };
+#[cfg(not(feature = "no_aws"))]
+use crate::common_lib::secret::{ redact_uris, SecretKeySet, SecretString };
+#[cfg(not(any(feature = "no_mongo", feature = "no_web")))]
 use crate::common_lib::shared_models::MyObjectId;
+#[cfg(not(feature = "no_mongo"))]
 use chrono::{ TimeZone, Utc };
+#[cfg(not(feature = "no_mongo"))]
 use mongodb::bson::DateTime;
 
 pub fn generate_random_token() -> String {
@@ -44,13 +56,27 @@ pub fn get_env_var(
     }
 }
 
+#[cfg(not(feature = "no_aws"))]
+const DEFAULT_AWS_REGION: &str = "eu-west-2";
+
+/// Region for rusoto clients: `AWS_REGION` (eu-west-2 when unset), served from `AWS_ENDPOINT_URL`
+/// when that is set, e.g. LocalStack in `test_containers`
+#[cfg(not(feature = "no_aws"))]
+pub fn aws_region_from_env() -> Result<Region, Box<dyn std::error::Error>> {
+    let name = std::env::var(AWS_REGION).unwrap_or_else(|_| DEFAULT_AWS_REGION.to_string());
+    match std::env::var(AWS_ENDPOINT_URL) {
+        Ok(endpoint) if !endpoint.is_empty() => Ok(Region::Custom { name, endpoint }),
+        _ => Ok(name.parse::<Region>()?),
+    }
+}
+
+#[cfg(not(feature = "no_aws"))]
 pub async fn download_file_from_s3(
     bucket_name: &str,
     object_key: &str
 ) -> Result<String, Box<dyn std::error::Error>> {
     // Create an S3 client
-    let region = Region::EuWest2;
-    let s3_client = S3Client::new(region);
+    let s3_client = S3Client::new(aws_region_from_env()?);
 
     // Create request to get object from S3
     let request = GetObjectRequest {
@@ -60,7 +86,7 @@ pub async fn download_file_from_s3(
     };
 
     // Send request to S3
-    let response = s3_client.get_object(request).await?;
+    let response = s3_client.get_object(request).await.map_err(|e| redact_uris(&e.to_string()))?;
     let body = response.body.unwrap();
     debug!("Received this from S3 {:?}", body);
 
@@ -73,6 +99,7 @@ pub async fn download_file_from_s3(
     Ok(content)
 }
 
+#[cfg(not(feature = "no_aws"))]
 pub async fn get_secret_value(secret_name: &str) -> Result<String, Box<dyn std::error::Error>> {
     let config = aws_config::load_from_env().await;
     let secret_manager = aws_sdk_secretsmanager::Client::new(&config);
@@ -87,8 +114,9 @@ pub async fn get_secret_value(secret_name: &str) -> Result<String, Box<dyn std::
             }
         }
         Err(err) => {
-            debug!("Error trying to get connection secret for {secret_name}: {err:?}");
-            return Err(Box::new(err));
+            let err = redact_uris(&err.to_string());
+            debug!("Error trying to get connection secret for {secret_name}: {err}");
+            return Err(err.into());
         }
     };
 
@@ -98,9 +126,125 @@ pub async fn get_secret_value(secret_name: &str) -> Result<String, Box<dyn std::
     Ok(secret)
 }
 
+/// Read a secret as a current/previous key set, see `SecretKeySet`
+#[cfg(not(feature = "no_aws"))]
+pub async fn get_secret_key_set(secret_name: &str) -> Result<SecretKeySet, Box<dyn std::error::Error>> {
+    Ok(SecretKeySet::parse(&get_secret_value(secret_name).await?)?)
+}
+
+#[cfg(not(feature = "no_aws"))]
+const AWS_CURRENT: &str = "AWSCURRENT";
+#[cfg(not(feature = "no_aws"))]
+const AWS_PENDING: &str = "AWSPENDING";
+
+/// Current key set of a secret and the version ID it was read from
+#[cfg(not(feature = "no_aws"))]
+async fn get_secret_key_set_version(
+    secret_manager: &aws_sdk_secretsmanager::Client,
+    secret_name: &str
+) -> Result<(SecretKeySet, String), Box<dyn std::error::Error>> {
+    let output = secret_manager
+        .get_secret_value()
+        .secret_id(secret_name)
+        .version_stage(AWS_CURRENT)
+        .send().await
+        .map_err(|e| redact_uris(&e.to_string()))?;
+    let value = output.secret_string.ok_or_else(|| format!("No secret found for {secret_name}"))?;
+    let version_id = output.version_id.ok_or_else(|| format!("No version for secret {secret_name}"))?;
+
+    Ok((SecretKeySet::parse(&value)?, version_id))
+}
+
+/// Store `keys` only if `AWSCURRENT` still points at `read_version_id`
+///
+/// The value is written as a new `AWSPENDING` version, then `AWSCURRENT` is moved to it from the
+/// version that was read. Secrets Manager rejects the move when `AWSCURRENT` has changed since,
+/// so a concurrent rotation fails here instead of being overwritten.
+#[cfg(not(feature = "no_aws"))]
+async fn put_secret_key_set(
+    secret_manager: &aws_sdk_secretsmanager::Client,
+    secret_name: &str,
+    keys: &SecretKeySet,
+    read_version_id: &str
+) -> Result<(), Box<dyn std::error::Error>> {
+    let new_version_id = uuid::Uuid::new_v4().to_string();
+
+    secret_manager
+        .put_secret_value()
+        .secret_id(secret_name)
+        .client_request_token(&new_version_id)
+        .secret_string(keys.to_secret_string().expose_secret())
+        .version_stages(AWS_PENDING)
+        .send().await
+        .map_err(|e| redact_uris(&e.to_string()))?;
+
+    secret_manager
+        .update_secret_version_stage()
+        .secret_id(secret_name)
+        .version_stage(AWS_CURRENT)
+        .move_to_version_id(&new_version_id)
+        .remove_from_version_id(read_version_id)
+        .send().await
+        .map_err(|e| {
+            warn!(
+                "UTILS:put_secret_key_set [CONFLICT] Secret changed while updating it - secret: {}, read_version: {}",
+                secret_name,
+                read_version_id
+            );
+            format!("Secret {secret_name} changed while it was being updated, retry: {}", redact_uris(&e.to_string()))
+        })?;
+    Ok(())
+}
+
+/// Store `new_value` as the current key while the old key stays valid for `grace`
+///
+/// Services reload the key set on their own schedule and accept both keys meanwhile, so no
+/// synchronized deploy is needed. Call `cleanup_rotated_secret` after `grace` to drop the old key.
+/// Fails, leaving the current key set in place, if another update lands between the read and the write.
+#[cfg(not(feature = "no_aws"))]
+pub async fn rotate_secret(
+    secret_name: &str,
+    new_value: SecretString,
+    grace: chrono::Duration
+) -> Result<SecretKeySet, Box<dyn std::error::Error>> {
+    let config = aws_config::load_from_env().await;
+    let secret_manager = aws_sdk_secretsmanager::Client::new(&config);
+
+    let (keys, version_id) = get_secret_key_set_version(&secret_manager, secret_name).await?;
+    let rotated = keys.rotate(new_value, grace, chrono::Utc::now());
+    put_secret_key_set(&secret_manager, secret_name, &rotated, &version_id).await?;
+
+    info!(
+        "UTILS:rotate_secret [ROTATED] Secret rotated - secret: {}, previous_expires_at: {:?}",
+        secret_name,
+        rotated.previous_expires_at
+    );
+    Ok(rotated)
+}
+
+/// Remove the previous key of a rotated secret once its grace period is over
+///
+/// Returns whether the secret changed; safe to run on a schedule.
+#[cfg(not(feature = "no_aws"))]
+pub async fn cleanup_rotated_secret(secret_name: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let config = aws_config::load_from_env().await;
+    let secret_manager = aws_sdk_secretsmanager::Client::new(&config);
+
+    let (keys, version_id) = get_secret_key_set_version(&secret_manager, secret_name).await?;
+    let cleaned = keys.without_expired(chrono::Utc::now());
+    if cleaned == keys {
+        return Ok(false);
+    }
+
+    put_secret_key_set(&secret_manager, secret_name, &cleaned, &version_id).await?;
+    info!("UTILS:cleanup_rotated_secret [CLEANED] Previous key removed - secret: {}", secret_name);
+    Ok(true)
+}
+
 // === ObjectId Parsing Utilities ===
 
 /// Parse an optional ObjectId string, returning None for empty or None strings
+#[cfg(not(any(feature = "no_mongo", feature = "no_web")))]
 pub fn parse_optional_object_id(id_str: Option<&str>) -> Result<Option<MyObjectId>, String> {
     match id_str {
         Some(s) if !s.is_empty() =>
@@ -112,11 +256,13 @@ pub fn parse_optional_object_id(id_str: Option<&str>) -> Result<Option<MyObjectI
 }
 
 /// Parse a required ObjectId string from a String reference
+#[cfg(not(any(feature = "no_mongo", feature = "no_web")))]
 pub fn parse_required_object_id_from_string(id_str: &str) -> Result<MyObjectId, String> {
     MyObjectId::parse_string(id_str).map_err(|e| e.to_string())
 }
 
 /// Parse a required ObjectId string, returning an error for empty or None strings
+#[cfg(not(any(feature = "no_mongo", feature = "no_web")))]
 pub fn parse_required_object_id(
     id_str: Option<&str>,
     field_name: &str
@@ -131,6 +277,7 @@ pub fn parse_required_object_id(
 }
 
 /// Parse an optional ObjectId from an Option<String>, handling Option<String> cases
+#[cfg(not(any(feature = "no_mongo", feature = "no_web")))]
 pub fn parse_optional_object_id_from_option_string(
     id_str: Option<String>
 ) -> Result<Option<MyObjectId>, String> {
@@ -144,6 +291,7 @@ pub fn parse_optional_object_id_from_option_string(
 }
 
 /// Convert an optional MyObjectId to an optional string
+#[cfg(not(any(feature = "no_mongo", feature = "no_web")))]
 pub fn optional_object_id_to_string(id: &Option<MyObjectId>) -> Option<String> {
     id.as_ref().map(|oid| oid.to_string())
 }
@@ -151,6 +299,7 @@ pub fn optional_object_id_to_string(id: &Option<MyObjectId>) -> Option<String> {
 // === DateTime Conversion Utilities ===
 
 /// Convert MongoDB DateTime to Chrono DateTime<Utc>
+#[cfg(not(feature = "no_mongo"))]
 pub fn chrono_from_mongo_datetime(dt: &DateTime) -> Result<chrono::DateTime<Utc>, String> {
     Utc.timestamp_millis_opt(dt.timestamp_millis())
         .single()
@@ -158,6 +307,7 @@ pub fn chrono_from_mongo_datetime(dt: &DateTime) -> Result<chrono::DateTime<Utc>
 }
 
 /// Convert Chrono DateTime<Utc> to MongoDB DateTime
+#[cfg(not(feature = "no_mongo"))]
 pub fn mongo_from_chrono_datetime(dt: chrono::DateTime<Utc>) -> DateTime {
     DateTime::from_millis(dt.timestamp_millis())
 }
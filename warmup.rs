@@ -0,0 +1,284 @@
+//! Startup warm-up tasks run before the service reports ready
+//!
+//! Modules register async tasks (loading the geolocation cache snapshot, rendering templates once,
+//! fetching JWKS, creating Mongo indexes) so the first requests after a deploy don't pay for them.
+//! Tasks run concurrently, each bounded by the registry timeout. A failing required task aborts
+//! startup; other failures are logged and reported as degraded.
+//!
+//! ```ignore
+//! let geo = geolocation_service.clone();
+//! let warmup = Arc::new(
+//!     WarmupRegistry::new()
+//!         .task("geolocation", move || {
+//!             let geo = geo.clone();
+//!             async move { geo.load_cache_snapshot("/var/cache/geo.json").await.map(|_| ()) }
+//!         })
+//!         .required_task("mongo_indexes", move || create_indexes(db.clone()))
+//! );
+//! rocket::build().attach(WarmupFairing::new(warmup))
+//! ```
+//!
+//! Unless the `no_web` feature is set, `WarmupFairing` runs the tasks at ignite, before Rocket
+//! starts listening, and manages the registry so `/health` handlers can include `health_status()`.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::time::{ Duration, Instant };
+#[cfg(not(feature = "no_web"))]
+use rocket::fairing::{ self, Fairing, Info, Kind };
+#[cfg(not(feature = "no_web"))]
+use rocket::{ Build, Rocket };
+use tokio::sync::RwLock;
+use tracing::{ error, info, warn };
+
+use crate::common_lib::error::ApiError;
+use crate::common_lib::health::{ HealthState, HealthStatus };
+
+pub type WarmupFuture = Pin<Box<dyn Future<Output = Result<(), ApiError>> + Send>>;
+
+type WarmupFn = Arc<dyn Fn() -> WarmupFuture + Send + Sync>;
+
+struct WarmupTask {
+    name: String,
+    required: bool,
+    run: WarmupFn,
+}
+
+/// Warm-up tasks and whether they have completed
+pub struct WarmupRegistry {
+    tasks: Vec<WarmupTask>,
+    timeout: Duration,
+    ready: AtomicBool,
+    results: RwLock<Vec<HealthStatus>>,
+}
+
+impl Default for WarmupRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WarmupRegistry {
+    pub fn new() -> Self {
+        Self {
+            tasks: Vec::new(),
+            timeout: Duration::from_secs(30),
+            ready: AtomicBool::new(false),
+            results: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Limit for each task; a task still running is reported as failed
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Best-effort task: failures are logged and the service starts anyway
+    pub fn task<F, Fut>(self, name: &str, task: F) -> Self
+        where F: Fn() -> Fut + Send + Sync + 'static, Fut: Future<Output = Result<(), ApiError>> + Send + 'static
+    {
+        self.add(name, false, task)
+    }
+
+    /// Task the service cannot serve without: a failure makes `run` return an error
+    pub fn required_task<F, Fut>(self, name: &str, task: F) -> Self
+        where F: Fn() -> Fut + Send + Sync + 'static, Fut: Future<Output = Result<(), ApiError>> + Send + 'static
+    {
+        self.add(name, true, task)
+    }
+
+    fn add<F, Fut>(mut self, name: &str, required: bool, task: F) -> Self
+        where F: Fn() -> Fut + Send + Sync + 'static, Fut: Future<Output = Result<(), ApiError>> + Send + 'static
+    {
+        self.tasks.push(WarmupTask {
+            name: name.to_string(),
+            required,
+            run: Arc::new(move || Box::pin(task())),
+        });
+        self
+    }
+
+    /// Run every task concurrently and mark the service ready unless a required task failed
+    /// Returns the outcome of each task, in registration order.
+    pub async fn run(&self) -> Result<Vec<HealthStatus>, ApiError> {
+        let started = Instant::now();
+        let mut running = tokio::task::JoinSet::new();
+
+        for (index, task) in self.tasks.iter().enumerate() {
+            let run = Arc::clone(&task.run);
+            let timeout = self.timeout;
+            running.spawn(async move {
+                let task_started = Instant::now();
+                let result = match tokio::time::timeout(timeout, run()).await {
+                    Ok(result) => result,
+                    Err(_) => Err(ApiError::InternalServerError {
+                        message: format!("timed out after {}s", timeout.as_secs()),
+                    }),
+                };
+                (index, task_started.elapsed().as_millis() as u64, result)
+            });
+        }
+
+        let mut results: Vec<Option<HealthStatus>> = vec![None; self.tasks.len()];
+        let mut failed_required = Vec::new();
+        while let Some(joined) = running.join_next().await {
+            // Spawned tasks only fail to join when they panic
+            let (index, latency_ms, result) = match joined {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    error!("WARMUP:run [TASK_PANIC] Warm-up task panicked - error: {}", e);
+                    continue;
+                }
+            };
+            let task = &self.tasks[index];
+
+            results[index] = Some(match result {
+                Ok(()) => {
+                    info!("WARMUP:run [TASK_DONE] Warm-up task finished - task: {}, latency_ms: {}", task.name, latency_ms);
+                    HealthStatus::up(&task.name, latency_ms)
+                }
+                Err(e) if task.required => {
+                    error!("WARMUP:run [REQUIRED_FAILED] Required warm-up task failed - task: {}, error: {}", task.name, e);
+                    failed_required.push(task.name.clone());
+                    HealthStatus::down(&task.name, latency_ms, &e.to_string())
+                }
+                Err(e) => {
+                    warn!("WARMUP:run [TASK_FAILED] Warm-up task failed, continuing - task: {}, error: {}", task.name, e);
+                    HealthStatus::degraded(&task.name, latency_ms, &e.to_string())
+                }
+            });
+        }
+
+        let results: Vec<HealthStatus> = results
+            .into_iter()
+            .zip(&self.tasks)
+            .map(|(result, task)| {
+                result.unwrap_or_else(|| {
+                    if task.required {
+                        failed_required.push(task.name.clone());
+                        HealthStatus::down(&task.name, 0, "task panicked")
+                    } else {
+                        HealthStatus::degraded(&task.name, 0, "task panicked")
+                    }
+                })
+            })
+            .collect();
+        *self.results.write().await = results.clone();
+
+        if !failed_required.is_empty() {
+            return Err(ApiError::InternalServerError {
+                message: format!("Required warm-up tasks failed: {}", failed_required.join(", ")),
+            });
+        }
+
+        self.ready.store(true, Ordering::Release);
+        info!(
+            "WARMUP:run [READY] Warm-up complete - tasks: {}, duration_ms: {}",
+            results.len(),
+            started.elapsed().as_millis()
+        );
+        Ok(results)
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    /// Health of the warm-up as one component, `Down` until it completed
+    pub async fn health_status(&self) -> HealthStatus {
+        if !self.is_ready() {
+            return HealthStatus::down("warmup", 0, "warm-up has not completed");
+        }
+
+        let results = self.results.read().await;
+        let latency_ms = results
+            .iter()
+            .map(|result| result.latency_ms)
+            .max()
+            .unwrap_or(0);
+        let failed: Vec<&str> = results
+            .iter()
+            .filter(|result| result.status != HealthState::Up)
+            .map(|result| result.component.as_str())
+            .collect();
+
+        if failed.is_empty() {
+            HealthStatus::up("warmup", latency_ms)
+        } else {
+            HealthStatus::degraded("warmup", latency_ms, &format!("failed tasks: {}", failed.join(", ")))
+        }
+    }
+}
+
+/// Fairing running the warm-up at ignite, aborting launch when a required task fails
+#[cfg(not(feature = "no_web"))]
+pub struct WarmupFairing {
+    registry: Arc<WarmupRegistry>,
+}
+
+#[cfg(not(feature = "no_web"))]
+impl WarmupFairing {
+    pub fn new(registry: Arc<WarmupRegistry>) -> Self {
+        Self { registry }
+    }
+}
+
+#[cfg(not(feature = "no_web"))]
+#[rocket::async_trait]
+impl Fairing for WarmupFairing {
+    fn info(&self) -> Info {
+        Info { name: "Warm-up", kind: Kind::Ignite }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        match self.registry.run().await {
+            Ok(_) => Ok(rocket.manage(Arc::clone(&self.registry))),
+            Err(e) => {
+                error!("WARMUP:ignite [ABORT] Not starting - error: {}", e);
+                Err(rocket)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_optional_failures_degrade_and_required_failures_abort() {
+        let registry = WarmupRegistry::new()
+            .timeout(Duration::from_millis(50))
+            .task("templates", || async { Ok(()) })
+            .task("jwks", || async {
+                Err(ApiError::InternalServerError { message: "JWKS endpoint unreachable".to_string() })
+            })
+            .task("geolocation", || async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(())
+            });
+
+        assert_eq!(registry.health_status().await.status, HealthState::Down);
+        let results = registry.run().await.unwrap();
+        let statuses: Vec<(&str, HealthState)> = results
+            .iter()
+            .map(|result| (result.component.as_str(), result.status))
+            .collect();
+        assert_eq!(statuses, vec![
+            ("templates", HealthState::Up),
+            ("jwks", HealthState::Degraded),
+            ("geolocation", HealthState::Degraded),
+        ]);
+        assert!(registry.is_ready());
+        assert_eq!(registry.health_status().await.status, HealthState::Degraded);
+
+        let registry = WarmupRegistry::new().required_task("mongo_indexes", || async {
+            Err(ApiError::InternalServerError { message: "index build failed".to_string() })
+        });
+        assert!(registry.run().await.is_err());
+        assert!(!registry.is_ready());
+    }
+}
@@ -0,0 +1,201 @@
+//! In-process sliding-window counters, e.g. errors per operation over the last 5 minutes
+//!
+//! Counts are kept in fixed-width time buckets per key, so recording is O(1) and a count over
+//! any window up to the retention sums at most `retention / bucket_width` buckets. Meant for
+//! decisions local to one instance (circuit breakers, spam heuristics) and debugging routes;
+//! cross-instance totals belong in the metrics pipeline. Keys that go quiet for longer than the
+//! retention are swept on the first write of each new bucket, so per-IP or per-user keys don't
+//! accumulate on a busy service.
+//!
+//! ```ignore
+//! let errors = Arc::new(WindowedCounters::new(Duration::from_secs(10), Duration::from_secs(900)));
+//! errors.record("GEO:get_location");
+//! if errors.count("GEO:get_location", Duration::from_secs(300)) > 50 { ... }
+//! ```
+
+use std::collections::{ HashMap, VecDeque };
+use std::sync::{ Arc, Mutex };
+use std::time::{ Duration, Instant };
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::JsonSchema;
+#[cfg(not(feature = "no_web"))]
+use rocket_okapi::okapi::schemars::{ self };
+use serde::Serialize;
+
+use crate::common_lib::clock::{ system_clock, Clock };
+
+/// Count of one key within a window, as returned by `WindowedCounters::snapshot`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(not(feature = "no_web"), derive(JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct WindowedCount {
+    pub key: String,
+    pub count: u64,
+    pub window_seconds: u64,
+}
+
+#[derive(Default)]
+struct Series {
+    /// Per key, `(bucket index, count)` from oldest to newest
+    buckets: HashMap<String, VecDeque<(u64, u64)>>,
+    /// Bucket of the last sweep for keys without events in the retention
+    swept_at: u64,
+}
+
+impl Series {
+    fn sweep(&mut self, oldest_kept: u64) {
+        self.buckets.retain(|_, buckets| buckets.back().is_some_and(|(index, _)| *index >= oldest_kept));
+    }
+}
+
+/// Sliding-window event counters keyed by name (operation, user, IP, ...)
+pub struct WindowedCounters {
+    bucket_width: Duration,
+    retention: Duration,
+    series: Mutex<Series>,
+    origin: Instant,
+    clock: Arc<dyn Clock>,
+}
+
+impl WindowedCounters {
+    /// Counters answering windows up to `retention`, at `bucket_width` resolution
+    pub fn new(bucket_width: Duration, retention: Duration) -> Self {
+        Self::with_clock(bucket_width, retention, system_clock())
+    }
+
+    pub fn with_clock(bucket_width: Duration, retention: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            bucket_width: bucket_width.max(Duration::from_millis(1)),
+            retention,
+            series: Mutex::new(Series::default()),
+            origin: clock.now(),
+            clock,
+        }
+    }
+
+    fn current_bucket(&self) -> u64 {
+        let elapsed = self.clock.now().duration_since(self.origin);
+        (elapsed.as_nanos() / self.bucket_width.as_nanos()) as u64
+    }
+
+    /// Number of buckets covering `window`, including the partially filled current one
+    fn buckets_in(&self, window: Duration) -> u64 {
+        window.as_nanos().div_ceil(self.bucket_width.as_nanos()) as u64
+    }
+
+    pub fn record(&self, key: &str) {
+        self.record_many(key, 1);
+    }
+
+    pub fn record_many(&self, key: &str, count: u64) {
+        let bucket = self.current_bucket();
+        let oldest_kept = bucket.saturating_sub(self.buckets_in(self.retention));
+        let mut series = self.series.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if series.swept_at < bucket {
+            series.sweep(oldest_kept);
+            series.swept_at = bucket;
+        }
+        let buckets = series.buckets.entry(key.to_string()).or_default();
+
+        while buckets.front().is_some_and(|(index, _)| *index < oldest_kept) {
+            buckets.pop_front();
+        }
+        match buckets.back_mut() {
+            Some((index, total)) if *index == bucket => {
+                *total += count;
+            }
+            _ => buckets.push_back((bucket, count)),
+        }
+    }
+
+    /// Events recorded for `key` within the last `window`, capped at the retention
+    pub fn count(&self, key: &str, window: Duration) -> u64 {
+        let series = self.series.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        series
+            .buckets
+            .get(key)
+            .map(|buckets| self.sum(buckets, window))
+            .unwrap_or(0)
+    }
+
+    fn sum(&self, buckets: &VecDeque<(u64, u64)>, window: Duration) -> u64 {
+        let first = (self.current_bucket() + 1).saturating_sub(self.buckets_in(window.min(self.retention)));
+        buckets
+            .iter()
+            .rev()
+            .take_while(|(index, _)| *index >= first)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// Non-zero counts of every key within `window`, highest first, as served by `/debug/stats`
+    pub fn snapshot(&self, window: Duration) -> Vec<WindowedCount> {
+        let oldest_kept = self.current_bucket().saturating_sub(self.buckets_in(self.retention));
+        let mut series = self.series.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        series.sweep(oldest_kept);
+
+        let mut counts: Vec<WindowedCount> = series
+            .buckets
+            .iter()
+            .map(|(key, buckets)| WindowedCount {
+                key: key.clone(),
+                count: self.sum(buckets, window),
+                window_seconds: window.as_secs(),
+            })
+            .filter(|count| count.count > 0)
+            .collect();
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_lib::clock::MockClock;
+
+    #[test]
+    fn test_sliding_window_counts() {
+        let clock = Arc::new(MockClock::default());
+        let counters = WindowedCounters::with_clock(Duration::from_secs(10), Duration::from_secs(300), clock.clone());
+
+        counters.record("GEO:get_location");
+        counters.record_many("GEO:get_location", 2);
+        clock.advance(Duration::from_secs(60));
+        counters.record("GEO:get_location");
+        counters.record("SMS:send");
+
+        assert_eq!(counters.count("GEO:get_location", Duration::from_secs(30)), 1);
+        assert_eq!(counters.count("GEO:get_location", Duration::from_secs(300)), 4);
+        assert_eq!(counters.count("unknown", Duration::from_secs(300)), 0);
+
+        clock.advance(Duration::from_secs(250));
+        assert_eq!(counters.count("GEO:get_location", Duration::from_secs(300)), 1, "first events slid out");
+        assert_eq!(counters.snapshot(Duration::from_secs(300)), vec![
+            WindowedCount { key: "GEO:get_location".to_string(), count: 1, window_seconds: 300 },
+            WindowedCount { key: "SMS:send".to_string(), count: 1, window_seconds: 300 },
+        ]);
+
+        clock.advance(Duration::from_secs(600));
+        assert!(counters.snapshot(Duration::from_secs(300)).is_empty());
+    }
+
+    #[test]
+    fn test_quiet_keys_are_swept_on_write() {
+        let clock = Arc::new(MockClock::default());
+        let counters = WindowedCounters::with_clock(Duration::from_secs(10), Duration::from_secs(60), clock.clone());
+
+        for ip in 0..100 {
+            counters.record(&format!("203.0.113.{}", ip));
+        }
+        clock.advance(Duration::from_secs(30));
+        counters.record("203.0.113.0");
+        assert_eq!(counters.series.lock().unwrap().buckets.len(), 100, "keys inside the retention are kept");
+
+        clock.advance(Duration::from_secs(45));
+        counters.record("198.51.100.1");
+        let series = counters.series.lock().unwrap();
+        assert_eq!(series.buckets.len(), 2);
+        assert!(series.buckets.contains_key("203.0.113.0"));
+    }
+}